@@ -0,0 +1,64 @@
+#[macro_use]
+extern crate criterion;
+
+use criterion::{black_box, Benchmark, Criterion};
+use metrics_core::{FrozenKey, Key, KeyLookup, Label};
+use std::collections::HashMap;
+
+fn keys() -> Vec<Key> {
+    (0..1000)
+        .map(|i| {
+            let labels = vec![
+                Label::new("method", ["get", "post", "put", "delete"][i % 4]),
+                Label::new("route", format!("/api/v1/resource_{}", i / 4)),
+                Label::new("status", "200"),
+            ];
+            Key::from_name_and_labels("http.requests", labels)
+        })
+        .collect()
+}
+
+// Compares looking up every key of a map keyed by `Key` with one keyed by `FrozenKey`, both with
+// frozen keys and with borrowed keys.
+fn lookup_benchmark(c: &mut Criterion) {
+    c.bench(
+        "key lookup",
+        Benchmark::new("key", |b| {
+            let keys = keys();
+            let map = keys
+                .iter()
+                .cloned()
+                .map(|key| (key, 1u64))
+                .collect::<HashMap<_, _>>();
+            b.iter(|| keys.iter().map(|key| map[black_box(key)]).sum::<u64>())
+        })
+        .with_function("frozen_key", |b| {
+            let keys = keys()
+                .into_iter()
+                .map(Key::freeze)
+                .collect::<Vec<FrozenKey>>();
+            let map = keys
+                .iter()
+                .cloned()
+                .map(|key| (key, 1u64))
+                .collect::<HashMap<_, _>>();
+            b.iter(|| keys.iter().map(|key| map[black_box(key)]).sum::<u64>())
+        })
+        .with_function("borrowed_key", |b| {
+            let keys = keys();
+            let map = keys
+                .iter()
+                .cloned()
+                .map(|key| (key.freeze(), 1u64))
+                .collect::<HashMap<_, _>>();
+            b.iter(|| {
+                keys.iter()
+                    .map(|key| map[black_box(key as &dyn KeyLookup)])
+                    .sum::<u64>()
+            })
+        }),
+    );
+}
+
+criterion_group!(benches, lookup_benchmark);
+criterion_main!(benches);
@@ -32,23 +32,803 @@
 //!
 //! Histograms are a convenient way to measure behavior not only at the median, but at the edges of
 //! normal operating behavior.
+//!
+//! # Keys and Labels
+//! Every metric is identified by a [`Key`], which is a name plus an optional set of [`Label`]s.
+//! Labels are key/value pairs that further qualify a metric, such that `http_requests` with a
+//! label of `method=get` and `http_requests` with a label of `method=post` are distinct metrics.
+//!
+//! Recorders which keep long-lived maps of metrics can key them by [`FrozenKey`], which is created
+//! with [`Key::freeze`] and can be hashed and compared much faster than a [`Key`].  Such maps can
+//! be looked up with a borrowed [`Key`], through [`KeyLookup`], without freezing it first.
+//!
+//! # `no_std` support
+//! This crate is `no_std` compatible, and only requires `alloc`, when its default `std` feature
+//! is disabled.  This allows implementing recorders for constrained targets, such as a recorder
+//! writing to a serial port.  The `std` feature is only needed for [`AsyncSnapshotProvider`].
+#![cfg_attr(not(feature = "std"), no_std)]
+extern crate alloc;
+
+use alloc::borrow::{Cow, ToOwned};
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec::{self, Vec};
+use core::borrow::Borrow;
+use core::cmp::Ordering;
+use core::fmt;
+use core::hash::{Hash, Hasher};
+use core::iter::FromIterator;
+use core::slice;
+use core::time::Duration;
+#[cfg(feature = "std")]
 use futures::future::Future;
-use std::borrow::Cow;
-use std::time::Duration;
+#[cfg(feature = "std")]
+use std::collections::HashMap;
+#[cfg(feature = "std")]
+use std::sync::{Mutex, Weak};
 
-/// An optimized metric key.
+/// An allocation-optimized string.
 ///
 /// As some metrics might be sent at high frequency, it makes no sense to constantly allocate and
 /// reallocate owned [`String`]s when a static [`str`] would suffice.  As we don't want to limit
 /// callers, though, we opt to use a copy-on-write pointer -- [`Cow`] -- to allow callers
 /// flexiblity in how and what they pass.
-pub type Key = Cow<'static, str>;
+pub type ScopedString = Cow<'static, str>;
+
+/// A key/value pair used to further describe a metric.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Debug)]
+pub struct Label(ScopedString, ScopedString);
+
+impl Label {
+    /// Creates a [`Label`] from a key and value.
+    pub fn new<K, V>(key: K, value: V) -> Self
+    where
+        K: Into<ScopedString>,
+        V: Into<ScopedString>,
+    {
+        Label(key.into(), value.into())
+    }
+
+    /// The key of this label.
+    pub fn key(&self) -> &str {
+        self.0.as_ref()
+    }
+
+    /// The value of this label.
+    pub fn value(&self) -> &str {
+        self.1.as_ref()
+    }
+
+    /// Consumes this [`Label`], returning the key and value.
+    pub fn into_parts(self) -> (ScopedString, ScopedString) {
+        (self.0, self.1)
+    }
+}
+
+impl<K, V> From<(K, V)> for Label
+where
+    K: Into<ScopedString>,
+    V: Into<ScopedString>,
+{
+    fn from(pair: (K, V)) -> Label {
+        Label::new(pair.0, pair.1)
+    }
+}
+
+impl<K, V> From<&(K, V)> for Label
+where
+    K: Into<ScopedString> + Clone,
+    V: Into<ScopedString> + Clone,
+{
+    fn from(pair: &(K, V)) -> Label {
+        Label::new(pair.0.clone(), pair.1.clone())
+    }
+}
+
+/// A value that can be used as the value of a [`Label`].
+///
+/// This is implemented for every type that implements [`Display`](fmt::Display), so an enum used
+/// as a label value only needs a `Display` implementation to be used directly, and is rendered
+/// the same way at every call site.
+pub trait AsLabelValue {
+    /// Gets the label value for this value.
+    fn as_label_value(&self) -> ScopedString;
+}
+
+impl<T: fmt::Display + ?Sized> AsLabelValue for T {
+    fn as_label_value(&self) -> ScopedString {
+        self.to_string().into()
+    }
+}
+
+/// A value that can be converted to a vector of [`Label`]s.
+pub trait IntoLabels {
+    /// Consumes this value, turning it into a vector of [`Label`]s.
+    fn into_labels(self) -> Vec<Label>;
+}
+
+impl IntoLabels for Vec<Label> {
+    fn into_labels(self) -> Vec<Label> {
+        self
+    }
+}
+
+impl<T, L> IntoLabels for &T
+where
+    Self: IntoIterator<Item = L>,
+    L: Into<Label>,
+{
+    fn into_labels(self) -> Vec<Label> {
+        self.into_iter().map(Into::into).collect()
+    }
+}
+
+/// Labels are in the order of their keys.
+impl<K, V> IntoLabels for BTreeMap<K, V>
+where
+    K: Into<ScopedString>,
+    V: Into<ScopedString>,
+{
+    fn into_labels(self) -> Vec<Label> {
+        self.into_iter().map(Label::from).collect()
+    }
+}
+
+/// Labels are sorted by key, so that the same map always gives the same labels.
+///
+/// Requires the `std` feature.
+#[cfg(feature = "std")]
+impl<K, V, S> IntoLabels for HashMap<K, V, S>
+where
+    K: Into<ScopedString>,
+    V: Into<ScopedString>,
+{
+    fn into_labels(self) -> Vec<Label> {
+        let mut labels = self.into_iter().map(Label::from).collect::<Vec<_>>();
+        labels.sort();
+        labels
+    }
+}
+
+/// A collection of [`Label`]s.
+///
+/// `Labels` can be built up from any iterator of labels, or extended in place, and converts to and
+/// from a plain `Vec<Label>` without copying.
+#[derive(PartialEq, Eq, Hash, Clone, Debug, Default)]
+pub struct Labels(Vec<Label>);
+
+impl Labels {
+    /// Creates an empty [`Labels`].
+    pub fn new() -> Self {
+        Labels(Vec::new())
+    }
+
+    /// Adds a label.
+    pub fn push<L: Into<Label>>(&mut self, label: L) {
+        self.0.push(label.into());
+    }
+
+    /// Merges another set of labels into this one.
+    ///
+    /// If a label in `other` has the same key as a label already present, the existing label's
+    /// value is replaced, keeping its original position.  Otherwise, the label is appended.
+    pub fn merge<L: IntoLabels>(&mut self, other: L) {
+        for label in other.into_labels() {
+            match self.0.iter_mut().find(|l| l.key() == label.key()) {
+                Some(existing) => *existing = label,
+                None => self.0.push(label),
+            }
+        }
+    }
+
+    /// Gets the label with the given key, if present.
+    pub fn get(&self, key: &str) -> Option<&Label> {
+        self.0.iter().find(|l| l.key() == key)
+    }
+
+    /// Returns an iterator over the labels.
+    pub fn iter(&self) -> slice::Iter<'_, Label> {
+        self.0.iter()
+    }
+
+    /// Returns the number of labels.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Returns `true` if there are no labels.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl FromIterator<Label> for Labels {
+    fn from_iter<I: IntoIterator<Item = Label>>(iter: I) -> Self {
+        Labels(iter.into_iter().collect())
+    }
+}
+
+impl Extend<Label> for Labels {
+    fn extend<I: IntoIterator<Item = Label>>(&mut self, iter: I) {
+        self.0.extend(iter)
+    }
+}
+
+impl IntoIterator for Labels {
+    type Item = Label;
+    type IntoIter = vec::IntoIter<Label>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+impl IntoLabels for Labels {
+    fn into_labels(self) -> Vec<Label> {
+        self.0
+    }
+}
+
+impl From<Vec<Label>> for Labels {
+    fn from(labels: Vec<Label>) -> Self {
+        Labels(labels)
+    }
+}
+
+impl From<Labels> for Vec<Label> {
+    fn from(labels: Labels) -> Self {
+        labels.0
+    }
+}
+
+/// A metric key.
+///
+/// A key is made up of a name and, optionally, a set of labels.
+///
+/// Keys are ordered by name, and then by their labels sorted by key and value, so that keys can
+/// be stored in a `BTreeMap` to get naturally sorted output.  Keys with the same labels in a
+/// different order are still distinct, and are ordered by their labels as given.
+#[derive(PartialEq, Eq, Hash, Clone, Debug)]
+pub struct Key {
+    name: ScopedString,
+    labels: Option<Vec<Label>>,
+}
+
+impl Key {
+    /// Creates a [`Key`] from a name.
+    pub fn from_name<N: Into<ScopedString>>(name: N) -> Self {
+        Key {
+            name: name.into(),
+            labels: None,
+        }
+    }
+
+    /// Creates a [`Key`] from a static name, without any labels.
+    ///
+    /// Unlike [`Key::from_name`], this can be used in `const` and `static` items, so keys for
+    /// metrics with a fixed name can be built at compile time.
+    ///
+    /// ```rust
+    /// # extern crate metrics_core;
+    /// use metrics_core::Key;
+    ///
+    /// const REQUESTS: Key = Key::from_static_name("http.requests");
+    /// assert_eq!(REQUESTS, Key::from_name("http.requests"));
+    /// ```
+    pub const fn from_static_name(name: &'static str) -> Self {
+        Key {
+            name: ScopedString::Borrowed(name),
+            labels: None,
+        }
+    }
+
+    /// Creates a [`Key`] from a name and a set of labels.
+    ///
+    /// An empty set of labels is normalized away, so the resulting key is equal to one created
+    /// with [`Key::from_name`].
+    pub fn from_name_and_labels<N, L>(name: N, labels: L) -> Self
+    where
+        N: Into<ScopedString>,
+        L: IntoLabels,
+    {
+        let labels = labels.into_labels();
+        Key {
+            name: name.into(),
+            labels: if labels.is_empty() {
+                None
+            } else {
+                Some(labels)
+            },
+        }
+    }
+
+    /// Name of this key.
+    pub fn name(&self) -> &str {
+        self.name.as_ref()
+    }
+
+    /// Labels of this key, if they exist.
+    pub fn labels(&self) -> slice::Iter<'_, Label> {
+        self.labels.as_deref().unwrap_or(&[]).iter()
+    }
+
+    /// Consumes this [`Key`], returning the name and any labels.
+    pub fn into_parts(self) -> (ScopedString, Vec<Label>) {
+        (self.name, self.labels.unwrap_or_default())
+    }
+
+    /// Gets a canonical string identifying this [`Key`], for use as a map or cache key.
+    ///
+    /// The ID is rendered as `name{key="value",...}`, with the labels sorted by key and their
+    /// values escaped, so two keys with the same labels in a different order get the same ID.  A
+    /// key without labels is identified by its name alone.
+    ///
+    /// The ID is only meant to tell keys apart, and isn't how any exporter renders the key: the
+    /// name and label keys are used as is, without the sanitization an exporter may apply.
+    pub fn as_stable_id(&self) -> String {
+        let mut labels = self.labels().collect::<Vec<_>>();
+        if labels.is_empty() {
+            return self.name().to_owned();
+        }
+        labels.sort_by(|a, b| (a.key(), a.value()).cmp(&(b.key(), b.value())));
+
+        let labels = labels
+            .iter()
+            .map(|label| {
+                let value = label
+                    .value()
+                    .replace('\\', "\\\\")
+                    .replace('"', "\\\"")
+                    .replace('\n', "\\n");
+                format!("{}=\"{}\"", label.key(), value)
+            })
+            .collect::<Vec<_>>();
+        format!("{}{{{}}}", self.name(), labels.join(","))
+    }
+
+    /// Freezes this [`Key`] into a [`FrozenKey`], for use as a long-lived map key.
+    pub fn freeze(self) -> FrozenKey {
+        FrozenKey::new(self)
+    }
+
+    /// Maps the name of this [`Key`] to a new name, keeping the labels.
+    pub fn map_name<F, N>(self, f: F) -> Self
+    where
+        F: FnOnce(ScopedString) -> N,
+        N: Into<ScopedString>,
+    {
+        Key {
+            name: f(self.name).into(),
+            labels: self.labels,
+        }
+    }
+}
+
+impl PartialOrd for Key {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Key {
+    fn cmp(&self, other: &Self) -> Ordering {
+        fn sorted(key: &Key) -> Vec<&Label> {
+            let mut labels = key.labels().collect::<Vec<_>>();
+            labels.sort();
+            labels
+        }
+
+        self.name()
+            .cmp(other.name())
+            .then_with(|| sorted(self).cmp(&sorted(other)))
+            // Ties are broken by the original order, to stay consistent with equality.
+            .then_with(|| self.labels().cmp(other.labels()))
+    }
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())?;
+
+        let mut labels = self.labels().peekable();
+        if labels.peek().is_some() {
+            f.write_str("{")?;
+            for (i, label) in labels.enumerate() {
+                if i > 0 {
+                    f.write_str(", ")?;
+                }
+                write!(f, "{}={}", label.key(), label.value())?;
+            }
+            f.write_str("}")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl From<String> for Key {
+    fn from(name: String) -> Key {
+        Key::from_name(name)
+    }
+}
+
+impl From<&'static str> for Key {
+    fn from(name: &'static str) -> Key {
+        Key::from_name(name)
+    }
+}
+
+impl From<ScopedString> for Key {
+    fn from(name: ScopedString) -> Key {
+        Key::from_name(name)
+    }
+}
+
+/// An immutable, cheaply cloneable [`Key`].
+///
+/// Freezing a key with [`Key::freeze`] sorts its labels by key and value, and computes its hash
+/// once, so that a frozen key can be hashed and compared without looking at its name or labels in
+/// most cases.  This makes it a good fit for the keys of maps that are looked up on every
+/// recording or render.  Cloning a frozen key only bumps a reference count.
+///
+/// With the `std` feature, frozen keys are interned: freezing a key equal to a frozen key that's
+/// still alive gives a clone of that frozen key, so equal frozen keys share their name and labels
+/// and are compared by pointer.  A map keyed by frozen keys can be looked up with any
+/// [`KeyLookup`], such as a borrowed [`Key`], without freezing it first.
+///
+/// Two frozen keys are equal if they have the same name and the same labels, regardless of the
+/// order the labels were given in.  The hash is computed with FNV-1a over the name and the sorted
+/// labels, so it's the same across runs, processes, and platforms, and can be retrieved with
+/// [`FrozenKey::stable_hash`].
+#[derive(Clone, Debug)]
+pub struct FrozenKey(Arc<FrozenInner>);
+
+#[derive(Debug)]
+struct FrozenInner {
+    name: ScopedString,
+    labels: Vec<Label>,
+    hash: u64,
+}
+
+impl FrozenKey {
+    fn new(key: Key) -> Self {
+        let (name, mut labels) = key.into_parts();
+        labels.sort();
+        let hash = frozen_hash(&name, labels.iter());
+        FrozenKey(intern(FrozenInner { name, labels, hash }))
+    }
+
+    /// Name of this key.
+    pub fn name(&self) -> &str {
+        self.0.name.as_ref()
+    }
+
+    /// Labels of this key, sorted by key and value.
+    pub fn labels(&self) -> slice::Iter<'_, Label> {
+        self.0.labels.iter()
+    }
+
+    /// Gets the precomputed hash of this key.
+    pub fn stable_hash(&self) -> u64 {
+        self.0.hash
+    }
+
+    /// Gets a [`Key`] with the same name and labels, with the labels sorted.
+    pub fn to_key(&self) -> Key {
+        Key::from_name_and_labels(self.0.name.clone(), self.0.labels.clone())
+    }
+}
+
+impl PartialEq for FrozenKey {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+            || (self.0.hash == other.0.hash
+                && self.0.name == other.0.name
+                && self.0.labels == other.0.labels)
+    }
+}
+
+impl Eq for FrozenKey {}
+
+impl Hash for FrozenKey {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        state.write_u64(self.0.hash);
+    }
+}
+
+impl PartialOrd for FrozenKey {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for FrozenKey {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.name()
+            .cmp(other.name())
+            .then_with(|| self.labels().cmp(other.labels()))
+    }
+}
+
+impl fmt::Display for FrozenKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.to_key(), f)
+    }
+}
+
+impl From<Key> for FrozenKey {
+    fn from(key: Key) -> FrozenKey {
+        key.freeze()
+    }
+}
+
+impl From<FrozenKey> for Key {
+    fn from(key: FrozenKey) -> Key {
+        key.to_key()
+    }
+}
+
+impl<'a> Borrow<dyn KeyLookup + 'a> for FrozenKey {
+    fn borrow(&self) -> &(dyn KeyLookup + 'a) {
+        self
+    }
+}
+
+/// A key that a map keyed by [`FrozenKey`] can be looked up with.
+///
+/// A frozen key can be borrowed as a `dyn KeyLookup`, whose hash and equality agree with those of
+/// the frozen key, so a map keyed by frozen keys can be looked up with a borrowed [`Key`],
+/// without cloning and freezing it:
+///
+/// ```rust
+/// # extern crate metrics_core;
+/// use metrics_core::{Key, KeyLookup};
+/// use std::collections::HashMap;
+///
+/// let mut map = HashMap::new();
+/// map.insert(Key::from_name_and_labels("requests", &[("method", "get")]).freeze(), 1);
+///
+/// let key = Key::from_name_and_labels("requests", &[("method", "get")]);
+/// assert_eq!(map.get(&key as &dyn KeyLookup), Some(&1));
+/// ```
+pub trait KeyLookup {
+    /// Name of the key.
+    fn name(&self) -> &str;
+
+    /// Labels of the key, in any order.
+    fn labels(&self) -> slice::Iter<'_, Label>;
+
+    /// Gets the hash of the key, which is the [`FrozenKey::stable_hash`] of the key once frozen.
+    fn stable_hash(&self) -> u64 {
+        let labels = self.labels().as_slice();
+        if labels.windows(2).all(|pair| pair[0] <= pair[1]) {
+            frozen_hash(self.name(), labels.iter())
+        } else {
+            frozen_hash(self.name(), sorted_labels(labels).into_iter())
+        }
+    }
+}
+
+impl KeyLookup for Key {
+    fn name(&self) -> &str {
+        Key::name(self)
+    }
+
+    fn labels(&self) -> slice::Iter<'_, Label> {
+        Key::labels(self)
+    }
+}
+
+impl KeyLookup for FrozenKey {
+    fn name(&self) -> &str {
+        FrozenKey::name(self)
+    }
+
+    fn labels(&self) -> slice::Iter<'_, Label> {
+        FrozenKey::labels(self)
+    }
+
+    fn stable_hash(&self) -> u64 {
+        FrozenKey::stable_hash(self)
+    }
+}
+
+impl PartialEq for dyn KeyLookup + '_ {
+    fn eq(&self, other: &Self) -> bool {
+        let (labels, other_labels) = (self.labels().as_slice(), other.labels().as_slice());
+        self.name() == other.name()
+            && labels.len() == other_labels.len()
+            && (labels == other_labels || sorted_labels(labels) == sorted_labels(other_labels))
+    }
+}
+
+impl Eq for dyn KeyLookup + '_ {}
+
+impl Hash for dyn KeyLookup + '_ {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        state.write_u64(self.stable_hash());
+    }
+}
+
+fn sorted_labels(labels: &[Label]) -> Vec<&Label> {
+    let mut labels = labels.iter().collect::<Vec<_>>();
+    labels.sort();
+    labels
+}
+
+// Hashes a name and its labels, which have to be sorted, into the hash of their frozen key.
+fn frozen_hash<'a, I: Iterator<Item = &'a Label>>(name: &str, labels: I) -> u64 {
+    // The separator can't appear in UTF-8, so the parts can't run into each other.
+    let mut hash = Fnv::default();
+    hash.write(name.as_bytes());
+    for label in labels {
+        hash.write(&[0xff]);
+        hash.write(label.key().as_bytes());
+        hash.write(&[0xff]);
+        hash.write(label.value().as_bytes());
+    }
+    hash.finish()
+}
+
+// The frozen keys that are alive, by hash.  Keys that have been dropped are pruned from their own
+// hash when a key with the same hash is frozen, and from every hash once the number of hashes
+// has doubled since the last time.
+#[cfg(feature = "std")]
+static INTERNED: Mutex<Interned> = Mutex::new(Interned {
+    keys: BTreeMap::new(),
+    prune_at: 0,
+});
+
+#[cfg(feature = "std")]
+struct Interned {
+    keys: BTreeMap<u64, Vec<Weak<FrozenInner>>>,
+    prune_at: usize,
+}
+
+// Gets the frozen key equal to `inner` that's still alive, or makes `inner` that key.
+#[cfg(feature = "std")]
+fn intern(inner: FrozenInner) -> Arc<FrozenInner> {
+    let mut interned = INTERNED.lock().expect("interned keys poisoned");
+    let interned = &mut *interned;
+    if interned.keys.len() >= interned.prune_at {
+        interned
+            .keys
+            .retain(|_, keys| keys.iter().any(|key| key.strong_count() > 0));
+        interned.prune_at = (interned.keys.len() * 2).max(64);
+    }
+
+    let keys = interned.keys.entry(inner.hash).or_default();
+    keys.retain(|key| key.strong_count() > 0);
+    let existing = keys
+        .iter()
+        .filter_map(Weak::upgrade)
+        .find(|key| key.name == inner.name && key.labels == inner.labels);
+    existing.unwrap_or_else(|| {
+        let key = Arc::new(inner);
+        keys.push(Arc::downgrade(&key));
+        key
+    })
+}
+
+#[cfg(not(feature = "std"))]
+fn intern(inner: FrozenInner) -> Arc<FrozenInner> {
+    Arc::new(inner)
+}
+
+// The 64-bit FNV-1a hash, which is simple, fast for short inputs, and doesn't depend on `std`.
+struct Fnv(u64);
+
+impl Default for Fnv {
+    fn default() -> Self {
+        Fnv(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Hasher for Fnv {
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= u64::from(*byte);
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+/// A value that can be converted to a [`Key`].
+///
+/// Beyond anything that converts into a [`Key`], this is implemented for tuples of a name and a
+/// set of labels: either [`Labels`], a vector of [`Label`]s, a map of labels, or string pairs,
+/// which are copied.
+/// This allows building a labeled key inline, such as
+/// `("http_requests", &[("method", "get")]).into_key()`.
+pub trait IntoKey {
+    /// Consumes this value, turning it into a [`Key`].
+    fn into_key(self) -> Key;
+}
+
+impl<T: Into<Key>> IntoKey for T {
+    fn into_key(self) -> Key {
+        self.into()
+    }
+}
+
+impl<K: Into<ScopedString>> IntoKey for (K, Vec<Label>) {
+    fn into_key(self) -> Key {
+        Key::from_name_and_labels(self.0, self.1)
+    }
+}
+
+impl<K: Into<ScopedString>> IntoKey for (K, Labels) {
+    fn into_key(self) -> Key {
+        Key::from_name_and_labels(self.0, self.1)
+    }
+}
+
+impl<K: Into<ScopedString>> IntoKey for (K, &[(&str, &str)]) {
+    fn into_key(self) -> Key {
+        Key::from_name_and_labels(self.0, owned_labels(self.1))
+    }
+}
+
+impl<K: Into<ScopedString>, const N: usize> IntoKey for (K, &[(&str, &str); N]) {
+    fn into_key(self) -> Key {
+        Key::from_name_and_labels(self.0, owned_labels(&self.1[..]))
+    }
+}
+
+impl<K: Into<ScopedString>> IntoKey for (K, &[(String, String)]) {
+    fn into_key(self) -> Key {
+        Key::from_name_and_labels(self.0, owned_labels(self.1))
+    }
+}
+
+impl<K: Into<ScopedString>> IntoKey for (K, Vec<(String, String)>) {
+    fn into_key(self) -> Key {
+        let labels = self.1.into_iter().map(Label::from).collect::<Vec<_>>();
+        Key::from_name_and_labels(self.0, labels)
+    }
+}
+
+impl<K, LK, LV> IntoKey for (K, BTreeMap<LK, LV>)
+where
+    K: Into<ScopedString>,
+    LK: Into<ScopedString>,
+    LV: Into<ScopedString>,
+{
+    fn into_key(self) -> Key {
+        Key::from_name_and_labels(self.0, self.1)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<K, LK, LV, S> IntoKey for (K, HashMap<LK, LV, S>)
+where
+    K: Into<ScopedString>,
+    LK: Into<ScopedString>,
+    LV: Into<ScopedString>,
+{
+    fn into_key(self) -> Key {
+        Key::from_name_and_labels(self.0, self.1)
+    }
+}
+
+fn owned_labels<K: AsRef<str>, V: AsRef<str>>(pairs: &[(K, V)]) -> Vec<Label> {
+    pairs
+        .iter()
+        .map(|(k, v)| Label::new(k.as_ref().to_owned(), v.as_ref().to_owned()))
+        .collect()
+}
 
 /// A value which can be converted into a nanosecond representation.
 ///
 /// This trait allows us to interchangably accept raw integer time values, ones already in
 /// nanoseconds, as well as the more conventional [`Duration`] which is a result of getting the
-/// difference between two [`Instant`](std::time::Instant)s.
+/// difference between two `Instant`s.
 pub trait AsNanoseconds {
     fn as_nanos(&self) -> u64;
 }
@@ -65,6 +845,48 @@ impl AsNanoseconds for Duration {
     }
 }
 
+/// A unit of time that durations can be recorded in.
+///
+/// Durations are recorded as nanoseconds by default, which is the most precise unit but may not
+/// be the unit a metric is thought of in.  Converting to a coarser unit rounds down.
+///
+/// ```rust
+/// # extern crate metrics_core;
+/// use metrics_core::Unit;
+///
+/// let nanos = 2_500_000_000;
+/// assert_eq!(Unit::Nanoseconds.convert_nanos(nanos), 2_500_000_000);
+/// assert_eq!(Unit::Microseconds.convert_nanos(nanos), 2_500_000);
+/// assert_eq!(Unit::Milliseconds.convert_nanos(nanos), 2_500);
+/// assert_eq!(Unit::Seconds.convert_nanos(nanos), 2);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Unit {
+    /// Nanoseconds.
+    Nanoseconds,
+
+    /// Microseconds.
+    Microseconds,
+
+    /// Milliseconds.
+    Milliseconds,
+
+    /// Seconds.
+    Seconds,
+}
+
+impl Unit {
+    /// Converts a number of nanoseconds into this unit.
+    pub fn convert_nanos(self, nanos: u64) -> u64 {
+        match self {
+            Unit::Nanoseconds => nanos,
+            Unit::Microseconds => nanos / 1_000,
+            Unit::Milliseconds => nanos / 1_000_000,
+            Unit::Seconds => nanos / 1_000_000_000,
+        }
+    }
+}
+
 /// A value that records metrics.
 pub trait Recorder {
     /// Records a counter.
@@ -83,8 +905,32 @@ pub trait Recorder {
     /// counters and gauges usually have slightly different modes of operation.
     ///
     /// For the sake of flexibility on the exporter side, both are provided.
+    ///
+    /// The value is the absolute value of the gauge.  To change a gauge relative to its current
+    /// value, use [`Recorder::record_gauge_add`] instead.
     fn record_gauge<K: Into<Key>>(&mut self, key: K, value: i64);
 
+    /// Records a change to a gauge.
+    ///
+    /// Unlike [`Recorder::record_gauge`], `delta` is added to the current value of the gauge,
+    /// which starts at zero.  Not every recorder supports this, which is declared by
+    /// [`Recorder::supports_gauge_add`]: the Prometheus and aggregating recorders apply the delta
+    /// to the value they hold, the StatsD recorder renders it as a signed gauge delta, and the
+    /// wrapper recorders in `metrics-util` pass it through.
+    ///
+    /// By default, the delta is ignored.
+    fn record_gauge_add<K: Into<Key>>(&mut self, _key: K, _delta: i64) {}
+
+    /// Whether this recorder supports [`Recorder::record_gauge_add`].
+    ///
+    /// Callers that only have deltas, such as the increment and decrement operations of a gauge,
+    /// can check this to decide whether they need to track the absolute value themselves.
+    ///
+    /// Defaults to `false`.
+    fn supports_gauge_add(&self) -> bool {
+        false
+    }
+
     /// Records a histogram.
     ///
     /// Recorders are expected to tally their own histogram views, so this will be called with all
@@ -92,6 +938,94 @@ pub trait Recorder {
     ///
     /// There is no guarantee that this method will not be called multiple times for the same key.
     fn record_histogram<K: Into<Key>>(&mut self, key: K, values: &[u64]);
+
+    /// Records a histogram from sampled values.
+    ///
+    /// `rate` is the fraction of the observed values that were recorded, such as `0.1` when only
+    /// one value in ten is recorded.  Recorders that support sample rates natively pass it on, so
+    /// that the backend can scale its counts: the StatsD recorder renders it as a `|@rate`
+    /// suffix, and the wrapper recorders in `metrics-util` pass it through.  Recorders that
+    /// aggregate histograms themselves count each value [`sample_weight`] times instead, which
+    /// keeps their counts and sums statistically correct.
+    ///
+    /// By default, `values` are recorded [`sample_weight`] times with
+    /// [`Recorder::record_histogram`], without copying them.
+    fn record_histogram_sampled<K: Into<Key>>(&mut self, key: K, values: &[u64], rate: f64) {
+        let key = key.into();
+        for _ in 1..sample_weight(rate) {
+            self.record_histogram(key.clone(), values);
+        }
+        self.record_histogram(key, values);
+    }
+
+    /// Records a value of a set.
+    ///
+    /// Sets count the number of unique values recorded for a key, such as unique user IDs, which
+    /// is natively supported by StatsD.  Not every recorder supports sets: the StatsD recorder
+    /// renders them as `|s` metrics and the wrapper recorders in `metrics-util` pass them through,
+    /// while the Prometheus, InfluxDB, and text recorders ignore them.
+    ///
+    /// By default, the value is ignored.
+    fn record_set<K: Into<Key>>(&mut self, _key: K, _value: &str) {}
+}
+
+/// Gets the number of observed values that each value sampled at `rate` stands for.
+///
+/// This is `1 / rate`, rounded to the nearest integer, and capped at [`MAX_SAMPLE_WEIGHT`], which
+/// bounds the work done by recorders that record each sampled value repeatedly, so rates below
+/// `1 / MAX_SAMPLE_WEIGHT` are undercounted.  Rates outside of `(0, 1]`, including NaN, are
+/// treated as `1`, i.e. as not sampled.  Used by [`Recorder::record_histogram_sampled`].
+pub fn sample_weight(rate: f64) -> u64 {
+    if rate > 0.0 && rate < 1.0 {
+        ((1.0 / rate + 0.5) as u64).min(MAX_SAMPLE_WEIGHT)
+    } else {
+        1
+    }
+}
+
+/// The highest weight given to a sampled value by [`sample_weight`].
+pub const MAX_SAMPLE_WEIGHT: u64 = 10_000;
+
+/// Convenience methods for recording metrics without building a [`Key`] first.
+///
+/// This is implemented for every [`Recorder`], and is mostly useful when calling recorders
+/// directly rather than through a snapshot.  The name and labels are copied into the key.
+pub trait RecorderExt: Recorder {
+    /// Records a counter with the given name.
+    fn record_counter_named(&mut self, name: &str, value: u64) {
+        self.record_counter(Key::from_name(name.to_owned()), value);
+    }
+
+    /// Records a counter with the given name and labels.
+    fn record_counter_labeled(&mut self, name: &str, labels: &[(&str, &str)], value: u64) {
+        self.record_counter(labeled_key(name, labels), value);
+    }
+
+    /// Records a gauge with the given name.
+    fn record_gauge_named(&mut self, name: &str, value: i64) {
+        self.record_gauge(Key::from_name(name.to_owned()), value);
+    }
+
+    /// Records a gauge with the given name and labels.
+    fn record_gauge_labeled(&mut self, name: &str, labels: &[(&str, &str)], value: i64) {
+        self.record_gauge(labeled_key(name, labels), value);
+    }
+
+    /// Records a histogram with the given name.
+    fn record_histogram_named(&mut self, name: &str, values: &[u64]) {
+        self.record_histogram(Key::from_name(name.to_owned()), values);
+    }
+
+    /// Records a histogram with the given name and labels.
+    fn record_histogram_labeled(&mut self, name: &str, labels: &[(&str, &str)], values: &[u64]) {
+        self.record_histogram(labeled_key(name, labels), values);
+    }
+}
+
+impl<R: Recorder> RecorderExt for R {}
+
+fn labeled_key(name: &str, labels: &[(&str, &str)]) -> Key {
+    (name.to_owned(), labels).into_key()
 }
 
 /// A value that holds a point-in-time view of collected metrics.
@@ -110,6 +1044,9 @@ pub trait SnapshotProvider {
 }
 
 /// A value that can provide on-demand snapshots asynchronously.
+///
+/// Requires the `std` feature.
+#[cfg(feature = "std")]
 pub trait AsyncSnapshotProvider {
     type Snapshot: Snapshot;
     type SnapshotError;
@@ -118,3 +1055,418 @@ pub trait AsyncSnapshotProvider {
     /// Gets a snapshot asynchronously.
     fn get_snapshot_async(&self) -> Self::SnapshotFuture;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        sample_weight, FrozenKey, IntoKey, IntoLabels, Key, KeyLookup, Label, Labels, Recorder,
+        RecorderExt, MAX_SAMPLE_WEIGHT,
+    };
+    use alloc::collections::BTreeMap;
+    use alloc::format;
+    use alloc::string::{String, ToString};
+    use alloc::vec;
+    use alloc::vec::Vec;
+
+    // The tests always link `std`, even when the crate itself is built without it.
+    extern crate std;
+
+    #[test]
+    fn test_labels_from_iter_and_extend() {
+        let mut labels = vec![Label::new("method", "get")]
+            .into_iter()
+            .collect::<Labels>();
+        assert_eq!(labels.len(), 1);
+
+        labels.extend(vec![Label::new("status", "200")]);
+        assert_eq!(labels.len(), 2);
+        assert_eq!(labels.get("status").map(Label::value), Some("200"));
+
+        let raw: Vec<Label> = labels.into();
+        assert_eq!(
+            raw,
+            vec![Label::new("method", "get"), Label::new("status", "200")]
+        );
+    }
+
+    #[test]
+    fn test_labels_merge() {
+        let mut labels = (&[("method", "get"), ("status", "200")])
+            .into_labels()
+            .into_iter()
+            .collect::<Labels>();
+        labels.merge(&[("status", "404"), ("path", "/")]);
+
+        let merged = labels.into_labels();
+        assert_eq!(
+            merged,
+            vec![
+                Label::new("method", "get"),
+                Label::new("status", "404"),
+                Label::new("path", "/"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_key_from_labels() {
+        let mut labels = Labels::new();
+        labels.push(("method", "get"));
+
+        let key = Key::from_name_and_labels("http_requests", labels);
+        assert_eq!(key.name(), "http_requests");
+        assert_eq!(key.labels().count(), 1);
+        assert_eq!(key.to_string(), "http_requests{method=get}");
+    }
+
+    #[test]
+    fn test_key_empty_labels() {
+        use std::collections::hash_map::DefaultHasher;
+        use std::hash::{Hash, Hasher};
+
+        fn hash(key: &Key) -> u64 {
+            let mut hasher = DefaultHasher::new();
+            key.hash(&mut hasher);
+            hasher.finish()
+        }
+
+        let bare = Key::from_name("http_requests");
+        let empty_vec = Key::from_name_and_labels("http_requests", Vec::<Label>::new());
+        let empty_labels = Key::from_name_and_labels("http_requests", Labels::new());
+
+        assert_eq!(bare, empty_vec);
+        assert_eq!(bare, empty_labels);
+        assert_eq!(hash(&bare), hash(&empty_vec));
+        assert_eq!(hash(&bare), hash(&empty_labels));
+        assert_eq!(empty_vec.to_string(), "http_requests");
+    }
+
+    #[test]
+    fn test_recorder_ext() {
+        #[derive(Default)]
+        struct MockRecorder(Vec<String>);
+
+        impl Recorder for MockRecorder {
+            fn record_counter<K: Into<Key>>(&mut self, key: K, value: u64) {
+                self.0.push(format!("counter {} {}", key.into(), value));
+            }
+
+            fn record_gauge<K: Into<Key>>(&mut self, key: K, value: i64) {
+                self.0.push(format!("gauge {} {}", key.into(), value));
+            }
+
+            fn record_histogram<K: Into<Key>>(&mut self, key: K, values: &[u64]) {
+                self.0
+                    .push(format!("histogram {} {:?}", key.into(), values));
+            }
+        }
+
+        let name = String::from("requests");
+        let mut recorder = MockRecorder::default();
+        recorder.record_counter_named(&name, 1);
+        recorder.record_counter_labeled(&name, &[("method", "get")], 2);
+        recorder.record_gauge_named("connections", -1);
+        recorder.record_gauge_labeled("connections", &[("pool", "db")], 3);
+        recorder.record_histogram_named("latency", &[1, 2]);
+        recorder.record_histogram_labeled("latency", &[("method", "get")], &[3]);
+
+        assert_eq!(
+            recorder.0,
+            vec![
+                "counter requests 1",
+                "counter requests{method=get} 2",
+                "gauge connections -1",
+                "gauge connections{pool=db} 3",
+                "histogram latency [1, 2]",
+                "histogram latency{method=get} [3]",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_record_histogram_sampled() {
+        #[derive(Default)]
+        struct MockRecorder(Vec<u64>);
+
+        impl Recorder for MockRecorder {
+            fn record_counter<K: Into<Key>>(&mut self, _key: K, _value: u64) {}
+
+            fn record_gauge<K: Into<Key>>(&mut self, _key: K, _value: i64) {}
+
+            fn record_histogram<K: Into<Key>>(&mut self, _key: K, values: &[u64]) {
+                self.0.extend_from_slice(values);
+            }
+        }
+
+        let mut recorder = MockRecorder::default();
+        recorder.record_histogram_sampled("latency", &[1, 2], 0.25);
+        recorder.record_histogram_sampled("latency", &[3], 1.0);
+        recorder.record_histogram_sampled("latency", &[4], f64::NAN);
+        assert_eq!(recorder.0, vec![1, 2, 1, 2, 1, 2, 1, 2, 3, 4]);
+
+        // Tiny rates are capped, rather than recording billions of copies.
+        let mut recorder = MockRecorder::default();
+        recorder.record_histogram_sampled("latency", &[5, 6], 1e-9);
+        assert_eq!(recorder.0.len() as u64, 2 * MAX_SAMPLE_WEIGHT);
+        assert_eq!(sample_weight(f64::MIN_POSITIVE), MAX_SAMPLE_WEIGHT);
+
+        assert_eq!(sample_weight(0.1), 10);
+        assert_eq!(sample_weight(0.3), 3);
+        assert_eq!(sample_weight(0.0), 1);
+        assert_eq!(sample_weight(2.0), 1);
+    }
+
+    #[test]
+    fn test_into_key() {
+        let expected =
+            Key::from_name_and_labels("http_requests", vec![Label::new("method", "get")]);
+
+        assert_eq!(("http_requests", &[("method", "get")]).into_key(), expected);
+
+        let pairs: &[(&'static str, &'static str)] = &[("method", "get")];
+        assert_eq!(("http_requests", pairs).into_key(), expected);
+
+        let method = String::from("method");
+        let get = String::from("get");
+        let borrowed: &[(&str, &str)] = &[(method.as_str(), get.as_str())];
+        assert_eq!(("http_requests", borrowed).into_key(), expected);
+
+        let owned = vec![(method.clone(), get.clone())];
+        assert_eq!(
+            (String::from("http_requests"), &owned[..]).into_key(),
+            expected
+        );
+        assert_eq!((String::from("http_requests"), owned).into_key(), expected);
+
+        let labels: Labels = vec![Label::new("method", "get")].into();
+        assert_eq!(("http_requests", labels).into_key(), expected);
+        assert_eq!("http_requests".into_key(), Key::from_name("http_requests"));
+    }
+
+    #[test]
+    fn test_labels_from_maps() {
+        let expected = Key::from_name_and_labels(
+            "http_requests",
+            vec![Label::new("method", "get"), Label::new("status", "200")],
+        );
+
+        let mut ordered = BTreeMap::new();
+        ordered.insert(String::from("status"), String::from("200"));
+        ordered.insert(String::from("method"), String::from("get"));
+        assert_eq!(
+            Key::from_name_and_labels("http_requests", ordered.clone()),
+            expected
+        );
+        assert_eq!(("http_requests", ordered).into_key(), expected);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_labels_from_hash_map() {
+        use std::collections::HashMap;
+
+        let expected = Key::from_name_and_labels(
+            "http_requests",
+            vec![Label::new("method", "get"), Label::new("status", "200")],
+        );
+
+        let mut unordered = HashMap::new();
+        unordered.insert("status", "200");
+        unordered.insert("method", "get");
+        assert_eq!(
+            unordered.clone().into_labels(),
+            vec![Label::new("method", "get"), Label::new("status", "200")]
+        );
+        assert_eq!(("http_requests", unordered).into_key(), expected);
+        assert!(HashMap::<String, String>::new().into_labels().is_empty());
+    }
+
+    #[test]
+    fn test_key_stable_id() {
+        assert_eq!(
+            Key::from_name("http_requests").as_stable_id(),
+            "http_requests"
+        );
+        assert_eq!(
+            Key::from_name_and_labels("http_requests", Vec::<Label>::new()).as_stable_id(),
+            "http_requests"
+        );
+
+        let a = Key::from_name_and_labels("http_requests", &[("status", "200"), ("method", "get")]);
+        let b = Key::from_name_and_labels("http_requests", &[("method", "get"), ("status", "200")]);
+        assert_ne!(a, b);
+        assert_eq!(
+            a.as_stable_id(),
+            "http_requests{method=\"get\",status=\"200\"}"
+        );
+        assert_eq!(a.as_stable_id(), b.as_stable_id());
+
+        let escaped = Key::from_name_and_labels("errors", &[("message", "a \"b\"\n\\c")]);
+        assert_eq!(
+            escaped.as_stable_id(),
+            "errors{message=\"a \\\"b\\\"\\n\\\\c\"}"
+        );
+    }
+
+    static STATIC_KEY: Key = Key::from_static_name("static.key");
+
+    #[test]
+    fn test_static_key() {
+        const CONST_KEY: Key = Key::from_static_name("const.key");
+
+        assert_eq!(CONST_KEY, Key::from_name("const.key"));
+        assert_eq!(STATIC_KEY, Key::from_name("static.key"));
+        assert_eq!(STATIC_KEY.name(), "static.key");
+        assert_eq!(STATIC_KEY.labels().count(), 0);
+    }
+
+    #[test]
+    fn test_frozen_key() {
+        use std::collections::HashMap;
+
+        let forward =
+            Key::from_name_and_labels("requests", &[("method", "get"), ("status", "200")]);
+        let reverse =
+            Key::from_name_and_labels("requests", &[("status", "200"), ("method", "get")]);
+        let frozen = forward.clone().freeze();
+        assert_eq!(frozen, reverse.clone().freeze());
+        assert_eq!(frozen.stable_hash(), reverse.freeze().stable_hash());
+        assert_ne!(frozen, Key::from_name("requests").freeze());
+        assert_ne!(
+            frozen,
+            Key::from_name_and_labels("requests", &[("method", "post"), ("status", "200")])
+                .freeze()
+        );
+
+        // The hash is the same across runs and platforms, so it can be persisted.
+        assert_eq!(
+            Key::from_name("requests").freeze().stable_hash(),
+            0x7166_d3e8_0f26_d1f3
+        );
+
+        assert_eq!(frozen.name(), "requests");
+        assert_eq!(frozen.to_string(), "requests{method=get, status=200}");
+        assert_eq!(FrozenKey::from(forward.clone()).to_key(), forward);
+
+        let mut map = HashMap::new();
+        map.insert(frozen.clone(), 1);
+        assert_eq!(map.get(&frozen), Some(&1));
+        assert!(frozen < Key::from_name("responses").freeze());
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_frozen_key_interning() {
+        let key = Key::from_name_and_labels("requests", &[("status", "200"), ("method", "get")]);
+        let frozen = key.clone().freeze();
+        let reordered =
+            Key::from_name_and_labels("requests", &[("method", "get"), ("status", "200")]).freeze();
+        assert!(std::sync::Arc::ptr_eq(&frozen.0, &reordered.0));
+        assert!(!std::sync::Arc::ptr_eq(
+            &frozen.0,
+            &Key::from_name("requests").freeze().0
+        ));
+
+        // Keys that have been dropped are pruned once a key with the same hash is frozen.
+        drop((frozen, reordered));
+        let frozen = key.freeze();
+        let interned = super::INTERNED.lock().unwrap();
+        assert_eq!(interned.keys[&frozen.stable_hash()].len(), 1);
+    }
+
+    #[test]
+    fn test_frozen_key_lookup() {
+        use std::collections::HashMap;
+
+        let mut map = HashMap::new();
+        map.insert(
+            Key::from_name_and_labels("requests", &[("method", "get"), ("status", "200")]).freeze(),
+            1,
+        );
+        map.insert(Key::from_name("requests").freeze(), 2);
+
+        // Borrowed keys hash and compare like their frozen keys, whatever the order of labels.
+        let key = Key::from_name_and_labels("requests", &[("status", "200"), ("method", "get")]);
+        assert_eq!(key.stable_hash(), key.clone().freeze().stable_hash());
+        assert_eq!(map.get(&key as &dyn KeyLookup), Some(&1));
+        assert_eq!(
+            map.get(&Key::from_name("requests") as &dyn KeyLookup),
+            Some(&2)
+        );
+
+        let other = Key::from_name_and_labels("requests", &[("status", "200"), ("method", "put")]);
+        assert_eq!(map.get(&other as &dyn KeyLookup), None);
+        let frozen = other.freeze();
+        assert_eq!(map.get(&frozen as &dyn KeyLookup), None);
+    }
+
+    #[test]
+    fn test_key_ordering() {
+        use std::cmp::Ordering;
+        use std::collections::BTreeSet;
+
+        let key = |name: &'static str, labels: &[(&str, &str)]| (name, labels).into_key();
+
+        assert!(key("a", &[]) < key("b", &[]));
+        assert!(key("b", &[]) > key("a", &[("z", "z")]));
+        assert!(key("a", &[]) < key("a", &[("a", "1")]));
+        assert!(key("a", &[("a", "1")]) < key("a", &[("a", "2")]));
+        assert!(key("a", &[("a", "2")]) < key("a", &[("b", "1")]));
+        assert!(key("a", &[("a", "1")]) < key("a", &[("a", "1"), ("b", "1")]));
+
+        // Labels are compared sorted, with their original order only breaking ties.
+        let ab = key("a", &[("a", "1"), ("c", "1")]);
+        let ba = key("a", &[("c", "1"), ("a", "1")]);
+        assert!(ab < key("a", &[("b", "1")]));
+        assert!(ba < key("a", &[("b", "1")]));
+        assert_eq!(ab.cmp(&ab.clone()), Ordering::Equal);
+        assert_ne!(ab.cmp(&ba), Ordering::Equal);
+
+        let keys = vec![
+            key("requests", &[("method", "post")]),
+            key("connections", &[]),
+            key("requests", &[("method", "get")]),
+            key("requests", &[]),
+        ];
+        let sorted = keys
+            .into_iter()
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .map(|key| key.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            sorted,
+            vec![
+                "connections",
+                "requests",
+                "requests{method=get}",
+                "requests{method=post}"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_as_label_value() {
+        use super::AsLabelValue;
+        use std::fmt;
+
+        enum State {
+            Idle,
+            Busy,
+        }
+
+        impl fmt::Display for State {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                match self {
+                    State::Idle => f.write_str("idle"),
+                    State::Busy => f.write_str("busy"),
+                }
+            }
+        }
+
+        assert_eq!(State::Idle.as_label_value(), "idle");
+        assert_eq!(State::Busy.as_label_value(), "busy");
+        assert_eq!(200.as_label_value(), "200");
+        assert_eq!("get".as_label_value(), "get");
+    }
+}
@@ -0,0 +1,77 @@
+//! Checks that `metrics-core` can be used without the standard library.
+//!
+//! This crate implements a recorder for a target without `std`, so building it for such a target
+//! fails if `metrics-core` starts depending on `std` without its `std` feature:
+//!
+//! ```sh
+//! cargo build -p metrics-core-no-std --target thumbv7em-none-eabihf
+//! ```
+#![no_std]
+use core::fmt::{self, Write};
+use metrics_core::{Key, Recorder};
+
+/// A recorder which writes each recording as a line of text to a serial port, such as a UART.
+pub struct SerialRecorder<W> {
+    port: W,
+}
+
+impl<W: Write> SerialRecorder<W> {
+    /// Creates a new [`SerialRecorder`] writing to `port`.
+    pub fn new(port: W) -> Self {
+        SerialRecorder { port }
+    }
+
+    /// Consumes this recorder, returning the serial port.
+    pub fn into_inner(self) -> W {
+        self.port
+    }
+
+    fn write_line(&mut self, key: Key, value: fmt::Arguments<'_>) {
+        // There is nowhere to report a failed write to, so the recording is dropped.
+        let _ = writeln!(self.port, "{} {}", key.name(), value);
+        for label in key.labels() {
+            let _ = writeln!(self.port, "  {}={}", label.key(), label.value());
+        }
+    }
+}
+
+impl<W: Write> Recorder for SerialRecorder<W> {
+    fn record_counter<K: Into<Key>>(&mut self, key: K, value: u64) {
+        self.write_line(key.into(), format_args!("{}", value));
+    }
+
+    fn record_gauge<K: Into<Key>>(&mut self, key: K, value: i64) {
+        self.write_line(key.into(), format_args!("{}", value));
+    }
+
+    fn record_histogram<K: Into<Key>>(&mut self, key: K, values: &[u64]) {
+        self.write_line(key.into(), format_args!("{:?}", values));
+    }
+}
+
+/// Records a few metrics, just like the firmware of a device would.
+pub fn record_boot<R: Recorder>(recorder: &mut R) {
+    let labels = [("stage", "init")];
+    recorder.record_counter(Key::from_static_name("boots"), 1);
+    recorder.record_gauge(Key::from_name_and_labels("temperature", &labels), -4);
+    recorder.record_histogram("boot_time", &[120, 80]);
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::{record_boot, SerialRecorder};
+    use std::string::String;
+
+    #[test]
+    fn test_serial_recorder() {
+        let mut recorder = SerialRecorder::new(String::new());
+        record_boot(&mut recorder);
+
+        assert_eq!(
+            recorder.into_inner(),
+            "boots 1\ntemperature -4\n  stage=init\nboot_time [120, 80]\n"
+        );
+    }
+}
@@ -5,12 +5,32 @@
 //!
 //! # Run Modes
 //! - `run` can be used to block the current thread, running the HTTP server on the configured
-//! address
+//!   address
 //! - `into_future` will return a [`Future`] that when driven will run the HTTP server on the
-//! configured address
+//!   configured address
+//!
+//! # Web frameworks
+//! Applications which already run a web server can serve metrics from one of their own routes
+//! instead of running a separate server.  With the `axum` feature, [`axum_metrics_handler`]
+//! creates an `axum` route, and with the `warp` feature, [`warp_metrics_filter`] creates a
+//! `warp` filter.  Both render snapshots from a
+//! [`SnapshotProvider`](metrics_core::SnapshotProvider) on every request.
+//!
+//! # Content negotiation
+//! The server and routes serve the output of any recorder that can be converted into a string,
+//! so they ignore the `Accept` header of requests.  Serving OpenMetrics to scrapers that ask for
+//! it needs a custom route which calls `PrometheusRecorder::render_for_accept` from
+//! `metrics-recorder-prometheus`.  The protobuf format isn't supported either way.
 #[macro_use]
 extern crate log;
 
+#[cfg(any(feature = "axum", feature = "warp"))]
+mod web;
+#[cfg(feature = "axum")]
+pub use web::axum_metrics_handler;
+#[cfg(feature = "warp")]
+pub use web::warp_metrics_filter;
+
 use hyper::rt::run as hyper_run;
 use hyper::rt::Future;
 use hyper::service::service_fn;
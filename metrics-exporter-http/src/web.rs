@@ -0,0 +1,86 @@
+use metrics_core::{Recorder, Snapshot, SnapshotProvider};
+use std::fmt;
+
+// Content type of the Prometheus text exposition format, which is also fine for plain text.
+const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+// Takes a snapshot from `provider`, and renders it with a fresh copy of `recorder`.
+fn render<C, R>(provider: &C, recorder: &R) -> Result<String, String>
+where
+    C: SnapshotProvider,
+    C::SnapshotError: fmt::Display,
+    R: Recorder + Clone + Into<String>,
+{
+    let snapshot = provider
+        .get_snapshot()
+        .map_err(|e| format!("failed to get snapshot: {}", e))?;
+    let mut recorder = recorder.clone();
+    snapshot.record(&mut recorder);
+    Ok(recorder.into())
+}
+
+/// Creates an `axum` route which responds with the metrics from `provider`.
+///
+/// Every `GET` request takes a snapshot from `provider` and renders it with a clone of
+/// `recorder`.  The response is sent with the content type of the Prometheus text exposition
+/// format, and if a snapshot can't be taken, a `500 Internal Server Error` is sent instead.
+///
+/// Requires the `axum` feature.
+///
+/// ```rust,ignore
+/// let app = axum::Router::new().route("/metrics", axum_metrics_handler(controller, recorder));
+/// ```
+#[cfg(feature = "axum")]
+pub fn axum_metrics_handler<C, R, S>(provider: C, recorder: R) -> axum::routing::MethodRouter<S>
+where
+    C: SnapshotProvider + Clone + Send + Sync + 'static,
+    C::SnapshotError: fmt::Display,
+    R: Recorder + Clone + Into<String> + Send + Sync + 'static,
+    S: Clone + Send + Sync + 'static,
+{
+    use axum::http::{header, StatusCode};
+    use axum::response::IntoResponse;
+
+    axum::routing::get(move || {
+        let result = render(&provider, &recorder);
+        async move {
+            match result {
+                Ok(output) => ([(header::CONTENT_TYPE, CONTENT_TYPE)], output).into_response(),
+                Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e).into_response(),
+            }
+        }
+    })
+}
+
+/// Creates a `warp` filter which responds with the metrics from `provider`.
+///
+/// This matches any `GET` request, so it is usually combined with a path filter.  Every request
+/// takes a snapshot from `provider` and renders it with a clone of `recorder`.  The response is
+/// sent with the content type of the Prometheus text exposition format, and if a snapshot can't
+/// be taken, a `500 Internal Server Error` is sent instead.
+///
+/// Requires the `warp` feature.
+///
+/// ```rust,ignore
+/// let routes = warp::path("metrics").and(warp_metrics_filter(controller, recorder));
+/// ```
+#[cfg(feature = "warp")]
+pub fn warp_metrics_filter<C, R>(
+    provider: C,
+    recorder: R,
+) -> impl warp::Filter<Extract = (warp::reply::Response,), Error = warp::Rejection> + Clone
+where
+    C: SnapshotProvider + Clone + Send + Sync + 'static,
+    C::SnapshotError: fmt::Display,
+    R: Recorder + Clone + Into<String> + Send + Sync + 'static,
+{
+    use warp::http::StatusCode;
+    use warp::{Filter, Reply};
+
+    warp::get().map(move || match render(&provider, &recorder) {
+        Ok(output) => {
+            warp::reply::with_header(output, "content-type", CONTENT_TYPE).into_response()
+        }
+        Err(e) => warp::reply::with_status(e, StatusCode::INTERNAL_SERVER_ERROR).into_response(),
+    })
+}
@@ -0,0 +1,59 @@
+use metrics_core::Recorder;
+use metrics_recorder_prometheus::PrometheusRecorder;
+use metrics_util::AggregatingRecorder;
+
+const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+fn provider() -> AggregatingRecorder {
+    let mut provider = AggregatingRecorder::new();
+    provider.record_counter("http.requests", 3);
+    provider.record_gauge("connections", 2);
+    provider
+}
+
+fn assert_metrics(body: &[u8]) {
+    let body = std::str::from_utf8(body).unwrap();
+    assert!(body.contains("\nhttp_requests 3\n"), "{}", body);
+    assert!(body.contains("\nconnections 2\n"), "{}", body);
+}
+
+#[cfg(feature = "axum")]
+#[tokio::test]
+async fn test_axum_route() {
+    use axum::body::{to_bytes, Body};
+    use axum::http::{header, Request, StatusCode};
+    use metrics_exporter_http::axum_metrics_handler;
+    use tower::ServiceExt;
+
+    let handler = axum_metrics_handler(provider(), PrometheusRecorder::new());
+    let app = axum::Router::new().route("/metrics", handler);
+    let request = Request::get("/metrics").body(Body::empty()).unwrap();
+    let response = app.oneshot(request).await.unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[header::CONTENT_TYPE], CONTENT_TYPE);
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    assert_metrics(&body);
+}
+
+#[cfg(feature = "warp")]
+#[tokio::test]
+async fn test_warp_filter() {
+    use metrics_exporter_http::warp_metrics_filter;
+    use warp::Filter;
+
+    let filter = warp_metrics_filter(provider(), PrometheusRecorder::new());
+    let routes = warp::path("metrics").and(filter);
+    let response = warp::test::request().path("/metrics").reply(&routes).await;
+
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers()["content-type"], CONTENT_TYPE);
+    assert_metrics(response.body());
+
+    let response = warp::test::request()
+        .method("POST")
+        .path("/metrics")
+        .reply(&routes)
+        .await;
+    assert_eq!(response.status(), 405);
+}
@@ -6,9 +6,9 @@
 //!
 //! # Run Modes
 //! - `run` can be used to block the current thread, taking snapshots and exporting them on an
-//! interval
+//!   interval
 //! - `into_future` will return a [`Future`] that when driven will take a snapshot on the
-//! configured interval and log it
+//!   configured interval and log it
 #[macro_use]
 extern crate log;
 
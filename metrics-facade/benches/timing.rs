@@ -0,0 +1,30 @@
+#[macro_use]
+extern crate criterion;
+#[macro_use]
+extern crate metrics_facade;
+
+use criterion::{black_box, Benchmark, Criterion};
+use std::time::Instant;
+
+// Compares the overhead of timing a trivial block, which is dominated by reading the clock.  Run
+// with `--features quanta` to measure the TSC-based clock used by `timing_fast!`.
+fn timing_benchmark(c: &mut Criterion) {
+    c.bench(
+        "timing",
+        Benchmark::new("instant", |b| {
+            b.iter(|| {
+                let start = Instant::now();
+                let value = black_box(42);
+                let end = Instant::now();
+                timing!("bench.timing", start, end);
+                value
+            })
+        })
+        .with_function("timing_fast", |b| {
+            b.iter(|| timing_fast!("bench.timing", { black_box(42) }))
+        }),
+    );
+}
+
+criterion_group!(benches, timing_benchmark);
+criterion_main!(benches);
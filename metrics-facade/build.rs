@@ -3,6 +3,8 @@
 use std::env;
 
 fn main() {
+    println!("cargo:rustc-check-cfg=cfg(atomic_cas)");
+
     // CAS is not available on thumbv6.
     let target = env::var("TARGET").unwrap();
     if !target.starts_with("thumbv6") {
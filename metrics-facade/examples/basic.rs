@@ -26,7 +26,7 @@ impl Recorder for PrintRecorder {
 
 #[cfg(feature = "std")]
 fn init_print_logger() {
-    let recorder = PrintRecorder::default();
+    let recorder = PrintRecorder;
     metrics_facade::set_boxed_recorder(Box::new(recorder)).unwrap()
 }
 
@@ -0,0 +1,44 @@
+//! The clock used by [`timing_fast!`].
+//!
+//! Times are opaque `u64` readings, which are only meaningful when passed back to [`elapsed`].
+
+/// Reads the clock, using the TSC through `quanta`.
+#[cfg(feature = "quanta")]
+pub fn start() -> u64 {
+    clock().raw()
+}
+
+/// Gets the number of nanoseconds since `start` was read.
+#[cfg(feature = "quanta")]
+pub fn elapsed(start: u64) -> u64 {
+    let clock = clock();
+    clock.delta_as_nanos(start, clock.raw())
+}
+
+// Calibrating the clock takes a while, so it is only done once.
+#[cfg(feature = "quanta")]
+fn clock() -> &'static quanta::Clock {
+    static CLOCK: std::sync::OnceLock<quanta::Clock> = std::sync::OnceLock::new();
+    CLOCK.get_or_init(quanta::Clock::new)
+}
+
+/// Reads the clock, using [`Instant`](std::time::Instant).
+#[cfg(not(feature = "quanta"))]
+pub fn start() -> u64 {
+    use std::convert::TryFrom;
+
+    u64::try_from(epoch().elapsed().as_nanos()).unwrap_or(u64::MAX)
+}
+
+/// Gets the number of nanoseconds since `start` was read.
+#[cfg(not(feature = "quanta"))]
+pub fn elapsed(start: u64) -> u64 {
+    self::start().saturating_sub(start)
+}
+
+// Readings are relative to the first one, so they fit in a `u64`.
+#[cfg(not(feature = "quanta"))]
+fn epoch() -> std::time::Instant {
+    static EPOCH: std::sync::OnceLock<std::time::Instant> = std::sync::OnceLock::new();
+    *EPOCH.get_or_init(std::time::Instant::now)
+}
@@ -0,0 +1,408 @@
+use crate::Recorder;
+use metrics_core::Key;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::mem;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, Instant};
+
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    static PENDING: RefCell<Vec<Pending>> = const { RefCell::new(Vec::new()) };
+}
+
+// The increments buffered by one thread for one recorder, which the recorder can drain from any
+// thread.
+type Buffer = Arc<Mutex<HashMap<Key, u64>>>;
+
+// The buffer of the current thread for the recorder with the given ID, which is flushed to the
+// recorder when the thread exits, unless the recorder is gone by then.
+struct Pending {
+    id: usize,
+    buffer: Buffer,
+    last_flush: Instant,
+    shared: Weak<dyn Flush>,
+}
+
+impl Drop for Pending {
+    fn drop(&mut self) {
+        if let Some(shared) = self.shared.upgrade() {
+            shared.release(&self.buffer);
+        }
+    }
+}
+
+// The state of a recorder that outlives it for as long as a flush is in progress.
+struct Shared<R> {
+    inner: R,
+    buffers: Mutex<Vec<Buffer>>,
+}
+
+// Lets the buffers of a thread be flushed without knowing the type of the inner recorder.
+trait Flush {
+    fn release(&self, buffer: &Buffer);
+}
+
+impl<R: Recorder> Shared<R> {
+    fn flush(&self, counts: HashMap<Key, u64>) {
+        for (key, value) in counts {
+            self.inner.record_counter(key, value);
+        }
+    }
+}
+
+impl<R: Recorder> Flush for Shared<R> {
+    // Flushes the given buffer and stops tracking it, as its thread is exiting.
+    fn release(&self, buffer: &Buffer) {
+        let counts = take(buffer);
+        self.buffers
+            .lock()
+            .expect("coalescing buffers poisoned")
+            .retain(|b| !Arc::ptr_eq(b, buffer));
+        self.flush(counts);
+    }
+}
+
+fn take(buffer: &Buffer) -> HashMap<Key, u64> {
+    mem::take(&mut *buffer.lock().expect("coalescing buffer poisoned"))
+}
+
+/// A [`Recorder`] that coalesces counter increments in per-thread buffers.
+///
+/// Each thread sums its increments per key, and flushes the sums to the inner recorder at most
+/// once per flush interval, on the first increment after the interval has passed, so the inner
+/// recorder sees one increment per key and interval instead of one per call.  This takes the
+/// shared state of the inner recorder off the hot path of counters incremented at a very high
+/// frequency, at the cost of delaying their updates by up to the interval.
+///
+/// The buffer of a thread is flushed when the thread exits, and
+/// [`CoalescingRecorder::flush_all`] flushes the buffers of every thread, including those that
+/// have gone idle, so it should be called before the inner recorder is rendered.
+/// [`CoalescingRecorder::flush_thread_local`] only flushes the buffer of the current thread.
+/// Gauges and histograms are passed through as-is.
+///
+/// Requires the `std` feature.
+///
+/// ### Examples
+///
+/// ```rust
+/// # extern crate metrics_facade;
+/// # use metrics_facade::Recorder;
+/// # use metrics_core::Key;
+/// # struct PrintRecorder;
+/// # impl Recorder for PrintRecorder {
+/// #     fn record_counter(&self, key: Key, value: u64) { println!("{} {}", key, value) }
+/// #     fn record_gauge(&self, _: Key, _: i64) {}
+/// #     fn record_histogram(&self, _: Key, _: u64) {}
+/// # }
+/// use metrics_facade::CoalescingRecorder;
+/// use std::time::Duration;
+///
+/// let recorder = CoalescingRecorder::new(PrintRecorder)
+///     .with_flush_interval(Duration::from_millis(100));
+/// for _ in 0..1000 {
+///     recorder.record_counter(Key::from_name("requests"), 1);
+/// }
+///
+/// // Prints `requests 1000`.
+/// recorder.flush_all();
+/// ```
+pub struct CoalescingRecorder<R> {
+    shared: Arc<Shared<R>>,
+    id: usize,
+    interval: Duration,
+}
+
+impl<R: Recorder + 'static> CoalescingRecorder<R> {
+    /// Creates a new [`CoalescingRecorder`] wrapping `inner`, flushing increments every second.
+    pub fn new(inner: R) -> Self {
+        CoalescingRecorder {
+            shared: Arc::new(Shared {
+                inner,
+                buffers: Mutex::new(Vec::new()),
+            }),
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            interval: Duration::from_secs(1),
+        }
+    }
+
+    /// Sets how long increments are buffered for before being flushed to the inner recorder.
+    ///
+    /// A zero interval flushes every increment right away.
+    pub fn with_flush_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Flushes the increments buffered by every thread to the inner recorder.
+    ///
+    /// Increments buffered by threads that have since exited have already been flushed.
+    pub fn flush_all(&self) {
+        let counts = {
+            let buffers = self
+                .shared
+                .buffers
+                .lock()
+                .expect("coalescing buffers poisoned");
+            buffers.iter().map(take).collect::<Vec<_>>()
+        };
+        for counts in counts {
+            self.shared.flush(counts);
+        }
+    }
+
+    /// Flushes the increments buffered by the current thread to the inner recorder.
+    ///
+    /// Increments buffered by other threads are left as-is, and can be flushed with
+    /// [`CoalescingRecorder::flush_all`].
+    pub fn flush_thread_local(&self) {
+        let counts = PENDING
+            .try_with(|pending| {
+                let mut pending = pending.try_borrow_mut().ok()?;
+                let pending = pending.iter_mut().find(|p| p.id == self.id)?;
+                pending.last_flush = Instant::now();
+                Some(take(&pending.buffer))
+            })
+            .ok()
+            .flatten();
+        if let Some(counts) = counts {
+            self.shared.flush(counts);
+        }
+    }
+
+    /// Gets a reference to the inner recorder.
+    pub fn inner(&self) -> &R {
+        &self.shared.inner
+    }
+}
+
+impl<R: Recorder + 'static> Recorder for CoalescingRecorder<R> {
+    fn record_counter(&self, key: Key, value: u64) {
+        let mut key = Some(key);
+        let flushed = PENDING.try_with(|pending| {
+            // Buffering is skipped if the buffers are already borrowed, which only happens when
+            // recording from within a flush.
+            let mut pending = pending.try_borrow_mut().ok()?;
+            let index = match pending.iter().position(|p| p.id == self.id) {
+                Some(index) => index,
+                None => {
+                    // The buffers of recorders that have been dropped have nothing left to flush.
+                    pending.retain(|p| p.shared.strong_count() > 0);
+
+                    let buffer = Buffer::default();
+                    self.shared
+                        .buffers
+                        .lock()
+                        .expect("coalescing buffers poisoned")
+                        .push(buffer.clone());
+                    let shared = Arc::downgrade(&self.shared);
+                    pending.push(Pending {
+                        id: self.id,
+                        buffer,
+                        last_flush: Instant::now(),
+                        shared,
+                    });
+                    pending.len() - 1
+                }
+            };
+
+            let entry = &mut pending[index];
+            let mut counts = entry.buffer.lock().expect("coalescing buffer poisoned");
+            let count = counts.entry(key.take()?).or_insert(0);
+            *count = count.saturating_add(value);
+            if entry.last_flush.elapsed() >= self.interval {
+                entry.last_flush = Instant::now();
+                Some(Some(mem::take(&mut *counts)))
+            } else {
+                Some(None)
+            }
+        });
+
+        match flushed {
+            Ok(Some(Some(counts))) => self.shared.flush(counts),
+            Ok(Some(None)) => {}
+            // The thread-local storage is being torn down, or already borrowed, so the increment
+            // is recorded right away instead.
+            _ => {
+                if let Some(key) = key {
+                    self.shared.inner.record_counter(key, value);
+                }
+            }
+        }
+    }
+
+    fn record_gauge(&self, key: Key, value: i64) {
+        self.shared.inner.record_gauge(key, value);
+    }
+
+    fn record_histogram(&self, key: Key, value: u64) {
+        self.shared.inner.record_histogram(key, value);
+    }
+
+    fn record_histogram_n(&self, key: Key, value: u64, count: u64) {
+        self.shared.inner.record_histogram_n(key, value, count);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CoalescingRecorder;
+    use crate::Recorder;
+    use metrics_core::Key;
+    use std::collections::HashMap;
+    use std::sync::{mpsc, Arc, Mutex};
+    use std::thread;
+    use std::time::Duration;
+
+    #[derive(Default)]
+    struct MockRecorder {
+        counters: Mutex<HashMap<Key, u64>>,
+        calls: Mutex<usize>,
+    }
+
+    impl Recorder for MockRecorder {
+        fn record_counter(&self, key: Key, value: u64) {
+            *self.calls.lock().unwrap() += 1;
+            *self.counters.lock().unwrap().entry(key).or_insert(0) += value;
+        }
+
+        fn record_gauge(&self, _key: Key, _value: i64) {}
+
+        fn record_histogram(&self, _key: Key, _value: u64) {}
+    }
+
+    impl MockRecorder {
+        fn count(&self, name: &'static str) -> Option<u64> {
+            self.counters
+                .lock()
+                .unwrap()
+                .get(&Key::from_name(name))
+                .copied()
+        }
+    }
+
+    #[test]
+    fn test_flush_thread_local() {
+        let recorder = CoalescingRecorder::new(MockRecorder::default())
+            .with_flush_interval(Duration::from_secs(3600));
+        for _ in 0..100 {
+            recorder.record_counter(Key::from_name("requests"), 2);
+        }
+        recorder.record_counter(Key::from_name("errors"), 1);
+        assert_eq!(recorder.inner().count("requests"), None);
+
+        recorder.flush_thread_local();
+        assert_eq!(recorder.inner().count("requests"), Some(200));
+        assert_eq!(recorder.inner().count("errors"), Some(1));
+        assert_eq!(*recorder.inner().calls.lock().unwrap(), 2);
+
+        // Nothing is left to flush.
+        recorder.flush_thread_local();
+        assert_eq!(*recorder.inner().calls.lock().unwrap(), 2);
+    }
+
+    #[test]
+    fn test_flush_interval() {
+        let recorder = CoalescingRecorder::new(MockRecorder::default())
+            .with_flush_interval(Duration::from_secs(0));
+        recorder.record_counter(Key::from_name("requests"), 1);
+        recorder.record_counter(Key::from_name("requests"), 1);
+        assert_eq!(recorder.inner().count("requests"), Some(2));
+    }
+
+    #[test]
+    fn test_flush_all() {
+        let recorder = Arc::new(
+            CoalescingRecorder::new(MockRecorder::default())
+                .with_flush_interval(Duration::from_secs(3600)),
+        );
+        recorder.record_counter(Key::from_name("requests"), 1);
+
+        // The other thread stays alive, but idle, while its increments are flushed.
+        let (recorded_tx, recorded_rx) = mpsc::channel();
+        let (done_tx, done_rx) = mpsc::channel::<()>();
+        let thread = {
+            let recorder = recorder.clone();
+            thread::spawn(move || {
+                recorder.record_counter(Key::from_name("requests"), 2);
+                recorded_tx.send(()).unwrap();
+                done_rx.recv().unwrap();
+            })
+        };
+        recorded_rx.recv().unwrap();
+        assert_eq!(recorder.inner().count("requests"), None);
+
+        recorder.flush_all();
+        assert_eq!(recorder.inner().count("requests"), Some(3));
+        assert_eq!(recorder.shared.buffers.lock().unwrap().len(), 2);
+
+        // Once the thread has exited, its buffer is released.
+        done_tx.send(()).unwrap();
+        thread.join().unwrap();
+        assert_eq!(recorder.shared.buffers.lock().unwrap().len(), 1);
+        assert_eq!(*recorder.inner().calls.lock().unwrap(), 2);
+    }
+
+    #[test]
+    fn test_flush_on_thread_exit() {
+        let recorder = Arc::new(
+            CoalescingRecorder::new(MockRecorder::default())
+                .with_flush_interval(Duration::from_secs(3600)),
+        );
+        let thread = {
+            let recorder = recorder.clone();
+            thread::spawn(move || {
+                recorder.record_counter(Key::from_name("requests"), 2);
+                recorder.record_counter(Key::from_name("requests"), 3);
+            })
+        };
+        thread.join().unwrap();
+        assert_eq!(recorder.inner().count("requests"), Some(5));
+        assert!(recorder.shared.buffers.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_release_dropped_recorders() {
+        for _ in 0..10 {
+            let recorder = CoalescingRecorder::new(MockRecorder::default());
+            recorder.record_counter(Key::from_name("requests"), 1);
+        }
+
+        // Only the buffer of the latest recorder is left once a new one is buffered.
+        let recorder = CoalescingRecorder::new(MockRecorder::default());
+        recorder.record_counter(Key::from_name("requests"), 1);
+        assert_eq!(super::PENDING.with(|pending| pending.borrow().len()), 1);
+    }
+
+    #[test]
+    fn test_concurrent_flush_all() {
+        let recorder = Arc::new(
+            CoalescingRecorder::new(MockRecorder::default())
+                .with_flush_interval(Duration::from_secs(3600)),
+        );
+
+        let threads = (0..8)
+            .map(|_| {
+                let recorder = recorder.clone();
+                thread::spawn(move || {
+                    for i in 0..10_000 {
+                        recorder.record_counter(Key::from_name("requests"), 1);
+                        if i % 1_000 == 0 {
+                            recorder.record_counter(Key::from_name("batches"), 1);
+                        }
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        // The increments of each thread are flushed with one call per key as it exits.
+        assert_eq!(recorder.inner().count("requests"), Some(80_000));
+        assert_eq!(recorder.inner().count("batches"), Some(80));
+        assert_eq!(*recorder.inner().calls.lock().unwrap(), 16);
+    }
+}
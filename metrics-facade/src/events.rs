@@ -0,0 +1,157 @@
+use crate::Recorder;
+use metrics_core::Key;
+use tracing::{Level, Value};
+
+/// A [`Recorder`] that emits every measurement as a `tracing` event.
+///
+/// Nothing is aggregated: each recording becomes a single event, with the target `metrics`,
+/// which is handled by whatever subscriber is installed.  This is mostly useful during
+/// development, to see metrics alongside logs, and is the inverse of [`MetricsLayer`].
+///
+/// Each event has the following fields:
+///
+/// - `metric.kind`: one of `counter`, `gauge`, or `histogram`
+/// - `metric.name`: the name of the key
+/// - `metric.value`: the value recorded
+/// - `metric.labels`: the labels of the key, formatted as `key=value` pairs separated by commas
+///
+/// As the fields of a `tracing` event are fixed when it is compiled, labels are rendered into a
+/// single field rather than a field per label.
+///
+/// Requires the `tracing` feature.
+///
+/// [`MetricsLayer`]: crate::MetricsLayer
+///
+/// ### Examples
+///
+/// ```rust
+/// # extern crate metrics_facade;
+/// # extern crate tracing;
+/// use metrics_facade::TracingRecorder;
+///
+/// let recorder = TracingRecorder::new(tracing::Level::DEBUG);
+/// metrics_facade::set_boxed_recorder(Box::new(recorder)).unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct TracingRecorder {
+    level: Level,
+}
+
+impl TracingRecorder {
+    /// Creates a new [`TracingRecorder`] which emits events at the given level.
+    pub fn new(level: Level) -> Self {
+        TracingRecorder { level }
+    }
+
+    fn emit<V: Value>(&self, kind: &str, key: Key, value: V) {
+        let (name, labels) = key.into_parts();
+        let labels = labels
+            .iter()
+            .map(|label| format!("{}={}", label.key(), label.value()))
+            .collect::<Vec<_>>()
+            .join(",");
+
+        macro_rules! event {
+            ($level:expr) => {
+                tracing::event!(
+                    target: "metrics",
+                    $level,
+                    metric.kind = kind,
+                    metric.name = name.as_ref(),
+                    metric.value = value,
+                    metric.labels = labels.as_str()
+                )
+            };
+        }
+
+        match self.level {
+            Level::TRACE => event!(Level::TRACE),
+            Level::DEBUG => event!(Level::DEBUG),
+            Level::INFO => event!(Level::INFO),
+            Level::WARN => event!(Level::WARN),
+            Level::ERROR => event!(Level::ERROR),
+        }
+    }
+}
+
+impl Default for TracingRecorder {
+    fn default() -> Self {
+        Self::new(Level::TRACE)
+    }
+}
+
+impl Recorder for TracingRecorder {
+    fn record_counter(&self, key: Key, value: u64) {
+        self.emit("counter", key, value);
+    }
+
+    fn record_gauge(&self, key: Key, value: i64) {
+        self.emit("gauge", key, value);
+    }
+
+    fn record_histogram(&self, key: Key, value: u64) {
+        self.emit("histogram", key, value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TracingRecorder;
+    use crate::Recorder;
+    use metrics_core::Key;
+    use std::fmt;
+    use std::sync::{Arc, Mutex};
+    use tracing::field::{Field, Visit};
+    use tracing::{Event, Level, Subscriber};
+    use tracing_subscriber::layer::{Context, Layer, SubscriberExt};
+
+    struct CaptureLayer(Arc<Mutex<Vec<String>>>);
+
+    struct FieldVisitor(Vec<String>);
+
+    impl Visit for FieldVisitor {
+        fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+            self.0.push(format!("{}={:?}", field.name(), value));
+        }
+    }
+
+    impl<S: Subscriber> Layer<S> for CaptureLayer {
+        fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+            let mut visitor = FieldVisitor(Vec::new());
+            event.record(&mut visitor);
+            let metadata = event.metadata();
+            self.0.lock().unwrap().push(format!(
+                "{} {} {}",
+                metadata.level(),
+                metadata.target(),
+                visitor.0.join(" ")
+            ));
+        }
+    }
+
+    #[test]
+    fn test_events() {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let subscriber = tracing_subscriber::registry().with(CaptureLayer(events.clone()));
+        tracing::subscriber::with_default(subscriber, || {
+            let recorder = TracingRecorder::new(Level::DEBUG);
+            let labels = vec![("method", "get"), ("status", "200")];
+            recorder.record_counter(Key::from_name_and_labels("requests", &labels), 3);
+            recorder.record_gauge(Key::from_name("connections"), -2);
+            recorder.record_histogram(Key::from_name("latency"), 42);
+        });
+
+        let events = events.lock().unwrap();
+        assert_eq!(
+            *events,
+            vec![
+                "DEBUG metrics metric.kind=\"counter\" metric.name=\"requests\" metric.value=3 \
+                 metric.labels=\"method=get,status=200\"",
+                "DEBUG metrics metric.kind=\"gauge\" metric.name=\"connections\" metric.value=-2 \
+                 metric.labels=\"\"",
+                "DEBUG metrics metric.kind=\"histogram\" metric.name=\"latency\" metric.value=42 \
+                 metric.labels=\"\"",
+            ]
+        );
+    }
+}
@@ -0,0 +1,236 @@
+use crate::{recorder, Recorder};
+use metrics_core::{AsNanoseconds, IntoLabels, Key, Label, ScopedString};
+
+/// A handle for recording metrics through method calls, rather than macros.
+///
+/// Each method creates a builder for a single metric, which can be given labels before values
+/// are recorded to it.  This is equivalent to using the macros, but is easier to discover from
+/// an IDE, and builders can be kept around to record several values.
+///
+/// ### Examples
+///
+/// ```rust
+/// # extern crate metrics_facade;
+/// use metrics_facade::Metrics;
+///
+/// let metrics = Metrics::global();
+/// metrics.counter("http.requests").labels(&[("method", "get")]).increment(1);
+/// metrics.gauge("connections").set(3);
+/// ```
+#[derive(Clone, Copy)]
+pub struct Metrics<'a> {
+    recorder: &'a dyn Recorder,
+}
+
+impl Metrics<'static> {
+    /// Creates a [`Metrics`] handle which records to the global recorder.
+    ///
+    /// The recorder is looked up once, so a handle created before the global recorder is set
+    /// keeps recording to the no-op recorder.
+    pub fn global() -> Self {
+        Metrics::new(recorder())
+    }
+}
+
+impl<'a> Metrics<'a> {
+    /// Creates a [`Metrics`] handle which records to `recorder`.
+    pub fn new(recorder: &'a dyn Recorder) -> Self {
+        Metrics { recorder }
+    }
+
+    /// Gets a builder for the counter with the given name.
+    pub fn counter<N: Into<ScopedString>>(&self, name: N) -> Counter<'a> {
+        Counter(MetricBuilder::new(self.recorder, name))
+    }
+
+    /// Gets a builder for the gauge with the given name.
+    pub fn gauge<N: Into<ScopedString>>(&self, name: N) -> Gauge<'a> {
+        Gauge(MetricBuilder::new(self.recorder, name))
+    }
+
+    /// Gets a builder for the histogram with the given name.
+    pub fn histogram<N: Into<ScopedString>>(&self, name: N) -> Histogram<'a> {
+        Histogram(MetricBuilder::new(self.recorder, name))
+    }
+}
+
+#[derive(Clone)]
+struct MetricBuilder<'a> {
+    recorder: &'a dyn Recorder,
+    name: ScopedString,
+    labels: Vec<Label>,
+}
+
+impl<'a> MetricBuilder<'a> {
+    fn new<N: Into<ScopedString>>(recorder: &'a dyn Recorder, name: N) -> Self {
+        MetricBuilder {
+            recorder,
+            name: name.into(),
+            labels: Vec::new(),
+        }
+    }
+
+    fn labels<L: IntoLabels>(mut self, labels: L) -> Self {
+        self.labels.extend(labels.into_labels());
+        self
+    }
+
+    fn key(&self) -> Key {
+        Key::from_name_and_labels(self.name.clone(), self.labels.clone())
+    }
+}
+
+/// A counter, created by [`Metrics::counter`].
+///
+/// ### Examples
+///
+/// ```rust
+/// # extern crate metrics_facade;
+/// use metrics_facade::Metrics;
+///
+/// let requests = Metrics::global()
+///     .counter("http.requests")
+///     .labels(&[("method", "get"), ("status", "200")]);
+/// requests.increment(1);
+/// requests.increment(2);
+/// ```
+#[derive(Clone)]
+pub struct Counter<'a>(MetricBuilder<'a>);
+
+impl<'a> Counter<'a> {
+    /// Adds labels to this counter.
+    pub fn labels<L: IntoLabels>(self, labels: L) -> Self {
+        Counter(self.0.labels(labels))
+    }
+
+    /// Records `value` to this counter.
+    ///
+    /// Functionally equivalent to calling [`counter!`].
+    pub fn increment(&self, value: u64) {
+        self.0.recorder.record_counter(self.0.key(), value);
+    }
+}
+
+/// A gauge, created by [`Metrics::gauge`].
+///
+/// ### Examples
+///
+/// ```rust
+/// # extern crate metrics_facade;
+/// use metrics_facade::Metrics;
+///
+/// Metrics::global()
+///     .gauge("temperature")
+///     .labels(&[("room", "kitchen")])
+///     .set(-3);
+/// ```
+#[derive(Clone)]
+pub struct Gauge<'a>(MetricBuilder<'a>);
+
+impl<'a> Gauge<'a> {
+    /// Adds labels to this gauge.
+    pub fn labels<L: IntoLabels>(self, labels: L) -> Self {
+        Gauge(self.0.labels(labels))
+    }
+
+    /// Sets this gauge to `value`.
+    ///
+    /// Functionally equivalent to calling [`gauge!`].
+    pub fn set(&self, value: i64) {
+        self.0.recorder.record_gauge(self.0.key(), value);
+    }
+}
+
+/// A histogram, created by [`Metrics::histogram`].
+///
+/// ### Examples
+///
+/// ```rust
+/// # extern crate metrics_facade;
+/// use metrics_facade::Metrics;
+/// use std::time::Duration;
+///
+/// let query_time = Metrics::global().histogram("db.query_time");
+/// query_time.record(Duration::from_millis(12));
+/// query_time.labels(&[("table", "users")]).record(250_000u64);
+/// ```
+#[derive(Clone)]
+pub struct Histogram<'a>(MetricBuilder<'a>);
+
+impl<'a> Histogram<'a> {
+    /// Adds labels to this histogram.
+    pub fn labels<L: IntoLabels>(self, labels: L) -> Self {
+        Histogram(self.0.labels(labels))
+    }
+
+    /// Records `value` to this histogram.
+    ///
+    /// Durations are recorded as nanoseconds.  Functionally equivalent to calling [`value!`].
+    pub fn record<V: AsNanoseconds>(&self, value: V) {
+        self.0
+            .recorder
+            .record_histogram(self.0.key(), value.as_nanos());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Metrics;
+    use crate::Recorder;
+    use metrics_core::Key;
+    use std::sync::Mutex;
+    use std::time::Duration;
+
+    #[derive(Default)]
+    struct MockRecorder(Mutex<Vec<String>>);
+
+    impl Recorder for MockRecorder {
+        fn record_counter(&self, key: Key, value: u64) {
+            self.0
+                .lock()
+                .unwrap()
+                .push(format!("counter {} {}", key, value));
+        }
+
+        fn record_gauge(&self, key: Key, value: i64) {
+            self.0
+                .lock()
+                .unwrap()
+                .push(format!("gauge {} {}", key, value));
+        }
+
+        fn record_histogram(&self, key: Key, value: u64) {
+            self.0
+                .lock()
+                .unwrap()
+                .push(format!("histogram {} {}", key, value));
+        }
+    }
+
+    #[test]
+    fn test_builders() {
+        let recorder = MockRecorder::default();
+        let metrics = Metrics::new(&recorder);
+
+        let requests = metrics
+            .counter("http.requests")
+            .labels(&[("method", "get")])
+            .labels(&[("status", "200")]);
+        requests.increment(1);
+        requests.increment(2);
+        metrics.gauge("connections").set(-3);
+        metrics
+            .histogram("db.query_time")
+            .record(Duration::from_micros(5));
+
+        assert_eq!(
+            *recorder.0.lock().unwrap(),
+            vec![
+                "counter http.requests{method=get, status=200} 1",
+                "counter http.requests{method=get, status=200} 2",
+                "gauge connections -3",
+                "histogram db.query_time 5000",
+            ]
+        );
+    }
+}
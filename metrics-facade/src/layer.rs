@@ -0,0 +1,150 @@
+use crate::recorder;
+use metrics_core::{Key, Label};
+use std::fmt;
+use std::time::Instant;
+use tracing_core::field::{Field, Visit};
+use tracing_core::span::{Attributes, Id, Record};
+use tracing_core::Subscriber;
+use tracing_subscriber::layer::{Context, Layer};
+use tracing_subscriber::registry::LookupSpan;
+
+/// A [`Layer`] that records the duration of spans.
+///
+/// When a span closes, the time elapsed since it was created is recorded as a histogram via the
+/// installed recorder.  The key is named after the span, and the fields of the span become its
+/// labels, with non-string values formatted using their `Debug` representation.
+///
+/// Requires the `tracing` feature.
+///
+/// ### Examples
+///
+/// ```rust
+/// # extern crate metrics_facade;
+/// # extern crate tracing;
+/// # extern crate tracing_subscriber;
+/// use metrics_facade::MetricsLayer;
+/// use tracing_subscriber::layer::SubscriberExt;
+///
+/// let subscriber = tracing_subscriber::registry().with(MetricsLayer::new());
+/// tracing::subscriber::with_default(subscriber, || {
+///     // Records a histogram named `handle_request`, with the label `method=get`.
+///     let _span = tracing::info_span!("handle_request", method = "get").entered();
+/// });
+/// ```
+#[derive(Debug, Default)]
+pub struct MetricsLayer {
+    _priv: (),
+}
+
+impl MetricsLayer {
+    /// Creates a new [`MetricsLayer`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+struct SpanTiming {
+    start: Instant,
+    labels: Vec<Label>,
+}
+
+struct LabelVisitor<'a>(&'a mut Vec<Label>);
+
+impl<'a> LabelVisitor<'a> {
+    fn push(&mut self, field: &Field, value: String) {
+        let key = field.name();
+        // A field recorded again after creation replaces the earlier value.
+        self.0.retain(|label| label.key() != key);
+        self.0.push(Label::new(key, value));
+    }
+}
+
+impl<'a> Visit for LabelVisitor<'a> {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.push(field, value.to_owned());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.push(field, format!("{:?}", value));
+    }
+}
+
+impl<S> Layer<S> for MetricsLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            let mut labels = Vec::new();
+            attrs.record(&mut LabelVisitor(&mut labels));
+            span.extensions_mut().insert(SpanTiming {
+                start: Instant::now(),
+                labels,
+            });
+        }
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            if let Some(timing) = span.extensions_mut().get_mut::<SpanTiming>() {
+                values.record(&mut LabelVisitor(&mut timing.labels));
+            }
+        }
+    }
+
+    fn on_close(&self, id: Id, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(&id) {
+            if let Some(timing) = span.extensions_mut().remove::<SpanTiming>() {
+                let elapsed = timing.start.elapsed().as_nanos() as u64;
+                let key = Key::from_name_and_labels(span.name(), timing.labels);
+                recorder().record_histogram(key, elapsed);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MetricsLayer;
+    use crate::{set_boxed_recorder, Recorder};
+    use metrics_core::Key;
+    use std::sync::{Arc, Mutex};
+    use tracing_subscriber::layer::SubscriberExt;
+
+    struct MockRecorder(Arc<Mutex<Vec<(String, u64)>>>);
+
+    impl Recorder for MockRecorder {
+        fn record_counter(&self, _key: Key, _value: u64) {}
+        fn record_gauge(&self, _key: Key, _value: i64) {}
+        fn record_histogram(&self, key: Key, value: u64) {
+            self.0.lock().unwrap().push((key.to_string(), value));
+        }
+    }
+
+    #[test]
+    fn test_span_durations() {
+        let recorded = Arc::new(Mutex::new(Vec::new()));
+        set_boxed_recorder(Box::new(MockRecorder(recorded.clone()))).unwrap();
+
+        let subscriber = tracing_subscriber::registry().with(MetricsLayer::new());
+        tracing::subscriber::with_default(subscriber, || {
+            let span = tracing::info_span!(
+                "handle_request",
+                method = "get",
+                status = 200,
+                user = tracing::field::Empty
+            );
+            span.record("user", "bob");
+            let _guard = span.enter();
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        });
+
+        let recorded = recorded.lock().unwrap();
+        assert_eq!(recorded.len(), 1);
+        assert_eq!(
+            recorded[0].0,
+            "handle_request{method=get, status=200, user=bob}"
+        );
+        assert!(recorded[0].1 >= 1_000_000);
+    }
+}
@@ -12,6 +12,20 @@
 //! [`timing!`], and [`value!`].  These macros correspond to updating a counter, updating a gauge,
 //! updating a histogram based on a start/end, and updating a histogram with a single value.
 //!
+//! The same can be done without macros through a [`Metrics`] handle, whose methods build up and
+//! record each metric, e.g. `Metrics::global().counter("requests").increment(1)`.
+//!
+//! To time a block of code in a hot path, [`timing_fast!`] can be used instead of reading an
+//! [`Instant`](std::time::Instant) before and after.  With the `quanta` Cargo feature, it reads
+//! the CPU's timestamp counter, which is much cheaper than the system clock on most platforms.
+//!
+//! When several metrics are updated together, the [`metrics!`] macro records all of them as a
+//! single batch.
+//!
+//! Counters incremented at a very high frequency from many threads can have their increments
+//! summed in per-thread buffers before reaching the recorder, by wrapping it in a
+//! [`CoalescingRecorder`], which requires the `std` feature.
+//!
 //! Both [`timing!`] and [`value!`] are effectively identical in so far as that they both translate
 //! to recording a single value to an underlying histogram, but [`timing!`] is provided for
 //! contextual consistency: if you're recording a measurement of the time passed during an
@@ -19,6 +33,24 @@
 //! "value".  The [`timing!`] macro also has a branch to accept the start and end values which
 //! allows for a potentially clearer invocation.
 //!
+//! ## Metric names
+//! Names given to the macros as string literals are checked at compile time, so a typo such as a
+//! space in a name is a compile error rather than a metric which has to be sanitized, or is
+//! dropped, by the recorder.  Names may contain ASCII letters, digits, underscores, colons, and
+//! dots, and may not be empty or start with a digit.  Names which aren't literals, such as
+//! variables, aren't checked.  To use a literal name outside of this set on purpose, prefix it
+//! with `unchecked`, which turns the check off for that call only:
+//!
+//! ```rust
+//! #[macro_use]
+//! extern crate metrics_facade;
+//!
+//! # fn main() {
+//! counter!(unchecked "legacy-requests", 1);
+//! timing!(unchecked "legacy-request time", 20u64);
+//! # }
+//! ```
+//!
 //! ## In libraries
 //! Libraries should link only to the `metrics-facade` crate, and use the provided macros to record
 //! whatever metrics will be useful to downstream consumers.
@@ -143,9 +175,22 @@
 //! # fn main() {}
 //! ```
 //!
+//! # Use with `tracing`
+//!
+//! With the `tracing` Cargo feature, [`MetricsLayer`] can be added to a `tracing` subscriber to
+//! record the duration of every span as a histogram, without any manual [`timing!`] calls.
+//!
+//! Going the other way, [`TracingRecorder`] emits every measurement as a `tracing` event, so that
+//! metrics show up in the same subscribers and log pipelines as the rest of the application.
+//!
+//! # Use with OpenTelemetry
+//!
+//! With the `opentelemetry` Cargo feature, [`OtelRecorder`] records metrics as OpenTelemetry
+//! instruments, so that they can be exported through the OpenTelemetry SDK.
+//!
 //! [metrics]: https://docs.rs/metrics
 #![deny(missing_docs)]
-use metrics_core::{AsNanoseconds, Key};
+use metrics_core::{AsLabelValue, AsNanoseconds, Key, Label, ScopedString, Unit};
 #[cfg(feature = "std")]
 use std::error;
 use std::fmt;
@@ -154,14 +199,37 @@ use std::sync::atomic::{AtomicUsize, Ordering};
 #[macro_use]
 mod macros;
 
-static mut RECORDER: &'static Recorder = &NoopRecorder;
+mod clock;
+#[cfg(feature = "std")]
+mod coalesce;
+#[cfg(feature = "std")]
+pub use coalesce::CoalescingRecorder;
+mod handle;
+pub use handle::{Counter, Gauge, Histogram, Metrics};
+
+#[cfg(feature = "tracing")]
+mod events;
+#[cfg(feature = "tracing")]
+pub use events::TracingRecorder;
+
+#[cfg(feature = "tracing")]
+mod layer;
+#[cfg(feature = "tracing")]
+pub use layer::MetricsLayer;
+
+#[cfg(feature = "opentelemetry")]
+mod otel;
+#[cfg(feature = "opentelemetry")]
+pub use otel::OtelRecorder;
+
+static mut RECORDER: &'static dyn Recorder = &NoopRecorder;
 static STATE: AtomicUsize = AtomicUsize::new(0);
 
 const UNINITIALIZED: usize = 0;
 const INITIALIZING: usize = 1;
 const INITIALIZED: usize = 2;
 
-static SET_RECORDER_ERROR: &'static str =
+static SET_RECORDER_ERROR: &str =
     "attempted to set a recorder after the metrics system was already initialized";
 
 /// A value that records metrics behind the facade.
@@ -191,6 +259,45 @@ pub trait Recorder {
     ///
     /// There is no guarantee that this method will not be called multiple times for the same key.
     fn record_histogram(&self, key: Key, value: u64);
+
+    /// Records a histogram value that was observed `count` times.
+    ///
+    /// The default implementation calls [`record_histogram`](Recorder::record_histogram) once
+    /// per observation, which takes time proportional to `count`, so recorders which can store
+    /// pre-counted values, or record them in bulk, should override it.
+    fn record_histogram_n(&self, key: Key, value: u64, count: u64) {
+        for _ in 0..count {
+            self.record_histogram(key.clone(), value);
+        }
+    }
+
+    /// Records a batch of measurements.
+    ///
+    /// This is called by the [`metrics!`] macro, and allows recorders to handle several
+    /// measurements with a single dispatch, for example by taking a lock only once.  The default
+    /// implementation records each measurement individually, in order.
+    fn record_batch(&self, batch: Vec<Measurement>) {
+        for measurement in batch {
+            match measurement {
+                Measurement::Counter(key, value) => self.record_counter(key, value),
+                Measurement::Gauge(key, value) => self.record_gauge(key, value),
+                Measurement::Histogram(key, value) => self.record_histogram(key, value),
+            }
+        }
+    }
+}
+
+/// A single measurement, as part of a batch.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Measurement {
+    /// A counter update.
+    Counter(Key, u64),
+
+    /// A gauge update.
+    Gauge(Key, i64),
+
+    /// A histogram value.
+    Histogram(Key, u64),
 }
 
 struct NoopRecorder;
@@ -213,7 +320,7 @@ impl Recorder for NoopRecorder {
 ///
 /// An error is returned if a recorder has already been set.
 #[cfg(atomic_cas)]
-pub fn set_recorder(recorder: &'static Recorder) -> Result<(), SetRecorderError> {
+pub fn set_recorder(recorder: &'static dyn Recorder) -> Result<(), SetRecorderError> {
     set_recorder_inner(|| recorder)
 }
 
@@ -229,17 +336,25 @@ pub fn set_recorder(recorder: &'static Recorder) -> Result<(), SetRecorderError>
 ///
 /// An error is returned if a recorder has already been set.
 #[cfg(all(feature = "std", atomic_cas))]
-pub fn set_boxed_recorder(recorder: Box<Recorder>) -> Result<(), SetRecorderError> {
+pub fn set_boxed_recorder(recorder: Box<dyn Recorder>) -> Result<(), SetRecorderError> {
     set_recorder_inner(|| unsafe { &*Box::into_raw(recorder) })
 }
 
 #[cfg(atomic_cas)]
 fn set_recorder_inner<F>(make_recorder: F) -> Result<(), SetRecorderError>
 where
-    F: FnOnce() -> &'static Recorder,
+    F: FnOnce() -> &'static dyn Recorder,
 {
     unsafe {
-        match STATE.compare_and_swap(UNINITIALIZED, INITIALIZING, Ordering::SeqCst) {
+        match STATE
+            .compare_exchange(
+                UNINITIALIZED,
+                INITIALIZING,
+                Ordering::SeqCst,
+                Ordering::SeqCst,
+            )
+            .unwrap_or_else(|x| x)
+        {
             UNINITIALIZED => {
                 RECORDER = make_recorder();
                 STATE.store(INITIALIZED, Ordering::SeqCst);
@@ -271,7 +386,7 @@ where
 ///
 /// It is safe to use other metrics functions while this function runs (including all metrics
 /// macros).
-pub unsafe fn set_recorder_racy(recorder: &'static Recorder) -> Result<(), SetRecorderError> {
+pub unsafe fn set_recorder_racy(recorder: &'static dyn Recorder) -> Result<(), SetRecorderError> {
     match STATE.load(Ordering::SeqCst) {
         UNINITIALIZED => {
             RECORDER = recorder;
@@ -307,7 +422,7 @@ impl error::Error for SetRecorderError {
 /// Returns a reference to the recorder.
 ///
 /// If a recorder has not been set, a no-op implementation is returned.
-pub fn recorder() -> &'static Recorder {
+pub fn recorder() -> &'static dyn Recorder {
     unsafe {
         if STATE.load(Ordering::SeqCst) != INITIALIZED {
             static NOOP: NoopRecorder = NoopRecorder;
@@ -318,6 +433,20 @@ pub fn recorder() -> &'static Recorder {
     }
 }
 
+#[doc(hidden)]
+pub fn __private_api_label<K, V>(key: K, value: &V) -> Label
+where
+    K: Into<ScopedString>,
+    V: AsLabelValue + ?Sized,
+{
+    Label::new(key, value.as_label_value())
+}
+
+#[doc(hidden)]
+pub fn __private_api_labeled_key<N: Into<ScopedString>>(name: N, labels: Vec<Label>) -> Key {
+    Key::from_name_and_labels(name, labels)
+}
+
 #[doc(hidden)]
 pub fn __private_api_record_count<K: Into<Key>>(key: K, value: u64) {
     recorder().record_counter(key.into(), value);
@@ -332,3 +461,58 @@ pub fn __private_api_record_gauge<K: Into<Key>>(key: K, value: i64) {
 pub fn __private_api_record_histogram<K: Into<Key>, V: AsNanoseconds>(key: K, value: V) {
     recorder().record_histogram(key.into(), value.as_nanos());
 }
+
+#[doc(hidden)]
+pub fn __private_api_record_histogram_unit<K: Into<Key>, V: AsNanoseconds>(
+    key: K,
+    value: V,
+    unit: Unit,
+) {
+    recorder().record_histogram(key.into(), unit.convert_nanos(value.as_nanos()));
+}
+
+#[doc(hidden)]
+pub fn __private_api_record_histogram_n<K: Into<Key>, V: AsNanoseconds>(
+    key: K,
+    value: V,
+    count: u64,
+) {
+    recorder().record_histogram_n(key.into(), value.as_nanos(), count);
+}
+
+#[doc(hidden)]
+pub fn __private_api_fast_start() -> u64 {
+    clock::start()
+}
+
+#[doc(hidden)]
+pub fn __private_api_record_fast_timing<K: Into<Key>>(key: K, start: u64) {
+    recorder().record_histogram(key.into(), clock::elapsed(start));
+}
+
+#[doc(hidden)]
+pub fn __private_api_record_batch(batch: Vec<Measurement>) {
+    recorder().record_batch(batch);
+}
+
+#[doc(hidden)]
+pub const fn __private_api_is_valid_name(name: &str) -> bool {
+    let bytes = name.as_bytes();
+    if bytes.is_empty() || bytes[0].is_ascii_digit() {
+        return false;
+    }
+    let mut i = 0;
+    while i < bytes.len() {
+        let b = bytes[i];
+        if !(b.is_ascii_alphanumeric() || b == b'_' || b == b':' || b == b'.') {
+            return false;
+        }
+        i += 1;
+    }
+    true
+}
+
+#[doc(hidden)]
+pub fn __private_api_histogram_value<V: AsNanoseconds>(value: V) -> u64 {
+    value.as_nanos()
+}
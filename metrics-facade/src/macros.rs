@@ -13,10 +13,50 @@
 /// }
 /// # fn main() {}
 /// ```
+///
+/// Labels can be given after the value, as `key => value` pairs.  Label values can be anything
+/// that implements [`AsLabelValue`], such as strings, numbers, or enums implementing `Display`:
+///
+/// ```rust
+/// # #[macro_use]
+/// # extern crate metrics_facade;
+/// use std::fmt;
+///
+/// enum State {
+///     Idle,
+///     Busy,
+/// }
+///
+/// impl fmt::Display for State {
+///     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+///         match self {
+///             State::Idle => f.write_str("idle"),
+///             State::Busy => f.write_str("busy"),
+///         }
+///     }
+/// }
+///
+/// fn do_thing(state: State) {
+///     counter!("do_thing", 1, "state" => state, "attempt" => 2);
+/// }
+/// # fn main() {}
+/// ```
+///
+/// [`AsLabelValue`]: metrics_core::AsLabelValue
 #[macro_export]
 macro_rules! counter {
+    (unchecked $name:expr, $($rest:tt)*) => {
+        $crate::counter!([unchecked $name], $($rest)*)
+    };
+    ($name:tt, $value:expr, $($label:expr => $label_value:expr),+ $(,)?) => {{
+        let key = $crate::__private_api_labeled_key(
+            $crate::__metrics_name!($name),
+            vec![$($crate::__private_api_label($label, &$label_value)),+],
+        );
+        $crate::__private_api_record_count(key, $value);
+    }};
     ($name:tt, $value:expr) => {{
-        $crate::__private_api_record_count($name, $value);
+        $crate::__private_api_record_count($crate::__metrics_name!($name), $value);
     }};
 }
 
@@ -32,19 +72,33 @@ macro_rules! counter {
 /// fn update_current_value() {
 ///     let value: i64 = -131;
 ///     gauge!("current_value", value);
+///
+///     // Labels can be given in the same way as for `counter!`:
+///     gauge!("current_value", value, "region" => "eu");
 /// }
 /// # fn main() {}
 /// ```
 #[macro_export]
 macro_rules! gauge {
+    (unchecked $name:expr, $($rest:tt)*) => {
+        $crate::gauge!([unchecked $name], $($rest)*)
+    };
+    ($name:tt, $value:expr, $($label:expr => $label_value:expr),+ $(,)?) => {{
+        let key = $crate::__private_api_labeled_key(
+            $crate::__metrics_name!($name),
+            vec![$($crate::__private_api_label($label, &$label_value)),+],
+        );
+        $crate::__private_api_record_gauge(key, $value);
+    }};
     ($name:tt, $value:expr) => {{
-        $crate::__private_api_record_gauge($name, $value);
+        $crate::__private_api_record_gauge($crate::__metrics_name!($name), $value);
     }};
 }
 
 /// Records a timing.
 ///
-/// Functionally equivalent to calling [`Recorder::record_histogram`].
+/// Functionally equivalent to calling [`Recorder::record_histogram`], or
+/// [`Recorder::record_histogram_n`] when a count is given.
 ///
 /// ### Examples
 ///
@@ -73,23 +127,112 @@ macro_rules! gauge {
 ///     // And the delta notation:
 ///     let delta: u64 = end - start;
 ///     timing!("performance.request_processed", delta);
+///
+///     // If the same delta was observed several times, it can be recorded in one go:
+///     timing!("performance.request_processed", delta; count = 3);
+///
+///     // Labels can be given for a delta in the same way as for `counter!`:
+///     timing!("performance.request_processed", delta, "method" => "get");
+/// }
+/// # fn main() {}
+/// ```
+///
+/// Timings are recorded in nanoseconds by default.  To record a timing in another [`Unit`],
+/// pass the unit after the value; the value itself is still interpreted the same way, so a plain
+/// integer is a number of nanoseconds:
+///
+/// ```rust
+/// # #[macro_use]
+/// # extern crate metrics_facade;
+/// # extern crate metrics_core;
+/// use metrics_core::Unit;
+/// use std::time::Duration;
+///
+/// fn handle_request() {
+///     let delta = Duration::from_millis(1500);
+///
+///     // Records 1500000000.
+///     timing!("performance.request_processed_ns", delta, unit = Unit::Nanoseconds);
+///
+///     // Records 1500000.
+///     timing!("performance.request_processed_us", delta, unit = Unit::Microseconds);
+///
+///     // Records 1500.
+///     timing!("performance.request_processed_ms", delta, unit = Unit::Milliseconds);
+///
+///     // Records 1, as conversions round down.
+///     timing!("performance.request_processed_s", delta, unit = Unit::Seconds);
 /// }
 /// # fn main() {}
 /// ```
+///
+/// [`Unit`]: metrics_core::Unit
 #[macro_export]
 macro_rules! timing {
+    (unchecked $name:expr, $($rest:tt)*) => {
+        $crate::timing!([unchecked $name], $($rest)*)
+    };
+    ($name:tt, $value:expr; count = $count:expr) => {{
+        $crate::__private_api_record_histogram_n($crate::__metrics_name!($name), $value, $count);
+    }};
+    ($name:tt, $value:expr, unit = $unit:expr) => {{
+        $crate::__private_api_record_histogram_unit($crate::__metrics_name!($name), $value, $unit);
+    }};
+    ($name:tt, $value:expr, $($label:expr => $label_value:expr),+ $(,)?) => {{
+        let key = $crate::__private_api_labeled_key(
+            $crate::__metrics_name!($name),
+            vec![$($crate::__private_api_label($label, &$label_value)),+],
+        );
+        $crate::__private_api_record_histogram(key, $value);
+    }};
     ($name:tt, $value:expr) => {{
-        $crate::__private_api_record_histogram($name, $value);
+        $crate::__private_api_record_histogram($crate::__metrics_name!($name), $value);
     }};
     ($name:tt, $start:expr, $end:expr) => {{
         let delta = $end - $start;
-        $crate::__private_api_record_histogram($name, delta);
+        $crate::__private_api_record_histogram($crate::__metrics_name!($name), delta);
+    }};
+}
+
+/// Records how long a block takes to run, as a timing.
+///
+/// The block is run, and its value is returned, after recording the time it took in nanoseconds.
+/// By default, the time is measured with [`Instant`](std::time::Instant).  With the `quanta`
+/// Cargo feature, it is measured with the CPU's timestamp counter instead, which avoids the cost
+/// of reading the system clock, at the expense of a one-off calibration the first time it is
+/// used.  This is meant for hot paths, where reading the system clock would be a noticeable
+/// part of the time being measured.
+///
+/// ### Examples
+///
+/// ```rust
+/// # #[macro_use]
+/// # extern crate metrics_facade;
+/// # fn run_query() -> u64 { 42 }
+/// fn handle_request() -> u64 {
+///     timing_fast!("performance.query_time", {
+///         run_query()
+///     })
+/// }
+/// # fn main() {}
+/// ```
+#[macro_export]
+macro_rules! timing_fast {
+    (unchecked $name:expr, $($rest:tt)*) => {
+        $crate::timing_fast!([unchecked $name], $($rest)*)
+    };
+    ($name:tt, $body:block) => {{
+        let start = $crate::__private_api_fast_start();
+        let result = $body;
+        $crate::__private_api_record_fast_timing($crate::__metrics_name!($name), start);
+        result
     }};
 }
 
 /// Records a value.
 ///
-/// Functionally equivalent to calling [`Recorder::record_histogram`].
+/// Functionally equivalent to calling [`Recorder::record_histogram`], or
+/// [`Recorder::record_histogram_n`] when a count is given.
 ///
 /// ### Examples
 ///
@@ -101,12 +244,125 @@ macro_rules! timing {
 /// fn handle_request() {
 ///     let rows_read = process();
 ///     value!("client.process_num_rows", rows_read);
+///
+///     // A value observed several times can be recorded in one go:
+///     let batches = 4;
+///     value!("client.batch_num_rows", rows_read; count = batches);
+///
+///     // Labels can be given in the same way as for `counter!`:
+///     value!("client.process_num_rows", rows_read, "table" => "users");
 /// }
 /// # fn main() {}
 /// ```
 #[macro_export]
 macro_rules! value {
+    (unchecked $name:expr, $($rest:tt)*) => {
+        $crate::value!([unchecked $name], $($rest)*)
+    };
+    ($name:tt, $value:expr; count = $count:expr) => {{
+        $crate::__private_api_record_histogram_n($crate::__metrics_name!($name), $value, $count);
+    }};
+    ($name:tt, $value:expr, $($label:expr => $label_value:expr),+ $(,)?) => {{
+        let key = $crate::__private_api_labeled_key(
+            $crate::__metrics_name!($name),
+            vec![$($crate::__private_api_label($label, &$label_value)),+],
+        );
+        $crate::__private_api_record_histogram(key, $value);
+    }};
     ($name:tt, $value:expr) => {{
-        $crate::__private_api_record_histogram($name, $value);
+        $crate::__private_api_record_histogram($crate::__metrics_name!($name), $value);
+    }};
+}
+
+/// Records multiple metrics as a single batch.
+///
+/// Functionally equivalent to calling [`Recorder::record_batch`].
+///
+/// The macro takes a list of statements, separated by semicolons, of the form
+/// `<kind> <name> => <value>`, where `kind` is one of `counter`, `gauge`, `timing`, or `value`.
+/// Each kind accepts the same values as the macro of the same name, except that `timing` only
+/// accepts a delta and not a start/end pair.  A statement can be marked with `#[unchecked]` to
+/// skip the compile-time check of its name, like the `unchecked` prefix of the other macros.
+///
+/// ### Examples
+///
+/// ```rust
+/// # #[macro_use]
+/// # extern crate metrics_facade;
+/// # use std::time::Instant;
+/// # fn process() -> u64 { 42 }
+/// fn handle_request() {
+///     let start = Instant::now();
+///     let rows_read = process();
+///     let delta = Instant::now() - start;
+///
+///     metrics! {
+///         counter "client.requests_processed" => 1;
+///         timing "client.request_time" => delta;
+///         value "client.process_num_rows" => rows_read;
+///         gauge "client.queue_depth" => -3;
+///         #[unchecked]
+///         counter "client.requests-processed" => 1;
+///     }
+/// }
+/// # fn main() {}
+/// ```
+#[macro_export]
+macro_rules! metrics {
+    ($($(#[$attr:ident])? $kind:ident $name:tt => $value:expr);* $(;)?) => {{
+        $crate::__private_api_record_batch(vec![
+            $($crate::__metrics_measurement!($kind, [$($attr)?] $name, $value)),*
+        ]);
+    }};
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __metrics_measurement {
+    (counter, $attrs:tt $name:tt, $value:expr) => {
+        $crate::Measurement::Counter($crate::__metrics_name!($attrs $name).into(), $value)
+    };
+    (gauge, $attrs:tt $name:tt, $value:expr) => {
+        $crate::Measurement::Gauge($crate::__metrics_name!($attrs $name).into(), $value)
+    };
+    (timing, $attrs:tt $name:tt, $value:expr) => {
+        $crate::Measurement::Histogram(
+            $crate::__metrics_name!($attrs $name).into(),
+            $crate::__private_api_histogram_value($value),
+        )
+    };
+    (value, $attrs:tt $name:tt, $value:expr) => {
+        $crate::Measurement::Histogram(
+            $crate::__metrics_name!($attrs $name).into(),
+            $crate::__private_api_histogram_value($value),
+        )
+    };
+}
+
+// Checks names given as string literals at compile time, and passes any other name through as-is.
+// Names marked as unchecked, either as `[unchecked name]` or after `[unchecked]`, and names after
+// `[]`, which `metrics!` gives for statements without an attribute, are handled first.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __metrics_name {
+    ([unchecked $name:expr]) => {
+        $name
+    };
+    ([unchecked] $name:tt) => {
+        $name
+    };
+    ([] $name:tt) => {
+        $crate::__metrics_name!($name)
+    };
+    ($name:literal) => {{
+        const _: () = assert!(
+            $crate::__private_api_is_valid_name($name),
+            "{}",
+            concat!("invalid metric name: ", $name),
+        );
+        $name
     }};
+    ($name:tt) => {
+        $name
+    };
 }
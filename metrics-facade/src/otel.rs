@@ -0,0 +1,106 @@
+use crate::Recorder;
+use metrics_core::{Key, Label, ScopedString};
+use opentelemetry::metrics::{Counter, Gauge, Histogram, Meter};
+use opentelemetry::KeyValue;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// A [`Recorder`] that records metrics as OpenTelemetry instruments.
+///
+/// Counters are recorded with a `u64` [`Counter`], gauges with an `i64` [`Gauge`], and histogram
+/// values with a `u64` [`Histogram`].  Each instrument is created from the given [`Meter`] the
+/// first time its name is recorded, and the labels of a key become the attributes of the
+/// measurement.  Values are recorded as-is, so timings recorded with [`timing!`] are in
+/// nanoseconds.  Exporting is left to the meter provider, so metrics can be sent to any backend
+/// supported by the OpenTelemetry SDK.
+///
+/// Requires the `opentelemetry` feature.
+///
+/// ### Examples
+///
+/// ```rust
+/// # extern crate metrics_facade;
+/// # extern crate opentelemetry;
+/// use metrics_facade::OtelRecorder;
+///
+/// let meter = opentelemetry::global::meter("my_app");
+/// metrics_facade::set_boxed_recorder(Box::new(OtelRecorder::new(meter))).unwrap();
+/// ```
+pub struct OtelRecorder {
+    meter: Meter,
+    instruments: Mutex<Instruments>,
+}
+
+#[derive(Default)]
+struct Instruments {
+    counters: HashMap<ScopedString, Counter<u64>>,
+    gauges: HashMap<ScopedString, Gauge<i64>>,
+    histograms: HashMap<ScopedString, Histogram<u64>>,
+}
+
+impl OtelRecorder {
+    /// Creates a new [`OtelRecorder`] which creates its instruments from `meter`.
+    pub fn new(meter: Meter) -> Self {
+        OtelRecorder {
+            meter,
+            instruments: Mutex::new(Instruments::default()),
+        }
+    }
+}
+
+impl Recorder for OtelRecorder {
+    fn record_counter(&self, key: Key, value: u64) {
+        let (name, attributes) = key_to_parts(key);
+        let mut instruments = self.instruments.lock().unwrap();
+        instruments
+            .counters
+            .entry(name.clone())
+            .or_insert_with(|| self.meter.u64_counter(name).build())
+            .add(value, &attributes);
+    }
+
+    fn record_gauge(&self, key: Key, value: i64) {
+        let (name, attributes) = key_to_parts(key);
+        let mut instruments = self.instruments.lock().unwrap();
+        instruments
+            .gauges
+            .entry(name.clone())
+            .or_insert_with(|| self.meter.i64_gauge(name).build())
+            .record(value, &attributes);
+    }
+
+    fn record_histogram(&self, key: Key, value: u64) {
+        let (name, attributes) = key_to_parts(key);
+        let mut instruments = self.instruments.lock().unwrap();
+        instruments
+            .histograms
+            .entry(name.clone())
+            .or_insert_with(|| self.meter.u64_histogram(name).build())
+            .record(value, &attributes);
+    }
+
+    // OpenTelemetry histograms can't record a count, so the value is still recorded once per
+    // observation, but the instrument and attributes are only looked up once.
+    fn record_histogram_n(&self, key: Key, value: u64, count: u64) {
+        let (name, attributes) = key_to_parts(key);
+        let mut instruments = self.instruments.lock().unwrap();
+        let histogram = instruments
+            .histograms
+            .entry(name.clone())
+            .or_insert_with(|| self.meter.u64_histogram(name).build());
+        for _ in 0..count {
+            histogram.record(value, &attributes);
+        }
+    }
+}
+
+fn key_to_parts(key: Key) -> (ScopedString, Vec<KeyValue>) {
+    let (name, labels) = key.into_parts();
+    let attributes = labels
+        .into_iter()
+        .map(Label::into_parts)
+        .map(|(k, v)| KeyValue::new(k, v))
+        .collect();
+
+    (name, attributes)
+}
@@ -0,0 +1,77 @@
+#![cfg(feature = "std")]
+#[macro_use]
+extern crate metrics_facade;
+
+use metrics_core::Key;
+use metrics_facade::Recorder;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+struct MockRecorder(Arc<Mutex<Vec<String>>>);
+
+impl Recorder for MockRecorder {
+    fn record_counter(&self, key: Key, value: u64) {
+        self.0
+            .lock()
+            .unwrap()
+            .push(format!("counter {} {}", key, value));
+    }
+
+    fn record_gauge(&self, key: Key, value: i64) {
+        self.0
+            .lock()
+            .unwrap()
+            .push(format!("gauge {} {}", key, value));
+    }
+
+    fn record_histogram(&self, key: Key, value: u64) {
+        self.0
+            .lock()
+            .unwrap()
+            .push(format!("histogram {} {}", key, value));
+    }
+}
+
+#[derive(Clone, Copy)]
+enum State {
+    Idle,
+    Busy,
+}
+
+impl fmt::Display for State {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            State::Idle => f.write_str("idle"),
+            State::Busy => f.write_str("busy"),
+        }
+    }
+}
+
+#[test]
+fn test_enum_labels() {
+    let recorded = Arc::new(Mutex::new(Vec::new()));
+    metrics_facade::set_boxed_recorder(Box::new(MockRecorder(recorded.clone()))).unwrap();
+
+    let state = State::Busy;
+    counter!("jobs", 1, "state" => state);
+    counter!("jobs", 2, "state" => State::Idle, "attempt" => 3,);
+    gauge!("workers", 4, "state" => state);
+    timing!("job_time", Duration::from_nanos(7), "state" => state);
+    timing!("job_time", 8, unit = metrics_core::Unit::Nanoseconds);
+    value!("job_rows", 9, "state" => state, "table" => "users");
+    counter!("jobs", 5);
+
+    assert_eq!(
+        *recorded.lock().unwrap(),
+        vec![
+            "counter jobs{state=busy} 1",
+            "counter jobs{state=idle, attempt=3} 2",
+            "gauge workers{state=busy} 4",
+            "histogram job_time{state=busy} 7",
+            "histogram job_time 8",
+            "histogram job_rows{state=busy, table=users} 9",
+            "counter jobs 5",
+        ]
+    );
+}
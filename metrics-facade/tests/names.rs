@@ -0,0 +1,15 @@
+#[test]
+fn test_valid_literal_names() {
+    let cases = trybuild::TestCases::new();
+    cases.pass("tests/ui/valid-names.rs");
+}
+
+// The errors are compared against `tests/ui/invalid-names.stderr`, and rustc words the errors of
+// panics during constant evaluation differently from one release to the next, so this only runs
+// on the release the errors were written against.
+#[rustversion::attr(not(stable(1.95)), ignore)]
+#[test]
+fn test_invalid_literal_names() {
+    let cases = trybuild::TestCases::new();
+    cases.compile_fail("tests/ui/invalid-names.rs");
+}
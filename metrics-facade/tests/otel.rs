@@ -0,0 +1,85 @@
+#[macro_use]
+extern crate metrics_facade;
+
+use metrics_core::Key;
+use metrics_facade::OtelRecorder;
+use opentelemetry::metrics::MeterProvider;
+use opentelemetry_sdk::metrics::data::{AggregatedMetrics, MetricData};
+use opentelemetry_sdk::metrics::{InMemoryMetricExporter, PeriodicReader, SdkMeterProvider};
+
+// The metrics are exported to an in-memory exporter rather than to the stdout exporter of
+// `opentelemetry-stdout`, as its output is only meant to be read by people, and isn't stable
+// enough to be checked by a test, while the in-memory exporter exposes the same exported data as
+// values.  The recorder only talks to the meter, so it doesn't depend on the exporter either way.
+#[test]
+fn test_otel_recorder() {
+    let exporter = InMemoryMetricExporter::default();
+    let provider = SdkMeterProvider::builder()
+        .with_reader(PeriodicReader::builder(exporter.clone()).build())
+        .build();
+    let recorder = OtelRecorder::new(provider.meter("metrics-facade"));
+    metrics_facade::set_boxed_recorder(Box::new(recorder)).unwrap();
+
+    counter!("http.requests", 2);
+    let get = Key::from_name_and_labels("http.requests", &[("method", "get")]);
+    counter!(get, 3);
+    counter!("http.requests", 1);
+    gauge!("connections", 5);
+    gauge!("connections", -2);
+    value!("rows_read", 10);
+    value!("rows_read", 30);
+    value!("rows_read", 5; count = 3);
+    provider.force_flush().unwrap();
+
+    let metrics = exporter.get_finished_metrics().unwrap();
+    let metrics = metrics
+        .iter()
+        .flat_map(|rm| rm.scope_metrics())
+        .flat_map(|sm| sm.metrics())
+        .collect::<Vec<_>>();
+
+    let requests = metrics
+        .iter()
+        .find(|m| m.name() == "http.requests")
+        .unwrap();
+    match requests.data() {
+        AggregatedMetrics::U64(MetricData::Sum(sum)) => {
+            let mut points = sum
+                .data_points()
+                .map(|point| {
+                    let attributes = point
+                        .attributes()
+                        .map(|kv| format!("{}={}", kv.key, kv.value))
+                        .collect::<Vec<_>>();
+                    (attributes, point.value())
+                })
+                .collect::<Vec<_>>();
+            points.sort();
+            assert_eq!(
+                points,
+                vec![(vec![], 3), (vec!["method=get".to_owned()], 3)]
+            );
+        }
+        data => panic!("unexpected counter data: {:?}", data),
+    }
+
+    let connections = metrics.iter().find(|m| m.name() == "connections").unwrap();
+    match connections.data() {
+        AggregatedMetrics::I64(MetricData::Gauge(gauge)) => {
+            let values = gauge.data_points().map(|p| p.value()).collect::<Vec<_>>();
+            assert_eq!(values, vec![-2]);
+        }
+        data => panic!("unexpected gauge data: {:?}", data),
+    }
+
+    let rows_read = metrics.iter().find(|m| m.name() == "rows_read").unwrap();
+    match rows_read.data() {
+        AggregatedMetrics::U64(MetricData::Histogram(histogram)) => {
+            let point = histogram.data_points().next().unwrap();
+            assert_eq!((point.count(), point.sum()), (5, 55));
+        }
+        data => panic!("unexpected histogram data: {:?}", data),
+    }
+
+    provider.shutdown().unwrap();
+}
@@ -0,0 +1,40 @@
+#![cfg(feature = "std")]
+#[macro_use]
+extern crate metrics_facade;
+
+use metrics_core::Key;
+use metrics_facade::Recorder;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+struct MockRecorder(Arc<Mutex<Vec<(String, u64)>>>);
+
+impl Recorder for MockRecorder {
+    fn record_counter(&self, _key: Key, _value: u64) {}
+
+    fn record_gauge(&self, _key: Key, _value: i64) {}
+
+    fn record_histogram(&self, key: Key, value: u64) {
+        self.0.lock().unwrap().push((key.to_string(), value));
+    }
+}
+
+#[test]
+fn test_timing_fast() {
+    let recorded = Arc::new(Mutex::new(Vec::new()));
+    metrics_facade::set_boxed_recorder(Box::new(MockRecorder(recorded.clone()))).unwrap();
+
+    let value = timing_fast!("sleep_time", {
+        thread::sleep(Duration::from_millis(5));
+        42
+    });
+    assert_eq!(value, 42);
+
+    let recorded = recorded.lock().unwrap();
+    assert_eq!(recorded.len(), 1);
+    let (name, elapsed) = &recorded[0];
+    assert_eq!(name, "sleep_time");
+    assert!(*elapsed >= 4_000_000, "elapsed {}", elapsed);
+    assert!(*elapsed < 5_000_000_000, "elapsed {}", elapsed);
+}
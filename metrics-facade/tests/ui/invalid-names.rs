@@ -0,0 +1,11 @@
+#[macro_use]
+extern crate metrics_facade;
+
+fn main() {
+    counter!("http requests", 1);
+    gauge!("", 3, "region" => "eu");
+    value!("2xx_responses", 5);
+    metrics! {
+        counter "db-queries" => 1;
+    }
+}
@@ -0,0 +1,32 @@
+#[macro_use]
+extern crate metrics_facade;
+
+fn main() {
+    counter!("http.requests_total", 1);
+    counter!("http.requests_total", 1, "method" => "get");
+    gauge!("namespace:queue_depth", 3);
+    timing!("db.query_time_ns", 10u64, 20u64);
+    value!("db.rows_2", 5; count = 2);
+    timing_fast!("db.query_time", {});
+    metrics! {
+        counter "_internal.requests" => 1;
+        gauge "workers" => 2;
+    }
+
+    // Names which aren't literals aren't checked.
+    let name = "needs sanitizing";
+    counter!(name, 1);
+    gauge!(name, 2, "region" => "eu");
+
+    // Literal names can opt out of the check at each call site.
+    counter!(unchecked "http requests", 1);
+    gauge!(unchecked "", 3, "region" => "eu");
+    timing!(unchecked "db-query time", 10u64, 20u64);
+    value!(unchecked "2xx_rows", 5; count = 2);
+    timing_fast!(unchecked "db-query time", {});
+    metrics! {
+        #[unchecked]
+        counter "db-queries" => 1;
+        gauge "workers" => 2;
+    }
+}
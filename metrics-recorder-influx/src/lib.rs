@@ -0,0 +1,354 @@
+//! Records metrics in the InfluxDB line protocol.
+//!
+//! Each metric is rendered as a single line of the form `measurement,tags fields timestamp`,
+//! where the measurement is the metric name and the tags are the metric labels.  As an example, a
+//! counter named `http.requests` with the label `method=get` would be rendered as:
+//!
+//! ```c
+//! http.requests,method=get value=42i 1556372619000000000
+//! ```
+//!
+//! Counters and gauges have a single `value` field.  Histograms are aggregated until the output
+//! is rendered, and then emit a `count` field, a `sum` field, and one field per configured
+//! quantile, using the human-friendly quantile labels: `min`, `p50`, `p99`, `max`, and so on.
+//!
+//! Measurements, tag keys, tag values, and field keys are escaped according to the line protocol
+//! rules, including backslashes.  The line protocol doesn't allow empty tag values, so labels with
+//! an empty value are left out.
+//!
+//! Rendered output can be sent anywhere that accepts the line protocol.  For Telegraf or
+//! InfluxDB UDP listeners, [`InfluxRecorder::flush_udp`] sends the buffered lines directly.
+use hdrhistogram::Histogram;
+use metrics_core::{Key, Label, Recorder};
+use metrics_util::{parse_quantiles, Quantile};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::time::SystemTime;
+
+// Stays under the typical MTU, so that datagrams are not fragmented.
+const MAX_DATAGRAM_SIZE: usize = 1400;
+
+/// Records metrics in the InfluxDB line protocol.
+pub struct InfluxRecorder {
+    quantiles: Vec<Quantile>,
+    histos: HashMap<Key, (u128, Histogram<u64>)>,
+    output: String,
+}
+
+impl InfluxRecorder {
+    /// Creates a new [`InfluxRecorder`] with a default set of quantiles.
+    ///
+    /// Configures the recorder with these default quantiles: 0.0, 0.5, 0.9, 0.95, 0.99, 0.999, and
+    /// 1.0.  If you want to customize the quantiles used, you can call
+    ///   [`InfluxRecorder::with_quantiles`].
+    ///
+    /// The configured quantiles are used when rendering any histograms.
+    pub fn new() -> Self {
+        Self::with_quantiles(&[0.0, 0.5, 0.9, 0.95, 0.99, 0.999, 1.0])
+    }
+
+    /// Creates a new [`InfluxRecorder`] with the given set of quantiles.
+    ///
+    /// The configured quantiles are used when rendering any histograms.
+    pub fn with_quantiles(quantiles: &[f64]) -> Self {
+        let actual_quantiles = parse_quantiles(quantiles);
+
+        Self {
+            quantiles: actual_quantiles,
+            histos: HashMap::new(),
+            output: String::new(),
+        }
+    }
+
+    /// Sends all buffered lines to the given address over UDP.
+    ///
+    /// Lines are batched into datagrams that stay under the typical MTU.  Once sent, the buffered
+    /// lines and histograms are cleared, so the recorder can continue to be used.  If sending a
+    /// datagram fails, its lines and the ones after it are kept, and sent by the next flush.
+    pub fn flush_udp<A: ToSocketAddrs>(&mut self, addr: A) -> io::Result<()> {
+        let addr = addr
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no address to send to"))?;
+        let local: SocketAddr = match addr {
+            SocketAddr::V4(_) => ([0, 0, 0, 0], 0).into(),
+            SocketAddr::V6(_) => ([0u16; 8], 0).into(),
+        };
+        let socket = UdpSocket::bind(local)?;
+
+        let output = self.drain();
+        let mut start = 0;
+        for end in datagram_ends(&output) {
+            if let Err(e) = socket.send_to(&output.as_bytes()[start..end], addr) {
+                self.output = output[start..].to_owned();
+                return Err(e);
+            }
+            start = end;
+        }
+
+        Ok(())
+    }
+
+    fn drain(&mut self) -> String {
+        let ts = get_timestamp();
+        let mut output = std::mem::take(&mut self.output);
+
+        for (key, (sum, hist)) in self.histos.drain() {
+            let mut fields = vec![
+                ("count".to_owned(), format!("{}i", hist.len())),
+                ("sum".to_owned(), format_integer(sum)),
+            ];
+            for quantile in &self.quantiles {
+                let value = hist.value_at_quantile(quantile.value());
+                fields.push((quantile.label().to_owned(), format_integer(value.into())));
+            }
+
+            push_line(&mut output, key, &fields, ts);
+        }
+
+        output
+    }
+}
+
+impl Recorder for InfluxRecorder {
+    fn record_counter<K: Into<Key>>(&mut self, key: K, value: u64) {
+        let fields = [("value".to_owned(), format!("{}i", value))];
+        push_line(&mut self.output, key.into(), &fields, get_timestamp());
+    }
+
+    fn record_gauge<K: Into<Key>>(&mut self, key: K, value: i64) {
+        let fields = [("value".to_owned(), format!("{}i", value))];
+        push_line(&mut self.output, key.into(), &fields, get_timestamp());
+    }
+
+    fn record_histogram<K: Into<Key>>(&mut self, key: K, values: &[u64]) {
+        let (sum, h) = self.histos.entry(key.into()).or_insert_with(|| {
+            let h = Histogram::<u64>::new(3).expect("failed to create histogram");
+            (0, h)
+        });
+
+        for value in values {
+            // Values too large for the histogram to grow to are clamped, rather than dropped.
+            if h.record(*value).is_err() {
+                h.saturating_record(*value);
+            }
+            *sum += u128::from(*value);
+        }
+    }
+}
+
+impl Default for InfluxRecorder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clone for InfluxRecorder {
+    fn clone(&self) -> Self {
+        Self {
+            quantiles: self.quantiles.clone(),
+            histos: HashMap::new(),
+            output: String::new(),
+        }
+    }
+}
+
+impl From<InfluxRecorder> for String {
+    fn from(mut val: InfluxRecorder) -> Self {
+        val.drain()
+    }
+}
+
+fn push_line(output: &mut String, key: Key, fields: &[(String, String)], ts: u64) {
+    let (name, labels) = key.into_parts();
+    output.push_str(escape_measurement(name.as_ref()).as_str());
+    for (k, v) in labels.into_iter().map(Label::into_parts) {
+        if v.is_empty() {
+            continue;
+        }
+        output.push(',');
+        output.push_str(escape_key(k.as_ref()).as_str());
+        output.push('=');
+        output.push_str(escape_key(v.as_ref()).as_str());
+    }
+
+    output.push(' ');
+    for (i, (k, v)) in fields.iter().enumerate() {
+        if i > 0 {
+            output.push(',');
+        }
+        output.push_str(escape_key(k).as_str());
+        output.push('=');
+        output.push_str(v);
+    }
+
+    output.push(' ');
+    output.push_str(ts.to_string().as_str());
+    output.push('\n');
+}
+
+// Integer fields are signed 64-bit, so larger values are clamped to fit.
+fn format_integer(value: u128) -> String {
+    format!("{}i", i64::try_from(value).unwrap_or(i64::MAX))
+}
+
+// Splits the rendered lines into datagrams, returning the offset at which each datagram ends.
+fn datagram_ends(output: &str) -> Vec<usize> {
+    let mut ends = Vec::new();
+    let mut start = 0;
+    let mut end = 0;
+    for line in output.split_inclusive('\n') {
+        if end > start && end - start + line.len() > MAX_DATAGRAM_SIZE {
+            ends.push(end);
+            start = end;
+        }
+        end += line.len();
+    }
+    if end > start {
+        ends.push(end);
+    }
+    ends
+}
+
+fn escape_measurement(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace(',', "\\,")
+        .replace(' ', "\\ ")
+}
+
+fn escape_key(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace(',', "\\,")
+        .replace('=', "\\=")
+        .replace(' ', "\\ ")
+}
+
+fn get_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{datagram_ends, escape_key, escape_measurement, InfluxRecorder};
+    use metrics_core::{Key, Recorder};
+    use std::net::UdpSocket;
+
+    fn strip_timestamps(output: &str) -> Vec<String> {
+        output
+            .lines()
+            .map(|line| line.rsplit_once(' ').unwrap().0.to_owned())
+            .collect()
+    }
+
+    #[test]
+    fn test_escaping() {
+        assert_eq!(escape_measurement("disk free,total"), "disk\\ free\\,total");
+        assert_eq!(escape_measurement("a=b"), "a=b");
+        assert_eq!(escape_key("host name"), "host\\ name");
+        assert_eq!(escape_key("a=b,c"), "a\\=b\\,c");
+        assert_eq!(escape_measurement("C:\\disk"), "C:\\\\disk");
+        assert_eq!(escape_key("\\ ,"), "\\\\\\ \\,");
+
+        let mut recorder = InfluxRecorder::new();
+        let labels = vec![
+            ("mount point", "/var,/tmp"),
+            ("kind=", "ssd"),
+            ("empty", ""),
+        ];
+        recorder.record_gauge(Key::from_name_and_labels("disk free", &labels), 7);
+
+        let output: String = recorder.into();
+        assert_eq!(
+            strip_timestamps(&output),
+            vec!["disk\\ free,mount\\ point=/var\\,/tmp,kind\\==ssd value=7i"]
+        );
+    }
+
+    #[test]
+    fn test_histograms() {
+        let mut recorder = InfluxRecorder::with_quantiles(&[0.5, 1.0]);
+        recorder.record_counter(
+            Key::from_name_and_labels("http.requests", &[("method", "get")]),
+            3,
+        );
+        recorder.record_histogram("http.request_time", &[1, 2, 3]);
+        recorder.record_histogram("http.request_time", &[4]);
+
+        let output: String = recorder.into();
+        assert_eq!(
+            strip_timestamps(&output),
+            vec![
+                "http.requests,method=get value=3i",
+                "http.request_time count=4i,sum=10i,p50=2i,max=4i",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_large_histogram_values() {
+        let mut recorder = InfluxRecorder::with_quantiles(&[1.0]);
+        recorder.record_histogram("bytes", &[1 << 63, 1 << 63]);
+
+        let output: String = recorder.into();
+        assert_eq!(
+            strip_timestamps(&output),
+            vec!["bytes count=2i,sum=9223372036854775807i,max=9223372036854775807i"]
+        );
+    }
+
+    #[test]
+    fn test_flush_udp() {
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let mut recorder = InfluxRecorder::new();
+        recorder.record_counter("http.requests", 1);
+        recorder.flush_udp(server.local_addr().unwrap()).unwrap();
+
+        let mut buf = [0; 1500];
+        let len = server.recv(&mut buf).unwrap();
+        let received = std::str::from_utf8(&buf[..len]).unwrap();
+        assert_eq!(strip_timestamps(received), vec!["http.requests value=1i"]);
+
+        let output: String = recorder.into();
+        assert!(output.is_empty());
+    }
+
+    #[test]
+    fn test_datagram_ends() {
+        assert!(datagram_ends("").is_empty());
+        assert_eq!(datagram_ends("a 1i\nb 2i\n"), vec![10]);
+
+        let line = format!("{}\n", "a".repeat(999));
+        let output = line.repeat(3);
+        assert_eq!(datagram_ends(&output), vec![1000, 2000, 3000]);
+    }
+
+    #[test]
+    fn test_flush_udp_partial_failure() {
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let mut recorder = InfluxRecorder::new();
+        recorder.record_counter("first", 1);
+        // Too large for a UDP datagram, so sending it fails.
+        recorder.record_counter(Key::from_name("a".repeat(70_000)), 2);
+        recorder.record_counter("last", 3);
+        assert!(recorder.flush_udp(server.local_addr().unwrap()).is_err());
+
+        let mut buf = [0; 1500];
+        let len = server.recv(&mut buf).unwrap();
+        let received = std::str::from_utf8(&buf[..len]).unwrap();
+        assert_eq!(strip_timestamps(received), vec!["first value=1i"]);
+
+        // The lines which weren't sent are kept for the next flush.
+        let output: String = recorder.into();
+        let unsent = strip_timestamps(&output);
+        assert_eq!(unsent.len(), 2);
+        assert!(unsent[0].ends_with("aaa value=2i"));
+        assert_eq!(unsent[1], "last value=3i");
+    }
+}
@@ -0,0 +1,144 @@
+#[macro_use]
+extern crate criterion;
+
+use criterion::{black_box, Benchmark, Criterion, ParameterizedBenchmark, Throughput};
+use metrics_core::Recorder;
+use metrics_recorder_prometheus::PrometheusRecorder;
+use metrics_util::populate_synthetic;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+const SERIES: usize = 10_000;
+const VALUES_PER_THREAD: u64 = 10_000;
+
+fn recording_benchmark(c: &mut Criterion) {
+    c.bench(
+        "record",
+        Benchmark::new("counter", |b| {
+            let mut recorder = PrometheusRecorder::new();
+            let mut value = 0;
+            b.iter(|| {
+                value += 1;
+                recorder.record_counter("http.requests", black_box(value));
+            })
+        })
+        .with_function("gauge", |b| {
+            let mut recorder = PrometheusRecorder::new();
+            b.iter(|| recorder.record_gauge("connections", black_box(42)))
+        })
+        .with_function("histogram", |b| {
+            let mut recorder = PrometheusRecorder::new();
+            b.iter(|| recorder.record_histogram("http.request_time", black_box(&[1_234_567])))
+        })
+        .throughput(Throughput::Elements(1)),
+    );
+
+    c.bench(
+        "populate",
+        Benchmark::new("10k_series", |b| {
+            b.iter(|| {
+                let mut recorder = PrometheusRecorder::new();
+                populate_synthetic(&mut recorder, SERIES, 1);
+                recorder
+            })
+        })
+        .throughput(Throughput::Elements(SERIES as u32)),
+    );
+}
+
+fn rendering_benchmark(c: &mut Criterion) {
+    c.bench(
+        "render",
+        Benchmark::new("10k_series", |b| {
+            let mut recorder = PrometheusRecorder::new();
+            populate_synthetic(&mut recorder, SERIES, 10);
+            b.iter(|| recorder.to_string())
+        })
+        .with_function("10k_series_fresh_buffer", |b| {
+            let mut recorder = PrometheusRecorder::new();
+            populate_synthetic(&mut recorder, SERIES, 10);
+            b.iter(|| {
+                let mut buf = String::new();
+                recorder.render_into(&mut buf);
+                buf
+            })
+        })
+        .with_function("10k_series_reused_buffer", |b| {
+            let mut recorder = PrometheusRecorder::new();
+            populate_synthetic(&mut recorder, SERIES, 10);
+            let mut buf = String::new();
+            b.iter(|| {
+                buf.clear();
+                recorder.render_into(&mut buf);
+                black_box(buf.len())
+            })
+        })
+        .with_function("10k_series_textfile", |b| {
+            let mut recorder = PrometheusRecorder::new().with_textfile_mode(true);
+            populate_synthetic(&mut recorder, SERIES, 10);
+            b.iter(|| recorder.to_string())
+        })
+        .throughput(Throughput::Elements(SERIES as u32)),
+    );
+}
+
+// Records histograms from several threads at once, each thread recording its own histogram, either
+// through a recorder behind a mutex or through the histogram shards.
+fn concurrent_benchmark(c: &mut Criterion) {
+    c.bench(
+        "concurrent_histogram",
+        ParameterizedBenchmark::new(
+            "mutex",
+            |b, &threads| {
+                let recorder = Arc::new(Mutex::new(PrometheusRecorder::new()));
+                b.iter(|| {
+                    let handles = (0..threads)
+                        .map(|thread| {
+                            let recorder = recorder.clone();
+                            thread::spawn(move || {
+                                let key = format!("request_time_{}", thread);
+                                for value in 0..VALUES_PER_THREAD {
+                                    let mut recorder = recorder.lock().unwrap();
+                                    recorder.record_histogram(key.clone(), &[value]);
+                                }
+                            })
+                        })
+                        .collect::<Vec<_>>();
+                    for handle in handles {
+                        handle.join().unwrap();
+                    }
+                })
+            },
+            vec![1, 2, 4, 8],
+        )
+        .with_function("sharded", |b, &threads| {
+            let recorder = PrometheusRecorder::new().with_shards(threads * 4);
+            let shards = recorder.histogram_shards().unwrap();
+            b.iter(|| {
+                let handles = (0..threads)
+                    .map(|thread| {
+                        let shards = shards.clone();
+                        thread::spawn(move || {
+                            let key = format!("request_time_{}", thread);
+                            for value in 0..VALUES_PER_THREAD {
+                                shards.record(key.clone(), &[value]);
+                            }
+                        })
+                    })
+                    .collect::<Vec<_>>();
+                for handle in handles {
+                    handle.join().unwrap();
+                }
+            })
+        })
+        .throughput(|&threads| Throughput::Elements(threads as u32 * VALUES_PER_THREAD as u32)),
+    );
+}
+
+criterion_group!(
+    benches,
+    recording_benchmark,
+    rendering_benchmark,
+    concurrent_benchmark
+);
+criterion_main!(benches);
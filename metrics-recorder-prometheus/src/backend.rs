@@ -0,0 +1,344 @@
+use hdrhistogram::Histogram;
+#[cfg(feature = "ddsketch")]
+use std::collections::BTreeMap;
+
+/// Storage for the values of a histogram.
+///
+/// This is the interface the recorder needs from a histogram, so that it isn't tied to a single
+/// implementation.  It is implemented for HdrHistogram's [`Histogram`], which is used by default,
+/// and, with the `ddsketch` feature, for `DDSketch`.  Which one the recorder uses is chosen with
+/// [`PrometheusRecorder::with_histogram_backend`](crate::PrometheusRecorder::with_histogram_backend).
+pub trait HistogramBackend {
+    /// Records a value.
+    fn record(&mut self, value: u64) {
+        self.record_n(value, 1);
+    }
+
+    /// Records a value that was observed `count` times.
+    fn record_n(&mut self, value: u64, count: u64);
+
+    /// Gets the value at the given quantile, which should be between 0.0 and 1.0.
+    ///
+    /// Returns `0` if no values have been recorded.
+    fn quantile(&self, quantile: f64) -> u64;
+
+    /// Gets the sum of the recorded values, within the precision of the backend.
+    fn sum(&self) -> u128;
+
+    /// Gets the number of recorded values.
+    fn count(&self) -> u64;
+
+    /// Merges the values recorded by `other` into this backend.
+    fn merge(&mut self, other: &Self);
+}
+
+impl HistogramBackend for Histogram<u64> {
+    fn record_n(&mut self, value: u64, count: u64) {
+        self.saturating_record_n(value, count);
+    }
+
+    fn quantile(&self, quantile: f64) -> u64 {
+        self.value_at_quantile(quantile)
+    }
+
+    /// Gets the sum of the recorded values, computed from the highest value equivalent to each
+    /// recorded value.
+    fn sum(&self) -> u128 {
+        self.iter_recorded()
+            .map(|v| u128::from(v.value_iterated_to()) * u128::from(v.count_at_value()))
+            .sum()
+    }
+
+    fn count(&self) -> u64 {
+        self.len()
+    }
+
+    /// Merges the values recorded by `other` into this histogram.
+    ///
+    /// Values which are out of the bounds of this histogram are saturated to them.
+    fn merge(&mut self, other: &Self) {
+        if self.add(other).is_err() {
+            for v in other.iter_recorded() {
+                self.saturating_record_n(v.value_iterated_to(), v.count_at_value());
+            }
+        }
+    }
+}
+
+/// A DDSketch, a histogram with relative-error guarantees.
+///
+/// Values are counted in buckets whose bounds grow exponentially, so every quantile is within the
+/// configured relative accuracy of the exact value, e.g. within 1% for an accuracy of `0.01`.
+/// The memory used grows logarithmically with the range of the recorded values, rather than with
+/// their number.  Merging two sketches with the same accuracy is lossless: the result is exactly
+/// the sketch that would have been built from all of their values, so sketches from many hosts
+/// can be combined without losing accuracy.
+///
+/// The sum, minimum, and maximum of the recorded values are tracked exactly.
+///
+/// Requires the `ddsketch` feature.
+#[cfg(feature = "ddsketch")]
+#[derive(Debug, Clone, PartialEq)]
+pub struct DDSketch {
+    relative_accuracy: f64,
+    gamma: f64,
+    gamma_ln: f64,
+    bins: BTreeMap<i32, u64>,
+    zeros: u64,
+    count: u64,
+    sum: u128,
+    sum_squares: f64,
+    min: u64,
+    max: u64,
+}
+
+#[cfg(feature = "ddsketch")]
+impl DDSketch {
+    /// Creates a new, empty [`DDSketch`] with the given relative accuracy.
+    ///
+    /// # Panics
+    /// Panics if `relative_accuracy` is not strictly between 0.0 and 1.0.
+    pub fn new(relative_accuracy: f64) -> Self {
+        assert!(
+            relative_accuracy > 0.0 && relative_accuracy < 1.0,
+            "relative accuracy must be between 0.0 and 1.0"
+        );
+
+        let gamma = (1.0 + relative_accuracy) / (1.0 - relative_accuracy);
+        DDSketch {
+            relative_accuracy,
+            gamma,
+            gamma_ln: gamma.ln(),
+            bins: BTreeMap::new(),
+            zeros: 0,
+            count: 0,
+            sum: 0,
+            sum_squares: 0.0,
+            min: u64::MAX,
+            max: 0,
+        }
+    }
+
+    /// Gets the relative accuracy of this sketch.
+    pub fn relative_accuracy(&self) -> f64 {
+        self.relative_accuracy
+    }
+
+    /// Gets the lowest recorded value, or `0` if no values have been recorded.
+    pub fn min(&self) -> u64 {
+        if self.count == 0 {
+            0
+        } else {
+            self.min
+        }
+    }
+
+    /// Gets the highest recorded value, or `0` if no values have been recorded.
+    pub fn max(&self) -> u64 {
+        self.max
+    }
+
+    /// Gets the mean of the recorded values, or `0.0` if no values have been recorded.
+    pub fn mean(&self) -> f64 {
+        if self.count == 0 {
+            0.0
+        } else {
+            self.sum as f64 / self.count as f64
+        }
+    }
+
+    /// Gets the standard deviation of the recorded values, or `0.0` if no values have been
+    /// recorded.
+    pub fn stdev(&self) -> f64 {
+        if self.count == 0 {
+            return 0.0;
+        }
+        let mean = self.mean();
+        (self.sum_squares / self.count as f64 - mean * mean)
+            .max(0.0)
+            .sqrt()
+    }
+
+    // Gets the number of recorded values at or below `bound`, as estimated from the buckets.
+    pub(crate) fn count_at_or_below(&self, bound: u64) -> u64 {
+        let bucketed = self
+            .bins
+            .iter()
+            .take_while(|(index, _)| self.bin_value(**index) <= bound)
+            .map(|(_, count)| *count)
+            .sum::<u64>();
+        self.zeros + bucketed
+    }
+
+    #[cfg(test)]
+    pub(crate) fn footprint(&self) -> usize {
+        self.bins.len() * std::mem::size_of::<(i32, u64)>()
+    }
+
+    fn index(&self, value: u64) -> i32 {
+        ((value as f64).ln() / self.gamma_ln).ceil() as i32
+    }
+
+    // Gets the value representing a bucket, which is within the relative accuracy of every value
+    // in the bucket.
+    fn bin_value(&self, index: i32) -> u64 {
+        let value = 2.0 * self.gamma.powi(index) / (self.gamma + 1.0);
+        (value.round() as u64).clamp(self.min(), self.max)
+    }
+}
+
+#[cfg(feature = "ddsketch")]
+impl HistogramBackend for DDSketch {
+    fn record_n(&mut self, value: u64, count: u64) {
+        if count == 0 {
+            return;
+        }
+
+        if value == 0 {
+            self.zeros += count;
+        } else {
+            *self.bins.entry(self.index(value)).or_insert(0) += count;
+        }
+        self.count += count;
+        self.sum += u128::from(value) * u128::from(count);
+        self.sum_squares += (value as f64) * (value as f64) * count as f64;
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+    }
+
+    fn quantile(&self, quantile: f64) -> u64 {
+        if self.count == 0 {
+            return 0;
+        }
+
+        let rank = (quantile.clamp(0.0, 1.0) * (self.count - 1) as f64) as u64;
+        let mut seen = self.zeros;
+        if seen > rank {
+            return 0;
+        }
+        for (index, count) in &self.bins {
+            seen += count;
+            if seen > rank {
+                return self.bin_value(*index);
+            }
+        }
+        self.max
+    }
+
+    fn sum(&self) -> u128 {
+        self.sum
+    }
+
+    fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Merges the values recorded by `other` into this sketch.
+    ///
+    /// # Panics
+    /// Panics if the sketches have different relative accuracies, as their buckets don't line up.
+    fn merge(&mut self, other: &Self) {
+        assert!(
+            self.relative_accuracy == other.relative_accuracy,
+            "sketches with different relative accuracies can't be merged"
+        );
+
+        for (index, count) in &other.bins {
+            *self.bins.entry(*index).or_insert(0) += count;
+        }
+        self.zeros += other.zeros;
+        self.count += other.count;
+        self.sum += other.sum;
+        self.sum_squares += other.sum_squares;
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::HistogramBackend;
+    use hdrhistogram::Histogram;
+
+    // Exercises a backend through the trait alone, checking results against the exact values
+    // within the given relative error.
+    fn exercise<B: HistogramBackend + Clone>(mut backend: B, error: f64) {
+        assert_eq!(backend.count(), 0);
+        assert_eq!(backend.quantile(0.5), 0);
+
+        for value in 1..=1000 {
+            backend.record(value);
+        }
+        backend.record_n(0, 10);
+        assert_eq!(backend.count(), 1010);
+
+        let within =
+            |actual: u64, expected: f64| (actual as f64 - expected).abs() <= expected * error + 1.0;
+        assert_eq!(backend.quantile(0.0), 0);
+        assert!(
+            within(backend.quantile(0.5), 495.0),
+            "{}",
+            backend.quantile(0.5)
+        );
+        assert!(
+            within(backend.quantile(0.99), 990.0),
+            "{}",
+            backend.quantile(0.99)
+        );
+        assert!(
+            within(backend.quantile(1.0), 1000.0),
+            "{}",
+            backend.quantile(1.0)
+        );
+        let sum = backend.sum() as f64;
+        assert!((sum - 500_500.0).abs() <= 500_500.0 * error, "{}", sum);
+
+        let mut merged = backend.clone();
+        merged.merge(&backend);
+        assert_eq!(merged.count(), 2020);
+        assert_eq!(merged.quantile(0.5), backend.quantile(0.5));
+        assert_eq!(merged.sum(), backend.sum() * 2);
+    }
+
+    #[test]
+    fn test_hdr_backend() {
+        let hist = Histogram::<u64>::new_with_bounds(1, u64::MAX, 3).unwrap();
+        exercise(hist, 0.001);
+    }
+
+    #[cfg(feature = "ddsketch")]
+    #[test]
+    fn test_ddsketch_backend() {
+        use super::DDSketch;
+
+        exercise(DDSketch::new(0.01), 0.01);
+
+        // Merging is lossless: the merged sketch is the one built from all of the values.
+        let (mut left, mut right, mut all) = (
+            DDSketch::new(0.02),
+            DDSketch::new(0.02),
+            DDSketch::new(0.02),
+        );
+        for value in 1..500 {
+            left.record(value * 7);
+            all.record(value * 7);
+        }
+        for value in 1..300 {
+            right.record(value * 1_000);
+            all.record(value * 1_000);
+        }
+        left.merge(&right);
+        assert_eq!(left, all);
+        assert_eq!(left.min(), 7);
+        assert_eq!(left.max(), 299_000);
+    }
+
+    #[cfg(feature = "ddsketch")]
+    #[test]
+    #[should_panic(expected = "different relative accuracies")]
+    fn test_ddsketch_merge_mismatch() {
+        use super::DDSketch;
+
+        DDSketch::new(0.01).merge(&DDSketch::new(0.02));
+    }
+}
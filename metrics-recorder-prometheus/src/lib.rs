@@ -1,13 +1,541 @@
 //! Records metrics in the Prometheus exposition format.
-use hdrhistogram::Histogram;
-use metrics_core::{Key, Recorder};
-use metrics_util::{parse_quantiles, Quantile};
-use std::time::SystemTime;
+//!
+//! ## Metric types
+//!
+//! A metric name can only have a single type in the exposition format.  Once a name has been
+//! recorded as a counter, gauge, or histogram, recordings of the same name as a different type
+//! are dropped, or cause a panic if strict type checking has been enabled with
+//! [`PrometheusRecorder::with_strict_types`], which is useful in tests.
+//!
+//! ## Precision
+//!
+//! Counter and gauge values are rendered as exact integers, but Prometheus parses every sample as
+//! a 64-bit float, which can only represent integers exactly up to 2^53.  Larger values, such as
+//! byte counters in the exabyte range, are silently rounded by Prometheus, to the nearest multiple
+//! of 2^11 once past 2^63.  A warning is logged the first time a counter or gauge crosses 2^53.
+//!
+//! ## Histograms
+//!
+//! Histograms grow to fit the largest value recorded, so a single extreme outlier can make a
+//! histogram allocate more memory than expected.  This can be bounded with
+//! [`PrometheusRecorder::with_max_histogram_memory`], at the cost of accuracy for outliers: values
+//! that would require growing a histogram past the limit are recorded as the largest value that
+//! fits instead.  Sums are always computed from the original values, and are tracked with 128 bits,
+//! so they don't overflow even for long-lived recorders of large values, such as nanosecond
+//! timings.
+//!
+//! By default, histograms are rendered as summaries, with one series per configured quantile.
+//! They can instead be rendered as native Prometheus histograms, with one cumulative series per
+//! bucket, by setting a [`HistogramMode`] either for all histograms or for specific metrics.
+//! Bucket bounds can be generated with [`exponential_buckets`] and [`linear_buckets`].
+//!
+//! Histograms are stored in an HdrHistogram by default.  With the `ddsketch` feature, they can be
+//! stored in a DDSketch instead, which guarantees a relative error for every quantile, by calling
+//! [`PrometheusRecorder::with_histogram_backend`].  Both implement [`HistogramBackend`].
+//!
+//! Values which can be negative are recorded with [`PrometheusRecorder::record_signed_histogram`],
+//! which splits them into a histogram of non-negative values and one of negative magnitudes.
+//!
+//! ## Lazy gauges
+//!
+//! Gauges that are expensive to compute can be registered with
+//! [`PrometheusRecorder::register_lazy_gauge`], so that their value is only computed when the
+//! recorder is rendered, rather than on every recording.
+//!
+//! ## Escaping
+//!
+//! Metric names are sanitized with [`sanitize_metric_name`], and label values are escaped with
+//! [`escape_label_value`], when rendering.  Both are exposed so that names and values can be
+//! checked ahead of time.
+//!
+//! ## Concurrent histograms
+//!
+//! Recording takes `&mut self`, so a recorder shared between threads has to be locked for every
+//! recording.  For services recording histograms on many threads, the recorder can be configured
+//! with [`PrometheusRecorder::with_shards`], after which [`PrometheusRecorder::histogram_shards`]
+//! hands out a [`HistogramShards`] handle that records histograms without locking the recorder.
+#[macro_use]
+extern crate log;
+
+mod backend;
+#[cfg(feature = "ddsketch")]
+pub use backend::DDSketch;
+pub use backend::HistogramBackend;
+mod parse;
+pub use parse::{parse_exposition, ParseError};
+mod shards;
+pub use shards::HistogramShards;
+
+use hdrhistogram::serialization::{DeserializeError, Deserializer};
+use hdrhistogram::{CreationError, Histogram};
+use metrics_core::{sample_weight, IntoLabels, Key, Label, Recorder, Snapshot};
+use metrics_util::{matches_pattern, parse_quantiles, Quantile};
+use quanta::Clock;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::convert::TryFrom;
+use std::env;
+use std::error::Error;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+
+/// How a histogram is rendered.
+#[derive(Debug, Clone, PartialEq)]
+pub enum HistogramMode {
+    /// Renders the histogram as a summary, using the configured quantiles.
+    Summary,
+
+    /// Renders the histogram as a native histogram, using the given bucket upper bounds.
+    ///
+    /// A `+Inf` bucket is always rendered, so it does not need to be included.
+    Buckets(Vec<f64>),
+}
+
+/// Which [`HistogramBackend`] histograms are stored in.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HistogramBackendKind {
+    /// Stores histograms in an HdrHistogram, configured by
+    /// [`PrometheusRecorder::with_histogram_bounds`].
+    Hdr,
+
+    /// Stores histograms in a [`DDSketch`] with the given relative accuracy.
+    ///
+    /// Requires the `ddsketch` feature.
+    #[cfg(feature = "ddsketch")]
+    DDSketch(f64),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MetricKind {
+    Counter,
+    Gauge,
+    Histogram,
+    SignedHistogram,
+    PrecomputedSummary,
+}
+
+impl fmt::Display for MetricKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self {
+            MetricKind::Counter => "counter",
+            MetricKind::Gauge => "gauge",
+            MetricKind::Histogram => "histogram",
+            MetricKind::SignedHistogram => "signed histogram",
+            MetricKind::PrecomputedSummary => "precomputed summary",
+        };
+        f.write_str(kind)
+    }
+}
+
+// The largest integer up to which every integer can be represented exactly by an `f64`.
+const MAX_EXACT_FLOAT: u64 = 1 << 53;
+
+// A rough average of the length of a rendered line, used to size the output up front.
+const ESTIMATED_LINE_LEN: usize = 64;
+
+const DEFAULT_QUANTILES: &[f64] = &[0.0, 0.5, 0.9, 0.95, 0.99, 0.999, 1.0];
+
+// The counter of values saturated to the maximum set with `with_max_value`.
+const SATURATED_METRIC: &str = "metrics_values_saturated_total";
+
+#[derive(Clone)]
+struct HistogramEntry {
+    sum: u128,
+    hist: HistogramStorage,
+    last: Option<u64>,
+    underflow: u64,
+    overflow: u64,
+    // Values saturated to the maximum value of the recorder, which is only tracked for histograms
+    // recorded through the shards, as they can't update the saturation counter themselves.
+    saturated: u64,
+}
+
+impl HistogramEntry {
+    // Records `value` as observed `count` times, saturating it to `max` and counting it as
+    // clipped if it's outside of the bounds.
+    fn record_n(&mut self, value: u64, count: u64, lowest: u64, max: u64) {
+        if count == 0 {
+            return;
+        }
+
+        self.hist.record_n(value.min(max), count);
+        self.sum += u128::from(value) * u128::from(count);
+        self.last = Some(value);
+
+        // Zero is always tracked exactly, while anything else below the lowest discernible value
+        // can't be told apart from zero.
+        if value > 0 && value < lowest {
+            self.underflow += count;
+        } else if value > max {
+            self.overflow += count;
+        }
+    }
+
+    // Merges `other` into this entry.  Which of the two last values is kept is unspecified, as
+    // entries being merged were recorded concurrently.
+    fn merge(&mut self, other: &HistogramEntry) {
+        self.hist.merge(&other.hist);
+        self.sum += other.sum;
+        self.last = other.last.or(self.last);
+        self.underflow += other.underflow;
+        self.overflow += other.overflow;
+        self.saturated += other.saturated;
+    }
+}
+
+// The backend of a histogram, with everything the recorder needs to render it.
+#[derive(Clone)]
+enum HistogramStorage {
+    Hdr(Histogram<u64>),
+    #[cfg(feature = "ddsketch")]
+    DDSketch(DDSketch),
+}
+
+impl HistogramStorage {
+    fn record_n(&mut self, value: u64, count: u64) {
+        match self {
+            HistogramStorage::Hdr(hist) => hist
+                .record_n(value, count)
+                .expect("failed to record histogram value"),
+            #[cfg(feature = "ddsketch")]
+            HistogramStorage::DDSketch(sketch) => sketch.record_n(value, count),
+        }
+    }
+
+    fn merge(&mut self, other: &HistogramStorage) {
+        match (self, other) {
+            (HistogramStorage::Hdr(hist), HistogramStorage::Hdr(other)) => hist.merge(other),
+            #[cfg(feature = "ddsketch")]
+            (HistogramStorage::DDSketch(sketch), HistogramStorage::DDSketch(other)) => {
+                sketch.merge(other)
+            }
+            #[cfg(feature = "ddsketch")]
+            _ => unreachable!("histograms are merged with histograms of the same backend"),
+        }
+    }
+
+    fn value_at_quantile(&self, quantile: f64) -> u64 {
+        match self {
+            HistogramStorage::Hdr(hist) => hist.value_at_quantile(quantile),
+            #[cfg(feature = "ddsketch")]
+            HistogramStorage::DDSketch(sketch) => sketch.quantile(quantile),
+        }
+    }
+
+    fn interpolated_quantile(&self, quantile: f64) -> f64 {
+        match self {
+            HistogramStorage::Hdr(hist) => interpolated_quantile(hist, quantile),
+            // Buckets of a sketch don't hold their exact values, so there's nothing to
+            // interpolate between.
+            #[cfg(feature = "ddsketch")]
+            HistogramStorage::DDSketch(sketch) => sketch.quantile(quantile) as f64,
+        }
+    }
+
+    fn bucket_count(&self, bound: f64) -> u64 {
+        match self {
+            HistogramStorage::Hdr(hist) => bucket_count(hist, bound),
+            #[cfg(feature = "ddsketch")]
+            HistogramStorage::DDSketch(_) if bound < 0.0 => 0,
+            #[cfg(feature = "ddsketch")]
+            HistogramStorage::DDSketch(sketch) => sketch.count_at_or_below(bound.floor() as u64),
+        }
+    }
+
+    fn len(&self) -> u64 {
+        match self {
+            HistogramStorage::Hdr(hist) => hist.len(),
+            #[cfg(feature = "ddsketch")]
+            HistogramStorage::DDSketch(sketch) => sketch.count(),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn min(&self) -> u64 {
+        match self {
+            HistogramStorage::Hdr(hist) => hist.min(),
+            #[cfg(feature = "ddsketch")]
+            HistogramStorage::DDSketch(sketch) => sketch.min(),
+        }
+    }
+
+    fn max(&self) -> u64 {
+        match self {
+            HistogramStorage::Hdr(hist) => hist.max(),
+            #[cfg(feature = "ddsketch")]
+            HistogramStorage::DDSketch(sketch) => sketch.max(),
+        }
+    }
+
+    fn mean(&self) -> f64 {
+        match self {
+            HistogramStorage::Hdr(hist) => hist.mean(),
+            #[cfg(feature = "ddsketch")]
+            HistogramStorage::DDSketch(sketch) => sketch.mean(),
+        }
+    }
+
+    fn stdev(&self) -> f64 {
+        match self {
+            HistogramStorage::Hdr(hist) => hist.stdev(),
+            #[cfg(feature = "ddsketch")]
+            HistogramStorage::DDSketch(sketch) => sketch.stdev(),
+        }
+    }
+
+    #[cfg(test)]
+    fn footprint(&self) -> usize {
+        match self {
+            HistogramStorage::Hdr(hist) => histogram_footprint(hist),
+            #[cfg(feature = "ddsketch")]
+            HistogramStorage::DDSketch(sketch) => sketch.footprint(),
+        }
+    }
+}
+
+// A histogram of signed values, kept as a histogram of the non-negative values and a histogram
+// of the magnitudes of the negative values.
+#[derive(Clone)]
+struct SignedHistogramEntry {
+    sum: i128,
+    positive: Histogram<u64>,
+    negative: Histogram<u64>,
+}
+
+impl SignedHistogramEntry {
+    fn len(&self) -> u64 {
+        self.positive.len() + self.negative.len()
+    }
+
+    // Finds the value at the given quantile across both halves.  The negative values come first,
+    // and their order is the reverse of the order of their magnitudes, so the quantile within
+    // the negative half is mirrored before looking it up.
+    fn value_at_quantile(&self, quantile: f64) -> i64 {
+        let (negatives, positives) = (self.negative.len() as f64, self.positive.len() as f64);
+        let rank = quantile.clamp(0.0, 1.0) * (negatives + positives);
+        if negatives > 0.0 && (rank <= negatives || positives == 0.0) {
+            let magnitude = self.negative.value_at_quantile(1.0 - rank / negatives);
+            0i64.saturating_sub_unsigned(magnitude)
+        } else {
+            let value = self
+                .positive
+                .value_at_quantile((rank - negatives) / positives);
+            i64::try_from(value).unwrap_or(i64::MAX)
+        }
+    }
+}
+
+// A summary whose quantiles were computed elsewhere, sorted by quantile.
+#[derive(Clone)]
+struct PrecomputedSummary {
+    quantiles: Vec<(f64, u64)>,
+    sum: u64,
+    count: u64,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct HistogramBounds {
+    lowest: u64,
+    highest: Option<u64>,
+    sigfig: u8,
+}
+
+/// An error returned when configuring invalid histogram bounds.
+///
+/// Created by [`PrometheusRecorder::with_histogram_bounds`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HistogramConfigError(CreationError);
+
+impl Error for HistogramConfigError {}
+
+impl fmt::Display for HistogramConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let reason = match self.0 {
+            CreationError::LowIsZero => "lowest value must be at least 1",
+            CreationError::LowExceedsMax => "lowest value must be at most half of u64::MAX",
+            CreationError::HighLessThanTwiceLow => {
+                "highest value must be at least twice the lowest value"
+            }
+            CreationError::SigFigExceedsMax => "significant figures must be at most 5",
+            CreationError::CannotRepresentSigFigBeyondLow => {
+                "significant figures cannot be represented beyond the lowest value"
+            }
+            CreationError::UsizeTypeTooSmall => "histogram is too large for this platform",
+        };
+        write!(f, "invalid histogram bounds: {}", reason)
+    }
+}
+
+/// An error returned when merging a histogram that could not be deserialized.
+///
+/// Created by [`PrometheusRecorder::merge_serialized_histogram`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HistogramMergeError(DeserializeError);
+
+impl Error for HistogramMergeError {}
+
+impl fmt::Display for HistogramMergeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let reason = match self.0 {
+            DeserializeError::IoError(kind) => {
+                return write!(f, "failed to read histogram: {:?}", kind)
+            }
+            DeserializeError::InvalidCookie => "unknown serialization format",
+            DeserializeError::UnsupportedFeature => "histogram uses an unsupported feature",
+            DeserializeError::UnsuitableCounterType => "a count does not fit in 64 bits",
+            DeserializeError::InvalidParameters => "histogram has invalid bounds",
+            DeserializeError::UsizeTypeTooSmall => "histogram is too large for this platform",
+            DeserializeError::EncodedArrayTooLong => "encoded counts exceed the histogram's range",
+        };
+        write!(f, "invalid serialized histogram: {}", reason)
+    }
+}
+
+/// A problem with the recorded metrics which affects how they are rendered.
+///
+/// Returned by [`PrometheusRecorder::try_render`].
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum RenderWarning {
+    /// A metric name contains characters that aren't valid in the exposition format, so it is
+    /// rendered as `sanitized` instead.  Dots are not reported, as they are the usual separator
+    /// in names.
+    SanitizedName {
+        /// The name of the metric.
+        name: String,
+        /// The name it is rendered as.
+        sanitized: String,
+    },
+
+    /// A metric has multiple labels with the same key, so only the last one is rendered.
+    DuplicateLabel {
+        /// The name of the metric.
+        name: String,
+        /// The key of the duplicated label.
+        label: String,
+    },
+
+    /// A metric was recorded as a different type than it was first recorded as, so those
+    /// recordings were dropped.
+    TypeConflict {
+        /// The name of the metric, as it is rendered.
+        name: String,
+        /// The type the metric was first recorded as.
+        declared: String,
+        /// The type of the dropped recordings.
+        recorded: String,
+    },
+}
+
+impl RenderWarning {
+    // Whether this would have been rejected by the configured strict checks.
+    fn is_fatal(&self, strict_types: bool, strict_labels: bool) -> bool {
+        match self {
+            RenderWarning::SanitizedName { .. } => false,
+            RenderWarning::DuplicateLabel { .. } => strict_labels,
+            RenderWarning::TypeConflict { .. } => strict_types,
+        }
+    }
+}
+
+impl fmt::Display for RenderWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RenderWarning::SanitizedName { name, sanitized } => write!(
+                f,
+                "metric `{}` is not a valid name, and is rendered as `{}`",
+                name, sanitized
+            ),
+            RenderWarning::DuplicateLabel { name, label } => write!(
+                f,
+                "metric `{}` has multiple labels with the key `{}`",
+                name, label
+            ),
+            RenderWarning::TypeConflict {
+                name,
+                declared,
+                recorded,
+            } => write!(
+                f,
+                "metric `{}` recorded as a {}, but was previously recorded as a {}",
+                name, recorded, declared
+            ),
+        }
+    }
+}
+
+type LazyGauge = Arc<dyn Fn() -> i64 + Send + Sync>;
+
+// The number of renders so far, each of which starts a new window for peak gauges.
+#[derive(Default)]
+struct RenderCount(AtomicU64);
+
+impl RenderCount {
+    fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    fn increment(&self) {
+        self.0.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+impl Clone for RenderCount {
+    fn clone(&self) -> Self {
+        RenderCount(AtomicU64::new(self.get()))
+    }
+}
 
 /// Records metrics in the Prometheus exposition format.
+#[derive(Clone)]
 pub struct PrometheusRecorder {
     quantiles: Vec<Quantile>,
-    output: String,
+    quantile_overrides: HashMap<String, Vec<Quantile>>,
+    quantile_label: String,
+    interpolate_quantiles: bool,
+    namespace: Option<String>,
+    label_order: Vec<String>,
+    max_label_length: usize,
+    histogram_mode: HistogramMode,
+    histogram_mode_overrides: HashMap<String, HistogramMode>,
+    histogram_bounds: HistogramBounds,
+    histogram_backend: HistogramBackendKind,
+    max_histogram_memory: Option<usize>,
+    max_histogram_value: u64,
+    last_value: bool,
+    clipping_counters: bool,
+    extended_summary: bool,
+    float_precision: Option<usize>,
+    sample_count_label: bool,
+    max_value: Option<u64>,
+    scrape_duration_metric: bool,
+    infos: HashMap<String, Vec<Label>>,
+    lazy_gauges: HashMap<Key, LazyGauge>,
+    strict_types: bool,
+    strict_labels: bool,
+    textfile_mode: bool,
+    types: HashMap<String, MetricKind>,
+    conflicts: BTreeMap<String, (MetricKind, MetricKind)>,
+    clock: Option<Clock>,
+    updated: Option<HashMap<Key, u64>>,
+    rates: HashMap<Key, (u64, f64)>,
+    counters: HashMap<Key, u64>,
+    // Counters whose total is saturated to the maximum value, which are only counted as saturated
+    // once, rather than on every recording of the saturated total.
+    saturated_counters: HashSet<Key>,
+    gauges: HashMap<Key, i64>,
+    // The render during which the window of each peak gauge started.
+    peak_windows: HashMap<Key, u64>,
+    renders: RenderCount,
+    timestamps: HashMap<Key, u64>,
+    histos: HashMap<Key, HistogramEntry>,
+    signed_histos: HashMap<Key, SignedHistogramEntry>,
+    summaries: HashMap<Key, PrecomputedSummary>,
+    shards: Option<HistogramShards>,
 }
 
 impl PrometheusRecorder {
@@ -19,7 +547,20 @@ impl PrometheusRecorder {
     ///
     /// The configured quantiles are used when rendering any histograms.
     pub fn new() -> Self {
-        Self::with_quantiles(&[0.0, 0.5, 0.9, 0.95, 0.99, 0.999, 1.0])
+        Self::with_quantiles(DEFAULT_QUANTILES)
+    }
+
+    /// Creates a new [`PrometheusRecorder`] with the quantiles listed in an environment variable.
+    ///
+    /// The variable should hold a comma-separated list of quantiles between 0.0 and 1.0, such as
+    /// `0.5,0.99,0.999`.  This allows operators to change the exported quantiles without
+    /// recompiling.  If the variable is unset, or if any value in it is invalid, the default
+    /// quantiles of [`PrometheusRecorder::new`] are used instead, and invalid values are logged.
+    pub fn with_quantiles_from_env(var_name: &str) -> Self {
+        match quantiles_from_env(var_name) {
+            Some(quantiles) => Self::with_quantiles(&quantiles),
+            None => Self::new(),
+        }
     }
 
     /// Creates a new [`PrometheusRecorder`] with the given set of quantiles.
@@ -29,90 +570,4015 @@ impl PrometheusRecorder {
         let actual_quantiles = parse_quantiles(quantiles);
         Self {
             quantiles: actual_quantiles,
-            output: get_prom_expo_header(),
+            quantile_overrides: HashMap::new(),
+            quantile_label: "quantile".to_owned(),
+            interpolate_quantiles: false,
+            namespace: None,
+            label_order: Vec::new(),
+            max_label_length: usize::MAX,
+            histogram_mode: HistogramMode::Summary,
+            histogram_mode_overrides: HashMap::new(),
+            histogram_bounds: HistogramBounds {
+                lowest: 1,
+                highest: None,
+                sigfig: 3,
+            },
+            histogram_backend: HistogramBackendKind::Hdr,
+            max_histogram_memory: None,
+            max_histogram_value: u64::MAX,
+            last_value: false,
+            clipping_counters: false,
+            extended_summary: false,
+            float_precision: None,
+            sample_count_label: false,
+            max_value: None,
+            scrape_duration_metric: false,
+            infos: HashMap::new(),
+            lazy_gauges: HashMap::new(),
+            strict_types: false,
+            strict_labels: false,
+            textfile_mode: false,
+            types: HashMap::new(),
+            conflicts: BTreeMap::new(),
+            clock: None,
+            updated: None,
+            rates: HashMap::new(),
+            counters: HashMap::new(),
+            saturated_counters: HashSet::new(),
+            gauges: HashMap::new(),
+            peak_windows: HashMap::new(),
+            renders: RenderCount::default(),
+            timestamps: HashMap::new(),
+            histos: HashMap::new(),
+            signed_histos: HashMap::new(),
+            summaries: HashMap::new(),
+            shards: None,
         }
     }
-}
 
-impl Recorder for PrometheusRecorder {
-    fn record_counter<K: Into<Key>>(&mut self, key: K, value: u64) {
-        let label = key.into().as_ref().replace('.', "_");
-        self.output.push_str("\n# TYPE ");
-        self.output.push_str(label.as_str());
-        self.output.push_str(" counter\n");
-        self.output.push_str(label.as_str());
-        self.output.push_str(" ");
-        self.output.push_str(value.to_string().as_str());
-        self.output.push_str("\n");
+    /// Sets whether recording a metric name as a different type than it was first recorded as
+    /// panics.
+    ///
+    /// When disabled, which is the default, such recordings are silently dropped.
+    pub fn with_strict_types(mut self, strict: bool) -> Self {
+        self.strict_types = strict;
+        self
     }
 
-    fn record_gauge<K: Into<Key>>(&mut self, key: K, value: i64) {
-        let label = key.into().as_ref().replace('.', "_");
-        self.output.push_str("\n# TYPE ");
-        self.output.push_str(label.as_str());
-        self.output.push_str(" gauge\n");
-        self.output.push_str(label.as_str());
-        self.output.push_str(" ");
-        self.output.push_str(value.to_string().as_str());
-        self.output.push_str("\n");
+    /// Sets whether recording a metric with multiple labels of the same key panics.
+    ///
+    /// Prometheus rejects series with duplicate label keys, so when disabled, which is the
+    /// default, only the last label with each key is rendered.  Enabling this is useful in tests,
+    /// to catch keys that are built with conflicting labels.
+    pub fn with_strict_labels(mut self, strict: bool) -> Self {
+        self.strict_labels = strict;
+        self
     }
 
-    fn record_histogram<K: Into<Key>>(&mut self, key: K, values: &[u64]) {
-        let mut sum = 0;
-        let mut h = Histogram::<u64>::new(3).expect("failed to create histogram");
-        for value in values {
-            h.record(*value).expect("failed to record histogram value");
-            sum += *value;
+    /// Sets whether output is rendered for the `node_exporter` textfile collector.
+    ///
+    /// The textfile collector is stricter than a Prometheus server: in textfile mode, the header
+    /// comment and blank lines are omitted, and each family has a single `# TYPE` line followed by
+    /// its series, sorted and without duplicates.  Families and series which would otherwise be
+    /// duplicated, such as a derived `_last` gauge clashing with a counter of the same name, or
+    /// keys whose labels only differ in their order, are rendered once, and a warning is logged
+    /// for every family or series that was dropped.  Output always ends with a newline, so it can
+    /// be written to a `.prom` file as-is.  Disabled by default.
+    ///
+    /// This only affects the text exposition format, and not OpenMetrics.
+    pub fn with_textfile_mode(mut self, enabled: bool) -> Self {
+        self.textfile_mode = enabled;
+        self
+    }
+
+    /// Limits the memory used by each histogram to roughly `bytes`.
+    ///
+    /// Histograms grow as larger values are recorded.  With a limit, values that would grow a
+    /// histogram past it are saturated to the largest value that can be tracked within the
+    /// limit, which keeps memory bounded even with pathological outliers, but means quantiles
+    /// near the maximum under-report those outliers.
+    pub fn with_max_histogram_memory(mut self, bytes: usize) -> Self {
+        self.max_histogram_memory = Some(bytes);
+        self.update_max_histogram_value();
+        self
+    }
+
+    /// Sets the bounds and precision of histograms.
+    ///
+    /// `lowest` is the smallest value that can be told apart from zero, `highest` is the largest
+    /// value that can be tracked, with larger values being saturated to it, and `sigfig` is the
+    /// number of significant figures, between 0 and 5, that values are tracked with.  By
+    /// default, histograms track values from 1 with 3 significant figures, and grow to fit the
+    /// largest value recorded.
+    ///
+    /// # Errors
+    /// Returns an error if the configuration is invalid.  It is validated here, so creating
+    /// histograms while recording cannot fail afterwards.
+    pub fn with_histogram_bounds(
+        mut self,
+        lowest: u64,
+        highest: u64,
+        sigfig: u8,
+    ) -> Result<Self, HistogramConfigError> {
+        let bounds = HistogramBounds {
+            lowest,
+            highest: Some(highest),
+            sigfig,
+        };
+        new_histogram(bounds).map_err(HistogramConfigError)?;
+
+        self.histogram_bounds = bounds;
+        self.update_max_histogram_value();
+        Ok(self)
+    }
+
+    /// Sets which backend histograms are stored in.
+    ///
+    /// Histograms are stored in an HdrHistogram by default, which tracks values with a fixed
+    /// number of significant figures, and allows interpolating quantiles between the recorded
+    /// values.  With the `ddsketch` feature, they can instead be stored in a `DDSketch`, which
+    /// guarantees a relative error for every quantile, and can be merged across hosts without
+    /// losing accuracy.  Values are clamped to the configured bounds and memory limit either way,
+    /// and signed histograms always use an HdrHistogram.
+    ///
+    /// # Panics
+    /// Panics if the relative accuracy of a `DDSketch` is not strictly between 0.0 and 1.0.
+    pub fn with_histogram_backend(mut self, backend: HistogramBackendKind) -> Self {
+        #[cfg(feature = "ddsketch")]
+        {
+            if let HistogramBackendKind::DDSketch(relative_accuracy) = backend {
+                DDSketch::new(relative_accuracy);
+            }
         }
 
-        let label = key.into().as_ref().replace('.', "_");
-        self.output.push_str("\n# TYPE ");
-        self.output.push_str(label.as_str());
-        self.output.push_str(" summary\n");
+        self.histogram_backend = backend;
+        self.update_shards();
+        self
+    }
+
+    /// Spreads histograms recorded through [`PrometheusRecorder::histogram_shards`] over `count`
+    /// shards.
+    ///
+    /// Each shard has its own lock, and histograms are assigned to shards by the hash of their
+    /// key, so threads recording different histograms through the shards can do so in parallel,
+    /// without locking the recorder.  Whenever the recorder is rendered, the shards are merged
+    /// into a copy of it, so sharded histograms are rendered along with the histograms recorded
+    /// directly, and any histogram recorded both ways is rendered once, with all of its values.
+    /// Sharding is disabled by default, and is only worthwhile with many recording threads.  A
+    /// good starting point is a few shards per recording thread.
+    ///
+    /// The shards are cleared by any histogram configuration that follows, so this is best
+    /// called last.
+    ///
+    /// # Panics
+    /// Panics if `count` is zero.
+    pub fn with_shards(mut self, count: usize) -> Self {
+        assert!(count > 0, "shard count must be greater than zero");
+        self.shards = Some(HistogramShards::new(
+            count,
+            self.histogram_bounds,
+            self.histogram_backend,
+            self.max_histogram_value,
+            self.max_value,
+        ));
+        self
+    }
+
+    /// Sets whether the last value recorded for each histogram is also rendered, as a gauge.
+    ///
+    /// The gauge is named after the histogram with a `_last` suffix, which is handy for showing a
+    /// "current" reading derived from a stream of timings alongside their distribution.  Disabled
+    /// by default.
+    pub fn with_last_value(mut self, enabled: bool) -> Self {
+        self.last_value = enabled;
+        self
+    }
+
+    /// Sets whether the number of clipped values is rendered for each histogram.
+    ///
+    /// When enabled, every histogram also gets a `<name>_overflow_total` counter, with the number
+    /// of values that were saturated because they exceeded the highest trackable value, and a
+    /// `<name>_underflow_total` counter, with the number of non-zero values below the lowest
+    /// discernible value.  This surfaces misconfigured histogram bounds.  Disabled by default.
+    pub fn with_clipping_counters(mut self, enabled: bool) -> Self {
+        self.clipping_counters = enabled;
+        self
+    }
+
+    /// Caps every counter and histogram value at `max`.
+    ///
+    /// Larger values are saturated to `max`, and counted by the `metrics_values_saturated_total`
+    /// counter, which guards against obviously wrong values, such as a timing computed from an
+    /// underflowed duration, corrupting a histogram or a counter.  Unlike the histogram bounds,
+    /// this applies to counters too, and the cap is surfaced by a single counter that's rendered
+    /// as soon as a value is saturated.  As counters are recorded as their total, a counter is
+    /// only counted once while its total stays saturated.  Unlimited by default.
+    pub fn with_max_value(mut self, max: u64) -> Self {
+        self.max_value = Some(max);
+        self.update_shards();
+        self
+    }
+
+    /// Sets whether the time each metric was last recorded is tracked.
+    ///
+    /// Tracking allows checking whether a metric is still being recorded with
+    /// [`PrometheusRecorder::is_stale`], such as a heartbeat gauge in a liveness check, at the
+    /// cost of reading the clock on every recording.  Histograms recorded through
+    /// [`PrometheusRecorder::histogram_shards`] aren't tracked.  Disabled by default.
+    pub fn with_update_tracking(mut self, enabled: bool) -> Self {
+        self.updated = if enabled { Some(HashMap::new()) } else { None };
+        self
+    }
+
+    /// Sets whether the time taken to render is rendered too, as a gauge.
+    ///
+    /// The gauge is named `scrape_duration_seconds`, prefixed with the namespace if any, and is
+    /// rendered last, with the time elapsed from the start of the render until just before it
+    /// is written.  This makes the cost of rendering a large set of metrics visible.  Partitioned
+    /// renders leave it out.  Disabled by default.
+    pub fn with_scrape_duration_metric(mut self, enabled: bool) -> Self {
+        self.scrape_duration_metric = enabled;
+        self
+    }
+
+    /// Sets whether descriptive statistics are rendered for each histogram.
+    ///
+    /// When enabled, every histogram that has recorded values also gets `<name>_min`,
+    /// `<name>_max`, `<name>_mean`, and `<name>_stddev` gauges, computed from the whole
+    /// distribution.  These are approximations within the precision of the histogram, just like
+    /// the quantiles are.  Disabled by default.
+    pub fn with_extended_summary(mut self, enabled: bool) -> Self {
+        self.extended_summary = enabled;
+        self
+    }
+
+    /// Sets the number of significant digits fractional values are rendered with.
+    ///
+    /// Values such as interpolated quantiles and the means of extended summaries are rounded to
+    /// `digits` significant digits, with no trailing zeros, so that `0.30000000000000004` is
+    /// rendered as `0.3`.  Integer values, such as counters, gauges, and sums, are always exact.
+    /// At least one digit is used.  By default, values are rendered with as many digits as
+    /// needed to parse back to exactly the same value.
+    pub fn with_float_precision(mut self, digits: usize) -> Self {
+        self.float_precision = Some(digits.max(1));
+        self
+    }
+
+    /// Sets whether the quantiles of summaries are labeled with their sample count.
+    ///
+    /// When enabled, every quantile series gets an `n` label holding the number of values the
+    /// quantile was computed from, e.g. `{quantile="0.99",n="42"}`, which makes it obvious when a
+    /// quantile is based on too few values to be meaningful.  As labels are part of the identity
+    /// of a series, this creates a new series every time the count changes, so it should only be
+    /// used while debugging, and never with a server that retains the series.  Disabled by
+    /// default.
+    pub fn with_sample_count_label(mut self, enabled: bool) -> Self {
+        self.sample_count_label = enabled;
+        self
+    }
+
+    /// Sets the name of the label holding the quantile when rendering summaries.
+    ///
+    /// Defaults to `quantile`.  This eases migrating dashboards which expect a different label,
+    /// such as `percentile`.
+    pub fn with_quantile_label_name(mut self, name: &str) -> Self {
+        self.quantile_label = name.to_owned();
+        self
+    }
+
+    /// Sets whether summary quantiles are linearly interpolated when rendering.
+    ///
+    /// By default, each quantile is rendered as a value that was actually recorded, which makes
+    /// quantiles of histograms with few samples move in coarse steps.  With interpolation, the
+    /// rendered value is interpolated between the two recorded values bracketing the quantile,
+    /// and may be fractional.  This is an approximation, so it is disabled by default.
+    ///
+    /// This only affects rendering, and not [`PrometheusRecorder::quantile`].
+    pub fn with_interpolated_quantiles(mut self, interpolate: bool) -> Self {
+        self.interpolate_quantiles = interpolate;
+        self
+    }
+
+    /// Sets a namespace which is prepended to the name of every metric, separated with an
+    /// underscore.
+    ///
+    /// Unlike wrapping the recorder to rename metrics, the namespace is applied to the family
+    /// name itself, so it is consistent across the metadata lines, such as `# TYPE`, and every
+    /// series of the family, including the derived `_sum`, `_count`, and `_bucket` series.
+    pub fn with_namespace(mut self, namespace: &str) -> Self {
+        self.namespace = Some(sanitize_metric_name(namespace).into_owned());
+        self
+    }
+
+    /// Sets the order in which labels are rendered.
+    ///
+    /// Labels with the given keys are rendered first, in the given order, followed by any other
+    /// labels in alphabetical order.  By default, all labels are rendered in alphabetical order.
+    /// The `quantile` and `le` labels added when rendering histograms always come last.
+    pub fn with_label_order(mut self, keys: &[&str]) -> Self {
+        self.label_order = keys.iter().map(|key| (*key).to_owned()).collect();
+        self
+    }
+
+    /// Limits label values to `bytes` when rendering.
+    ///
+    /// Longer values are cut down to at most `bytes`, on a character boundary, and marked with a
+    /// trailing `…`.  This protects backends from accidentally huge values, such as a stack trace
+    /// used as a label.  By default, label values are not limited.
+    pub fn with_max_label_length(mut self, bytes: usize) -> Self {
+        self.max_label_length = bytes;
+        self
+    }
+
+    /// Sets the quantiles used when rendering the histogram with the given name.
+    ///
+    /// Histograms without an override are rendered with the quantiles the recorder was created
+    /// with.  This allows trimming the number of series emitted for metrics that only need a
+    /// handful of quantiles, while keeping the full set for everything else.
+    pub fn set_quantiles_for<N: Into<String>>(&mut self, name: N, quantiles: &[f64]) {
+        let actual_quantiles = parse_quantiles(quantiles);
+        self.quantile_overrides
+            .insert(name.into(), actual_quantiles);
+    }
+
+    /// Sets the mode used when rendering histograms without a per-metric override.
+    ///
+    /// Defaults to [`HistogramMode::Summary`].
+    pub fn set_default_histogram_mode(&mut self, mode: HistogramMode) {
+        self.histogram_mode = mode;
+    }
+
+    /// Sets the mode used when rendering the histogram with the given name.
+    ///
+    /// This takes precedence over the default mode, which allows rendering some histograms as
+    /// summaries and others as native histograms from the same recorder.
+    pub fn set_histogram_mode<N: Into<String>>(&mut self, name: N, mode: HistogramMode) {
+        self.histogram_mode_overrides.insert(name.into(), mode);
+    }
 
-        for quantile in &self.quantiles {
-            let value = h.value_at_quantile(quantile.value());
-            self.output.push_str(label.as_str());
-            self.output.push_str("{quantile=\"");
-            self.output.push_str(quantile.value().to_string().as_str());
-            self.output.push_str("\"} ");
-            self.output.push_str(value.to_string().as_str());
-            self.output.push_str("\n");
+    /// Sets an info metric with the given name and labels.
+    ///
+    /// Info metrics are rendered as a gauge with a constant value of `1`, and are used to attach
+    /// metadata, such as the build version, via their labels.  Setting an info metric with the
+    /// same name again replaces its labels.
+    ///
+    /// Unlike other metrics, info metrics are kept by
+    /// [`PrometheusRecorder::fresh_with_same_config`], so they only need to be set once.
+    pub fn set_info<N, L>(&mut self, name: N, labels: L)
+    where
+        N: Into<String>,
+        L: IntoLabels,
+    {
+        let name = name.into();
+        let labels = labels.into_labels();
+        self.check_labels(&name, &labels);
+        if self.check_type(&name, MetricKind::Gauge) {
+            self.infos.insert(name, labels);
         }
-        self.output.push_str(label.as_str());
-        self.output.push_str("_sum ");
-        self.output.push_str(sum.to_string().as_str());
-        self.output.push_str("\n");
-        self.output.push_str(label.as_str());
-        self.output.push_str("_count ");
-        self.output.push_str(values.len().to_string().as_str());
-        self.output.push_str("\n");
     }
-}
 
-impl Clone for PrometheusRecorder {
-    fn clone(&self) -> Self {
+    /// Registers a gauge whose value is computed by `f` when the recorder is rendered.
+    ///
+    /// `f` is called at most once per render, and not at all if the recorder is never rendered,
+    /// which keeps the cost of gauges that are expensive to compute, such as those querying the
+    /// operating system, down to one computation per scrape.  The lazy gauge takes precedence
+    /// over any value recorded for the same key, and registering the same key again replaces
+    /// the previous callback.
+    ///
+    /// Like info metrics, lazy gauges are kept by [`PrometheusRecorder::fresh_with_same_config`].
+    pub fn register_lazy_gauge<K, F>(&mut self, key: K, f: F)
+    where
+        K: Into<Key>,
+        F: Fn() -> i64 + Send + Sync + 'static,
+    {
+        let key = key.into();
+        if self.check_key(&key, MetricKind::Gauge) {
+            self.lazy_gauges.insert(key, Arc::new(f));
+        }
+    }
+
+    /// Creates a new, empty [`PrometheusRecorder`] with the same configuration as this one.
+    ///
+    /// Quantiles, histogram modes, type checking, info metrics, and lazy gauges are kept, while
+    /// all recorded metrics are dropped.  This is useful for rendering a fresh set of metrics with
+    /// every snapshot.
+    pub fn fresh_with_same_config(&self) -> Self {
         Self {
-            output: get_prom_expo_header(),
             quantiles: self.quantiles.clone(),
+            quantile_overrides: self.quantile_overrides.clone(),
+            quantile_label: self.quantile_label.clone(),
+            interpolate_quantiles: self.interpolate_quantiles,
+            namespace: self.namespace.clone(),
+            label_order: self.label_order.clone(),
+            max_label_length: self.max_label_length,
+            histogram_mode: self.histogram_mode.clone(),
+            histogram_mode_overrides: self.histogram_mode_overrides.clone(),
+            histogram_bounds: self.histogram_bounds,
+            histogram_backend: self.histogram_backend,
+            max_histogram_memory: self.max_histogram_memory,
+            max_histogram_value: self.max_histogram_value,
+            last_value: self.last_value,
+            clipping_counters: self.clipping_counters,
+            extended_summary: self.extended_summary,
+            float_precision: self.float_precision,
+            sample_count_label: self.sample_count_label,
+            max_value: self.max_value,
+            scrape_duration_metric: self.scrape_duration_metric,
+            infos: self.infos.clone(),
+            lazy_gauges: self.lazy_gauges.clone(),
+            strict_types: self.strict_types,
+            strict_labels: self.strict_labels,
+            textfile_mode: self.textfile_mode,
+            // Info metrics and lazy gauges are kept, so their names stay declared as gauges.
+            types: self
+                .infos
+                .keys()
+                .map(String::as_str)
+                .chain(self.lazy_gauges.keys().map(Key::name))
+                .map(|name| (sanitize_metric_name(name).into_owned(), MetricKind::Gauge))
+                .collect(),
+            conflicts: BTreeMap::new(),
+            clock: self.clock.clone(),
+            updated: self.updated.as_ref().map(|_| HashMap::new()),
+            rates: HashMap::new(),
+            counters: HashMap::new(),
+            saturated_counters: HashSet::new(),
+            gauges: HashMap::new(),
+            peak_windows: HashMap::new(),
+            renders: RenderCount::default(),
+            timestamps: HashMap::new(),
+            histos: HashMap::new(),
+            signed_histos: HashMap::new(),
+            summaries: HashMap::new(),
+            shards: self.shards.as_ref().map(|shards| {
+                HistogramShards::new(
+                    shards.shard_count(),
+                    self.histogram_bounds,
+                    self.histogram_backend,
+                    self.max_histogram_value,
+                    self.max_value,
+                )
+            }),
         }
     }
-}
 
-impl Into<String> for PrometheusRecorder {
-    fn into(self) -> String {
-        self.output
+    /// Gets the quantiles this recorder was configured with.
+    ///
+    /// Per-metric overrides set via [`PrometheusRecorder::set_quantiles_for`] are not included.
+    pub fn quantiles(&self) -> &[Quantile] {
+        &self.quantiles
     }
-}
 
-fn get_prom_expo_header() -> String {
-    let ts = SystemTime::now()
-        .duration_since(SystemTime::UNIX_EPOCH)
-        .map(|d| d.as_secs())
-        .unwrap_or(0);
+    /// Gets a handle for recording histograms from many threads, if the recorder was configured
+    /// with [`PrometheusRecorder::with_shards`].
+    ///
+    /// Histograms recorded through the handle are checked against the types of other metrics
+    /// when the recorder is rendered, rather than when they're recorded.
+    pub fn histogram_shards(&self) -> Option<HistogramShards> {
+        self.shards.clone()
+    }
 
-    format!(
-        "# metrics snapshot (ts={}) (prometheus exposition format)",
-        ts
-    )
+    /// Gets the value at the given quantile for the histogram identified by `key`.
+    ///
+    /// The quantile should be between 0.0 and 1.0.  Returns `None` if no histogram has been
+    /// recorded for `key`.
+    pub fn quantile(&self, key: &Key, quantile: f64) -> Option<u64> {
+        self.histogram(key)
+            .map(|entry| entry.hist.value_at_quantile(quantile))
+    }
+
+    /// Gets the number of samples in the histogram identified by `key`.
+    ///
+    /// Returns `None` if no histogram has been recorded for `key`.
+    pub fn histogram_len(&self, key: &Key) -> Option<u64> {
+        self.histogram(key).map(|entry| entry.hist.len())
+    }
+
+    /// Gets the value at the given quantile for the signed histogram identified by `key`.
+    ///
+    /// The quantile should be between 0.0 and 1.0.  Returns `None` if no signed histogram has
+    /// been recorded for `key`.
+    pub fn signed_quantile(&self, key: &Key, quantile: f64) -> Option<i64> {
+        self.signed_histos
+            .get(key)
+            .map(|entry| entry.value_at_quantile(quantile))
+    }
+
+    /// Records a histogram value, and gets the value at the given quantile afterwards.
+    ///
+    /// The value is recorded and the quantile is read from the same histogram within a single
+    /// call, so the result always reflects the value that was just recorded, along with any values
+    /// recorded through [`PrometheusRecorder::histogram_shards`].  Since this takes `&mut self`,
+    /// no other recording can happen in between, other than through the shards.
+    ///
+    /// Returns `0` if the value was dropped because the name was already recorded as a different
+    /// type.
+    pub fn record_and_quantile<K: Into<Key>>(&mut self, key: K, value: u64, quantile: f64) -> u64 {
+        let key = key.into();
+        let (lowest, max) = (self.histogram_bounds.lowest, self.max_histogram_value);
+        let max_value = self.max_value;
+        let mut saturated = 0;
+        let recorded = match self.get_histogram(key.clone()) {
+            Some(entry) => {
+                let value = cap_value(value, 1, max_value, &mut saturated);
+                entry.record_n(value, 1, lowest, max);
+                true
+            }
+            None => false,
+        };
+        self.count_saturated(saturated);
+        if !recorded {
+            return 0;
+        }
+
+        self.histogram(&key)
+            .map_or(0, |entry| entry.hist.value_at_quantile(quantile))
+    }
+
+    /// Integrates a rate into a counter.
+    ///
+    /// The counter is incremented by `per_second` multiplied by the time elapsed since the rate
+    /// was last recorded for `key`, which bridges sources that only provide a rate into the
+    /// monotonic counter model.  The first recording for a key only starts the clock.  Fractions
+    /// of an increment are carried over to the next recording, and negative rates are treated as
+    /// zero, so the counter never goes down.
+    pub fn record_rate<K: Into<Key>>(&mut self, key: K, per_second: f64) {
+        let key = key.into();
+        if !self.check_key(&key, MetricKind::Counter) {
+            return;
+        }
+
+        let now = self.clock.get_or_insert_with(Clock::new).now();
+        let (last, carry) = self.rates.entry(key.clone()).or_insert((now, 0.0));
+        let elapsed = now.saturating_sub(*last) as f64 / 1_000_000_000.0;
+        let total = *carry + per_second.max(0.0) * elapsed;
+        let increment = total.floor();
+        *last = now;
+        *carry = total - increment;
+
+        let previous = self.counters.get(&key).copied().unwrap_or(0);
+        let value = self.saturate_counter(&key, previous.saturating_add(increment as u64));
+        self.counters.insert(key, value);
+    }
+
+    /// Records a histogram from pre-counted `(value, count)` pairs.
+    ///
+    /// Each pair is recorded as if `value` had been observed `count` times, without having to
+    /// expand the pairs into a slice of raw samples.  This is useful when merging in data that has
+    /// already been bucketed, such as the output of another histogram.
+    pub fn record_histogram_counts<K: Into<Key>>(&mut self, key: K, pairs: &[(u64, u64)]) {
+        let (lowest, max) = (self.histogram_bounds.lowest, self.max_histogram_value);
+        let max_value = self.max_value;
+        let mut saturated = 0;
+        if let Some(entry) = self.get_histogram(key.into()) {
+            for (value, count) in pairs {
+                let value = cap_value(*value, *count, max_value, &mut saturated);
+                entry.record_n(value, *count, lowest, max);
+            }
+        }
+        self.count_saturated(saturated);
+    }
+
+    /// Registers a counter, so that it is rendered as zero until it is first recorded.
+    ///
+    /// Initializing known series avoids gaps in dashboards, and makes `rate()` and `increase()`
+    /// see the first increment.  A counter that was already recorded is left as-is.
+    pub fn register_counter_zero<K: Into<Key>>(&mut self, key: K) {
+        let key = key.into();
+        if self.check_key(&key, MetricKind::Counter) {
+            self.counters.entry(key).or_insert(0);
+        }
+    }
+
+    /// Registers a gauge, so that it is rendered as zero until it is first recorded.
+    ///
+    /// A gauge that was already recorded is left as-is.
+    pub fn register_gauge_zero<K: Into<Key>>(&mut self, key: K) {
+        let key = key.into();
+        if self.check_key(&key, MetricKind::Gauge) {
+            self.gauges.entry(key).or_insert(0);
+        }
+    }
+
+    /// Registers a histogram, so that it is rendered before any value is recorded.
+    ///
+    /// An empty histogram is rendered with a zero `_sum` and `_count`, and, when rendered as
+    /// buckets, with every bucket at zero.  No quantiles are rendered until a value is recorded.
+    /// A histogram that was already recorded is left as-is.
+    pub fn register_histogram_empty<K: Into<Key>>(&mut self, key: K) {
+        self.get_histogram(key.into());
+    }
+
+    /// Gets the time the metric identified by `key` was last recorded.
+    ///
+    /// Returns `None` if it hasn't been recorded, or if update tracking isn't enabled with
+    /// [`PrometheusRecorder::with_update_tracking`].
+    pub fn last_updated(&self, key: &Key) -> Option<SystemTime> {
+        let elapsed = self.since_updated(key)?;
+        SystemTime::now().checked_sub(elapsed)
+    }
+
+    /// Checks whether the metric identified by `key` hasn't been recorded within the last
+    /// `within`.
+    ///
+    /// A metric that was never recorded is stale, and so is every metric if update tracking isn't
+    /// enabled with [`PrometheusRecorder::with_update_tracking`], so that a misconfigured check
+    /// fails rather than passing silently.
+    pub fn is_stale(&self, key: &Key, within: Duration) -> bool {
+        self.since_updated(key)
+            .is_none_or(|elapsed| elapsed > within)
+    }
+
+    // Gets the time elapsed since `key` was last recorded, according to the recorder's clock.
+    fn since_updated(&self, key: &Key) -> Option<Duration> {
+        let updated = *self.updated.as_ref()?.get(key)?;
+        let now = self.clock.as_ref()?.now();
+        Some(Duration::from_nanos(now.saturating_sub(updated)))
+    }
+
+    /// Clears everything recorded for `key`, leaving every other metric as-is.
+    ///
+    /// The counter, gauge, histogram, signed histogram, or precomputed summary identified by
+    /// `key` is removed, including histograms recorded through
+    /// [`PrometheusRecorder::histogram_shards`], so it isn't rendered until it is recorded again.
+    /// Info metrics and lazy gauges are configuration rather than recorded state, so they're kept.
+    /// The type the name was first recorded as is kept too, as other metrics with the same name
+    /// may still be recorded.
+    ///
+    /// Returns whether anything was recorded for `key`.
+    pub fn reset_metric(&mut self, key: &Key) -> bool {
+        self.timestamps.remove(key);
+        self.rates.remove(key);
+        self.saturated_counters.remove(key);
+        self.peak_windows.remove(key);
+        if let Some(updated) = &mut self.updated {
+            updated.remove(key);
+        }
+        let sharded = self
+            .shards
+            .as_ref()
+            .is_some_and(|shards| shards.remove(key));
+
+        let removed = [
+            self.counters.remove(key).is_some(),
+            self.gauges.remove(key).is_some(),
+            self.histos.remove(key).is_some(),
+            self.signed_histos.remove(key).is_some(),
+            self.summaries.remove(key).is_some(),
+        ];
+        sharded || removed.contains(&true)
+    }
+
+    /// Records the value of a counter as of the given time.
+    ///
+    /// This is the same as [`Recorder::record_counter`], except that the sample is rendered with
+    /// `timestamp` rather than being stamped by Prometheus when it is scraped, which allows
+    /// backfilling historical values.  Recording the counter again without a timestamp clears it.
+    ///
+    /// Timestamps are left out in textfile mode, as the textfile collector rejects them.
+    pub fn record_counter_at<K: Into<Key>>(&mut self, key: K, value: u64, timestamp: SystemTime) {
+        let key = key.into();
+        self.record_counter(key.clone(), value);
+        if self.counters.contains_key(&key) {
+            self.timestamps.insert(key, unix_millis(timestamp));
+        }
+    }
+
+    /// Records the value of a gauge as of the given time.
+    ///
+    /// This is the same as [`Recorder::record_gauge`], except that the sample is rendered with
+    /// `timestamp` rather than being stamped by Prometheus when it is scraped, which allows
+    /// backfilling historical values.  Recording the gauge again without a timestamp clears it.
+    ///
+    /// Timestamps are left out in textfile mode, as the textfile collector rejects them.
+    pub fn record_gauge_at<K: Into<Key>>(&mut self, key: K, value: i64, timestamp: SystemTime) {
+        let key = key.into();
+        self.record_gauge(key.clone(), value);
+        if self.gauges.contains_key(&key) {
+            self.timestamps.insert(key, unix_millis(timestamp));
+        }
+    }
+
+    /// Records a value for a gauge that reports the highest value seen.
+    ///
+    /// The gauge is only updated if `value` is higher than its current value, which gives a peak,
+    /// such as the most concurrent connections, rather than whatever the value happened to be at
+    /// scrape time.  Every render starts a new window: the peak is rendered until the next value
+    /// is recorded, which replaces it, so each scrape reports the peak since the previous one.
+    /// Setting the gauge with [`Recorder::record_gauge`] sets the current value of the window.
+    pub fn record_gauge_max<K: Into<Key>>(&mut self, key: K, value: i64) {
+        self.record_gauge_peak(key.into(), value, i64::max);
+    }
+
+    /// Records a value for a gauge that reports the lowest value seen.
+    ///
+    /// This is the counterpart of [`PrometheusRecorder::record_gauge_max`], and only updates the
+    /// gauge if `value` is lower than its current value within the window of the current render.
+    pub fn record_gauge_min<K: Into<Key>>(&mut self, key: K, value: i64) {
+        self.record_gauge_peak(key.into(), value, i64::min);
+    }
+
+    fn record_gauge_peak(&mut self, key: Key, value: i64, peak: fn(i64, i64) -> i64) {
+        let render = self.renders.get();
+        let current = match self.peak_windows.get(&key) {
+            Some(start) if *start == render => self.gauges.get(&key).copied(),
+            _ => None,
+        };
+        self.record_gauge(
+            key.clone(),
+            current.map_or(value, |current| peak(current, value)),
+        );
+        if self.gauges.contains_key(&key) {
+            self.peak_windows.insert(key, render);
+        }
+    }
+
+    /// Merges a histogram serialized in the HdrHistogram V2 format into the histogram for `key`.
+    ///
+    /// This allows aggregating histograms recorded by other processes, which can serialize them
+    /// with `hdrhistogram::serialization::V2Serializer`.  Both the plain and the deflate-compressed
+    /// V2 formats are accepted.  The serialized histogram doesn't have to use the same bounds as
+    /// this recorder: its values are recorded as if they had been observed here, so they are
+    /// clamped and counted as underflow or overflow the same way.
+    ///
+    /// The serialized format only stores bucketed counts, so the sum of the merged values is
+    /// computed from the highest value of each bucket, and is only exact for values which fall
+    /// within the precision of the serialized histogram.
+    pub fn merge_serialized_histogram<K: Into<Key>>(
+        &mut self,
+        key: K,
+        bytes: &[u8],
+    ) -> Result<(), HistogramMergeError> {
+        let source: Histogram<u64> = Deserializer::new()
+            .deserialize(&mut &bytes[..])
+            .map_err(HistogramMergeError)?;
+        let pairs = source
+            .iter_recorded()
+            .map(|v| (v.value_iterated_to(), v.count_at_value()))
+            .collect::<Vec<_>>();
+
+        self.record_histogram_counts(key, &pairs);
+        Ok(())
+    }
+
+    /// Loads the metrics of a snapshot into this recorder.
+    ///
+    /// This is meant for combining metrics from other sources, such as a snapshot restored from
+    /// disk or one received from a remote worker.  Unlike recording the snapshot directly, which
+    /// would replace the value of every counter, counter values are added to the current values.
+    /// Gauges take the value from the snapshot, and histogram values are merged into the current
+    /// histograms, with repeated values being recorded once with their count.
+    pub fn load_snapshot<S: Snapshot>(&mut self, snapshot: &S) {
+        snapshot.record(&mut SnapshotLoader(self));
+    }
+
+    /// Records histogram values which can be negative.
+    ///
+    /// Signed histograms are for naturally signed measurements, such as temperature deltas or
+    /// profit and loss.  As the underlying histograms can only track non-negative values, each
+    /// signed histogram is kept as two histograms: one for the non-negative values, and one for
+    /// the magnitudes of the negative values.  Quantiles are computed across both, with the
+    /// negative half mirrored, so the 0.0 quantile is the most negative value.
+    ///
+    /// Signed histograms are always rendered as summaries, with the configured quantiles, as
+    /// there is no way to configure buckets below zero.  A name can't be recorded both as a
+    /// signed and an unsigned histogram.  Magnitudes are saturated to the largest value that
+    /// can be tracked, just like unsigned values, while the sum is computed from the original
+    /// values.
+    pub fn record_signed_histogram<K: Into<Key>>(&mut self, key: K, values: &[i64]) {
+        let key = key.into();
+        if !self.check_key(&key, MetricKind::SignedHistogram) {
+            return;
+        }
+
+        let (bounds, max) = (self.histogram_bounds, self.max_histogram_value);
+        let entry = self.signed_histos.entry(key).or_insert_with(|| {
+            let new =
+                || new_histogram(bounds).expect("histogram bounds are validated when configured");
+            SignedHistogramEntry {
+                sum: 0,
+                positive: new(),
+                negative: new(),
+            }
+        });
+        for value in values {
+            let hist = if *value < 0 {
+                &mut entry.negative
+            } else {
+                &mut entry.positive
+            };
+            hist.record(value.unsigned_abs().min(max))
+                .expect("failed to record histogram value");
+            entry.sum += i128::from(*value);
+        }
+    }
+
+    /// Records a summary whose quantiles have already been computed.
+    ///
+    /// This is for metrics which were aggregated elsewhere, such as by a client which only
+    /// reports percentiles, where the original values aren't available to build a histogram
+    /// from.  `quantiles` holds `(quantile, value)` pairs, which are rendered as-is, sorted by
+    /// quantile, along with the given `sum` and `count`.  Each recording replaces the previous
+    /// summary for `key`, as precomputed quantiles can't be merged.
+    ///
+    /// Precomputed summaries are stored separately from histograms, so a name can't be recorded
+    /// as both.
+    pub fn record_precomputed_summary<K: Into<Key>>(
+        &mut self,
+        key: K,
+        quantiles: &[(f64, u64)],
+        sum: u64,
+        count: u64,
+    ) {
+        let key = key.into();
+        if !self.check_key(&key, MetricKind::PrecomputedSummary) {
+            return;
+        }
+
+        let mut quantiles = quantiles.to_vec();
+        quantiles.sort_by(|a, b| a.0.total_cmp(&b.0));
+        let summary = PrecomputedSummary {
+            quantiles,
+            sum,
+            count,
+        };
+        self.summaries.insert(key, summary);
+    }
+
+    fn get_histogram(&mut self, key: Key) -> Option<&mut HistogramEntry> {
+        if !self.check_key(&key, MetricKind::Histogram) {
+            return None;
+        }
+
+        let (bounds, backend) = (self.histogram_bounds, self.histogram_backend);
+        let entry = self
+            .histos
+            .entry(key)
+            .or_insert_with(|| new_histogram_entry(bounds, backend));
+        Some(entry)
+    }
+
+    // Saturates the total of the counter `key` to the maximum value, counting the counter as
+    // saturated if its total exceeds it and it wasn't saturated already.
+    fn saturate_counter(&mut self, key: &Key, value: u64) -> u64 {
+        let max_value = self.max_value.filter(|max| value > *max);
+        match max_value {
+            Some(max) => {
+                if self.saturated_counters.insert(key.clone()) {
+                    self.count_saturated(1);
+                }
+                max
+            }
+            None => {
+                self.saturated_counters.remove(key);
+                value
+            }
+        }
+    }
+
+    // Adds `count` values to the counter of values saturated to the maximum value.
+    fn count_saturated(&mut self, count: u64) {
+        if count == 0 {
+            return;
+        }
+
+        let key = Key::from_name(SATURATED_METRIC);
+        if self.check_key(&key, MetricKind::Counter) {
+            let counter = self.counters.entry(key).or_insert(0);
+            *counter = counter.saturating_add(count);
+        }
+    }
+
+    fn update_max_histogram_value(&mut self) {
+        let bounds = self.histogram_bounds;
+        let max = self
+            .max_histogram_memory
+            .map_or(u64::MAX, |bytes| max_value_for_memory(bytes, bounds));
+        self.max_histogram_value = max.min(bounds.highest.unwrap_or(u64::MAX));
+        self.update_shards();
+    }
+
+    // Recreates the shards, if any, so that they use the current histogram configuration.
+    fn update_shards(&mut self) {
+        if let Some(shards) = &self.shards {
+            self.shards = Some(HistogramShards::new(
+                shards.shard_count(),
+                self.histogram_bounds,
+                self.histogram_backend,
+                self.max_histogram_value,
+                self.max_value,
+            ));
+        }
+    }
+
+    // Gets the histogram for `key`, merged with the one recorded through the shards, if any.
+    fn histogram(&self, key: &Key) -> Option<Cow<'_, HistogramEntry>> {
+        let direct = self.histos.get(key);
+        let merged = self.shards.as_ref().and_then(|shards| {
+            shards.with_entry(key, |entry| {
+                if self.shard_conflict(key).is_some() {
+                    return None;
+                }
+
+                let (bounds, backend) = (self.histogram_bounds, self.histogram_backend);
+                let mut merged = direct
+                    .cloned()
+                    .unwrap_or_else(|| new_histogram_entry(bounds, backend));
+                merged.merge(entry);
+                Some(merged)
+            })
+        });
+        match merged.flatten() {
+            Some(merged) => Some(Cow::Owned(merged)),
+            None => direct.map(Cow::Borrowed),
+        }
+    }
+
+    // Merges the histograms recorded through the shards, if any, with copies of the histograms
+    // recorded directly for the same keys, leaving the rest of the recorder untouched.
+    fn sharded(&self) -> ShardedHistograms {
+        let mut sharded = ShardedHistograms::default();
+        let shards = match &self.shards {
+            Some(shards) => shards,
+            None => return sharded,
+        };
+
+        let (bounds, backend) = (self.histogram_bounds, self.histogram_backend);
+        shards.for_each(|key, entry| {
+            self.check_labels(key.name(), key.labels());
+            if let Some(declared) = self.shard_conflict(key) {
+                if self.strict_types {
+                    panic!(
+                        "metric `{}` recorded as a {}, but was previously recorded as a {}",
+                        key.name(),
+                        MetricKind::Histogram,
+                        declared
+                    );
+                }
+                let sanitized = sanitize_metric_name(key.name()).into_owned();
+                sharded
+                    .conflicts
+                    .entry(sanitized)
+                    .or_insert((declared, MetricKind::Histogram));
+                return;
+            }
+
+            sharded
+                .histos
+                .entry(key.clone())
+                .or_insert_with(|| {
+                    self.histos
+                        .get(key)
+                        .cloned()
+                        .unwrap_or_else(|| new_histogram_entry(bounds, backend))
+                })
+                .merge(entry);
+            sharded.saturated = sharded.saturated.saturating_add(entry.saturated);
+        });
+        sharded
+    }
+
+    // Gets the type the name of `key` was declared as, if a histogram recorded for it through the
+    // shards conflicts with it.
+    fn shard_conflict(&self, key: &Key) -> Option<MetricKind> {
+        let sanitized = sanitize_metric_name(key.name());
+        self.types
+            .get(sanitized.as_ref())
+            .copied()
+            .filter(|declared| *declared != MetricKind::Histogram)
+    }
+
+    // Checks the labels of `key`, and that it is being recorded as the same type it was first
+    // recorded as, tracking it as updated if so.
+    fn check_key(&mut self, key: &Key, kind: MetricKind) -> bool {
+        self.check_labels(key.name(), key.labels());
+        if !self.check_type(key.name(), kind) {
+            return false;
+        }
+
+        if let Some(updated) = &mut self.updated {
+            let now = self.clock.get_or_insert_with(Clock::new).now();
+            match updated.get_mut(key) {
+                Some(last) => *last = now,
+                None => {
+                    updated.insert(key.clone(), now);
+                }
+            }
+        }
+        true
+    }
+
+    // Checks that no two labels have the same key, when strict label checking is enabled.
+    fn check_labels<'a, I>(&self, name: &str, labels: I)
+    where
+        I: IntoIterator<Item = &'a Label>,
+    {
+        if !self.strict_labels {
+            return;
+        }
+
+        let mut seen = Vec::new();
+        for label in labels {
+            if seen.contains(&label.key()) {
+                panic!(
+                    "metric `{}` has multiple labels with the key `{}`",
+                    name,
+                    label.key()
+                );
+            }
+            seen.push(label.key());
+        }
+    }
+
+    // Checks that `name` is being recorded as the same type it was first recorded as.
+    fn check_type(&mut self, name: &str, kind: MetricKind) -> bool {
+        let sanitized = sanitize_metric_name(name).into_owned();
+        let declared = *self.types.entry(sanitized.clone()).or_insert(kind);
+        if declared == kind {
+            return true;
+        }
+
+        if self.strict_types {
+            panic!(
+                "metric `{}` recorded as a {}, but was previously recorded as a {}",
+                name, kind, declared
+            );
+        }
+        self.conflicts.entry(sanitized).or_insert((declared, kind));
+        false
+    }
+}
+
+impl Recorder for PrometheusRecorder {
+    fn record_counter<K: Into<Key>>(&mut self, key: K, value: u64) {
+        let key = key.into();
+        if self.check_key(&key, MetricKind::Counter) {
+            let value = self.saturate_counter(&key, value);
+            let previous = self.counters.get(&key).copied().unwrap_or(0);
+            warn_on_precision_loss(&key, previous, value);
+            self.timestamps.remove(&key);
+            self.counters.insert(key, value);
+        }
+    }
+
+    fn record_gauge<K: Into<Key>>(&mut self, key: K, value: i64) {
+        let key = key.into();
+        if self.check_key(&key, MetricKind::Gauge) {
+            let previous = self.gauges.get(&key).copied().unwrap_or(0);
+            warn_on_precision_loss(&key, previous.unsigned_abs(), value.unsigned_abs());
+            self.timestamps.remove(&key);
+            self.gauges.insert(key, value);
+        }
+    }
+
+    fn record_gauge_add<K: Into<Key>>(&mut self, key: K, delta: i64) {
+        let key = key.into();
+        let value = self.gauges.get(&key).copied().unwrap_or(0);
+        self.record_gauge(key, value.saturating_add(delta));
+    }
+
+    fn supports_gauge_add(&self) -> bool {
+        true
+    }
+
+    fn record_histogram<K: Into<Key>>(&mut self, key: K, values: &[u64]) {
+        let (lowest, max) = (self.histogram_bounds.lowest, self.max_histogram_value);
+        let max_value = self.max_value;
+        let mut saturated = 0;
+        if let Some(entry) = self.get_histogram(key.into()) {
+            for value in values {
+                let value = cap_value(*value, 1, max_value, &mut saturated);
+                entry.record_n(value, 1, lowest, max);
+            }
+        }
+        self.count_saturated(saturated);
+    }
+
+    fn record_histogram_sampled<K: Into<Key>>(&mut self, key: K, values: &[u64], rate: f64) {
+        let weight = sample_weight(rate);
+        let pairs = values
+            .iter()
+            .map(|value| (*value, weight))
+            .collect::<Vec<_>>();
+        self.record_histogram_counts(key, &pairs);
+    }
+}
+
+// Applies a snapshot to a recorder, summing counters rather than replacing them.
+struct SnapshotLoader<'a>(&'a mut PrometheusRecorder);
+
+impl<'a> Recorder for SnapshotLoader<'a> {
+    fn record_counter<K: Into<Key>>(&mut self, key: K, value: u64) {
+        let key = key.into();
+        let value = self
+            .0
+            .counters
+            .get(&key)
+            .copied()
+            .unwrap_or(0)
+            .saturating_add(value);
+        self.0.record_counter(key, value);
+    }
+
+    fn record_gauge<K: Into<Key>>(&mut self, key: K, value: i64) {
+        self.0.record_gauge(key, value);
+    }
+
+    fn record_gauge_add<K: Into<Key>>(&mut self, key: K, delta: i64) {
+        self.0.record_gauge_add(key, delta);
+    }
+
+    fn supports_gauge_add(&self) -> bool {
+        true
+    }
+
+    fn record_histogram<K: Into<Key>>(&mut self, key: K, values: &[u64]) {
+        let mut values = values.to_vec();
+        values.sort_unstable();
+
+        let mut pairs: Vec<(u64, u64)> = Vec::new();
+        for value in values {
+            match pairs.last_mut() {
+                Some((last, count)) if *last == value => *count += 1,
+                _ => pairs.push((value, 1)),
+            }
+        }
+        self.0.record_histogram_counts(key, &pairs);
+    }
+}
+
+impl Default for PrometheusRecorder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Display for PrometheusRecorder {
+    /// Renders the recorded metrics in the text exposition format, without consuming the recorder.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.render_to(f, ContentType::Text)
+    }
+}
+
+impl From<PrometheusRecorder> for String {
+    fn from(val: PrometheusRecorder) -> Self {
+        let mut output = String::new();
+        val.render_into(&mut output);
+        output
+    }
+}
+
+/// The type of the metric family a [`Series`] belongs to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum SeriesKind {
+    /// A counter.
+    Counter,
+
+    /// A gauge.
+    Gauge,
+
+    /// A summary, rendered from a histogram using quantiles.
+    Summary,
+
+    /// A native histogram, rendered from a histogram using buckets.
+    Histogram,
+}
+
+impl SeriesKind {
+    /// Gets the name of this type, as used in `# TYPE` lines.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            SeriesKind::Counter => "counter",
+            SeriesKind::Gauge => "gauge",
+            SeriesKind::Summary => "summary",
+            SeriesKind::Histogram => "histogram",
+        }
+    }
+}
+
+/// The value of a [`Series`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SeriesValue {
+    /// An exact integer value.
+    Integer(i128),
+
+    /// A fractional value, such as an interpolated quantile.
+    Float(f64),
+}
+
+impl fmt::Display for SeriesValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SeriesValue::Integer(value) => write!(f, "{}", value),
+            SeriesValue::Float(value) => write!(f, "{}", value),
+        }
+    }
+}
+
+impl SeriesValue {
+    /// Gets this value as a float, which is lossy for integers beyond 2^53.
+    pub fn as_f64(self) -> f64 {
+        match self {
+            SeriesValue::Integer(value) => value as f64,
+            SeriesValue::Float(value) => value,
+        }
+    }
+}
+
+/// A single series, as it would be rendered.
+///
+/// Created by [`PrometheusRecorder::series`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Series {
+    name: String,
+    labels: Vec<(String, String)>,
+    kind: SeriesKind,
+    value: SeriesValue,
+    timestamp: Option<u64>,
+}
+
+impl Series {
+    /// Gets the name of this series, including any suffix such as `_sum` or `_bucket`.
+    pub fn name(&self) -> &str {
+        self.name.as_str()
+    }
+
+    /// Gets the labels of this series, as unescaped key/value pairs in rendering order.
+    pub fn labels(&self) -> &[(String, String)] {
+        &self.labels
+    }
+
+    /// Gets the type of the metric family this series belongs to.
+    pub fn kind(&self) -> SeriesKind {
+        self.kind
+    }
+
+    /// Gets the value of this series.
+    pub fn value(&self) -> SeriesValue {
+        self.value
+    }
+
+    /// Gets the timestamp of this series, in milliseconds since the Unix epoch, if it has one.
+    pub fn timestamp(&self) -> Option<u64> {
+        self.timestamp
+    }
+}
+
+type LabelPairs = Vec<(String, String)>;
+
+// A single metric within a family, waiting to be expanded into its series.
+enum Entry<'a> {
+    Counter(u64, Option<u64>),
+    Gauge(i64, Option<u64>),
+    Stat(f64),
+    Summary(&'a [Quantile], u128, &'a HistogramStorage),
+    Buckets(&'a [f64], u128, &'a HistogramStorage),
+    SignedSummary(&'a [Quantile], &'a SignedHistogramEntry),
+    PrecomputedSummary(&'a PrecomputedSummary),
+}
+
+type Families<'a> = BTreeMap<(String, SeriesKind), Vec<(LabelPairs, Entry<'a>)>>;
+
+// Histograms recorded through the shards, merged with the ones recorded directly for the same
+// keys, which are rendered in place of the latter.
+#[derive(Default)]
+struct ShardedHistograms {
+    histos: HashMap<Key, HistogramEntry>,
+    saturated: u64,
+    conflicts: BTreeMap<String, (MetricKind, MetricKind)>,
+}
+
+impl PrometheusRecorder {
+    // Groups every metric by family, so that each family is rendered as one contiguous block
+    // under a single TYPE line, with its metrics sorted by their labels.
+    fn families<'a>(&'a self, sharded: &'a ShardedHistograms) -> Families<'a> {
+        self.renders.increment();
+        let mut families = Families::new();
+        let mut push = |key: &Key, kind, entry| {
+            let (name, labels) = key_to_parts(key, &self.label_order, self.max_label_length);
+            let name = match &self.namespace {
+                Some(namespace) => format!("{}_{}", namespace, name),
+                None => name,
+            };
+            families
+                .entry((name, kind))
+                .or_default()
+                .push((labels, entry));
+        };
+
+        // Values saturated through the shards are added to the counter of saturated values.
+        let saturated_key = Key::from_name(SATURATED_METRIC);
+        let mut saturated = Some(sharded.saturated).filter(|count| {
+            *count > 0
+                && self
+                    .types
+                    .get(SATURATED_METRIC)
+                    .is_none_or(|kind| *kind == MetricKind::Counter)
+        });
+        for (key, value) in &self.counters {
+            let mut value = *value;
+            if *key == saturated_key {
+                value = value.saturating_add(saturated.take().unwrap_or(0));
+            }
+            let timestamp = self.timestamps.get(key).copied();
+            push(key, SeriesKind::Counter, Entry::Counter(value, timestamp));
+        }
+        if let Some(count) = saturated {
+            push(
+                &saturated_key,
+                SeriesKind::Counter,
+                Entry::Counter(count, None),
+            );
+        }
+
+        for (key, value) in &self.gauges {
+            if !self.lazy_gauges.contains_key(key) {
+                let timestamp = self.timestamps.get(key).copied();
+                push(key, SeriesKind::Gauge, Entry::Gauge(*value, timestamp));
+            }
+        }
+
+        // Families are built once per render, so each callback is only called once per render.
+        for (key, f) in &self.lazy_gauges {
+            push(key, SeriesKind::Gauge, Entry::Gauge(f(), None));
+        }
+
+        for (name, labels) in &self.infos {
+            let key = Key::from_name_and_labels(name.clone(), labels.clone());
+            push(&key, SeriesKind::Gauge, Entry::Gauge(1, None));
+        }
+
+        let histos = self
+            .histos
+            .iter()
+            .filter(|(key, _)| !sharded.histos.contains_key(*key))
+            .chain(&sharded.histos);
+        for (key, entry) in histos {
+            let (sum, hist) = (&entry.sum, &entry.hist);
+            if let (true, Some(last)) = (self.last_value, entry.last) {
+                let key = key.clone().map_name(|name| format!("{}_last", name));
+                let value = i64::try_from(last).unwrap_or(i64::MAX);
+                push(&key, SeriesKind::Gauge, Entry::Gauge(value, None));
+            }
+
+            if self.clipping_counters {
+                let clipped = [("underflow", entry.underflow), ("overflow", entry.overflow)];
+                for (suffix, count) in clipped.iter() {
+                    let key = key
+                        .clone()
+                        .map_name(|name| format!("{}_{}_total", name, suffix));
+                    push(&key, SeriesKind::Counter, Entry::Counter(*count, None));
+                }
+            }
+
+            if self.extended_summary && !hist.is_empty() {
+                let stats = [
+                    ("min", hist.min() as f64),
+                    ("max", hist.max() as f64),
+                    ("mean", hist.mean()),
+                    ("stddev", hist.stdev()),
+                ];
+                for (suffix, value) in stats.iter() {
+                    let key = key.clone().map_name(|name| format!("{}_{}", name, suffix));
+                    push(&key, SeriesKind::Gauge, Entry::Stat(*value));
+                }
+            }
+
+            let mode = self
+                .histogram_mode_overrides
+                .get(key.name())
+                .unwrap_or(&self.histogram_mode);
+            match mode {
+                HistogramMode::Summary => {
+                    let quantiles = self
+                        .quantile_overrides
+                        .get(key.name())
+                        .unwrap_or(&self.quantiles);
+                    let entry = Entry::Summary(quantiles, *sum, hist);
+                    push(key, SeriesKind::Summary, entry);
+                }
+                HistogramMode::Buckets(buckets) => {
+                    let entry = Entry::Buckets(buckets, *sum, hist);
+                    push(key, SeriesKind::Histogram, entry);
+                }
+            }
+        }
+
+        for (key, entry) in &self.signed_histos {
+            let quantiles = self
+                .quantile_overrides
+                .get(key.name())
+                .unwrap_or(&self.quantiles);
+            push(
+                key,
+                SeriesKind::Summary,
+                Entry::SignedSummary(quantiles, entry),
+            );
+        }
+
+        for (key, summary) in &self.summaries {
+            push(key, SeriesKind::Summary, Entry::PrecomputedSummary(summary));
+        }
+
+        for entries in families.values_mut() {
+            entries.sort_by(|a, b| a.0.cmp(&b.0));
+        }
+        if self.textfile_mode {
+            for dropped in dedup_textfile(&mut families) {
+                warn!("{} in textfile mode, so it was dropped", dropped);
+            }
+        }
+
+        families
+    }
+
+    fn float(&self, value: f64) -> SeriesValue {
+        match self.float_precision {
+            Some(digits) if value.is_finite() => {
+                // Formatting in scientific notation rounds to the given number of significant
+                // digits, and parsing it back lets `Display` drop any trailing zeros.
+                let rounded = format!("{:.*e}", digits - 1, value);
+                SeriesValue::Float(rounded.parse().unwrap_or(value))
+            }
+            _ => SeriesValue::Float(value),
+        }
+    }
+
+    fn quantile_labels(&self, labels: &LabelPairs, quantile: f64, count: u64) -> LabelPairs {
+        let mut labels = labels.clone();
+        labels.push((self.quantile_label.clone(), quantile.to_string()));
+        if self.sample_count_label {
+            labels.push(("n".to_owned(), count.to_string()));
+        }
+        labels
+    }
+
+    // Expands a metric into its series, as `(name suffix, labels, value)`.
+    fn samples(
+        &self,
+        labels: LabelPairs,
+        entry: Entry<'_>,
+    ) -> Vec<(&'static str, LabelPairs, SeriesValue)> {
+        let mut samples = Vec::new();
+        match entry {
+            Entry::Counter(value, _) => {
+                samples.push(("", labels, SeriesValue::Integer(value.into())))
+            }
+            Entry::Gauge(value, _) => {
+                samples.push(("", labels, SeriesValue::Integer(value.into())))
+            }
+            Entry::Stat(value) => samples.push(("", labels, self.float(value))),
+            Entry::Summary(quantiles, sum, hist) => {
+                // Quantiles of an empty histogram would all be rendered as zero, which is
+                // misleading, so only the sum and count are rendered.
+                let quantiles = if hist.is_empty() { &[][..] } else { quantiles };
+                for quantile in quantiles {
+                    let value = if self.interpolate_quantiles {
+                        self.float(hist.interpolated_quantile(quantile.value()))
+                    } else {
+                        SeriesValue::Integer(hist.value_at_quantile(quantile.value()).into())
+                    };
+                    let qlabels = self.quantile_labels(&labels, quantile.value(), hist.len());
+                    samples.push(("", qlabels, value));
+                }
+                samples.extend(sum_and_count(labels, sum, hist));
+            }
+            Entry::Buckets(buckets, sum, hist) => {
+                let bounds = buckets
+                    .iter()
+                    .map(|b| (b.to_string(), hist.bucket_count(*b)))
+                    .chain(Some(("+Inf".to_owned(), hist.len())));
+                for (bound, count) in bounds {
+                    let mut blabels = labels.clone();
+                    blabels.push(("le".to_owned(), bound));
+                    samples.push(("_bucket", blabels, SeriesValue::Integer(count.into())));
+                }
+                samples.extend(sum_and_count(labels, sum, hist));
+            }
+            Entry::SignedSummary(quantiles, entry) => {
+                let quantiles = if entry.len() == 0 { &[][..] } else { quantiles };
+                for quantile in quantiles {
+                    let value = entry.value_at_quantile(quantile.value());
+                    let qlabels = self.quantile_labels(&labels, quantile.value(), entry.len());
+                    samples.push(("", qlabels, SeriesValue::Integer(value.into())));
+                }
+                samples.push(("_sum", labels.clone(), SeriesValue::Integer(entry.sum)));
+                samples.push(("_count", labels, SeriesValue::Integer(entry.len().into())));
+            }
+            Entry::PrecomputedSummary(summary) => {
+                for (quantile, value) in &summary.quantiles {
+                    let qlabels = self.quantile_labels(&labels, *quantile, summary.count);
+                    samples.push(("", qlabels, SeriesValue::Integer((*value).into())));
+                }
+                let (sum, count) = (summary.sum.into(), summary.count.into());
+                samples.push(("_sum", labels.clone(), SeriesValue::Integer(sum)));
+                samples.push(("_count", labels, SeriesValue::Integer(count)));
+            }
+        }
+        samples
+    }
+
+    /// Gets every series that would be rendered, in the order they would be rendered in.
+    ///
+    /// This is the structured counterpart of rendering the recorder, for transforming metrics
+    /// into other formats without parsing the rendered output.  Histograms yield multiple series:
+    /// one per quantile or bucket, followed by their `_sum` and `_count`.  Names and labels are
+    /// the same as in the text exposition format, but label values are not escaped.  Rendered
+    /// output can be read back into series with [`parse_exposition`].
+    pub fn series(&self) -> impl Iterator<Item = Series> {
+        let sharded = self.sharded();
+        let mut series = Vec::new();
+        for ((name, kind), entries) in self.families(&sharded) {
+            for (labels, entry) in entries {
+                let timestamp = match entry {
+                    Entry::Counter(_, Some(ts)) | Entry::Gauge(_, Some(ts))
+                        if !self.textfile_mode =>
+                    {
+                        Some(ts)
+                    }
+                    _ => None,
+                };
+                for (suffix, labels, value) in self.samples(labels, entry) {
+                    series.push(Series {
+                        name: format!("{}{}", name, suffix),
+                        labels,
+                        kind,
+                        value,
+                        timestamp,
+                    });
+                }
+            }
+        }
+        series.into_iter()
+    }
+
+    /// Renders the metric families with a name matching `pattern` in the text exposition format.
+    ///
+    /// The pattern is matched against the family name as it is rendered, including any namespace
+    /// and with dots replaced by underscores, and `*` matches any sequence of characters, so
+    /// `http_*` matches every family starting with `http_`.  Matching families are rendered
+    /// exactly as in the full output, including the `_sum`, `_count`, and `_bucket` series of
+    /// histograms.  This is useful for debugging endpoints that only return a few metrics out of
+    /// a large set.
+    pub fn render_filtered(&self, pattern: &str) -> String {
+        let mut output = String::new();
+        self.render_matching(&mut output, ContentType::Text, |name| {
+            matches_pattern(pattern, name)
+        })
+        .expect("writing to a string cannot fail");
+        output
+    }
+
+    /// Renders the recorded metrics in the text exposition format, along with any problems that
+    /// affect the output.
+    ///
+    /// Problems such as names that had to be sanitized, or duplicate label keys, are silently
+    /// worked around when rendering, and recordings with a conflicting type are dropped.  This
+    /// reports them as [`RenderWarning`]s, so the quality of the output can be checked, for
+    /// example in tests or at startup.  Warnings that the configured strict checks reject, such
+    /// as type conflicts with [`PrometheusRecorder::with_strict_types`], are fatal, in which case
+    /// an error with every warning is returned instead of the output.
+    pub fn try_render(&self) -> Result<(String, Vec<RenderWarning>), Vec<RenderWarning>> {
+        let start = Instant::now();
+        let sharded = self.sharded();
+        let warnings = self.render_warnings(&sharded);
+        if warnings
+            .iter()
+            .any(|warning| warning.is_fatal(self.strict_types, self.strict_labels))
+        {
+            return Err(warnings);
+        }
+
+        let mut output = String::with_capacity(self.estimated_len());
+        self.render_sharded(&mut output, ContentType::Text, &sharded, |_| true, start)
+            .expect("writing to a string cannot fail");
+        Ok((output, warnings))
+    }
+
+    fn render_warnings(&self, sharded: &ShardedHistograms) -> Vec<RenderWarning> {
+        let keys = self
+            .counters
+            .keys()
+            .chain(self.gauges.keys())
+            .chain(self.lazy_gauges.keys())
+            .chain(self.histos.keys())
+            .chain(sharded.histos.keys())
+            .chain(self.signed_histos.keys())
+            .chain(self.summaries.keys());
+        let infos = self
+            .infos
+            .iter()
+            .map(|(name, labels)| (name.as_str(), labels.iter().collect::<Vec<_>>()));
+        let metrics = keys
+            .map(|key| (key.name(), key.labels().collect::<Vec<_>>()))
+            .chain(infos);
+
+        let mut warnings = BTreeSet::new();
+        for (name, labels) in metrics {
+            // Dots are the usual separator in names, and are always rendered as underscores.
+            let dotless = name.replace('.', "_");
+            if let Cow::Owned(sanitized) = sanitize_metric_name(&dotless) {
+                let name = name.to_owned();
+                warnings.insert(RenderWarning::SanitizedName { name, sanitized });
+            }
+            for (i, label) in labels.iter().enumerate() {
+                if labels[..i].iter().any(|other| other.key() == label.key()) {
+                    warnings.insert(RenderWarning::DuplicateLabel {
+                        name: name.to_owned(),
+                        label: label.key().to_owned(),
+                    });
+                }
+            }
+        }
+
+        for (name, (declared, recorded)) in self.conflicts.iter().chain(&sharded.conflicts) {
+            warnings.insert(RenderWarning::TypeConflict {
+                name: name.clone(),
+                declared: declared.to_string(),
+                recorded: recorded.to_string(),
+            });
+        }
+
+        warnings.into_iter().collect()
+    }
+
+    /// Renders the recorded metrics in the text exposition format, appending them to `buf`.
+    ///
+    /// The buffer is grown to fit the estimated length of the output before rendering, so that it
+    /// isn't reallocated repeatedly as it fills up.  Reusing the same buffer across scrapes, by
+    /// clearing it in between, avoids allocating the output altogether once it is large enough.
+    pub fn render_into(&self, buf: &mut String) {
+        buf.reserve(self.estimated_len());
+        self.render_to(buf, ContentType::Text)
+            .expect("writing to a string cannot fail");
+    }
+
+    // Estimates the length of the rendered output from the number of series to render.
+    fn estimated_len(&self) -> usize {
+        let histogram_series = self
+            .histos
+            .keys()
+            .map(|key| {
+                let mode = self
+                    .histogram_mode_overrides
+                    .get(key.name())
+                    .unwrap_or(&self.histogram_mode);
+                match mode {
+                    HistogramMode::Summary => self.quantiles.len() + 2,
+                    HistogramMode::Buckets(buckets) => buckets.len() + 3,
+                }
+            })
+            .sum::<usize>();
+        let series =
+            self.counters.len() + self.gauges.len() + self.lazy_gauges.len() + self.infos.len();
+        let signed_series = self.signed_histos.len() * (self.quantiles.len() + 2);
+        let summary_series = self
+            .summaries
+            .values()
+            .map(|summary| summary.quantiles.len() + 2)
+            .sum::<usize>();
+        (series + histogram_series + signed_series + summary_series) * ESTIMATED_LINE_LEN
+    }
+
+    /// Renders the recorded metrics as one document per value of the label `label_key`.
+    ///
+    /// Each document is in the text exposition format, and only contains the series with that
+    /// value for the label, such as the metrics of a single tenant.  Series without the label
+    /// aren't part of any document.  The label is kept on every series if `keep_label` is set,
+    /// and is removed otherwise.  The documents are keyed by label value.
+    pub fn render_partitioned(&self, label_key: &str, keep_label: bool) -> HashMap<String, String> {
+        let sharded = self.sharded();
+        let mut partitions = HashMap::<String, Families<'_>>::new();
+        for (family, entries) in self.families(&sharded) {
+            for (mut labels, entry) in entries {
+                let position = match labels.iter().position(|(key, _)| key == label_key) {
+                    Some(position) => position,
+                    None => continue,
+                };
+                let value = if keep_label {
+                    labels[position].1.clone()
+                } else {
+                    labels.remove(position).1
+                };
+                partitions
+                    .entry(value)
+                    .or_default()
+                    .entry(family.clone())
+                    .or_default()
+                    .push((labels, entry));
+            }
+        }
+
+        partitions
+            .into_iter()
+            .map(|(value, mut families)| {
+                for entries in families.values_mut() {
+                    entries.sort_by(|a, b| a.0.cmp(&b.0));
+                }
+                let mut output = String::new();
+                self.render_families(&mut output, ContentType::Text, families, None)
+                    .expect("writing to a string cannot fail");
+                (value, output)
+            })
+            .collect()
+    }
+
+    fn render_to<W: fmt::Write>(&self, out: &mut W, content_type: ContentType) -> fmt::Result {
+        self.render_matching(out, content_type, |_| true)
+    }
+
+    fn render_matching<W, F>(
+        &self,
+        out: &mut W,
+        content_type: ContentType,
+        filter: F,
+    ) -> fmt::Result
+    where
+        W: fmt::Write,
+        F: Fn(&str) -> bool,
+    {
+        let start = Instant::now();
+        let sharded = self.sharded();
+        self.render_sharded(out, content_type, &sharded, filter, start)
+    }
+
+    // Renders the families matching `filter`, with the histograms merged from the shards, timing
+    // the render from `start`.
+    fn render_sharded<W, F>(
+        &self,
+        out: &mut W,
+        content_type: ContentType,
+        sharded: &ShardedHistograms,
+        filter: F,
+        start: Instant,
+    ) -> fmt::Result
+    where
+        W: fmt::Write,
+        F: Fn(&str) -> bool,
+    {
+        let scrape_duration = if self.scrape_duration_metric {
+            let name = match &self.namespace {
+                Some(namespace) => format!("{}_scrape_duration_seconds", namespace),
+                None => "scrape_duration_seconds".to_owned(),
+            };
+            Some((name, start)).filter(|(name, _)| filter(name))
+        } else {
+            None
+        };
+
+        let mut families = self.families(sharded);
+        families.retain(|(name, _), _| filter(name));
+        self.render_families(out, content_type, families, scrape_duration)
+    }
+
+    // Renders `families`, followed by a gauge with the time elapsed since the given start, if
+    // any, under the given name.
+    fn render_families<W: fmt::Write>(
+        &self,
+        out: &mut W,
+        content_type: ContentType,
+        families: Families<'_>,
+        scrape_duration: Option<(String, Instant)>,
+    ) -> fmt::Result {
+        let openmetrics = content_type == ContentType::OpenMetrics;
+        // OpenMetrics only allows the TYPE/HELP/UNIT comments, and no blank lines, and neither
+        // does the textfile collector.
+        let compact = openmetrics || self.textfile_mode;
+        if !compact {
+            out.write_str(get_prom_expo_header().as_str())?;
+        }
+
+        for ((name, kind), entries) in families {
+            // OpenMetrics counter families are named without the `_total` suffix, which is then
+            // required on the samples themselves.
+            let (name, counter_suffix) = if openmetrics && kind == SeriesKind::Counter {
+                (name.trim_end_matches("_total").to_owned(), "_total")
+            } else {
+                (name, "")
+            };
+
+            if !compact {
+                out.write_char('\n')?;
+            }
+            writeln!(out, "# TYPE {} {}", name, kind.as_str())?;
+
+            for (labels, entry) in entries {
+                let timestamp = match entry {
+                    Entry::Counter(_, Some(ts)) | Entry::Gauge(_, Some(ts))
+                        if !self.textfile_mode =>
+                    {
+                        // OpenMetrics timestamps are in seconds, rather than milliseconds.
+                        if openmetrics {
+                            format!(" {}.{:03}", ts / 1000, ts % 1000)
+                        } else {
+                            format!(" {}", ts)
+                        }
+                    }
+                    _ => String::new(),
+                };
+                for (suffix, labels, value) in self.samples(labels, entry) {
+                    let suffix = if suffix.is_empty() {
+                        counter_suffix
+                    } else {
+                        suffix
+                    };
+                    writeln!(
+                        out,
+                        "{}{}{} {}{}",
+                        name,
+                        suffix,
+                        render_labels(&labels),
+                        value,
+                        timestamp
+                    )?;
+                }
+            }
+        }
+
+        if let Some((name, start)) = scrape_duration {
+            if !compact {
+                out.write_char('\n')?;
+            }
+            writeln!(out, "# TYPE {} gauge", name)?;
+            let elapsed = self.float(start.elapsed().as_secs_f64());
+            writeln!(out, "{} {}", name, elapsed)?;
+        }
+
+        if openmetrics {
+            out.write_str("# EOF\n")?;
+        }
+
+        Ok(())
+    }
+
+    /// Renders the recorded metrics in the format preferred by the given `Accept` header.
+    ///
+    /// The media ranges in the header are weighed by their `q` parameter, and the most preferred
+    /// supported format is chosen: OpenMetrics for `application/openmetrics-text`, and the text
+    /// exposition format for `text/plain` or wildcards.  The protobuf format is not supported, so
+    /// a request for it falls back to the next preferred format.  If nothing matches, the text
+    /// exposition format, version 0.0.4, is used.
+    ///
+    /// Returns the chosen content type, which should be used as the `Content-Type` of the
+    /// response, along with the rendered output.
+    ///
+    /// This is meant for custom servers, which can pass along the `Accept` header of each
+    /// request.  The server and routes of `metrics-exporter-http` work with any recorder that can
+    /// be converted into a string, so they don't negotiate, and always serve the text exposition
+    /// format.
+    pub fn render_for_accept(&self, accept: &str) -> (ContentType, Vec<u8>) {
+        let content_type = ContentType::from_accept(accept);
+        let mut output = String::with_capacity(self.estimated_len());
+        self.render_to(&mut output, content_type)
+            .expect("writing to a string cannot fail");
+        (content_type, output.into_bytes())
+    }
+}
+
+/// Output format of a [`PrometheusRecorder`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContentType {
+    /// The Prometheus text exposition format, version 0.0.4.
+    Text,
+
+    /// The OpenMetrics text format, version 1.0.0.
+    OpenMetrics,
+}
+
+impl ContentType {
+    /// Gets the value to use for the `Content-Type` header.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ContentType::Text => "text/plain; version=0.0.4; charset=utf-8",
+            ContentType::OpenMetrics => {
+                "application/openmetrics-text; version=1.0.0; charset=utf-8"
+            }
+        }
+    }
+
+    fn from_accept(accept: &str) -> ContentType {
+        let mut best: Option<(f64, ContentType)> = None;
+        for range in accept.split(',') {
+            let mut params = range.split(';').map(str::trim);
+            let media_type = params.next().unwrap_or("").to_ascii_lowercase();
+            let quality = params
+                .filter_map(|p| p.strip_prefix("q="))
+                .find_map(|q| q.parse::<f64>().ok())
+                .unwrap_or(1.0);
+
+            let content_type = match media_type.as_str() {
+                "application/openmetrics-text" => ContentType::OpenMetrics,
+                "text/plain" | "text/*" | "*/*" => ContentType::Text,
+                _ => continue,
+            };
+
+            // Earlier ranges win ties, as clients tend to list their preferred formats first.
+            if quality > 0.0 && best.is_none_or(|(q, _)| quality > q) {
+                best = Some((quality, content_type));
+            }
+        }
+
+        best.map(|(_, content_type)| content_type)
+            .unwrap_or(ContentType::Text)
+    }
+}
+
+// Returns `true` if every integer with a magnitude of at most `value` is exactly representable
+// once parsed as a float.
+fn is_exact_as_float(value: u64) -> bool {
+    value <= MAX_EXACT_FLOAT
+}
+
+// Warns when a value first crosses the range where it can be parsed as a float without loss.
+fn warn_on_precision_loss(key: &Key, previous: u64, value: u64) {
+    if is_exact_as_float(previous) && !is_exact_as_float(value) {
+        warn!(
+            "metric `{}` exceeds 2^53, and will lose precision when parsed as a float",
+            key
+        );
+    }
+}
+
+fn quantiles_from_env(var_name: &str) -> Option<Vec<f64>> {
+    let raw = match env::var(var_name) {
+        Ok(raw) => raw,
+        Err(env::VarError::NotPresent) => return None,
+        Err(e) => {
+            warn!("failed to read quantiles from `{}`: {}", var_name, e);
+            return None;
+        }
+    };
+    parse_quantiles_var(var_name, &raw)
+}
+
+// Parses the comma-separated quantiles read from the variable `var_name`.
+fn parse_quantiles_var(var_name: &str, raw: &str) -> Option<Vec<f64>> {
+    let mut quantiles = Vec::new();
+    for value in raw.split(',').map(str::trim) {
+        match value.parse::<f64>() {
+            Ok(q) if (0.0..=1.0).contains(&q) => quantiles.push(q),
+            _ => {
+                warn!(
+                    "invalid quantile `{}` in `{}`, using the default quantiles",
+                    value, var_name
+                );
+                return None;
+            }
+        }
+    }
+
+    Some(quantiles)
+}
+
+fn sum_and_count(
+    labels: LabelPairs,
+    sum: u128,
+    hist: &HistogramStorage,
+) -> [(&'static str, LabelPairs, SeriesValue); 2] {
+    let sum = i128::try_from(sum).unwrap_or(i128::MAX);
+    [
+        ("_sum", labels.clone(), SeriesValue::Integer(sum)),
+        ("_count", labels, SeriesValue::Integer(hist.len().into())),
+    ]
+}
+
+// Caps `value`, observed `count` times, at `max_value`, if any, adding `count` to `saturated` if it
+// exceeds it.
+fn cap_value(value: u64, count: u64, max_value: Option<u64>, saturated: &mut u64) -> u64 {
+    match max_value {
+        Some(max) if value > max => {
+            *saturated = saturated.saturating_add(count);
+            max
+        }
+        _ => value,
+    }
+}
+
+// Finds the largest value a histogram can track while keeping its counts within `bytes`.
+fn max_value_for_memory(bytes: usize, bounds: HistogramBounds) -> u64 {
+    let mut max = bounds.lowest * 2;
+    for exp in 2..64 {
+        let high = (1u64 << exp) - 1;
+        if high < max {
+            continue;
+        }
+        let h = Histogram::<u64>::new_with_bounds(bounds.lowest, high, bounds.sigfig)
+            .expect("histogram bounds are validated when configured");
+        if histogram_footprint(&h) > bytes {
+            return max;
+        }
+        max = high;
+    }
+
+    // A histogram covering the full range fits, so there's nothing to limit.
+    u64::MAX
+}
+
+fn new_histogram(bounds: HistogramBounds) -> Result<Histogram<u64>, CreationError> {
+    match bounds.highest {
+        Some(highest) => Histogram::new_with_bounds(bounds.lowest, highest, bounds.sigfig),
+        None => {
+            let mut h =
+                Histogram::new_with_bounds(bounds.lowest, bounds.lowest * 2, bounds.sigfig)?;
+            h.auto(true);
+            Ok(h)
+        }
+    }
+}
+
+fn new_histogram_entry(bounds: HistogramBounds, backend: HistogramBackendKind) -> HistogramEntry {
+    let hist = match backend {
+        HistogramBackendKind::Hdr => HistogramStorage::Hdr(
+            new_histogram(bounds).expect("histogram bounds are validated when configured"),
+        ),
+        #[cfg(feature = "ddsketch")]
+        HistogramBackendKind::DDSketch(relative_accuracy) => {
+            HistogramStorage::DDSketch(DDSketch::new(relative_accuracy))
+        }
+    };
+    HistogramEntry {
+        sum: 0,
+        hist,
+        last: None,
+        underflow: 0,
+        overflow: 0,
+        saturated: 0,
+    }
+}
+
+fn histogram_footprint(hist: &Histogram<u64>) -> usize {
+    hist.distinct_values() * std::mem::size_of::<u64>()
+}
+
+// Finds the value at the given quantile, interpolating between the recorded values around it.
+fn interpolated_quantile(hist: &Histogram<u64>, quantile: f64) -> f64 {
+    if hist.is_empty() {
+        return 0.0;
+    }
+
+    let rank = quantile.clamp(0.0, 1.0) * (hist.len() - 1) as f64;
+    let (lower_rank, upper_rank) = (rank.floor() as u64, rank.ceil() as u64);
+
+    let mut seen = 0;
+    let mut lower = None;
+    for v in hist.iter_recorded() {
+        seen += v.count_since_last_iteration();
+        let value = v.value_iterated_to() as f64;
+        if lower.is_none() && seen > lower_rank {
+            lower = Some(value);
+        }
+        if seen > upper_rank {
+            let lower = lower.unwrap_or(value);
+            return lower + (value - lower) * (rank - lower_rank as f64);
+        }
+    }
+
+    hist.max() as f64
+}
+
+fn bucket_count(hist: &Histogram<u64>, bound: f64) -> u64 {
+    // Values are integers, so anything at or below the bound is at or below its floor.
+    if bound < 0.0 {
+        0
+    } else {
+        hist.count_between(0, bound.floor() as u64)
+    }
+}
+
+fn key_to_parts(key: &Key, label_order: &[String], max_length: usize) -> (String, LabelPairs) {
+    let name = sanitize_metric_name(key.name()).into_owned();
+    let mut labels = Vec::<&Label>::new();
+    for label in key.labels() {
+        // Prometheus rejects series with duplicate label keys, so the last value wins.
+        labels.retain(|previous| previous.key() != label.key());
+        labels.push(label);
+    }
+    labels.sort_by_key(|label| {
+        let position = label_order.iter().position(|key| key == label.key());
+        (position.unwrap_or(usize::MAX), label.key())
+    });
+    let labels = labels
+        .into_iter()
+        .map(|label| {
+            let value = truncate_label_value(label.value(), max_length);
+            (label.key().to_owned(), value.into_owned())
+        })
+        .collect();
+
+    (name, labels)
+}
+
+// Drops the series and families that would be rendered more than once, which the textfile
+// collector rejects, keeping the first of each, and describes every one that was dropped.
+//
+// Series are in a canonical order, as `key_to_parts` sorts their labels by their position in the
+// label order and then by key, and label keys are unique, so keys whose labels only differ in
+// their order are adjacent duplicates.  Families with the same name and different kinds can't be
+// merged under a single TYPE line, so only the first kind of each name is kept.
+fn dedup_textfile(families: &mut Families<'_>) -> Vec<String> {
+    let mut dropped = Vec::new();
+    for ((name, _), entries) in families.iter_mut() {
+        entries.dedup_by(|a, b| {
+            let duplicate = a.0 == b.0;
+            if duplicate {
+                dropped.push(format!(
+                    "series `{}{}` is rendered more than once",
+                    name,
+                    render_labels(&a.0)
+                ));
+            }
+            duplicate
+        });
+    }
+
+    let mut previous: Option<(String, SeriesKind)> = None;
+    families.retain(|(name, kind), _| match &previous {
+        Some((previous, first)) if previous == name => {
+            dropped.push(format!(
+                "{} family `{}` clashes with a {} family of the same name",
+                kind.as_str(),
+                name,
+                first.as_str()
+            ));
+            false
+        }
+        _ => {
+            previous = Some((name.clone(), *kind));
+            true
+        }
+    });
+    dropped
+}
+
+fn render_labels(labels: &[(String, String)]) -> String {
+    if labels.is_empty() {
+        String::new()
+    } else {
+        let labels = labels
+            .iter()
+            .map(|(key, value)| format!("{}=\"{}\"", key, escape_label_value(value)))
+            .collect::<Vec<_>>();
+        format!("{{{}}}", labels.join(","))
+    }
+}
+
+fn truncate_label_value(value: &str, max_length: usize) -> Cow<'_, str> {
+    if value.len() <= max_length {
+        return value.into();
+    }
+
+    let mut end = max_length;
+    while !value.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!("{}…", &value[..end]).into()
+}
+
+/// Gets `count` bucket bounds, where the first is `start` and each is `factor` times the last.
+///
+/// These are the same bounds as the `ExponentialBuckets` helper of the official Prometheus client
+/// libraries, and are meant to be used with [`HistogramMode::Buckets`].
+///
+/// # Panics
+/// Panics if `start` is not positive, if `factor` is not greater than one, or if `count` is zero.
+pub fn exponential_buckets(start: f64, factor: f64, count: usize) -> Vec<f64> {
+    assert!(start > 0.0, "exponential buckets need a positive start");
+    assert!(
+        factor > 1.0,
+        "exponential buckets need a factor greater than one"
+    );
+    assert!(count > 0, "exponential buckets need a positive count");
+
+    let mut bound = start;
+    (0..count)
+        .map(|_| {
+            let current = bound;
+            bound *= factor;
+            current
+        })
+        .collect()
+}
+
+/// Gets `count` bucket bounds, where the first is `start` and each is `width` more than the last.
+///
+/// These are the same bounds as the `LinearBuckets` helper of the official Prometheus client
+/// libraries, and are meant to be used with [`HistogramMode::Buckets`].
+///
+/// # Panics
+/// Panics if `width` is not positive, or if `count` is zero.
+pub fn linear_buckets(start: f64, width: f64, count: usize) -> Vec<f64> {
+    assert!(width > 0.0, "linear buckets need a positive width");
+    assert!(count > 0, "linear buckets need a positive count");
+
+    let mut bound = start;
+    (0..count)
+        .map(|_| {
+            let current = bound;
+            bound += width;
+            current
+        })
+        .collect()
+}
+
+/// Escapes a label value for the text exposition format.
+///
+/// Backslashes, double quotes, and line feeds are escaped as `\\`, `\"`, and `\n`, respectively.
+/// The value is borrowed as-is when there is nothing to escape.
+pub fn escape_label_value(value: &str) -> Cow<'_, str> {
+    if !value.contains(&['\\', '"', '\n'][..]) {
+        return value.into();
+    }
+
+    let mut escaped = String::with_capacity(value.len() + 2);
+    for c in value.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '"' => escaped.push_str("\\\""),
+            '\n' => escaped.push_str("\\n"),
+            c => escaped.push(c),
+        }
+    }
+    escaped.into()
+}
+
+/// Sanitizes a metric name for the text exposition format.
+///
+/// Metric names may only contain ASCII letters, digits, underscores, and colons, and may not start
+/// with a digit.  Any other character, such as the dots commonly used to separate the parts of a
+/// name, is replaced with an underscore, and an underscore is prepended to names starting with a
+/// digit.  The name is borrowed as-is when it is already valid.
+pub fn sanitize_metric_name(name: &str) -> Cow<'_, str> {
+    let is_valid = |c: char| c.is_ascii_alphanumeric() || c == '_' || c == ':';
+    let starts_with_digit = name.starts_with(|c: char| c.is_ascii_digit());
+    if !starts_with_digit && name.chars().all(is_valid) {
+        return name.into();
+    }
+
+    let mut sanitized = String::with_capacity(name.len() + 1);
+    if starts_with_digit {
+        sanitized.push('_');
+    }
+    sanitized.extend(name.chars().map(|c| if is_valid(c) { c } else { '_' }));
+    sanitized.into()
+}
+
+// Gets the number of milliseconds since the Unix epoch, which is how samples are timestamped.
+fn unix_millis(time: SystemTime) -> u64 {
+    time.duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| u64::try_from(d.as_millis()).unwrap_or(u64::MAX))
+        .unwrap_or(0)
+}
+
+fn get_prom_expo_header() -> String {
+    let ts = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+
+    format!(
+        "# metrics snapshot (ts={}) (prometheus exposition format)",
+        ts
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        dedup_textfile, escape_label_value, exponential_buckets, is_exact_as_float, linear_buckets,
+        parse_quantiles_var, sanitize_metric_name, ContentType, HistogramMode, PrometheusRecorder,
+        RenderWarning, SeriesKind, SeriesValue, ShardedHistograms,
+    };
+    use hdrhistogram::serialization::{Serializer, V2Serializer};
+    use hdrhistogram::Histogram;
+    use metrics_core::SnapshotProvider;
+    use metrics_core::{Key, Label, Recorder, MAX_SAMPLE_WEIGHT};
+    use metrics_util::{parse_quantiles, AggregatingRecorder};
+    use proptest::prelude::*;
+    use quanta::Clock;
+    use std::borrow::Cow;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::{Duration, SystemTime};
+
+    #[test]
+    fn test_default_quantiles() {
+        let recorder = PrometheusRecorder::new();
+        let expected = parse_quantiles(&[0.0, 0.5, 0.9, 0.95, 0.99, 0.999, 1.0]);
+        assert_eq!(recorder.quantiles(), expected.as_slice());
+
+        let recorder = PrometheusRecorder::with_quantiles(&[0.5, 0.99]);
+        let labels = recorder
+            .quantiles()
+            .iter()
+            .map(|q| q.label())
+            .collect::<Vec<_>>();
+        assert_eq!(labels, vec!["p50", "p99"]);
+    }
+
+    #[test]
+    fn test_labels() {
+        let mut recorder = PrometheusRecorder::with_quantiles(&[0.5]);
+        let labels = vec![("method", "get"), ("status", "200")];
+        recorder.record_counter(Key::from_name_and_labels("http.requests", &labels), 3);
+        recorder.record_histogram(Key::from_name_and_labels("http.latency", &labels), &[42]);
+
+        let output: String = recorder.into();
+        assert!(output.contains("http_requests{method=\"get\",status=\"200\"} 3\n"));
+        assert!(
+            output.contains("http_latency{method=\"get\",status=\"200\",quantile=\"0.5\"} 42\n")
+        );
+        assert!(output.contains("http_latency_sum{method=\"get\",status=\"200\"} 42\n"));
+        assert!(output.contains("http_latency_count{method=\"get\",status=\"200\"} 1\n"));
+    }
+
+    #[test]
+    fn test_quantile_overrides() {
+        let mut recorder = PrometheusRecorder::with_quantiles(&[0.5, 0.9, 0.99]);
+        recorder.set_quantiles_for("db.query_time", &[0.99]);
+
+        recorder.record_histogram("db.query_time", &[1, 2, 3]);
+        recorder.record_histogram("http.request_time", &[1, 2, 3]);
+
+        let output: String = recorder.into();
+        assert!(output.contains("db_query_time{quantile=\"0.99\"}"));
+        assert!(!output.contains("db_query_time{quantile=\"0.5\"}"));
+        assert!(!output.contains("db_query_time{quantile=\"0.9\"}"));
+        assert!(output.contains("http_request_time{quantile=\"0.5\"}"));
+        assert!(output.contains("http_request_time{quantile=\"0.9\"}"));
+        assert!(output.contains("http_request_time{quantile=\"0.99\"}"));
+    }
+
+    #[test]
+    fn test_quantile_lookup() {
+        let mut recorder = PrometheusRecorder::new();
+        let values = (1..=100).collect::<Vec<u64>>();
+        recorder.record_histogram("request_time", &values[..50]);
+        recorder.record_histogram("request_time", &values[50..]);
+
+        let key = Key::from_name("request_time");
+        assert_eq!(recorder.quantile(&key, 0.0), Some(1));
+        assert_eq!(recorder.quantile(&key, 0.5), Some(50));
+        assert_eq!(recorder.quantile(&key, 0.99), Some(99));
+        assert_eq!(recorder.quantile(&key, 1.0), Some(100));
+
+        let missing = Key::from_name("response_size");
+        assert_eq!(recorder.quantile(&missing, 0.5), None);
+    }
+
+    #[test]
+    fn test_histogram_counts() {
+        let mut raw = PrometheusRecorder::with_quantiles(&[0.5, 0.99]);
+        raw.record_histogram("db.rows", &[5, 5, 5, 10, 10, 250]);
+
+        let mut counted = PrometheusRecorder::with_quantiles(&[0.5, 0.99]);
+        counted.record_histogram_counts("db.rows", &[(5, 3), (10, 2), (250, 1)]);
+
+        let key = Key::from_name("db.rows");
+        for q in &[0.0, 0.5, 0.75, 0.99, 1.0] {
+            assert_eq!(raw.quantile(&key, *q), counted.quantile(&key, *q));
+        }
+
+        let raw_output: String = raw.into();
+        let counted_output: String = counted.into();
+        for line in &["db_rows_sum 285\n", "db_rows_count 6\n"] {
+            assert!(raw_output.contains(line));
+            assert!(counted_output.contains(line));
+        }
+    }
+
+    #[test]
+    fn test_merge_serialized_histogram() {
+        let mut source = Histogram::<u64>::new(3).unwrap();
+        for value in &[5, 5, 5, 10, 10, 250] {
+            source.record(*value).unwrap();
+        }
+        let mut bytes = Vec::new();
+        V2Serializer::new().serialize(&source, &mut bytes).unwrap();
+
+        let mut raw = PrometheusRecorder::with_quantiles(&[0.5, 0.99]);
+        raw.record_histogram("db.rows", &[5, 5, 5, 10, 10, 250, 40]);
+
+        let mut merged = PrometheusRecorder::with_quantiles(&[0.5, 0.99]);
+        merged.record_histogram("db.rows", &[40]);
+        merged
+            .merge_serialized_histogram("db.rows", &bytes)
+            .unwrap();
+
+        let key = Key::from_name("db.rows");
+        for q in &[0.0, 0.5, 0.75, 0.99, 1.0] {
+            assert_eq!(raw.quantile(&key, *q), merged.quantile(&key, *q));
+        }
+
+        let err = merged
+            .merge_serialized_histogram("db.rows", &[1, 2, 3, 4, 5, 6, 7, 8])
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid serialized histogram: unknown serialization format"
+        );
+
+        let output: String = merged.into();
+        assert!(output.contains("db_rows_sum 325\n"));
+        assert!(output.contains("db_rows_count 7\n"));
+    }
+
+    #[test]
+    fn test_histogram_modes() {
+        let mut recorder = PrometheusRecorder::with_quantiles(&[0.5]);
+        recorder.set_histogram_mode("db.query_time", HistogramMode::Buckets(vec![5.0, 10.0]));
+
+        recorder.record_histogram("db.query_time", &[1, 5, 7, 20]);
+        recorder.record_histogram("http.request_time", &[1, 5, 7, 20]);
+
+        let output: String = recorder.into();
+        assert!(output.contains("# TYPE db_query_time histogram\n"));
+        assert!(output.contains("db_query_time_bucket{le=\"5\"} 2\n"));
+        assert!(output.contains("db_query_time_bucket{le=\"10\"} 3\n"));
+        assert!(output.contains("db_query_time_bucket{le=\"+Inf\"} 4\n"));
+        assert!(output.contains("db_query_time_sum 33\n"));
+        assert!(output.contains("db_query_time_count 4\n"));
+        assert!(!output.contains("db_query_time{quantile"));
+
+        assert!(output.contains("# TYPE http_request_time summary\n"));
+        assert!(output.contains("http_request_time{quantile=\"0.5\"} 5\n"));
+        assert!(!output.contains("http_request_time_bucket"));
+    }
+
+    #[test]
+    fn test_default_histogram_mode() {
+        let mut recorder = PrometheusRecorder::new();
+        recorder.set_default_histogram_mode(HistogramMode::Buckets(vec![1.0]));
+        recorder.set_histogram_mode("http.request_time", HistogramMode::Summary);
+
+        recorder.record_histogram("db.query_time", &[1, 2]);
+        recorder.record_histogram("http.request_time", &[1, 2]);
+
+        let output: String = recorder.into();
+        assert!(output.contains("db_query_time_bucket{le=\"1\"} 1\n"));
+        assert!(output.contains("# TYPE http_request_time summary\n"));
+    }
+
+    #[test]
+    fn test_bucket_helpers() {
+        // The same bounds as the official Go client's `ExponentialBuckets` and `LinearBuckets`.
+        assert_eq!(
+            exponential_buckets(100.0, 1.2, 3),
+            vec![100.0, 120.0, 144.0]
+        );
+        assert_eq!(
+            exponential_buckets(0.005, 2.0, 4),
+            vec![0.005, 0.01, 0.02, 0.04]
+        );
+        assert_eq!(
+            linear_buckets(-15.0, 5.0, 6),
+            vec![-15.0, -10.0, -5.0, 0.0, 5.0, 10.0]
+        );
+        assert_eq!(linear_buckets(1.0, 0.5, 1), vec![1.0]);
+
+        let mut recorder = PrometheusRecorder::new();
+        let mode = HistogramMode::Buckets(exponential_buckets(1.0, 10.0, 3));
+        recorder.set_default_histogram_mode(mode);
+        recorder.record_histogram("db.query_time", &[5, 50]);
+        let output: String = recorder.into();
+        assert!(output.contains("db_query_time_bucket{le=\"10\"} 1\n"));
+        assert!(output.contains("db_query_time_bucket{le=\"100\"} 2\n"));
+    }
+
+    #[test]
+    #[should_panic(expected = "factor greater than one")]
+    fn test_exponential_buckets_factor() {
+        exponential_buckets(1.0, 1.0, 3);
+    }
+
+    #[test]
+    #[should_panic(expected = "positive start")]
+    fn test_exponential_buckets_start() {
+        exponential_buckets(0.0, 2.0, 3);
+    }
+
+    #[test]
+    #[should_panic(expected = "positive count")]
+    fn test_linear_buckets_count() {
+        linear_buckets(0.0, 1.0, 0);
+    }
+
+    #[test]
+    fn test_info() {
+        let mut recorder = PrometheusRecorder::new();
+        recorder.set_info("build_info", &[("version", "1.2.2")]);
+        recorder.set_info("build_info", &[("version", "1.2.3"), ("commit", "abc123")]);
+
+        let output: String = recorder.clone().into();
+        assert_eq!(output.matches("# TYPE build_info gauge\n").count(), 1);
+        assert!(output.contains("build_info{commit=\"abc123\",version=\"1.2.3\"} 1\n"));
+        assert!(!output.contains("1.2.2"));
+    }
+
+    #[test]
+    fn test_families_grouped() {
+        let mut recorder = PrometheusRecorder::with_quantiles(&[0.5]);
+        let post = vec![("method", "post")];
+        let get = vec![("method", "get")];
+        recorder.record_counter(Key::from_name_and_labels("http.requests", &post), 2);
+        recorder.record_gauge("connections", 4);
+        recorder.record_histogram(Key::from_name_and_labels("http.latency", &post), &[7]);
+        recorder.record_counter(Key::from_name_and_labels("http.requests", &get), 5);
+        recorder.record_histogram(Key::from_name_and_labels("http.latency", &get), &[3]);
+
+        let output: String = recorder.into();
+        let (header, body) = output.split_at(output.find('\n').unwrap());
+        assert!(header.starts_with("# metrics snapshot (ts="));
+        assert_eq!(
+            body,
+            concat!(
+                "\n# TYPE connections gauge\n",
+                "connections 4\n",
+                "\n# TYPE http_latency summary\n",
+                "http_latency{method=\"get\",quantile=\"0.5\"} 3\n",
+                "http_latency_sum{method=\"get\"} 3\n",
+                "http_latency_count{method=\"get\"} 1\n",
+                "http_latency{method=\"post\",quantile=\"0.5\"} 7\n",
+                "http_latency_sum{method=\"post\"} 7\n",
+                "http_latency_count{method=\"post\"} 1\n",
+                "\n# TYPE http_requests counter\n",
+                "http_requests{method=\"get\"} 5\n",
+                "http_requests{method=\"post\"} 2\n",
+            )
+        );
+    }
+
+    #[test]
+    fn test_accept_negotiation() {
+        let cases = &[
+            ("", ContentType::Text),
+            ("*/*", ContentType::Text),
+            ("text/plain;version=0.0.4", ContentType::Text),
+            ("application/openmetrics-text; version=1.0.0", ContentType::OpenMetrics),
+            (
+                "application/openmetrics-text;version=1.0.0;q=0.5,text/plain;version=0.0.4;q=0.4,*/*;q=0.1",
+                ContentType::OpenMetrics,
+            ),
+            (
+                "application/openmetrics-text;q=0.3,text/plain;q=0.9",
+                ContentType::Text,
+            ),
+            (
+                "application/vnd.google.protobuf;proto=io.prometheus.client.MetricFamily;encoding=delimited",
+                ContentType::Text,
+            ),
+            ("application/openmetrics-text;q=0", ContentType::Text),
+            ("application/json", ContentType::Text),
+        ];
+
+        for (accept, expected) in cases {
+            assert_eq!(ContentType::from_accept(accept), *expected, "{}", accept);
+        }
+    }
+
+    #[test]
+    fn test_render_for_accept() {
+        let mut recorder = PrometheusRecorder::new();
+        recorder.record_counter("http.requests_total", 3);
+
+        let (content_type, output) = recorder.render_for_accept("text/plain");
+        let output = String::from_utf8(output).unwrap();
+        assert_eq!(
+            content_type.as_str(),
+            "text/plain; version=0.0.4; charset=utf-8"
+        );
+        assert!(output.contains("\n# TYPE http_requests_total counter\nhttp_requests_total 3\n"));
+
+        let (content_type, output) =
+            recorder.render_for_accept("application/openmetrics-text; version=1.0.0");
+        let output = String::from_utf8(output).unwrap();
+        assert_eq!(content_type, ContentType::OpenMetrics);
+        assert_eq!(
+            output,
+            "# TYPE http_requests counter\nhttp_requests_total 3\n# EOF\n"
+        );
+    }
+
+    #[test]
+    fn test_record_and_quantile() {
+        let mut recorder = PrometheusRecorder::new();
+        recorder.record_histogram("request_time", &[10, 20, 30]);
+
+        assert_eq!(
+            recorder.record_and_quantile("request_time", 1000, 1.0),
+            1000
+        );
+        assert_eq!(recorder.record_and_quantile("request_time", 5, 0.0), 5);
+        assert_eq!(recorder.record_and_quantile("connect_time", 7, 0.99), 7);
+
+        let key = Key::from_name("request_time");
+        assert_eq!(recorder.quantile(&key, 0.5), Some(20));
+
+        // Values recorded through the shards are included too.
+        let mut recorder = PrometheusRecorder::new().with_shards(2);
+        recorder
+            .histogram_shards()
+            .unwrap()
+            .record("request_time", &[500, 600]);
+        assert_eq!(recorder.record_and_quantile("request_time", 5, 1.0), 600);
+        assert_eq!(recorder.histogram_len(&key), Some(3));
+        assert_eq!(recorder.quantile(&key, 0.0), Some(5));
+    }
+
+    #[test]
+    fn test_type_conflicts() {
+        let mut recorder = PrometheusRecorder::new();
+        recorder.record_counter("jobs.active", 3);
+        recorder.record_gauge("jobs.active", 5);
+        recorder.record_histogram("jobs_active", &[1]);
+        recorder.record_counter(
+            Key::from_name_and_labels("jobs.active", &[("queue", "a")]),
+            1,
+        );
+
+        let output: String = recorder.into();
+        assert_eq!(output.matches("# TYPE").count(), 1);
+        assert!(output.contains("# TYPE jobs_active counter\n"));
+        assert!(output.contains("jobs_active 3\n"));
+        assert!(output.contains("jobs_active{queue=\"a\"} 1\n"));
+        assert!(!output.contains("jobs_active 5\n"));
+    }
+
+    #[test]
+    #[should_panic(expected = "previously recorded as a counter")]
+    fn test_strict_type_conflicts() {
+        let mut recorder = PrometheusRecorder::new().with_strict_types(true);
+        recorder.record_counter("jobs.active", 3);
+        recorder.record_gauge("jobs.active", 5);
+    }
+
+    #[test]
+    fn test_quantile_label_name() {
+        let mut recorder =
+            PrometheusRecorder::with_quantiles(&[0.5]).with_quantile_label_name("percentile");
+        recorder.record_histogram("request_time", &[5]);
+
+        let output: String = recorder.into();
+        assert!(output.contains("request_time{percentile=\"0.5\"} 5\n"));
+        assert!(!output.contains("quantile="));
+    }
+
+    #[test]
+    fn test_clone() {
+        let mut recorder = PrometheusRecorder::with_quantiles(&[0.5, 0.99]);
+        recorder.set_info("build_info", &[("version", "1.2.3")]);
+        recorder.record_counter("http.requests", 12);
+        recorder.record_gauge("connections", 3);
+        recorder.record_histogram("http.request_time", &[1, 2, 3]);
+
+        let fresh: String = recorder.fresh_with_same_config().into();
+        let cloned: String = recorder.clone().into();
+        let original: String = recorder.into();
+
+        let body = |output: &str| output[output.find('\n').unwrap()..].to_owned();
+        assert_eq!(body(&cloned), body(&original));
+        assert!(cloned.contains("http_requests 12\n"));
+        assert!(fresh.contains("build_info{version=\"1.2.3\"} 1\n"));
+        assert!(!fresh.contains("http_requests"));
+        assert!(!fresh.contains("http_request_time"));
+    }
+
+    #[test]
+    fn test_shards() {
+        let mut recorder = PrometheusRecorder::with_quantiles(&[0.5, 1.0])
+            .with_last_value(true)
+            .with_shards(4);
+        let mut expected = recorder.fresh_with_same_config();
+        let shards = recorder.histogram_shards().unwrap();
+        assert_eq!(shards.shard_count(), 4);
+
+        let threads = (0..8u64)
+            .map(|thread| {
+                let shards = shards.clone();
+                std::thread::spawn(move || {
+                    for value in 1..=100 {
+                        let key = format!("worker.{}.request_time", thread % 4);
+                        shards.record(key, &[value * (thread + 1)]);
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        for thread in 0..8u64 {
+            for value in 1..=100 {
+                let key = format!("worker.{}.request_time", thread % 4);
+                expected.record_histogram(key, &[value * (thread + 1)]);
+            }
+        }
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        // Histograms recorded directly are merged with the sharded ones.
+        recorder.record_histogram("worker.0.request_time", &[1_000]);
+        expected.record_histogram("worker.0.request_time", &[1_000]);
+
+        let key = Key::from_name("worker.0.request_time");
+        assert_eq!(recorder.histogram_len(&key), Some(201));
+        assert_eq!(recorder.quantile(&key, 1.0), expected.quantile(&key, 1.0));
+
+        // Which last value wins is unspecified, so those are left out of the comparison.
+        let output = recorder.to_string();
+        let expected = expected.to_string();
+        let without_last = |output: &str| {
+            output
+                .lines()
+                .filter(|line| !line.contains("_last"))
+                .collect::<Vec<_>>()
+                .join("\n")
+        };
+        assert_eq!(without_last(&output), without_last(&expected));
+        assert!(output.contains("worker_3_request_time_count 200\n"));
+    }
+
+    #[test]
+    fn test_shards_type_conflict() {
+        let mut recorder = PrometheusRecorder::new().with_shards(2);
+        recorder.record_counter("jobs", 1);
+        recorder.histogram_shards().unwrap().record("jobs", &[5]);
+
+        let (output, warnings) = recorder.try_render().unwrap();
+        assert!(!output.contains("jobs_count"));
+        assert_eq!(
+            warnings,
+            vec![RenderWarning::TypeConflict {
+                name: "jobs".to_owned(),
+                declared: "counter".to_owned(),
+                recorded: "histogram".to_owned(),
+            }]
+        );
+        assert!(PrometheusRecorder::new().histogram_shards().is_none());
+    }
+
+    #[test]
+    fn test_max_value() {
+        let mut recorder = PrometheusRecorder::with_quantiles(&[1.0]).with_max_value(1_000);
+        recorder.record_histogram("http.request_time", &[10, u64::MAX - 5, 20]);
+        recorder.record_counter("http.requests", 5_000);
+        recorder.record_counter("jobs", 1_000);
+
+        let key = Key::from_name("http.request_time");
+        assert_eq!(recorder.quantile(&key, 1.0), Some(1_000));
+        let output = recorder.to_string();
+        assert!(output.contains("http_request_time_sum 1030\n"));
+        assert!(output.contains("http_requests 1000\n"));
+        assert!(output.contains("jobs 1000\n"));
+        assert!(output.contains(
+            "# TYPE metrics_values_saturated_total counter\nmetrics_values_saturated_total 2\n"
+        ));
+
+        recorder.record_histogram_counts("http.request_time", &[(2_000, 3)]);
+        assert_eq!(recorder.histogram_len(&key), Some(6));
+        assert!(recorder
+            .to_string()
+            .contains("metrics_values_saturated_total 5\n"));
+
+        // Values dropped for a type conflict aren't counted as saturated.
+        recorder.record_histogram("jobs", &[5_000]);
+        recorder.record_histogram_counts("jobs", &[(5_000, 2)]);
+        assert_eq!(recorder.record_and_quantile("jobs", 5_000, 1.0), 0);
+        assert!(recorder
+            .to_string()
+            .contains("metrics_values_saturated_total 5\n"));
+
+        let unlimited = PrometheusRecorder::new().to_string();
+        assert!(!unlimited.contains("metrics_values_saturated_total"));
+    }
+
+    #[test]
+    fn test_max_value_counter_saturated_once() {
+        let mut recorder = PrometheusRecorder::new().with_max_value(1_000);
+        recorder.record_counter("http.requests", 5_000);
+        recorder.record_counter("http.requests", 5_000);
+        recorder.record_counter("http.requests", 6_000);
+        let output = recorder.to_string();
+        assert!(output.contains(
+            "http_requests 1000
+"
+        ));
+        assert!(output.contains(
+            "metrics_values_saturated_total 1
+"
+        ));
+
+        // A counter that's reset below the maximum is counted again once it exceeds it again.
+        recorder.record_counter("http.requests", 10);
+        recorder.record_counter("http.requests", 5_000);
+        recorder.record_counter("http.requests", 5_000);
+        assert!(recorder.to_string().contains(
+            "metrics_values_saturated_total 2
+"
+        ));
+    }
+
+    #[test]
+    fn test_max_value_rates_and_shards() {
+        let (clock, ctl) = Clock::mock();
+        let mut recorder = PrometheusRecorder::with_quantiles(&[1.0])
+            .with_max_value(1_000)
+            .with_shards(2);
+        recorder.clock = Some(clock);
+
+        recorder.record_rate("bytes_received", 600.0);
+        ctl.increment(Duration::from_secs(2));
+        recorder.record_rate("bytes_received", 600.0);
+        recorder
+            .histogram_shards()
+            .unwrap()
+            .record("http.request_time", &[10, 5_000, 6_000]);
+
+        let key = Key::from_name("http.request_time");
+        assert_eq!(recorder.quantile(&key, 1.0), Some(1_000));
+        let output = recorder.to_string();
+        assert!(output.contains("bytes_received 1000\n"));
+        assert!(output.contains("http_request_time_sum 2010\n"));
+        assert!(output.contains("metrics_values_saturated_total 3\n"));
+    }
+
+    #[test]
+    fn test_reset_metric() {
+        let mut recorder = PrometheusRecorder::with_quantiles(&[0.5]).with_shards(2);
+        let labels = vec![("method", "get")];
+        let get = Key::from_name_and_labels("http.request_time", &labels);
+        recorder.record_counter("http.requests", 12);
+        recorder.record_gauge("connections", 3);
+        recorder.record_histogram(get.clone(), &[1, 2, 3]);
+        recorder.record_histogram("http.request_time", &[4]);
+        let shards = recorder.histogram_shards().unwrap();
+        shards.record("db.query_time", &[5]);
+
+        assert!(recorder.reset_metric(&get));
+        assert!(recorder.reset_metric(&Key::from_name("db.query_time")));
+        assert!(!recorder.reset_metric(&get));
+        assert!(!recorder.reset_metric(&Key::from_name("missing")));
+
+        let output = recorder.to_string();
+        assert!(output.contains("http_requests 12\n"));
+        assert!(output.contains("connections 3\n"));
+        assert!(output.contains("http_request_time_count 1\n"));
+        assert!(!output.contains("method=\"get\""));
+        assert!(!output.contains("db_query_time"));
+
+        // The histogram is recorded from scratch afterwards.
+        recorder.record_histogram(get.clone(), &[10]);
+        assert_eq!(recorder.histogram_len(&get), Some(1));
+        assert!(recorder.reset_metric(&Key::from_name("http.requests")));
+        assert!(!recorder.to_string().contains("http_requests"));
+    }
+
+    #[test]
+    #[should_panic(expected = "shard count must be greater than zero")]
+    fn test_shards_zero() {
+        PrometheusRecorder::new().with_shards(0);
+    }
+
+    #[test]
+    fn test_display() {
+        let mut recorder = PrometheusRecorder::new();
+        recorder.record_counter("http.requests", 12);
+
+        let formatted = format!("{}", recorder);
+        assert!(formatted.contains("\n# TYPE http_requests counter\nhttp_requests 12\n"));
+
+        recorder.record_counter("http.requests", 13);
+        let output: String = recorder.into();
+        assert!(output.contains("http_requests 13\n"));
+    }
+
+    #[test]
+    fn test_max_histogram_memory() {
+        let limit = 64 * 1024;
+        let mut recorder = PrometheusRecorder::new().with_max_histogram_memory(limit);
+        recorder.record_histogram("request_time", &[10, 1 << 60]);
+        recorder.record_histogram_counts("request_time", &[(1 << 62, 2)]);
+
+        let key = Key::from_name("request_time");
+        let (sum, hist) = (&recorder.histos[&key].sum, &recorder.histos[&key].hist);
+        assert!(hist.footprint() <= limit);
+        assert_eq!(*sum, 10 + (1 << 60) + (1 << 63));
+        assert_eq!(hist.len(), 4);
+        assert_eq!(recorder.quantile(&key, 0.0), Some(10));
+        assert!(recorder.quantile(&key, 1.0).unwrap() < 1 << 60);
+
+        let mut unbounded = PrometheusRecorder::new();
+        unbounded.record_histogram("request_time", &[1 << 60]);
+        assert!(unbounded.histos[&key].hist.footprint() > limit);
+    }
+
+    #[test]
+    fn test_lazy_gauges() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let mut recorder = PrometheusRecorder::new();
+        let counted = calls.clone();
+        recorder.register_lazy_gauge("open_files", move || {
+            counted.fetch_add(1, Ordering::SeqCst);
+            42
+        });
+        recorder.record_gauge("open_files", 7);
+        assert_eq!(calls.load(Ordering::SeqCst), 0);
+
+        let output = recorder.to_string();
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        assert!(output.contains("open_files 42\n"));
+        assert!(!output.contains("open_files 7\n"));
+
+        let (_, _) = recorder.render_for_accept("text/plain");
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        let fresh: String = recorder.fresh_with_same_config().into();
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+        assert_eq!(fresh.matches("open_files 42\n").count(), 1);
+    }
+
+    #[test]
+    fn test_label_order() {
+        let labels = vec![
+            ("zone", "b"),
+            ("instance", "db-1"),
+            ("env", "prod"),
+            ("job", "db"),
+        ];
+        let key = Key::from_name_and_labels("up", &labels);
+
+        let mut recorder = PrometheusRecorder::new();
+        recorder.record_gauge(key.clone(), 1);
+        let output: String = recorder.into();
+        assert!(output.contains("up{env=\"prod\",instance=\"db-1\",job=\"db\",zone=\"b\"} 1\n"));
+
+        let mut recorder =
+            PrometheusRecorder::with_quantiles(&[0.5]).with_label_order(&["job", "instance"]);
+        recorder.record_gauge(key, 1);
+        recorder.record_histogram(Key::from_name_and_labels("latency", &labels), &[3]);
+        let output: String = recorder.into();
+        assert!(output.contains("up{job=\"db\",instance=\"db-1\",env=\"prod\",zone=\"b\"} 1\n"));
+        assert!(output.contains(
+            "latency{job=\"db\",instance=\"db-1\",env=\"prod\",zone=\"b\",quantile=\"0.5\"} 3\n"
+        ));
+    }
+
+    #[test]
+    fn test_max_label_length() {
+        let trace = "panicked at 'boom', src/main.rs:10:5\nstack backtrace: ...";
+        let labels = vec![("error", trace), ("city", "Zürich")];
+
+        let mut recorder = PrometheusRecorder::new().with_max_label_length(11);
+        recorder.record_counter(Key::from_name_and_labels("errors", &labels), 1);
+        let output: String = recorder.into();
+        assert!(output.contains("errors{city=\"Zürich\",error=\"panicked at…\"} 1\n"));
+
+        // Values are cut on a character boundary, which can be shorter than the limit.
+        let mut recorder = PrometheusRecorder::new().with_max_label_length(2);
+        recorder.record_counter(Key::from_name_and_labels("errors", &labels), 1);
+        let output: String = recorder.into();
+        assert!(output.contains("errors{city=\"Z…\",error=\"pa…\"} 1\n"));
+
+        let mut recorder = PrometheusRecorder::new();
+        recorder.record_counter(Key::from_name_and_labels("errors", &labels), 1);
+        let output: String = recorder.into();
+        assert!(output.contains("stack backtrace: ..."));
+    }
+
+    #[test]
+    fn test_histogram_sum_overflow() {
+        let mut recorder = PrometheusRecorder::with_quantiles(&[1.0]);
+        recorder.record_histogram("request_time", &[u64::MAX, u64::MAX]);
+        recorder.record_histogram_counts("request_time", &[(u64::MAX, 2)]);
+
+        let output: String = recorder.into();
+        let expected = u128::from(u64::MAX) * 4;
+        assert!(output.contains(&format!("request_time_sum {}\n", expected)));
+        assert!(output.contains("request_time_count 4\n"));
+    }
+
+    #[test]
+    fn test_interpolated_quantiles() {
+        let mut recorder = PrometheusRecorder::with_quantiles(&[0.0, 0.5, 0.75, 1.0]);
+        recorder.record_histogram("request_time", &[1, 2, 3, 10]);
+        let output: String = recorder.clone().into();
+        assert!(output.contains("request_time{quantile=\"0.5\"} 2\n"));
+        assert!(output.contains("request_time{quantile=\"0.75\"} 3\n"));
+
+        let recorder = recorder.with_interpolated_quantiles(true);
+        let output: String = recorder.into();
+        assert!(output.contains("request_time{quantile=\"0\"} 1\n"));
+        assert!(output.contains("request_time{quantile=\"0.5\"} 2.5\n"));
+        assert!(output.contains("request_time{quantile=\"0.75\"} 4.75\n"));
+        assert!(output.contains("request_time{quantile=\"1\"} 10\n"));
+    }
+
+    #[test]
+    fn test_empty_histograms() {
+        let mut recorder = PrometheusRecorder::with_quantiles(&[0.5, 0.99]);
+        recorder.record_histogram("request_time", &[]);
+        recorder.record_histogram("response_size", &[5, 7]);
+
+        assert_eq!(
+            recorder.histogram_len(&Key::from_name("request_time")),
+            Some(0)
+        );
+        assert_eq!(
+            recorder.histogram_len(&Key::from_name("response_size")),
+            Some(2)
+        );
+        assert_eq!(
+            recorder.histogram_len(&Key::from_name("connect_time")),
+            None
+        );
+
+        let output: String = recorder.into();
+        assert!(output
+            .contains("\n# TYPE request_time summary\nrequest_time_sum 0\nrequest_time_count 0\n"));
+        assert!(!output.contains("request_time{quantile"));
+        assert!(output.contains("response_size{quantile=\"0.5\"} 5\n"));
+    }
+
+    #[test]
+    fn test_quantiles_from_env() {
+        // The variable is never set, as setting one would race with the other tests.
+        let labels = |recorder: &PrometheusRecorder| {
+            recorder
+                .quantiles()
+                .iter()
+                .map(|q| q.label().to_owned())
+                .collect::<Vec<_>>()
+        };
+        let recorder = PrometheusRecorder::with_quantiles_from_env("TEST_PROM_QUANTILES_UNSET");
+        assert_eq!(labels(&recorder), labels(&PrometheusRecorder::new()));
+
+        assert_eq!(
+            parse_quantiles_var("QUANTILES", "0.5, 0.99,1"),
+            Some(vec![0.5, 0.99, 1.0])
+        );
+        for malformed in &["0.5,abc", "0.5,1.5", "", "0.5,,0.9", "NaN"] {
+            assert_eq!(
+                parse_quantiles_var("QUANTILES", malformed),
+                None,
+                "{:?}",
+                malformed
+            );
+        }
+    }
+
+    #[test]
+    fn test_last_value() {
+        let mut recorder = PrometheusRecorder::with_quantiles(&[0.5]).with_last_value(true);
+        let labels = vec![("method", "get")];
+        recorder.record_histogram(Key::from_name_and_labels("http.latency", &labels), &[9, 3]);
+        recorder.record_histogram(Key::from_name_and_labels("http.latency", &labels), &[]);
+        recorder.record_histogram_counts("db.rows", &[(4, 2), (7, 1)]);
+        recorder.record_and_quantile("connect_time", 12, 0.5);
+        recorder.record_histogram("empty", &[]);
+
+        let output: String = recorder.clone().into();
+        assert!(output
+            .contains("# TYPE http_latency_last gauge\nhttp_latency_last{method=\"get\"} 3\n"));
+        assert!(output.contains("db_rows_last 7\n"));
+        assert!(output.contains("connect_time_last 12\n"));
+        assert!(!output.contains("empty_last"));
+
+        let output: String = recorder.with_last_value(false).into();
+        assert!(!output.contains("_last"));
+    }
+
+    #[test]
+    fn test_float_precision_boundary() {
+        let boundary = 1u64 << 53;
+        assert!(is_exact_as_float(boundary));
+        assert!(!is_exact_as_float(boundary + 1));
+        assert_eq!(boundary as f64 as u64, boundary);
+        assert_ne!((boundary + 1) as f64 as u64, boundary + 1);
+
+        let mut recorder = PrometheusRecorder::new();
+        recorder.record_counter("disk.bytes_written", u64::MAX);
+        recorder.record_gauge("disk.bytes_free", -(boundary as i64) - 1);
+
+        // The rendered output is still exact, even though Prometheus rounds it when parsing.
+        let output: String = recorder.into();
+        assert!(output.contains("disk_bytes_written 18446744073709551615\n"));
+        assert!(output.contains("disk_bytes_free -9007199254740993\n"));
+    }
+
+    #[test]
+    fn test_namespace() {
+        let mut recorder = PrometheusRecorder::with_quantiles(&[0.5])
+            .with_namespace("my.app")
+            .with_last_value(true);
+        recorder.set_info("build_info", &[("version", "1.2.3")]);
+        recorder.record_counter("http.requests_total", 3);
+        recorder.record_histogram("http.latency", &[5]);
+
+        let output: String = recorder.clone().into();
+        let body = &output[output.find('\n').unwrap()..];
+        assert_eq!(
+            body,
+            concat!(
+                "\n# TYPE my_app_build_info gauge\n",
+                "my_app_build_info{version=\"1.2.3\"} 1\n",
+                "\n# TYPE my_app_http_latency summary\n",
+                "my_app_http_latency{quantile=\"0.5\"} 5\n",
+                "my_app_http_latency_sum 5\n",
+                "my_app_http_latency_count 1\n",
+                "\n# TYPE my_app_http_latency_last gauge\n",
+                "my_app_http_latency_last 5\n",
+                "\n# TYPE my_app_http_requests_total counter\n",
+                "my_app_http_requests_total 3\n",
+            )
+        );
+
+        let (_, output) = recorder.render_for_accept("application/openmetrics-text");
+        let output = String::from_utf8(output).unwrap();
+        assert!(
+            output.contains("# TYPE my_app_http_requests counter\nmy_app_http_requests_total 3\n")
+        );
+    }
+
+    #[test]
+    fn test_series() {
+        let mut recorder = PrometheusRecorder::with_quantiles(&[0.5]);
+        recorder.record_counter(
+            Key::from_name_and_labels("http.requests", &[("method", "get")]),
+            3,
+        );
+        recorder.record_gauge("connections", -2);
+        recorder.record_histogram("http.latency", &[4, 6]);
+
+        let series = recorder
+            .series()
+            .map(|s| {
+                let labels = s
+                    .labels()
+                    .iter()
+                    .map(|(k, v)| format!("{}={}", k, v))
+                    .collect::<Vec<_>>();
+                (s.name().to_owned(), labels, s.kind(), s.value())
+            })
+            .collect::<Vec<_>>();
+
+        let labels = |labels: &[&str]| labels.iter().map(|l| (*l).to_owned()).collect::<Vec<_>>();
+        assert_eq!(
+            series,
+            vec![
+                (
+                    "connections".to_owned(),
+                    labels(&[]),
+                    SeriesKind::Gauge,
+                    SeriesValue::Integer(-2)
+                ),
+                (
+                    "http_latency".to_owned(),
+                    labels(&["quantile=0.5"]),
+                    SeriesKind::Summary,
+                    SeriesValue::Integer(4)
+                ),
+                (
+                    "http_latency_sum".to_owned(),
+                    labels(&[]),
+                    SeriesKind::Summary,
+                    SeriesValue::Integer(10)
+                ),
+                (
+                    "http_latency_count".to_owned(),
+                    labels(&[]),
+                    SeriesKind::Summary,
+                    SeriesValue::Integer(2)
+                ),
+                (
+                    "http_requests".to_owned(),
+                    labels(&["method=get"]),
+                    SeriesKind::Counter,
+                    SeriesValue::Integer(3)
+                ),
+            ]
+        );
+
+        let recorder = recorder.with_interpolated_quantiles(true);
+        let quantile = recorder
+            .series()
+            .find(|s| s.name() == "http_latency")
+            .unwrap();
+        assert_eq!(quantile.value(), SeriesValue::Float(5.0));
+        assert_eq!(quantile.value().to_string(), "5");
+    }
+
+    #[test]
+    fn test_scrape_duration_metric() {
+        let mut recorder = PrometheusRecorder::new().with_scrape_duration_metric(true);
+        recorder.record_counter("http.requests", 12);
+
+        let output = recorder.to_string();
+        let line = output
+            .lines()
+            .find(|line| line.starts_with("scrape_duration_seconds "))
+            .expect("scrape duration should be rendered");
+        let seconds: f64 = line["scrape_duration_seconds ".len()..].parse().unwrap();
+        assert!((0.0..60.0).contains(&seconds));
+        assert!(output.contains("\n# TYPE scrape_duration_seconds gauge\n"));
+        assert!(output.ends_with(&format!("{}\n", line)));
+
+        let recorder = recorder.with_namespace("app");
+        let (_, output) = recorder.render_for_accept("application/openmetrics-text");
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("# TYPE app_scrape_duration_seconds gauge\n"));
+        assert!(output.ends_with("# EOF\n"));
+        assert!(!recorder
+            .render_filtered("app_http_*")
+            .contains("scrape_duration"));
+
+        let output = PrometheusRecorder::new().to_string();
+        assert!(!output.contains("scrape_duration_seconds"));
+    }
+
+    #[test]
+    fn test_update_tracking() {
+        let (clock, ctl) = Clock::mock();
+        let mut recorder = PrometheusRecorder::new().with_update_tracking(true);
+        recorder.clock = Some(clock);
+
+        let heartbeat = Key::from_name("heartbeat");
+        let requests = Key::from_name("http.requests");
+        assert!(recorder.is_stale(&heartbeat, Duration::from_secs(30)));
+        assert_eq!(recorder.last_updated(&heartbeat), None);
+
+        recorder.record_gauge("heartbeat", 1);
+        recorder.record_counter("http.requests", 1);
+        ctl.increment(Duration::from_secs(20));
+        recorder.record_gauge("heartbeat", 2);
+        ctl.increment(Duration::from_secs(20));
+
+        assert!(!recorder.is_stale(&heartbeat, Duration::from_secs(30)));
+        assert!(recorder.is_stale(&requests, Duration::from_secs(30)));
+        let updated = recorder.last_updated(&requests).unwrap();
+        let age = SystemTime::now().duration_since(updated).unwrap();
+        assert!(age >= Duration::from_secs(40) && age < Duration::from_secs(50));
+
+        // Recordings dropped because of a type conflict don't count as updates.
+        ctl.increment(Duration::from_secs(20));
+        recorder.record_histogram("heartbeat", &[1]);
+        assert!(recorder.is_stale(&heartbeat, Duration::from_secs(30)));
+
+        assert!(recorder.reset_metric(&heartbeat));
+        assert_eq!(recorder.last_updated(&heartbeat), None);
+
+        let mut untracked = PrometheusRecorder::new();
+        untracked.record_gauge("heartbeat", 1);
+        assert!(untracked.is_stale(&heartbeat, Duration::from_secs(30)));
+    }
+
+    #[test]
+    fn test_record_rate() {
+        let (clock, ctl) = Clock::mock();
+        let mut recorder = PrometheusRecorder::new();
+        recorder.clock = Some(clock);
+
+        let key = Key::from_name("bytes_received");
+        recorder.record_rate("bytes_received", 100.0);
+        assert_eq!(recorder.counters[&key], 0);
+
+        ctl.increment(Duration::from_secs(2));
+        recorder.record_rate("bytes_received", 100.0);
+        assert_eq!(recorder.counters[&key], 200);
+
+        // Fractional increments are carried over rather than dropped.
+        ctl.increment(Duration::from_millis(250));
+        recorder.record_rate("bytes_received", 2.0);
+        assert_eq!(recorder.counters[&key], 200);
+        ctl.increment(Duration::from_millis(250));
+        recorder.record_rate("bytes_received", 2.0);
+        assert_eq!(recorder.counters[&key], 201);
+
+        ctl.increment(Duration::from_secs(1));
+        recorder.record_rate("bytes_received", -50.0);
+        assert_eq!(recorder.counters[&key], 201);
+
+        let output: String = recorder.into();
+        assert!(output.contains("# TYPE bytes_received counter\nbytes_received 201\n"));
+    }
+
+    #[test]
+    fn test_histogram_bounds() {
+        let invalid = &[
+            (0, 1000, 3),
+            (1, 1000, 6),
+            (600, 1000, 3),
+            (1 << 60, u64::MAX, 5),
+        ];
+        for (lowest, highest, sigfig) in invalid {
+            let result =
+                PrometheusRecorder::new().with_histogram_bounds(*lowest, *highest, *sigfig);
+            assert!(result.is_err(), "{} {} {}", lowest, highest, sigfig);
+        }
+
+        let err = PrometheusRecorder::new()
+            .with_histogram_bounds(1, 1000, 6)
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.to_string(),
+            "invalid histogram bounds: significant figures must be at most 5"
+        );
+
+        let mut recorder = PrometheusRecorder::with_quantiles(&[1.0])
+            .with_histogram_bounds(1, 1000, 2)
+            .unwrap();
+        recorder.record_histogram("request_time", &[5, 5000]);
+
+        let key = Key::from_name("request_time");
+        assert_eq!(recorder.histogram_len(&key), Some(2));
+        assert!(recorder.quantile(&key, 1.0).unwrap() < 1100);
+
+        let output: String = recorder.into();
+        assert!(output.contains("request_time_sum 5005\n"));
+    }
+
+    #[test]
+    fn test_clipping_counters() {
+        let mut recorder = PrometheusRecorder::with_quantiles(&[0.5])
+            .with_histogram_bounds(10, 1000, 2)
+            .unwrap()
+            .with_clipping_counters(true);
+        recorder.record_histogram("request_time", &[0, 5, 50, 5000]);
+        recorder.record_histogram_counts("request_time", &[(2000, 3), (1, 2)]);
+        recorder.record_histogram("response_size", &[100]);
+
+        let output: String = recorder.clone().into();
+        assert!(output.contains(
+            "# TYPE request_time_underflow_total counter\nrequest_time_underflow_total 3\n"
+        ));
+        assert!(output.contains(
+            "# TYPE request_time_overflow_total counter\nrequest_time_overflow_total 4\n"
+        ));
+        assert!(output.contains("response_size_underflow_total 0\n"));
+        assert!(output.contains("response_size_overflow_total 0\n"));
+
+        let output: String = recorder.with_clipping_counters(false).into();
+        assert!(!output.contains("flow_total"));
+    }
+
+    // Strings that favour the characters that need escaping or sanitizing, mixed with any others.
+    fn exposition_text() -> impl Strategy<Value = String> {
+        let special = vec![
+            'a', 'Z', '0', '9', '_', ':', '.', '-', ' ', '"', '\\', '\n', '\r', '\t', '{', '}',
+            '=', ',', 'n', 'é', '…', '🦀', '\0',
+        ];
+        let chars = prop_oneof![3 => prop::sample::select(special), 1 => any::<char>()];
+        prop::collection::vec(chars, 0..16).prop_map(|chars| chars.into_iter().collect())
+    }
+
+    fn unescape_label_value(escaped: &str) -> Option<String> {
+        let mut value = String::new();
+        let mut chars = escaped.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => match chars.next()? {
+                    '\\' => value.push('\\'),
+                    '"' => value.push('"'),
+                    'n' => value.push('\n'),
+                    _ => return None,
+                },
+                '"' | '\n' => return None,
+                c => value.push(c),
+            }
+        }
+        Some(value)
+    }
+
+    #[test]
+    fn test_escape_label_value() {
+        assert!(matches!(
+            escape_label_value("plain value"),
+            Cow::Borrowed(_)
+        ));
+        assert_eq!(escape_label_value(r#"a\b"c"#), r#"a\\b\"c"#);
+        assert_eq!(escape_label_value("line\nbreak"), "line\\nbreak");
+    }
+
+    proptest! {
+        #[test]
+        fn prop_escape_label_value_round_trips(value in exposition_text()) {
+            let escaped = escape_label_value(&value);
+            prop_assert_eq!(unescape_label_value(&escaped), Some(value.clone()));
+            prop_assert_eq!(matches!(escaped, Cow::Borrowed(_)), escaped == value);
+        }
+
+        #[test]
+        fn prop_sanitize_metric_name_is_valid(name in exposition_text()) {
+            let sanitized = sanitize_metric_name(&name);
+            let mut chars = sanitized.chars();
+            if let Some(first) = chars.next() {
+                prop_assert!(first.is_ascii_alphabetic() || first == '_' || first == ':');
+            }
+            prop_assert!(chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == ':'));
+            prop_assert_eq!(&sanitize_metric_name(&sanitized), &sanitized);
+            prop_assert_eq!(matches!(sanitized, Cow::Borrowed(_)), sanitized == name);
+        }
+    }
+
+    #[test]
+    fn test_sanitize_metric_name() {
+        assert!(matches!(
+            sanitize_metric_name("http_requests:rate5m"),
+            Cow::Borrowed(_)
+        ));
+        assert_eq!(sanitize_metric_name("http.requests"), "http_requests");
+        assert_eq!(sanitize_metric_name("cache-hits"), "cache_hits");
+        assert_eq!(sanitize_metric_name("5xx.errors"), "_5xx_errors");
+    }
+
+    #[test]
+    fn test_float_precision() {
+        fn render(recorder: PrometheusRecorder) -> String {
+            let mut recorder = recorder
+                .with_interpolated_quantiles(true)
+                .with_extended_summary(true);
+            recorder.record_histogram("db.query_time", &[1, 2, 2]);
+            recorder.into()
+        }
+
+        // The mean is 5/3, which isn't exact as a float, and the interpolated median is exactly 2.
+        let quantiles = [0.5];
+        let output = render(PrometheusRecorder::with_quantiles(&quantiles));
+        assert!(output.contains("db_query_time_mean 1.6666666666666665\n"));
+        assert!(output.contains("db_query_time{quantile=\"0.5\"} 2\n"));
+
+        let recorder = PrometheusRecorder::with_quantiles(&quantiles).with_float_precision(3);
+        let output = render(recorder);
+        assert!(output.contains("db_query_time_mean 1.67\n"));
+        assert!(output.contains("db_query_time{quantile=\"0.5\"} 2\n"));
+
+        let recorder = PrometheusRecorder::with_quantiles(&quantiles).with_float_precision(1);
+        let output = render(recorder);
+        assert!(output.contains("db_query_time_mean 2\n"));
+        assert!(output.contains("db_query_time_stddev 0.5\n"));
+
+        // Sums stay exact regardless of the precision.
+        let mut recorder = PrometheusRecorder::new().with_float_precision(1);
+        recorder.record_histogram("db.rows", &[123_456]);
+        let output: String = recorder.into();
+        assert!(output.contains("db_rows_sum 123456\n"));
+
+        let recorder = PrometheusRecorder::new().with_float_precision(2);
+        assert_eq!(recorder.float(0.1 + 0.2), SeriesValue::Float(0.3));
+        assert_eq!(recorder.float(123_456.0), SeriesValue::Float(120_000.0));
+        assert_eq!(recorder.float(-0.004_56), SeriesValue::Float(-0.004_6));
+        assert_eq!(recorder.float(0.0), SeriesValue::Float(0.0));
+    }
+
+    #[test]
+    fn test_sample_count_label() {
+        let mut recorder = PrometheusRecorder::with_quantiles(&[0.5, 0.99]);
+        recorder.record_histogram("db.query_time", &[1, 2, 3]);
+        let output: String = recorder.clone().into();
+        assert!(output.contains("db_query_time{quantile=\"0.99\"} 3\n"));
+
+        let mut recorder = recorder.with_sample_count_label(true);
+        let labels = vec![("db", "users")];
+        recorder.record_histogram(
+            Key::from_name_and_labels("db.query_time", &labels),
+            &[1, 2, 3],
+        );
+        recorder.record_signed_histogram("temperature", &[-5, 5]);
+        recorder.record_precomputed_summary("rpc.latency", &[(0.5, 7)], 30, 4);
+
+        let output: String = recorder.into();
+        assert!(output.contains("db_query_time{db=\"users\",quantile=\"0.5\",n=\"3\"} 2\n"));
+        assert!(output.contains("db_query_time{db=\"users\",quantile=\"0.99\",n=\"3\"} 3\n"));
+        assert!(output.contains("db_query_time_count{db=\"users\"} 3\n"));
+        assert!(output.contains("temperature{quantile=\"0.5\",n=\"2\"}"));
+        assert!(output.contains("rpc_latency{quantile=\"0.5\",n=\"4\"} 7\n"));
+    }
+
+    #[test]
+    fn test_load_snapshot() {
+        let mut worker = AggregatingRecorder::new();
+        worker.record_counter("requests", 5);
+        worker.record_gauge("connections", 7);
+        worker.record_histogram("db.query_time", &[3, 1, 3]);
+        let snapshot = worker.get_snapshot().unwrap();
+
+        let mut recorder = PrometheusRecorder::with_quantiles(&[0.5]);
+        recorder.record_counter("requests", 10);
+        recorder.record_gauge("connections", 2);
+        recorder.record_histogram("db.query_time", &[2]);
+        recorder.load_snapshot(&snapshot);
+        recorder.load_snapshot(&snapshot);
+
+        assert_eq!(
+            recorder.quantile(&Key::from_name("db.query_time"), 0.5),
+            Some(3)
+        );
+        let output: String = recorder.into();
+        assert!(output.contains("\nrequests 20\n"));
+        assert!(output.contains("\nconnections 7\n"));
+        assert!(output.contains("\ndb_query_time_sum 16\n"));
+        assert!(output.contains("\ndb_query_time_count 7\n"));
+    }
+
+    #[cfg(feature = "ddsketch")]
+    #[test]
+    fn test_ddsketch_recorder() {
+        use super::HistogramBackendKind;
+
+        let backend = HistogramBackendKind::DDSketch(0.01);
+        let mut recorder = PrometheusRecorder::with_quantiles(&[0.5, 1.0])
+            .with_histogram_backend(backend)
+            .with_extended_summary(true);
+        recorder.set_histogram_mode("db.rows", HistogramMode::Buckets(vec![10.0, 100.0]));
+        let values = (1..=1000).collect::<Vec<_>>();
+        recorder.record_histogram("db.query_time", &values);
+        recorder.record_histogram("db.rows", &[5, 50, 500]);
+
+        let key = Key::from_name("db.query_time");
+        let median = recorder.quantile(&key, 0.5).unwrap();
+        assert!((495..=505).contains(&median), "{}", median);
+        assert_eq!(recorder.quantile(&key, 1.0), Some(1000));
+        assert_eq!(recorder.histogram_len(&key), Some(1000));
+
+        let output: String = recorder.into();
+        assert!(output.contains("db_query_time{quantile=\"1\"} 1000\n"));
+        assert!(output.contains("db_query_time_sum 500500\n"));
+        assert!(output.contains("db_query_time_min 1\n"));
+        assert!(output.contains("db_rows_bucket{le=\"10\"} 1\n"));
+        assert!(output.contains("db_rows_bucket{le=\"100\"} 2\n"));
+        assert!(output.contains("db_rows_bucket{le=\"+Inf\"} 3\n"));
+    }
+
+    #[test]
+    fn test_extended_summary() {
+        let mut recorder = PrometheusRecorder::with_quantiles(&[0.5]).with_extended_summary(true);
+        recorder.record_histogram("db.rows", &[2, 4, 4, 4, 5, 5, 7, 9]);
+        recorder.record_histogram_counts("db.empty", &[]);
+
+        let stat = |name: &str| {
+            let series = recorder.series().find(|s| s.name() == name);
+            match series.map(|s| (s.kind(), s.value())) {
+                Some((SeriesKind::Gauge, SeriesValue::Float(value))) => Some(value),
+                _ => None,
+            }
+        };
+        assert_eq!(stat("db_rows_min"), Some(2.0));
+        assert_eq!(stat("db_rows_max"), Some(9.0));
+        assert!((stat("db_rows_mean").unwrap() - 5.0).abs() < 0.01);
+        assert!((stat("db_rows_stddev").unwrap() - 2.0).abs() < 0.01);
+        assert_eq!(stat("db_empty_stddev"), None);
+
+        let output = recorder.to_string();
+        assert!(output.contains("# TYPE db_rows_stddev gauge\ndb_rows_stddev 2\n"));
+    }
+
+    #[test]
+    fn test_register_zero() {
+        let mut recorder = PrometheusRecorder::with_quantiles(&[0.5]);
+        recorder.set_histogram_mode("db.query_time", HistogramMode::Buckets(vec![5.0]));
+        recorder.register_counter_zero("http.requests");
+        recorder.register_gauge_zero("connections");
+        recorder.register_histogram_empty("db.rows");
+        recorder.register_histogram_empty("db.query_time");
+        recorder.record_gauge("connections", 2);
+        recorder.register_gauge_zero("connections");
+
+        let output = recorder.to_string();
+        for line in &[
+            "\nhttp_requests 0\n",
+            "\nconnections 2\n",
+            "\ndb_rows_sum 0\ndb_rows_count 0\n",
+            "\ndb_query_time_bucket{le=\"5\"} 0\n",
+        ] {
+            assert!(output.contains(line), "missing {:?} in {}", line, output);
+        }
+        assert!(!output.contains("quantile"));
+
+        recorder.record_counter("http.requests", 1);
+        assert!(recorder.to_string().contains("\nhttp_requests 1\n"));
+    }
+
+    #[test]
+    fn test_timestamps() {
+        let ts = SystemTime::UNIX_EPOCH + Duration::from_millis(1_500_000_000_123);
+        let mut recorder = PrometheusRecorder::new();
+        recorder.record_gauge_at("temperature", 21, ts);
+        recorder.record_counter_at("http.requests", 7, ts);
+        recorder.record_counter_at("db.queries", 3, ts);
+        recorder.record_counter("db.queries", 4);
+
+        let output = recorder.to_string();
+        assert!(output.contains("\ntemperature 21 1500000000123\n"));
+        assert!(output.contains("\nhttp_requests 7 1500000000123\n"));
+        assert!(output.contains("\ndb_queries 4\n"));
+
+        let (_, output) = recorder.render_for_accept("application/openmetrics-text");
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("\ntemperature 21 1500000000.123\n"));
+        assert!(output.contains("\nhttp_requests_total 7 1500000000.123\n"));
+
+        let mut recorder = PrometheusRecorder::new().with_textfile_mode(true);
+        recorder.record_gauge_at("temperature", 21, ts);
+        assert_eq!(
+            recorder.to_string(),
+            "# TYPE temperature gauge\ntemperature 21\n"
+        );
+    }
+
+    #[test]
+    fn test_textfile_duplicate_series() {
+        let mut recorder = PrometheusRecorder::new().with_label_order(&["status"]);
+        let labels = vec![("method", "get"), ("status", "200"), ("route", "/")];
+        let reordered = vec![("route", "/"), ("method", "get"), ("status", "200")];
+        recorder.record_counter(Key::from_name_and_labels("http.requests", &labels), 2);
+        recorder.record_counter(Key::from_name_and_labels("http.requests", &reordered), 2);
+        recorder.record_counter(Key::from_name_and_labels("http.requests", &[("a", "b")]), 1);
+
+        let sharded = ShardedHistograms::default();
+        let mut families = recorder.families(&sharded);
+        assert_eq!(
+            dedup_textfile(&mut families),
+            vec![
+                "series `http_requests{status=\"200\",method=\"get\",route=\"/\"}` is rendered \
+                  more than once"
+                    .to_owned()
+            ]
+        );
+        assert_eq!(families.values().next().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_textfile_family_clash() {
+        let mut recorder = PrometheusRecorder::with_quantiles(&[0.5]).with_last_value(true);
+        recorder.record_histogram("db.query_time", &[7]);
+        recorder.record_counter("db.query_time_last", 1);
+        recorder.record_gauge("jobs", 1);
+
+        let sharded = ShardedHistograms::default();
+        let mut families = recorder.families(&sharded);
+        assert_eq!(
+            dedup_textfile(&mut families),
+            vec![
+                "gauge family `db_query_time_last` clashes with a counter family of the same name"
+                    .to_owned()
+            ]
+        );
+        let kinds = families.keys().map(|(name, kind)| (name.as_str(), *kind));
+        assert_eq!(
+            kinds.collect::<Vec<_>>(),
+            vec![
+                ("db_query_time", SeriesKind::Summary),
+                ("db_query_time_last", SeriesKind::Counter),
+                ("jobs", SeriesKind::Gauge),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_textfile_mode() {
+        let mut recorder = PrometheusRecorder::with_quantiles(&[0.5])
+            .with_textfile_mode(true)
+            .with_last_value(true);
+        let labels = vec![("method", "get"), ("status", "200")];
+        let reversed = vec![("status", "200"), ("method", "get")];
+        recorder.record_counter(Key::from_name_and_labels("http.requests", &labels), 2);
+        recorder.record_counter(Key::from_name_and_labels("http.requests", &reversed), 2);
+        recorder.record_histogram("db.query_time", &[7]);
+        recorder.record_counter("db.query_time_last", 1);
+        recorder.set_info("build_info", &[("version", "1.0")]);
+        recorder.record_gauge("build_info", 3);
+
+        let output: String = recorder.into();
+        assert_eq!(
+            output,
+            concat!(
+                "# TYPE build_info gauge\n",
+                "build_info 3\n",
+                "build_info{version=\"1.0\"} 1\n",
+                "# TYPE db_query_time summary\n",
+                "db_query_time{quantile=\"0.5\"} 7\n",
+                "db_query_time_sum 7\n",
+                "db_query_time_count 1\n",
+                "# TYPE db_query_time_last counter\n",
+                "db_query_time_last 1\n",
+                "# TYPE http_requests counter\n",
+                "http_requests{method=\"get\",status=\"200\"} 2\n",
+            )
+        );
+
+        let mut families = Vec::new();
+        let mut series = Vec::new();
+        for line in output.lines() {
+            assert!(!line.is_empty());
+            match line.strip_prefix("# TYPE ") {
+                Some(family) => families.push(family.split(' ').next().unwrap()),
+                None => {
+                    assert!(!line.starts_with('#'));
+                    assert!(line.starts_with(families.last().unwrap()));
+                    series.push(line.rsplit_once(' ').unwrap().0);
+                }
+            }
+        }
+        let (unique_families, unique_series) = (families.len(), series.len());
+        families.dedup();
+        series.sort();
+        series.dedup();
+        assert_eq!(families.len(), unique_families);
+        assert_eq!(series.len(), unique_series);
+        assert!(output.ends_with('\n'));
+    }
+
+    #[test]
+    fn test_gauge_deltas() {
+        let mut recorder = PrometheusRecorder::new();
+        assert!(recorder.supports_gauge_add());
+        recorder.record_gauge_add("connections", 3);
+        recorder.record_gauge_add("connections", -1);
+        recorder.record_gauge("queue_depth", 4);
+        recorder.record_gauge("queue_depth", 2);
+        recorder.record_gauge_add("queue_depth", 5);
+        recorder.record_gauge("pool_size", i64::MAX);
+        recorder.record_gauge_add("pool_size", 1);
+        recorder.record_counter("requests", 1);
+        recorder.record_gauge_add("requests", 1);
+
+        let series = recorder
+            .series()
+            .map(|series| (series.name().to_owned(), series.value().to_string()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            series,
+            vec![
+                ("connections".to_owned(), "2".to_owned()),
+                ("pool_size".to_owned(), i64::MAX.to_string()),
+                ("queue_depth".to_owned(), "7".to_owned()),
+                ("requests".to_owned(), "1".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_gauge_peaks() {
+        let mut recorder = PrometheusRecorder::new();
+        for value in [3, 7, -2, 5].iter() {
+            recorder.record_gauge_max("connections_peak", *value);
+            recorder.record_gauge_min("free_slots_low", *value);
+        }
+        let output = recorder.to_string();
+        assert!(output.contains("\nconnections_peak 7\n"));
+        assert!(output.contains("\nfree_slots_low -2\n"));
+
+        // Each render starts a new window, whose peak is rendered by the next render.
+        assert!(recorder.to_string().contains("\nconnections_peak 7\n"));
+        recorder.record_gauge_max("connections_peak", 5);
+        recorder.record_gauge_max("connections_peak", 2);
+        recorder.record_gauge_min("free_slots_low", 9);
+        let output = recorder.to_string();
+        assert!(output.contains("\nconnections_peak 5\n"));
+        assert!(output.contains("\nfree_slots_low 9\n"));
+
+        // A fresh recorder starts a new window, in which only later values count.
+        let mut recorder = recorder.fresh_with_same_config();
+        recorder.record_gauge_max("connections_peak", 4);
+        recorder.record_gauge_max("connections_peak", 1);
+        recorder.record_gauge_min("free_slots_low", 4);
+        let output = recorder.to_string();
+        assert!(output.contains("\nconnections_peak 4\n"));
+        assert!(output.contains("\nfree_slots_low 4\n"));
+
+        // Setting the gauge directly resets its peak.
+        recorder.record_gauge("connections_peak", 2);
+        recorder.record_gauge_max("connections_peak", 3);
+        assert!(recorder.to_string().contains("\nconnections_peak 3\n"));
+
+        // Peaks are dropped for names already recorded as another type.
+        recorder.record_counter("requests", 1);
+        recorder.record_gauge_max("requests", 10);
+        assert!(recorder.to_string().contains("\nrequests 1\n"));
+    }
+
+    #[test]
+    fn test_sampled_histograms() {
+        let mut recorder = PrometheusRecorder::with_quantiles(&[0.5]);
+        recorder.record_histogram_sampled("request_time", &[10, 20], 0.1);
+        recorder.record_histogram_sampled("request_time", &[30], 1.0);
+
+        let output = recorder.to_string();
+        assert!(output.contains("\nrequest_time_count 21\n"));
+        assert!(output.contains("\nrequest_time_sum 330\n"));
+
+        // Tiny rates are capped at the maximum weight.
+        let mut recorder = PrometheusRecorder::with_quantiles(&[0.5]);
+        recorder.record_histogram_sampled("request_time", &[10], 1e-12);
+        let output = recorder.to_string();
+        assert!(output.contains(&format!("\nrequest_time_count {}\n", MAX_SAMPLE_WEIGHT)));
+    }
+
+    #[test]
+    fn test_duplicate_label_keys() {
+        let mut recorder = PrometheusRecorder::new();
+        let labels = vec![("method", "get"), ("status", "200"), ("method", "post")];
+        recorder.record_counter(Key::from_name_and_labels("http.requests", &labels), 1);
+        recorder.set_info("build_info", &[("version", "1.0"), ("version", "1.1")]);
+
+        let output: String = recorder.into();
+        assert!(output.contains("\nhttp_requests{method=\"post\",status=\"200\"} 1\n"));
+        assert!(output.contains("\nbuild_info{version=\"1.1\"} 1\n"));
+    }
+
+    #[test]
+    #[should_panic(expected = "metric `http.requests` has multiple labels with the key `method`")]
+    fn test_strict_duplicate_label_keys() {
+        let mut recorder = PrometheusRecorder::new().with_strict_labels(true);
+        recorder.record_counter(
+            Key::from_name_and_labels("http.requests", &[("method", "get")]),
+            1,
+        );
+        let labels = vec![("method", "get"), ("method", "post")];
+        recorder.record_counter(Key::from_name_and_labels("http.requests", &labels), 1);
+    }
+
+    #[test]
+    fn test_try_render() {
+        let mut recorder = PrometheusRecorder::new();
+        recorder.record_counter("http_requests", 1);
+        let (output, warnings) = recorder.try_render().unwrap();
+        assert_eq!(output, recorder.to_string());
+        assert!(warnings.is_empty());
+
+        recorder.record_counter("http.requests", 2);
+        recorder.record_gauge("http.requests", 3);
+        recorder.record_counter("http.2xx-responses", 1);
+        let labels = vec![("method", "get"), ("method", "post")];
+        recorder.record_counter(Key::from_name_and_labels("db_queries", &labels), 1);
+
+        let (output, warnings) = recorder.try_render().unwrap();
+        assert_eq!(output, recorder.to_string());
+        assert_eq!(
+            warnings,
+            vec![
+                RenderWarning::SanitizedName {
+                    name: "http.2xx-responses".to_owned(),
+                    sanitized: "http_2xx_responses".to_owned(),
+                },
+                RenderWarning::DuplicateLabel {
+                    name: "db_queries".to_owned(),
+                    label: "method".to_owned(),
+                },
+                RenderWarning::TypeConflict {
+                    name: "http_requests".to_owned(),
+                    declared: "counter".to_owned(),
+                    recorded: "gauge".to_owned(),
+                },
+            ]
+        );
+        assert_eq!(
+            warnings[2].to_string(),
+            "metric `http_requests` recorded as a gauge, but was previously recorded as a counter"
+        );
+
+        let recorder = recorder.with_strict_types(true);
+        assert_eq!(recorder.try_render().unwrap_err().len(), 3);
+    }
+
+    #[test]
+    fn test_signed_histograms() {
+        let mut recorder = PrometheusRecorder::with_quantiles(&[0.0, 0.25, 0.5, 0.75, 1.0]);
+        recorder.record_signed_histogram("pnl", &[-10, 7, -1, 0, 3, -5]);
+        recorder.record_signed_histogram("temperature_delta", &[-4, -2]);
+        recorder.record_signed_histogram("empty", &[]);
+        recorder.record_histogram("pnl", &[100]);
+
+        let key = Key::from_name("pnl");
+        let quantiles = [(0.0, -10), (0.25, -5), (0.5, -1), (0.75, 3), (1.0, 7)];
+        for (q, expected) in quantiles.iter() {
+            assert_eq!(recorder.signed_quantile(&key, *q), Some(*expected));
+        }
+        let key = Key::from_name("temperature_delta");
+        assert_eq!(recorder.signed_quantile(&key, 0.0), Some(-4));
+        assert_eq!(recorder.signed_quantile(&key, 1.0), Some(-2));
+        assert_eq!(recorder.quantile(&key, 1.0), None);
+
+        let output: String = recorder.into();
+        for line in &[
+            "# TYPE pnl summary\npnl{quantile=\"0\"} -10\n",
+            "pnl{quantile=\"0.5\"} -1\n",
+            "pnl{quantile=\"1\"} 7\npnl_sum -6\npnl_count 6\n",
+            "# TYPE empty summary\nempty_sum 0\nempty_count 0\n",
+        ] {
+            assert!(output.contains(line), "missing {:?} in {}", line, output);
+        }
+    }
+
+    #[test]
+    fn test_precomputed_summary() {
+        let mut recorder = PrometheusRecorder::new();
+        let labels = vec![("platform", "ios")];
+        let key = Key::from_name_and_labels("app.launch_time", &labels);
+        recorder.record_precomputed_summary(key.clone(), &[(0.5, 120), (0.99, 900)], 10_000, 50);
+        recorder.record_precomputed_summary(key, &[(0.99, 800), (0.5, 110), (0.9, 300)], 9_000, 60);
+        recorder.record_histogram("app.launch_time", &[1]);
+
+        let output: String = recorder.into();
+        assert!(output.contains(concat!(
+            "# TYPE app_launch_time summary\n",
+            "app_launch_time{platform=\"ios\",quantile=\"0.5\"} 110\n",
+            "app_launch_time{platform=\"ios\",quantile=\"0.9\"} 300\n",
+            "app_launch_time{platform=\"ios\",quantile=\"0.99\"} 800\n",
+            "app_launch_time_sum{platform=\"ios\"} 9000\n",
+            "app_launch_time_count{platform=\"ios\"} 60\n",
+        )));
+        assert_eq!(output.matches("app_launch_time_count").count(), 1);
+    }
+
+    #[test]
+    fn test_render_partitioned() {
+        let mut recorder = PrometheusRecorder::with_quantiles(&[0.5]);
+        for (tenant, requests) in &[("acme", 3), ("globex", 5)] {
+            let labels = vec![("tenant", *tenant), ("method", "get")];
+            recorder.record_counter(
+                Key::from_name_and_labels("http.requests", &labels),
+                *requests,
+            );
+        }
+        let labels = vec![("tenant", "acme")];
+        recorder.record_histogram(Key::from_name_and_labels("db.query_time", &labels), &[7]);
+        recorder.record_gauge("connections", 2);
+
+        let partitions = recorder.render_partitioned("tenant", false);
+        assert_eq!(partitions.len(), 2);
+        let acme = &partitions["acme"];
+        assert!(acme.contains("\nhttp_requests{method=\"get\"} 3\n"));
+        assert!(acme.contains("\ndb_query_time_count 1\n"));
+        let globex = &partitions["globex"];
+        assert!(globex.contains("\nhttp_requests{method=\"get\"} 5\n"));
+        assert!(!globex.contains("db_query_time"));
+        assert!(!globex.contains("connections"));
+
+        let partitions = recorder.render_partitioned("tenant", true);
+        let globex = &partitions["globex"];
+        assert!(globex.contains("\n# TYPE http_requests counter\n"));
+        assert!(globex.contains("\nhttp_requests{method=\"get\",tenant=\"globex\"} 5\n"));
+    }
+
+    #[test]
+    fn test_render_into() {
+        let mut recorder = PrometheusRecorder::with_quantiles(&[0.5, 0.99]);
+        for i in 0..20 {
+            let labels = vec![Label::new("shard", i.to_string())];
+            recorder.record_counter(Key::from_name_and_labels("http.requests", labels), i);
+        }
+        recorder.record_histogram("db.query_time", &[5, 10]);
+        let expected = recorder.to_string();
+
+        let mut buf = String::new();
+        recorder.render_into(&mut buf);
+        assert_eq!(buf, expected);
+        assert!(buf.capacity() >= buf.len());
+        assert!(recorder.estimated_len() >= expected.len());
+
+        // The output is appended, so a reused buffer has to be cleared first.
+        let capacity = buf.capacity();
+        buf.clear();
+        recorder.render_into(&mut buf);
+        assert_eq!(buf, expected);
+        assert_eq!(buf.capacity(), capacity);
+    }
+
+    #[test]
+    fn test_render_filtered() {
+        let mut recorder = PrometheusRecorder::with_quantiles(&[0.5]);
+        recorder.record_counter("http.requests", 1);
+        recorder.record_histogram("http.request_time", &[3]);
+        recorder.record_gauge("db.connections", 2);
+        recorder.record_counter("db.queries", 4);
+
+        let body = |output: String| {
+            let end = output.find('\n').unwrap_or(output.len());
+            let (header, body) = output.split_at(end);
+            assert!(header.starts_with("# metrics snapshot (ts="));
+            body.to_owned()
+        };
+
+        assert_eq!(
+            body(recorder.render_filtered("http_*")),
+            concat!(
+                "\n# TYPE http_request_time summary\n",
+                "http_request_time{quantile=\"0.5\"} 3\n",
+                "http_request_time_sum 3\n",
+                "http_request_time_count 1\n",
+                "\n# TYPE http_requests counter\n",
+                "http_requests 1\n",
+            )
+        );
+        assert_eq!(
+            body(recorder.render_filtered("*_connections")),
+            "\n# TYPE db_connections gauge\ndb_connections 2\n"
+        );
+        assert_eq!(
+            body(recorder.render_filtered("db_queries")),
+            "\n# TYPE db_queries counter\ndb_queries 4\n"
+        );
+        assert_eq!(body(recorder.render_filtered("db_quer")), "");
+        assert_eq!(
+            body(recorder.render_filtered("*")),
+            body(recorder.to_string())
+        );
+    }
 }
@@ -0,0 +1,372 @@
+use crate::{Series, SeriesKind, SeriesValue};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+
+/// An error returned when parsing invalid exposition.
+///
+/// Created by [`parse_exposition`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    line: usize,
+    reason: &'static str,
+}
+
+impl ParseError {
+    /// Gets the number of the line the error is on, starting from 1.
+    pub fn line(&self) -> usize {
+        self.line
+    }
+}
+
+impl Error for ParseError {}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid exposition on line {}: {}",
+            self.line, self.reason
+        )
+    }
+}
+
+/// Parses metrics in the text exposition format back into [`Series`].
+///
+/// This is the inverse of rendering a recorder, and yields the same series as
+/// [`PrometheusRecorder::series`](crate::PrometheusRecorder::series), in the order they appear
+/// in `text`, which allows comparing rendered output semantically rather than byte by byte.
+/// Every sample needs to belong to a family declared by a preceding `# TYPE` line, with one of
+/// the types of [`SeriesKind`], so that it can be typed.  Other comments, such as `# HELP`
+/// lines, and blank lines are skipped.  Label values are unescaped.
+///
+/// Values written as integers are parsed as [`SeriesValue::Integer`], and anything else as
+/// [`SeriesValue::Float`], so a float with an integral value, which is rendered without a
+/// fractional part, is parsed as an integer.  Text with an `# EOF` line is read as OpenMetrics,
+/// whose timestamps are in seconds, and parsing stops at that line, while any other text is read
+/// as the Prometheus text format, whose integral timestamps are in milliseconds.  Either way,
+/// timestamps are returned in milliseconds, and those with a fractional part are read in seconds.
+///
+/// # Errors
+/// Returns an error for the first line that is invalid, or whose family has no known type.
+pub fn parse_exposition(text: &str) -> Result<Vec<Series>, ParseError> {
+    let openmetrics = text.lines().any(|line| line.trim() == "# EOF");
+    let mut types = HashMap::new();
+    let mut series = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let error = |reason| ParseError {
+            line: i + 1,
+            reason,
+        };
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        if let Some(comment) = line.strip_prefix('#') {
+            let mut parts = comment.split_whitespace();
+            match parts.next() {
+                Some("EOF") => break,
+                Some("TYPE") => {
+                    let name = parts.next().ok_or_else(|| error("missing metric name"))?;
+                    let kind = match parts.next() {
+                        Some("counter") => SeriesKind::Counter,
+                        Some("gauge") => SeriesKind::Gauge,
+                        Some("summary") => SeriesKind::Summary,
+                        Some("histogram") => SeriesKind::Histogram,
+                        Some(_) => return Err(error("unsupported metric type")),
+                        None => return Err(error("missing metric type")),
+                    };
+                    types.insert(name.to_owned(), kind);
+                }
+                _ => {}
+            }
+            continue;
+        }
+
+        let (name, labels, rest) = parse_sample(line).map_err(error)?;
+        let kind = family_kind(&types, name).ok_or_else(|| error("sample has no known type"))?;
+
+        let mut parts = rest.split_whitespace();
+        let value = parts.next().ok_or_else(|| error("missing value"))?;
+        let value = match value.parse::<i128>() {
+            Ok(value) => SeriesValue::Integer(value),
+            Err(_) => SeriesValue::Float(parse_float(value).ok_or_else(|| error("invalid value"))?),
+        };
+        let timestamp = parts
+            .next()
+            .map(|ts| parse_timestamp(ts, openmetrics).ok_or_else(|| error("invalid timestamp")))
+            .transpose()?;
+        if parts.next().is_some() {
+            return Err(error("unexpected data after the timestamp"));
+        }
+
+        series.push(Series {
+            name: name.to_owned(),
+            labels,
+            kind,
+            value,
+            timestamp,
+        });
+    }
+    Ok(series)
+}
+
+type LabelPairs = Vec<(String, String)>;
+
+// Splits a sample line into its name, its unescaped labels, and the rest of the line.
+fn parse_sample(line: &str) -> Result<(&str, LabelPairs, &str), &'static str> {
+    let end = line
+        .find(|c: char| c == '{' || c.is_whitespace())
+        .unwrap_or(line.len());
+    let (name, mut rest) = line.split_at(end);
+    if name.is_empty() {
+        return Err("missing metric name");
+    }
+
+    let mut labels = Vec::new();
+    if let Some(inner) = rest.strip_prefix('{') {
+        rest = inner;
+        loop {
+            rest = rest.trim_start();
+            if let Some(after) = rest.strip_prefix('}') {
+                rest = after;
+                break;
+            }
+
+            let eq = rest.find('=').ok_or("invalid label")?;
+            let key = rest[..eq].trim();
+            if key.is_empty() {
+                return Err("missing label name");
+            }
+            let (value, after) = parse_label_value(rest[eq + 1..].trim_start())?;
+            labels.push((key.to_owned(), value));
+
+            rest = after.trim_start();
+            if let Some(after) = rest.strip_prefix(',') {
+                rest = after;
+            } else if !rest.starts_with('}') {
+                return Err("expected `,` or `}` after label");
+            }
+        }
+    }
+    Ok((name, labels, rest))
+}
+
+// Parses a quoted, escaped label value, returning it unescaped along with the rest of the input.
+fn parse_label_value(input: &str) -> Result<(String, &str), &'static str> {
+    let input = input.strip_prefix('"').ok_or("label value is not quoted")?;
+    let mut value = String::new();
+    let mut chars = input.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => return Ok((value, &input[i + 1..])),
+            '\\' => match chars.next() {
+                Some((_, '\\')) => value.push('\\'),
+                Some((_, '"')) => value.push('"'),
+                Some((_, 'n')) => value.push('\n'),
+                _ => return Err("invalid escape in label value"),
+            },
+            c => value.push(c),
+        }
+    }
+    Err("unterminated label value")
+}
+
+// Finds the type of the family `name` belongs to, which can be declared under the name itself, or
+// under the name without the suffix of a summary, histogram, or OpenMetrics counter sample.
+fn family_kind(types: &HashMap<String, SeriesKind>, name: &str) -> Option<SeriesKind> {
+    if let Some(kind) = types.get(name) {
+        return Some(*kind);
+    }
+
+    let suffixes: &[(&str, &[SeriesKind])] = &[
+        ("_sum", &[SeriesKind::Summary, SeriesKind::Histogram]),
+        ("_count", &[SeriesKind::Summary, SeriesKind::Histogram]),
+        ("_bucket", &[SeriesKind::Histogram]),
+        ("_total", &[SeriesKind::Counter]),
+    ];
+    suffixes.iter().find_map(|(suffix, kinds)| {
+        let family = name.strip_suffix(suffix)?;
+        types
+            .get(family)
+            .copied()
+            .filter(|kind| kinds.contains(kind))
+    })
+}
+
+fn parse_float(value: &str) -> Option<f64> {
+    match value {
+        "+Inf" | "Inf" => Some(f64::INFINITY),
+        "-Inf" => Some(f64::NEG_INFINITY),
+        "NaN" => Some(f64::NAN),
+        value => value.parse().ok(),
+    }
+}
+
+// Parses a timestamp into milliseconds, where integral timestamps are in seconds in OpenMetrics.
+fn parse_timestamp(value: &str, openmetrics: bool) -> Option<u64> {
+    match value.split_once('.') {
+        Some((seconds, millis)) => {
+            let seconds = seconds.parse::<u64>().ok()?;
+            // Only millisecond precision is kept, so any further digits are truncated.
+            let digits = millis.get(..millis.len().min(3))?;
+            if !millis.bytes().all(|b| b.is_ascii_digit()) {
+                return None;
+            }
+            let millis = format!("{:0<3}", digits).parse::<u64>().ok()?;
+            seconds.checked_mul(1_000)?.checked_add(millis)
+        }
+        None if openmetrics => value.parse::<u64>().ok()?.checked_mul(1_000),
+        None => value.parse().ok(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_exposition;
+    use crate::{PrometheusRecorder, SeriesKind, SeriesValue};
+    use metrics_core::{Key, Recorder};
+    use std::time::{Duration, SystemTime};
+
+    // Compares series semantically, as integral floats are parsed back as integers.
+    fn normalized(series: impl Iterator<Item = crate::Series>) -> Vec<String> {
+        series
+            .map(|s| {
+                format!(
+                    "{} {:?} {:?} {} {:?}",
+                    s.name(),
+                    s.labels(),
+                    s.kind(),
+                    s.value().as_f64(),
+                    s.timestamp()
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut recorder = PrometheusRecorder::with_quantiles(&[0.5, 0.99])
+            .with_interpolated_quantiles(true)
+            .with_namespace("app");
+        recorder.set_histogram_mode(
+            "http.request_time",
+            crate::HistogramMode::Buckets(vec![5.0, 50.0]),
+        );
+        let labels = vec![("path", "/a \"b\"\\c\nd"), ("method", "get")];
+        recorder.record_counter(Key::from_name_and_labels("http.requests", &labels), 12);
+        let ts = SystemTime::UNIX_EPOCH + Duration::from_millis(1_500_000_000_123);
+        recorder.record_gauge_at("connections", -3, ts);
+        recorder.record_histogram("http.request_time", &[1, 10, 100]);
+        recorder.record_histogram("db.query_time", &[1, 2, 3, 4]);
+        recorder.set_info("build_info", &[("version", "1.2.3")]);
+
+        let parsed = parse_exposition(&recorder.to_string()).unwrap();
+        assert_eq!(
+            normalized(parsed.into_iter()),
+            normalized(recorder.series())
+        );
+
+        let (_, openmetrics) = recorder.render_for_accept("application/openmetrics-text");
+        let parsed = parse_exposition(std::str::from_utf8(&openmetrics).unwrap()).unwrap();
+        let counter = parsed
+            .iter()
+            .find(|s| s.kind() == SeriesKind::Counter)
+            .unwrap();
+        assert_eq!(counter.name(), "app_http_requests_total");
+        assert_eq!(counter.labels()[1].1, "/a \"b\"\\c\nd");
+        let gauge = parsed
+            .iter()
+            .find(|s| s.name() == "app_connections")
+            .unwrap();
+        assert_eq!(gauge.value(), SeriesValue::Integer(-3));
+        assert_eq!(gauge.timestamp(), Some(1_500_000_000_123));
+    }
+
+    #[test]
+    fn test_parse_comments_and_values() {
+        let text = "\
+# HELP requests Total requests.
+# TYPE requests counter
+requests{method=\"get\",} 12 1500000000.123
+requests{method=\"post\"} 3 1500000000
+
+# TYPE latency summary
+latency{quantile=\"0.5\"} 0.25
+latency{ quantile = \"1\" } +Inf
+latency_sum 10.5
+latency_count 4
+# EOF
+garbage
+";
+        let parsed = parse_exposition(text).unwrap();
+        assert_eq!(parsed.len(), 6);
+        assert_eq!(
+            parsed[0].labels(),
+            &[("method".to_owned(), "get".to_owned())]
+        );
+        assert_eq!(parsed[0].value(), SeriesValue::Integer(12));
+        assert_eq!(parsed[0].timestamp(), Some(1_500_000_000_123));
+        assert_eq!(parsed[1].timestamp(), Some(1_500_000_000_000));
+        assert_eq!(parsed[2].value(), SeriesValue::Float(0.25));
+        assert_eq!(parsed[3].value(), SeriesValue::Float(f64::INFINITY));
+        assert_eq!(parsed[4].name(), "latency_sum");
+        assert_eq!(parsed[4].kind(), SeriesKind::Summary);
+        assert!(parsed[4].labels().is_empty());
+    }
+
+    #[test]
+    fn test_parse_openmetrics_timestamps() {
+        let text = "# TYPE x gauge\nx 1 1520879607\nx 2 1520879607.789\n# EOF\n";
+        let parsed = parse_exposition(text).unwrap();
+        assert_eq!(parsed[0].timestamp(), Some(1_520_879_607_000));
+        assert_eq!(parsed[1].timestamp(), Some(1_520_879_607_789));
+
+        let text = "# TYPE x gauge\nx 1 1520879607\n";
+        let parsed = parse_exposition(text).unwrap();
+        assert_eq!(parsed[0].timestamp(), Some(1_520_879_607));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let cases = [
+            ("requests 1\n", 1, "sample has no known type"),
+            ("# TYPE requests untyped\n", 1, "unsupported metric type"),
+            ("# TYPE requests counter\nrequests\n", 2, "missing value"),
+            (
+                "# TYPE requests counter\nrequests abc\n",
+                2,
+                "invalid value",
+            ),
+            (
+                "# TYPE requests counter\nrequests 1 2 3\n",
+                2,
+                "unexpected data after the timestamp",
+            ),
+            (
+                "# TYPE requests counter\nrequests{a=\"b} 1\n",
+                2,
+                "unterminated label value",
+            ),
+            (
+                "# TYPE requests counter\nrequests{a=b} 1\n",
+                2,
+                "label value is not quoted",
+            ),
+            (
+                "# TYPE requests counter\nrequests_bucket 1\n",
+                2,
+                "sample has no known type",
+            ),
+        ];
+        for (text, line, reason) in cases.iter() {
+            let error = parse_exposition(text).unwrap_err();
+            assert_eq!(error.line(), *line, "{}", text);
+            assert_eq!(
+                error.to_string(),
+                format!("invalid exposition on line {}: {}", line, reason)
+            );
+        }
+    }
+}
@@ -0,0 +1,112 @@
+use crate::{
+    cap_value, new_histogram_entry, HistogramBackendKind, HistogramBounds, HistogramEntry,
+};
+use metrics_core::Key;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, Mutex};
+
+/// A handle for recording histograms into a [`PrometheusRecorder`](crate::PrometheusRecorder)
+/// from many threads at once.
+///
+/// Histograms are spread over a fixed number of shards by the hash of their key, each behind its
+/// own lock, so threads recording different histograms rarely contend with each other.  The
+/// shards are merged into the histograms of the recorder whenever it is rendered.
+///
+/// Handles are cheap to clone, and every clone records into the same shards.  Created by
+/// [`PrometheusRecorder::histogram_shards`](crate::PrometheusRecorder::histogram_shards).
+#[derive(Clone)]
+pub struct HistogramShards {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    shards: Box<[Mutex<HashMap<Key, HistogramEntry>>]>,
+    bounds: HistogramBounds,
+    backend: HistogramBackendKind,
+    max: u64,
+    max_value: Option<u64>,
+}
+
+impl HistogramShards {
+    pub(crate) fn new(
+        count: usize,
+        bounds: HistogramBounds,
+        backend: HistogramBackendKind,
+        max: u64,
+        max_value: Option<u64>,
+    ) -> Self {
+        let shards = (0..count).map(|_| Mutex::new(HashMap::new())).collect();
+        HistogramShards {
+            inner: Arc::new(Inner {
+                shards,
+                bounds,
+                backend,
+                max,
+                max_value,
+            }),
+        }
+    }
+
+    /// Gets the number of shards.
+    pub fn shard_count(&self) -> usize {
+        self.inner.shards.len()
+    }
+
+    /// Records histogram values for `key`.
+    ///
+    /// Only the shard `key` belongs to is locked while recording.  Values are clamped to the
+    /// bounds and capped at the maximum value of the recorder, as with
+    /// [`Recorder::record_histogram`](metrics_core::Recorder), though capped values are only
+    /// counted once the shards are merged.
+    pub fn record<K: Into<Key>>(&self, key: K, values: &[u64]) {
+        let key = key.into();
+        let (bounds, backend, max) = (self.inner.bounds, self.inner.backend, self.inner.max);
+        let max_value = self.inner.max_value;
+        let mut shard = self.shard(&key).lock().expect("histogram shard poisoned");
+        let entry = shard
+            .entry(key)
+            .or_insert_with(|| new_histogram_entry(bounds, backend));
+        for value in values {
+            let value = cap_value(*value, 1, max_value, &mut entry.saturated);
+            entry.record_n(value, 1, bounds.lowest, max);
+        }
+    }
+
+    // Removes the histogram for `key`, returning whether there was one.
+    pub(crate) fn remove(&self, key: &Key) -> bool {
+        self.shard(key)
+            .lock()
+            .expect("histogram shard poisoned")
+            .remove(key)
+            .is_some()
+    }
+
+    // Calls `f` with the histogram for `key`, if there is one, locking only its shard.
+    pub(crate) fn with_entry<R, F: FnOnce(&HistogramEntry) -> R>(
+        &self,
+        key: &Key,
+        f: F,
+    ) -> Option<R> {
+        let shard = self.shard(key).lock().expect("histogram shard poisoned");
+        shard.get(key).map(f)
+    }
+
+    fn shard(&self, key: &Key) -> &Mutex<HashMap<Key, HistogramEntry>> {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        let index = hasher.finish() % self.inner.shards.len() as u64;
+        &self.inner.shards[index as usize]
+    }
+
+    // Calls `f` with every histogram in the shards, locking one shard at a time.
+    pub(crate) fn for_each<F: FnMut(&Key, &HistogramEntry)>(&self, mut f: F) {
+        for shard in self.inner.shards.iter() {
+            let shard = shard.lock().expect("histogram shard poisoned");
+            for (key, entry) in shard.iter() {
+                f(key, entry);
+            }
+        }
+    }
+}
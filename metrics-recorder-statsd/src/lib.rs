@@ -0,0 +1,424 @@
+//! Records metrics as StatsD lines.
+//!
+//! Each recording is rendered as a single line in the StatsD format, i.e. `name:value|type`.
+//! Labels are rendered as DogStatsD tags, so a counter with the labels `method=get` and
+//! `status=200` would be rendered as:
+//!
+//! ```c
+//! http.requests:1|c|#method:get,status:200
+//! ```
+//!
+//! Characters that are part of the line format, i.e. `:`, `|`, `,`, `#` and newlines, are
+//! replaced with underscores in names and tags, so labels can't break the line.
+//!
+//! ## Counters
+//!
+//! Counters are recorded as their total, while StatsD counters are increments, so only the
+//! difference to the total last rendered for the same counter is rendered, and nothing is
+//! rendered if the total didn't change.  A total lower than the last one means the counter was
+//! reset, and is rendered in full.  The last totals are shared by every clone of a recorder, so
+//! that rendering each snapshot with a fresh clone works as expected.
+//!
+//! ## Histograms
+//!
+//! DogStatsD has multiple types that can be used for histogram values: histograms (`|h`), which
+//! are aggregated by the agent running on each host, and distributions (`|d`), which are
+//! aggregated server-side and thus allow computing correct percentiles across hosts.  Plain
+//! StatsD also has timings (`|ms`).
+//!
+//! The type used is controlled by [`HistogramMode`], which can be configured for all histograms
+//! when creating the recorder, and overridden for specific metrics with
+//! [`StatsdRecorder::set_histogram_mode_for`].  Every value recorded is rendered on its own line.
+//!
+//! Values recorded with [`Recorder::record_histogram_sampled`] are rendered with their sample
+//! rate, which the server uses to scale its counts:
+//!
+//! ```c
+//! http.request_time:12|h|@0.1
+//! ```
+//!
+//! ## Gauges
+//!
+//! StatsD treats a gauge value with a leading sign as a delta to apply to the current value, so
+//! `-5` decrements the gauge rather than setting it.  In [`GaugeMode::Absolute`], which is the
+//! default, negative values are rendered by first resetting the gauge to zero and then applying
+//! the value as a delta:
+//!
+//! ```c
+//! temperature:0|g
+//! temperature:-5|g
+//! ```
+//!
+//! In [`GaugeMode::Delta`], every value is rendered with an explicit sign and is applied as a
+//! delta by the server.
+//!
+//! Regardless of the mode, values recorded with [`Recorder::record_gauge_add`] are always
+//! rendered as deltas.
+//!
+//! ## Sets
+//!
+//! Values recorded with [`Recorder::record_set`] are rendered as sets (`|s`), which the server
+//! uses to count the unique values seen for each metric.
+use metrics_core::{Key, Label, Recorder};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// Which StatsD type to use when rendering histogram values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HistogramMode {
+    /// Renders values as histograms (`|h`), which are aggregated on the host.
+    Histogram,
+
+    /// Renders values as distributions (`|d`), which are aggregated server-side.
+    Distribution,
+
+    /// Renders values as timings (`|ms`).
+    Timing,
+}
+
+/// How gauge values are interpreted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GaugeMode {
+    /// Values are the absolute value of the gauge.
+    Absolute,
+
+    /// Values are deltas to apply to the current value of the gauge.
+    Delta,
+}
+
+impl HistogramMode {
+    fn suffix(self) -> &'static str {
+        match self {
+            HistogramMode::Histogram => "h",
+            HistogramMode::Distribution => "d",
+            HistogramMode::Timing => "ms",
+        }
+    }
+}
+
+/// Records metrics as StatsD lines.
+pub struct StatsdRecorder {
+    histogram_mode: HistogramMode,
+    histogram_overrides: HashMap<String, HistogramMode>,
+    gauge_mode: GaugeMode,
+    counter_totals: Arc<Mutex<HashMap<Key, u64>>>,
+    output: String,
+}
+
+impl StatsdRecorder {
+    /// Creates a new [`StatsdRecorder`] which renders histograms as [`HistogramMode::Histogram`].
+    ///
+    /// If you want to use a different mode, you can call [`StatsdRecorder::with_histogram_mode`].
+    pub fn new() -> Self {
+        Self::with_histogram_mode(HistogramMode::Histogram)
+    }
+
+    /// Creates a new [`StatsdRecorder`] with the given histogram mode.
+    ///
+    /// The mode is used when rendering any histograms which don't have an override.
+    pub fn with_histogram_mode(mode: HistogramMode) -> Self {
+        Self {
+            histogram_mode: mode,
+            histogram_overrides: HashMap::new(),
+            gauge_mode: GaugeMode::Absolute,
+            counter_totals: Arc::new(Mutex::new(HashMap::new())),
+            output: String::new(),
+        }
+    }
+
+    /// Sets the mode used when rendering the histogram with the given name.
+    ///
+    /// This is how a metric is "tagged" as a timing, for example, while other histograms keep
+    /// using the mode the recorder was created with.
+    pub fn set_histogram_mode_for<N: Into<String>>(&mut self, name: N, mode: HistogramMode) {
+        self.histogram_overrides.insert(name.into(), mode);
+    }
+
+    /// Sets how gauge values are interpreted.
+    ///
+    /// Defaults to [`GaugeMode::Absolute`].
+    pub fn set_gauge_mode(&mut self, mode: GaugeMode) {
+        self.gauge_mode = mode;
+    }
+
+    fn push_histogram(&mut self, key: Key, values: &[u64], rate: f64) {
+        let (name, tags) = key_to_parts(key);
+        let mode = self
+            .histogram_overrides
+            .get(name.as_str())
+            .cloned()
+            .unwrap_or(self.histogram_mode);
+        // Rates outside of (0, 1) mean the values weren't sampled, so no rate is rendered.
+        let kind = if rate > 0.0 && rate < 1.0 {
+            format!("{}|@{}", mode.suffix(), rate)
+        } else {
+            mode.suffix().to_owned()
+        };
+
+        for value in values {
+            self.push_line(
+                name.as_str(),
+                value.to_string().as_str(),
+                kind.as_str(),
+                tags.as_str(),
+            );
+        }
+    }
+
+    fn push_line(&mut self, name: &str, value: &str, kind: &str, tags: &str) {
+        self.output.push_str(name);
+        self.output.push(':');
+        self.output.push_str(value);
+        self.output.push('|');
+        self.output.push_str(kind);
+        self.output.push_str(tags);
+        self.output.push('\n');
+    }
+}
+
+impl Recorder for StatsdRecorder {
+    fn record_counter<K: Into<Key>>(&mut self, key: K, value: u64) {
+        let key = key.into();
+        let last = self
+            .counter_totals
+            .lock()
+            .expect("counter totals poisoned")
+            .insert(key.clone(), value);
+        // A total lower than the last one means the counter was reset.
+        let delta = match last {
+            Some(last) if last <= value => value - last,
+            _ => value,
+        };
+        if delta == 0 {
+            return;
+        }
+
+        let (name, tags) = key_to_parts(key);
+        self.push_line(
+            name.as_str(),
+            delta.to_string().as_str(),
+            "c",
+            tags.as_str(),
+        );
+    }
+
+    fn record_gauge<K: Into<Key>>(&mut self, key: K, value: i64) {
+        let (name, tags) = key_to_parts(key.into());
+        let value = match self.gauge_mode {
+            GaugeMode::Absolute => {
+                if value < 0 {
+                    // A negative value would be treated as a delta, so reset the gauge first.
+                    self.push_line(name.as_str(), "0", "g", tags.as_str());
+                }
+                value.to_string()
+            }
+            GaugeMode::Delta => format!("{:+}", value),
+        };
+        self.push_line(name.as_str(), value.as_str(), "g", tags.as_str());
+    }
+
+    fn record_gauge_add<K: Into<Key>>(&mut self, key: K, delta: i64) {
+        let (name, tags) = key_to_parts(key.into());
+        let delta = format!("{:+}", delta);
+        self.push_line(name.as_str(), delta.as_str(), "g", tags.as_str());
+    }
+
+    fn supports_gauge_add(&self) -> bool {
+        true
+    }
+
+    fn record_histogram<K: Into<Key>>(&mut self, key: K, values: &[u64]) {
+        self.push_histogram(key.into(), values, 1.0);
+    }
+
+    fn record_histogram_sampled<K: Into<Key>>(&mut self, key: K, values: &[u64], rate: f64) {
+        self.push_histogram(key.into(), values, rate);
+    }
+
+    fn record_set<K: Into<Key>>(&mut self, key: K, value: &str) {
+        let (name, tags) = key_to_parts(key.into());
+        self.push_line(name.as_str(), value, "s", tags.as_str());
+    }
+}
+
+impl Default for StatsdRecorder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clone for StatsdRecorder {
+    fn clone(&self) -> Self {
+        Self {
+            histogram_mode: self.histogram_mode,
+            histogram_overrides: self.histogram_overrides.clone(),
+            gauge_mode: self.gauge_mode,
+            counter_totals: self.counter_totals.clone(),
+            output: String::new(),
+        }
+    }
+}
+
+impl From<StatsdRecorder> for String {
+    fn from(val: StatsdRecorder) -> Self {
+        val.output
+    }
+}
+
+fn key_to_parts(key: Key) -> (String, String) {
+    let (name, labels) = key.into_parts();
+    let tags = labels
+        .into_iter()
+        .map(Label::into_parts)
+        .map(|(k, v)| format!("{}:{}", sanitize(&k), sanitize(&v)))
+        .collect::<Vec<_>>();
+
+    let tags = if tags.is_empty() {
+        String::new()
+    } else {
+        format!("|#{}", tags.join(","))
+    };
+
+    (sanitize(&name), tags)
+}
+
+// Replaces the characters that delimit the parts of a line.
+fn sanitize(value: &str) -> String {
+    value.replace(&[':', '|', ',', '#', '\n'][..], "_")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{GaugeMode, HistogramMode, StatsdRecorder};
+    use metrics_core::{Key, Recorder};
+
+    #[test]
+    fn test_tags() {
+        let mut recorder = StatsdRecorder::new();
+        let labels = vec![("method", "get"), ("status", "200")];
+        recorder.record_counter(Key::from_name_and_labels("http.requests", &labels), 3);
+        recorder.record_gauge("connections", 12);
+
+        let output: String = recorder.into();
+        assert_eq!(
+            output,
+            "http.requests:3|c|#method:get,status:200\nconnections:12|g\n"
+        );
+    }
+
+    #[test]
+    fn test_counter_deltas() {
+        let mut recorder = StatsdRecorder::new();
+        recorder.record_counter("http.requests", 10);
+        let mut next = recorder.clone();
+        let output: String = recorder.into();
+        assert_eq!(output, "http.requests:10|c\n");
+
+        next.record_counter("http.requests", 15);
+        next.record_counter("http.requests", 15);
+        next.record_counter("http.requests", 4);
+        let output: String = next.into();
+        assert_eq!(output, "http.requests:5|c\nhttp.requests:4|c\n");
+    }
+
+    #[test]
+    fn test_sanitized_names_and_tags() {
+        let mut recorder = StatsdRecorder::new();
+        let labels = vec![("path", "/a:b|c,d#e"), ("k:y", "v")];
+        recorder.record_gauge(Key::from_name_and_labels("http|requests:x", &labels), 1);
+
+        let output: String = recorder.into();
+        assert_eq!(output, "http_requests_x:1|g|#path:/a_b_c_d_e,k_y:v\n");
+    }
+
+    #[test]
+    fn test_histogram_modes() {
+        let mut recorder = StatsdRecorder::with_histogram_mode(HistogramMode::Distribution);
+        recorder.set_histogram_mode_for("db.query_time", HistogramMode::Timing);
+        recorder.set_histogram_mode_for("db.rows", HistogramMode::Histogram);
+
+        recorder.record_histogram("http.request_time", &[1, 2]);
+        recorder.record_histogram("db.query_time", &[3]);
+        recorder.record_histogram("db.rows", &[4]);
+
+        let output: String = recorder.into();
+        assert_eq!(
+            output,
+            "http.request_time:1|d\nhttp.request_time:2|d\ndb.query_time:3|ms\ndb.rows:4|h\n"
+        );
+    }
+
+    #[test]
+    fn test_sampled_histograms() {
+        let mut recorder = StatsdRecorder::new();
+        recorder.set_histogram_mode_for("db.query_time", HistogramMode::Timing);
+        let labels = vec![("method", "get")];
+        let key = Key::from_name_and_labels("http.request_time", &labels);
+        recorder.record_histogram_sampled(key, &[12, 15], 0.1);
+        recorder.record_histogram_sampled("db.query_time", &[3], 0.25);
+        recorder.record_histogram_sampled("db.rows", &[4], 1.0);
+
+        let output: String = recorder.into();
+        assert_eq!(
+            output,
+            concat!(
+                "http.request_time:12|h|@0.1|#method:get\n",
+                "http.request_time:15|h|@0.1|#method:get\n",
+                "db.query_time:3|ms|@0.25\n",
+                "db.rows:4|h\n",
+            )
+        );
+    }
+
+    #[test]
+    fn test_negative_gauges() {
+        let mut recorder = StatsdRecorder::new();
+        recorder.record_gauge("temperature", -5);
+        recorder.record_gauge("temperature", 3);
+
+        let output: String = recorder.into();
+        assert_eq!(
+            output,
+            "temperature:0|g\ntemperature:-5|g\ntemperature:3|g\n"
+        );
+
+        let mut recorder = StatsdRecorder::new();
+        recorder.set_gauge_mode(GaugeMode::Delta);
+        recorder.record_gauge("queue_depth", -5);
+        recorder.record_gauge("queue_depth", 3);
+
+        let output: String = recorder.into();
+        assert_eq!(output, "queue_depth:-5|g\nqueue_depth:+3|g\n");
+    }
+
+    #[test]
+    fn test_sets() {
+        let mut recorder = StatsdRecorder::new();
+        recorder.record_set("users.unique", "alice");
+        recorder.record_set(
+            Key::from_name_and_labels("users.unique", &[("region", "eu")]),
+            "bob",
+        );
+
+        let output: String = recorder.into();
+        assert_eq!(
+            output,
+            "users.unique:alice|s\nusers.unique:bob|s|#region:eu\n"
+        );
+    }
+
+    #[test]
+    fn test_gauge_deltas() {
+        let mut recorder = StatsdRecorder::new();
+        assert!(recorder.supports_gauge_add());
+        recorder.record_gauge("connections", 3);
+        recorder.record_gauge_add("connections", 2);
+        recorder.record_gauge_add("connections", -4);
+
+        let output: String = recorder.into();
+        assert_eq!(
+            output,
+            "connections:3|g\nconnections:+2|g\nconnections:-4|g\n"
+        );
+    }
+}
@@ -43,7 +43,7 @@
 //! ```
 //!
 use hdrhistogram::Histogram;
-use metrics_core::{Key, Recorder};
+use metrics_core::{Key, Label, Recorder};
 use metrics_util::{parse_quantiles, Quantile};
 use std::collections::{HashMap, VecDeque};
 use std::fmt::Display;
@@ -81,13 +81,13 @@ impl TextRecorder {
 
 impl Recorder for TextRecorder {
     fn record_counter<K: Into<Key>>(&mut self, key: K, value: u64) {
-        let (name_parts, name) = name_to_parts(key.into().as_ref());
+        let (name_parts, name) = key_to_parts(key.into());
         let mut values = single_value_to_values(name, value);
         self.structure.insert(name_parts, &mut values);
     }
 
     fn record_gauge<K: Into<Key>>(&mut self, key: K, value: i64) {
-        let (name_parts, name) = name_to_parts(key.into().as_ref());
+        let (name_parts, name) = key_to_parts(key.into());
         let mut values = single_value_to_values(name, value);
         self.structure.insert(name_parts, &mut values);
     }
@@ -98,12 +98,18 @@ impl Recorder for TextRecorder {
             h.record(*value).expect("failed to record histogram value");
         }
 
-        let (name_parts, name) = name_to_parts(key.into().as_ref());
+        let (name_parts, name) = key_to_parts(key.into());
         let mut values = hist_to_values(name, h, &self.quantiles);
         self.structure.insert(name_parts, &mut values);
     }
 }
 
+impl Default for TextRecorder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Clone for TextRecorder {
     fn clone(&self) -> Self {
         Self {
@@ -172,7 +178,7 @@ impl MetricsTree {
             match entry {
                 SortEntry::Inline(s) => {
                     output.push_str(s.as_str());
-                    output.push_str("\n");
+                    output.push('\n');
                 }
                 SortEntry::Nested(s, inner) => {
                     output.push_str(indent.as_str());
@@ -189,9 +195,9 @@ impl MetricsTree {
     }
 }
 
-impl Into<String> for TextRecorder {
-    fn into(self) -> String {
-        self.structure.into_output()
+impl From<TextRecorder> for String {
+    fn from(val: TextRecorder) -> Self {
+        val.structure.into_output()
     }
 }
 
@@ -229,13 +235,26 @@ impl std::cmp::Ord for SortEntry {
     }
 }
 
-fn name_to_parts(name: &str) -> (VecDeque<String>, String) {
+fn key_to_parts(key: Key) -> (VecDeque<String>, String) {
+    let (name, labels) = key.into_parts();
     let mut parts = name
         .split('.')
         .map(ToOwned::to_owned)
         .collect::<VecDeque<_>>();
     let name = parts.pop_back().expect("name didn't have a single part");
 
+    let labels = labels
+        .into_iter()
+        .map(Label::into_parts)
+        .map(|(k, v)| format!("{}=\"{}\"", k, v))
+        .collect::<Vec<_>>()
+        .join(",");
+    let name = if labels.is_empty() {
+        name
+    } else {
+        format!("{}{{{}}}", name, labels)
+    };
+
     (parts, name)
 }
 
@@ -9,7 +9,7 @@ use metrics_util::StreamingIntegers;
 use rand::{
     distributions::{Distribution, Gamma},
     rngs::SmallRng,
-    Rng, SeedableRng,
+    SeedableRng,
 };
 use std::time::Duration;
 
@@ -0,0 +1,60 @@
+#[macro_use]
+extern crate criterion;
+
+use criterion::{Benchmark, Criterion, Throughput};
+use metrics_util::{
+    populate_synthetic, AggregatingRecorder, DedupGaugeRecorder, PromoteLabelRecorder,
+    SuffixRecorder, ValidatingRecorder, Validation,
+};
+use std::time::Duration;
+
+const SERIES: usize = 1_000;
+const SAMPLES: usize = 10;
+
+fn wrappers_benchmark(c: &mut Criterion) {
+    let recordings = (SERIES / 3 * 2 * SAMPLES + SERIES / 3) as u32;
+    c.bench(
+        "wrappers",
+        Benchmark::new("aggregating", |b| {
+            b.iter(|| {
+                let mut recorder = AggregatingRecorder::new();
+                populate_synthetic(&mut recorder, SERIES, SAMPLES);
+                recorder
+            })
+        })
+        .with_function("suffix", |b| {
+            b.iter(|| {
+                let mut recorder = SuffixRecorder::new(AggregatingRecorder::new(), "_staging");
+                populate_synthetic(&mut recorder, SERIES, SAMPLES);
+                recorder
+            })
+        })
+        .with_function("promote_label", |b| {
+            b.iter(|| {
+                let mut recorder = PromoteLabelRecorder::new(AggregatingRecorder::new(), "shard");
+                populate_synthetic(&mut recorder, SERIES, SAMPLES);
+                recorder
+            })
+        })
+        .with_function("dedup", |b| {
+            b.iter(|| {
+                let mut recorder =
+                    DedupGaugeRecorder::new(AggregatingRecorder::new(), Duration::from_secs(60));
+                populate_synthetic(&mut recorder, SERIES, SAMPLES);
+                recorder
+            })
+        })
+        .with_function("validating", |b| {
+            b.iter(|| {
+                let mut recorder = ValidatingRecorder::new(AggregatingRecorder::new());
+                recorder.add_bounds("synthetic.gauge_*", 0, i64::MAX, Validation::Clamp);
+                populate_synthetic(&mut recorder, SERIES, SAMPLES);
+                recorder
+            })
+        })
+        .throughput(Throughput::Elements(recordings)),
+    );
+}
+
+criterion_group!(benches, wrappers_benchmark);
+criterion_main!(benches);
@@ -0,0 +1,219 @@
+use metrics_core::{Key, Recorder, Snapshot, SnapshotProvider};
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::mem;
+
+/// A recorder that aggregates recordings in memory.
+///
+/// Counters and gauges keep the last value recorded, as counters are recorded as their total, and
+/// gauge deltas are applied to the last value of the gauge.  Histograms keep every value recorded,
+/// with sampled values repeated by their sample weight.  As a [`SnapshotProvider`], it hands out
+/// point-in-time copies of the aggregated metrics, which can then be rendered by any other
+/// recorder.
+#[derive(Debug, Clone, Default)]
+pub struct AggregatingRecorder {
+    counters: HashMap<Key, u64>,
+    gauges: HashMap<Key, i64>,
+    histograms: HashMap<Key, Vec<u64>>,
+}
+
+impl AggregatingRecorder {
+    /// Creates a new, empty [`AggregatingRecorder`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Takes the metrics aggregated so far, leaving the recorder empty.
+    ///
+    /// This is meant for push-based exporters, which export what was recorded since their last
+    /// push.  Taking the metrics and resetting the recorder happen in a single call, so when the
+    /// recorder is shared behind a lock, nothing recorded by other threads can land in between
+    /// and be lost, or be exported twice.  Every metric is reset, including gauges, so a gauge
+    /// is only part of the next snapshot if it is recorded again.
+    pub fn drain_snapshot(&mut self) -> AggregatedSnapshot {
+        AggregatedSnapshot {
+            counters: mem::take(&mut self.counters),
+            gauges: mem::take(&mut self.gauges),
+            histograms: mem::take(&mut self.histograms),
+        }
+    }
+}
+
+impl Recorder for AggregatingRecorder {
+    fn record_counter<K: Into<Key>>(&mut self, key: K, value: u64) {
+        self.counters.insert(key.into(), value);
+    }
+
+    fn record_gauge<K: Into<Key>>(&mut self, key: K, value: i64) {
+        self.gauges.insert(key.into(), value);
+    }
+
+    fn record_gauge_add<K: Into<Key>>(&mut self, key: K, delta: i64) {
+        let gauge = self.gauges.entry(key.into()).or_default();
+        *gauge = gauge.wrapping_add(delta);
+    }
+
+    fn supports_gauge_add(&self) -> bool {
+        true
+    }
+
+    fn record_histogram<K: Into<Key>>(&mut self, key: K, values: &[u64]) {
+        self.histograms
+            .entry(key.into())
+            .or_default()
+            .extend_from_slice(values);
+    }
+}
+
+impl SnapshotProvider for AggregatingRecorder {
+    type Snapshot = AggregatedSnapshot;
+    type SnapshotError = Infallible;
+
+    /// Gets a copy of the metrics aggregated so far.
+    ///
+    /// The snapshot is unaffected by anything recorded afterwards.
+    fn get_snapshot(&self) -> Result<AggregatedSnapshot, Infallible> {
+        Ok(AggregatedSnapshot {
+            counters: self.counters.clone(),
+            gauges: self.gauges.clone(),
+            histograms: self.histograms.clone(),
+        })
+    }
+}
+
+/// A point-in-time copy of the metrics in an [`AggregatingRecorder`].
+#[derive(Debug, Clone, Default)]
+pub struct AggregatedSnapshot {
+    counters: HashMap<Key, u64>,
+    gauges: HashMap<Key, i64>,
+    histograms: HashMap<Key, Vec<u64>>,
+}
+
+impl Snapshot for AggregatedSnapshot {
+    /// Records the snapshot to the given recorder.
+    fn record<R: Recorder>(&self, recorder: &mut R) {
+        for (key, value) in &self.counters {
+            recorder.record_counter(key.clone(), *value);
+        }
+
+        for (key, value) in &self.gauges {
+            recorder.record_gauge(key.clone(), *value);
+        }
+
+        for (key, values) in &self.histograms {
+            recorder.record_histogram(key.clone(), values);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AggregatingRecorder;
+    use crate::test_util::MockRecorder;
+    use metrics_core::{Key, Recorder, Snapshot, SnapshotProvider};
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+
+    fn render<S: Snapshot>(snapshot: &S) -> Vec<String> {
+        let mut recorder = MockRecorder::default();
+        snapshot.record(&mut recorder);
+        recorder.0.sort();
+        recorder.0
+    }
+
+    #[test]
+    fn test_consistent_snapshot() {
+        let mut recorder = AggregatingRecorder::new();
+        recorder.record_counter("requests", 2);
+        recorder.record_counter("requests", 5);
+        recorder.record_gauge("connections", 4);
+        recorder.record_histogram("request_time", &[10, 20]);
+
+        let snapshot = recorder.get_snapshot().unwrap();
+        recorder.record_counter("requests", 6);
+        recorder.record_gauge("connections", 1);
+        recorder.record_histogram("request_time", &[30]);
+        recorder.record_counter("errors", 1);
+
+        let expected = vec![
+            "counter requests 5",
+            "gauge connections 4",
+            "histogram request_time [10, 20]",
+        ];
+        assert_eq!(render(&snapshot), expected);
+
+        let expected = vec![
+            "counter errors 1",
+            "counter requests 6",
+            "gauge connections 1",
+            "histogram request_time [10, 20, 30]",
+        ];
+        assert_eq!(render(&recorder.get_snapshot().unwrap()), expected);
+
+        // Replaying a snapshot doesn't count its counters twice.
+        let mut replayed = AggregatingRecorder::new();
+        snapshot.record(&mut replayed);
+        snapshot.record(&mut replayed);
+        assert_eq!(replayed.counters[&Key::from_name("requests")], 5);
+    }
+
+    #[test]
+    fn test_sampled_histograms() {
+        let mut recorder = AggregatingRecorder::new();
+        recorder.record_histogram_sampled("request_time", &[10, 20], 0.5);
+        recorder.record_histogram_sampled("request_time", &[30], 1.0);
+
+        let snapshot = recorder.drain_snapshot();
+        assert_eq!(
+            snapshot.histograms[&Key::from_name("request_time")],
+            vec![10, 20, 10, 20, 30]
+        );
+    }
+
+    #[test]
+    fn test_drain_snapshot() {
+        let recorder = Arc::new(Mutex::new(AggregatingRecorder::new()));
+        let handles = (0..4)
+            .map(|id| {
+                let recorder = recorder.clone();
+                thread::spawn(move || {
+                    let key = Key::from_name_and_labels("requests", &[("thread", id.to_string())]);
+                    for i in 0..1000 {
+                        let mut recorder = recorder.lock().unwrap();
+                        recorder.record_counter(key.clone(), i + 1);
+                        recorder.record_histogram("request_time", &[i]);
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let mut snapshots = Vec::new();
+        while snapshots.len() < 100 {
+            snapshots.push(recorder.lock().unwrap().drain_snapshot());
+            thread::yield_now();
+        }
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        snapshots.push(recorder.lock().unwrap().drain_snapshot());
+
+        // Each counter's total reaches exactly one snapshot, however the drains interleave.
+        for id in 0..4 {
+            let requests = Key::from_name_and_labels("requests", &[("thread", id.to_string())]);
+            let totals = snapshots
+                .iter()
+                .filter_map(|snapshot| snapshot.counters.get(&requests))
+                .filter(|total| **total == 1000)
+                .count();
+            assert_eq!(totals, 1);
+        }
+        let request_time = Key::from_name("request_time");
+        let timed = snapshots
+            .iter()
+            .filter_map(|snapshot| snapshot.histograms.get(&request_time))
+            .map(Vec::len)
+            .sum::<usize>();
+        assert_eq!(timed, 4000);
+        assert!(render(&recorder.lock().unwrap().get_snapshot().unwrap()).is_empty());
+    }
+}
@@ -235,25 +235,30 @@ impl<T> AtomicBucket<T> {
         // will see it as empty until another write proceeds.
         let guard = &epoch_pin();
         let tail = self.tail.load(Ordering::Acquire, guard);
-        if !tail.is_null() {
-            if self
+        if !tail.is_null()
+            && self
                 .tail
                 .compare_and_set(tail, Shared::null(), Ordering::SeqCst, guard)
                 .is_ok()
-            {
-                // We won the swap to delete the tail node.  Now configure a deferred drop to clean
-                // things up once nobody else is using it.
-                unsafe {
-                    // Drop the block, which will cause a cascading drop on the next block, and
-                    // so on and so forth, until all blocks linked to this one are dropped.
-                    guard.defer_destroy(tail);
-                }
-                guard.flush();
+        {
+            // We won the swap to delete the tail node.  Now configure a deferred drop to clean
+            // things up once nobody else is using it.
+            unsafe {
+                // Drop the block, which will cause a cascading drop on the next block, and
+                // so on and so forth, until all blocks linked to this one are dropped.
+                guard.defer_destroy(tail);
             }
+            guard.flush();
         }
     }
 }
 
+impl<T> Default for AtomicBucket<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::{AtomicBucket, Block, BLOCK_SIZE};
@@ -0,0 +1,58 @@
+/// Counts samples into cumulative buckets, as used by Prometheus histograms.
+///
+/// Each bucket counts the samples less than or equal to its boundary, so a sample exactly on a
+/// boundary is counted in that bucket and every bucket after it.  Boundaries are expected to be
+/// sorted in ascending order.
+///
+/// Returns the count for each boundary, in the same order, along with the total number of
+/// samples, which is the count of the implicit `+Inf` bucket.
+pub fn bucketize(samples: &[u64], boundaries: &[u64]) -> (Vec<u64>, u64) {
+    let mut counts = vec![0; boundaries.len()];
+    for sample in samples {
+        // Boundaries are sorted, so the sample lands in the first bucket at or above it.
+        let first = boundaries.partition_point(|boundary| boundary < sample);
+        if let Some(count) = counts.get_mut(first) {
+            *count += 1;
+        }
+    }
+
+    let mut total = 0;
+    for count in counts.iter_mut() {
+        total += *count;
+        *count = total;
+    }
+
+    (counts, samples.len() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::bucketize;
+
+    #[test]
+    fn test_bucketize() {
+        let (counts, total) = bucketize(&[1, 5, 7, 10, 11, 100], &[5, 10, 50]);
+        assert_eq!(counts, vec![2, 4, 5]);
+        assert_eq!(total, 6);
+    }
+
+    #[test]
+    fn test_bucketize_edge_cases() {
+        // Samples exactly on a boundary are counted in that boundary's bucket.
+        let (counts, total) = bucketize(&[0, 5, 5, 10], &[0, 5, 10]);
+        assert_eq!(counts, vec![1, 3, 4]);
+        assert_eq!(total, 4);
+
+        let (counts, total) = bucketize(&[u64::MAX], &[10, u64::MAX]);
+        assert_eq!(counts, vec![0, 1]);
+        assert_eq!(total, 1);
+
+        let (counts, total) = bucketize(&[3, 20], &[]);
+        assert!(counts.is_empty());
+        assert_eq!(total, 2);
+
+        let (counts, total) = bucketize(&[], &[1, 2]);
+        assert_eq!(counts, vec![0, 0]);
+        assert_eq!(total, 0);
+    }
+}
@@ -0,0 +1,336 @@
+use crate::DropReporter;
+use metrics_core::{Key, Recorder};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{sync_channel, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+/// Behavior of an [`AsyncBufferRecorder`] when its buffer is full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backpressure {
+    /// Block the caller until there is room in the buffer.
+    Block,
+
+    /// Silently drop the recording.
+    Drop,
+
+    /// Drop the recording, and count it.
+    ///
+    /// The number of dropped recordings can be retrieved with [`AsyncBufferRecorder::dropped`],
+    /// and reported to the inner recorder with [`AsyncBufferRecorder::report_drops`].
+    DropAndCount,
+}
+
+pub(crate) enum Message {
+    Counter(Key, u64),
+    Gauge(Key, i64),
+    GaugeAdd(Key, i64),
+    Histogram(Key, Vec<u64>),
+    HistogramSampled(Key, Vec<u64>, f64),
+    Set(Key, String),
+}
+
+impl Message {
+    pub(crate) fn apply<R: Recorder>(self, inner: &mut R) {
+        match self {
+            Message::Counter(key, value) => inner.record_counter(key, value),
+            Message::Gauge(key, value) => inner.record_gauge(key, value),
+            Message::GaugeAdd(key, delta) => inner.record_gauge_add(key, delta),
+            Message::Histogram(key, values) => inner.record_histogram(key, &values),
+            Message::HistogramSampled(key, values, rate) => {
+                inner.record_histogram_sampled(key, &values, rate)
+            }
+            Message::Set(key, value) => inner.record_set(key, &value),
+        }
+    }
+}
+
+// The recordings dropped by the calling threads, which can't reach the inner recorder, so the
+// background thread reports them to it after applying the next recording, if reporting is enabled.
+pub(crate) struct Drops {
+    reason: &'static str,
+    reporter: Mutex<DropReporter>,
+    report: AtomicBool,
+    unreported: AtomicBool,
+}
+
+impl Drops {
+    pub(crate) fn new(reason: &'static str) -> Self {
+        Drops {
+            reason,
+            reporter: Mutex::new(DropReporter::new()),
+            report: AtomicBool::new(false),
+            unreported: AtomicBool::new(false),
+        }
+    }
+
+    pub(crate) fn dropped(&self) -> u64 {
+        self.reporter.lock().unwrap().dropped(self.reason)
+    }
+
+    pub(crate) fn count(&self) {
+        self.reporter.lock().unwrap().count(self.reason);
+        if self.report.load(Ordering::Relaxed) {
+            self.unreported.store(true, Ordering::Release);
+        }
+    }
+
+    // Reports drops with `reporter` from now on, carrying over the drops counted so far.
+    pub(crate) fn report_with(&self, mut reporter: DropReporter) {
+        let mut current = self.reporter.lock().unwrap();
+        reporter.merge(&current);
+        *current = reporter;
+        self.report.store(true, Ordering::Relaxed);
+        self.unreported.store(true, Ordering::Release);
+    }
+
+    // Reports the drops to `inner`, if any were counted since the last report.
+    pub(crate) fn report<R: Recorder>(&self, inner: &mut R) {
+        if self.unreported.swap(false, Ordering::Acquire) {
+            // The totals are reported without holding the lock, so a slow inner recorder never
+            // blocks the calling threads.
+            let reporter = self.reporter.lock().unwrap().clone();
+            reporter.report_totals(inner);
+        }
+    }
+}
+
+/// A recorder that hands recordings off to a background thread.
+///
+/// Calls to the `record_*` methods only send a message over a bounded channel, and a background
+/// thread drains the channel, applying each recording to the inner recorder.  This keeps
+/// potentially expensive recorders, such as ones that serialize their output, off of the hot
+/// path.
+///
+/// When the channel is full, the configured [`Backpressure`] decides whether callers block or
+/// whether recordings are dropped.  Counted drops can be reported to the inner recorder with
+/// [`AsyncBufferRecorder::report_drops`].
+///
+/// Calling [`shutdown`](AsyncBufferRecorder::shutdown) waits for all buffered recordings to be
+/// applied, and hands back the inner recorder.
+pub struct AsyncBufferRecorder<R> {
+    sender: Option<SyncSender<Message>>,
+    handle: Option<JoinHandle<R>>,
+    backpressure: Backpressure,
+    drops: Arc<Drops>,
+    supports_gauge_add: bool,
+}
+
+impl<R> AsyncBufferRecorder<R>
+where
+    R: Recorder + Send + 'static,
+{
+    /// Creates a new [`AsyncBufferRecorder`] wrapping `inner`.
+    ///
+    /// Up to `capacity` recordings are buffered before `backpressure` is applied.
+    pub fn new(inner: R, capacity: usize, backpressure: Backpressure) -> Self {
+        let supports_gauge_add = inner.supports_gauge_add();
+        let (sender, receiver) = sync_channel::<Message>(capacity);
+        let drops = Arc::new(Drops::new("buffer"));
+        let worker = drops.clone();
+        let handle = thread::spawn(move || {
+            let mut inner = inner;
+            // The iterator ends once every sender has been dropped and the channel is empty, so
+            // everything sent before shutdown is applied.
+            for message in receiver {
+                message.apply(&mut inner);
+                worker.report(&mut inner);
+            }
+            inner
+        });
+
+        AsyncBufferRecorder {
+            sender: Some(sender),
+            handle: Some(handle),
+            backpressure,
+            drops,
+            supports_gauge_add,
+        }
+    }
+
+    /// Gets the number of recordings dropped because the buffer was full.
+    ///
+    /// Only tracked when using [`Backpressure::DropAndCount`].
+    pub fn dropped(&self) -> u64 {
+        self.drops.dropped()
+    }
+
+    /// Reports every counted drop to the inner recorder, using `reporter`.
+    ///
+    /// Drops are reported with the reason `buffer`, by the background thread, once it has applied
+    /// the next buffered recording.
+    pub fn report_drops(&mut self, reporter: DropReporter) {
+        self.drops.report_with(reporter);
+    }
+
+    /// Flushes all buffered recordings and returns the inner recorder.
+    ///
+    /// # Panics
+    /// Panics if the background thread panicked while applying a recording.
+    pub fn shutdown(mut self) -> R {
+        // Dropping the sender disconnects the channel, which lets the background thread finish
+        // once it has drained the remaining messages.
+        self.sender.take();
+        self.handle
+            .take()
+            .expect("background thread already joined")
+            .join()
+            .expect("background thread panicked")
+    }
+
+    fn send(&self, message: Message) {
+        let sender = match self.sender.as_ref() {
+            Some(sender) => sender,
+            None => return,
+        };
+
+        match self.backpressure {
+            Backpressure::Block => {
+                let _ = sender.send(message);
+            }
+            Backpressure::Drop => {
+                let _ = sender.try_send(message);
+            }
+            Backpressure::DropAndCount => {
+                if let Err(TrySendError::Full(_)) = sender.try_send(message) {
+                    self.drops.count();
+                }
+            }
+        }
+    }
+}
+
+impl<R> Recorder for AsyncBufferRecorder<R>
+where
+    R: Recorder + Send + 'static,
+{
+    fn record_counter<K: Into<Key>>(&mut self, key: K, value: u64) {
+        self.send(Message::Counter(key.into(), value));
+    }
+
+    fn record_gauge<K: Into<Key>>(&mut self, key: K, value: i64) {
+        self.send(Message::Gauge(key.into(), value));
+    }
+
+    fn record_gauge_add<K: Into<Key>>(&mut self, key: K, delta: i64) {
+        self.send(Message::GaugeAdd(key.into(), delta));
+    }
+
+    fn supports_gauge_add(&self) -> bool {
+        self.supports_gauge_add
+    }
+
+    fn record_histogram<K: Into<Key>>(&mut self, key: K, values: &[u64]) {
+        self.send(Message::Histogram(key.into(), values.to_vec()));
+    }
+
+    fn record_histogram_sampled<K: Into<Key>>(&mut self, key: K, values: &[u64], rate: f64) {
+        self.send(Message::HistogramSampled(key.into(), values.to_vec(), rate));
+    }
+
+    fn record_set<K: Into<Key>>(&mut self, key: K, value: &str) {
+        self.send(Message::Set(key.into(), value.to_owned()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AsyncBufferRecorder, Backpressure};
+    use crate::DropReporter;
+    use metrics_core::{Key, Recorder};
+    use std::sync::mpsc::{channel, Receiver};
+
+    #[derive(Default)]
+    struct MockRecorder {
+        counters: Vec<(String, u64)>,
+        gauges: Vec<(String, i64)>,
+        histograms: Vec<(String, Vec<u64>)>,
+        gate: Option<Receiver<()>>,
+    }
+
+    impl Recorder for MockRecorder {
+        fn record_counter<K: Into<Key>>(&mut self, key: K, value: u64) {
+            if let Some(gate) = self.gate.take() {
+                let _ = gate.recv();
+            }
+            self.counters.push((key.into().to_string(), value));
+        }
+
+        fn record_gauge<K: Into<Key>>(&mut self, key: K, value: i64) {
+            self.gauges.push((key.into().to_string(), value));
+        }
+
+        fn record_histogram<K: Into<Key>>(&mut self, key: K, values: &[u64]) {
+            self.histograms
+                .push((key.into().to_string(), values.to_vec()));
+        }
+    }
+
+    #[test]
+    fn test_buffer_flushes_on_shutdown() {
+        let mut recorder =
+            AsyncBufferRecorder::new(MockRecorder::default(), 8, Backpressure::Block);
+        for i in 0..100 {
+            recorder.record_counter("requests", i);
+        }
+        recorder.record_gauge("connections", -3);
+        recorder.record_histogram("latency", &[1, 2, 3]);
+
+        let inner = recorder.shutdown();
+        assert_eq!(inner.counters.len(), 100);
+        assert_eq!(inner.counters[99], ("requests".to_owned(), 99));
+        assert_eq!(inner.gauges, vec![("connections".to_owned(), -3)]);
+        assert_eq!(
+            inner.histograms,
+            vec![("latency".to_owned(), vec![1, 2, 3])]
+        );
+    }
+
+    #[test]
+    fn test_buffer_drop_and_count() {
+        // Stall the inner recorder on the first recording so the buffer fills up.
+        let (release, gate) = channel();
+        let inner = MockRecorder {
+            gate: Some(gate),
+            ..Default::default()
+        };
+
+        let mut recorder = AsyncBufferRecorder::new(inner, 2, Backpressure::DropAndCount);
+        for i in 0..10 {
+            recorder.record_counter("requests", i);
+        }
+        let dropped = recorder.dropped();
+        assert!(dropped >= 7);
+
+        release.send(()).unwrap();
+        let inner = recorder.shutdown();
+        assert_eq!(inner.counters.len() as u64 + dropped, 10);
+    }
+
+    #[test]
+    fn test_buffer_report_drops() {
+        let (release, gate) = channel();
+        let inner = MockRecorder {
+            gate: Some(gate),
+            ..Default::default()
+        };
+
+        let mut recorder = AsyncBufferRecorder::new(inner, 2, Backpressure::DropAndCount);
+        recorder.report_drops(DropReporter::new());
+        for i in 0..10 {
+            recorder.record_counter("requests", i);
+        }
+        let dropped = recorder.dropped();
+        assert!(dropped >= 7);
+
+        release.send(()).unwrap();
+        let inner = recorder.shutdown();
+        let reported = inner
+            .counters
+            .iter()
+            .filter(|(key, _)| key == "metrics_internal_dropped_total{reason=buffer}")
+            .map(|(_, value)| *value)
+            .collect::<Vec<_>>();
+        assert_eq!(reported.last(), Some(&dropped));
+    }
+}
@@ -0,0 +1,199 @@
+use metrics_core::{Key, Label, Recorder};
+use std::collections::HashMap;
+
+/// A recorder that aggregates away high-cardinality labels.
+///
+/// For each configured metric name, the given labels are removed, and the series which then
+/// share the same remaining labels are collapsed into one before being passed on.  With the
+/// `request_id` label dropped from `http.requests`, the counters `http.requests{request_id=1}` and
+/// `http.requests{request_id=2}` are recorded as a single `http.requests` counter with their sum.
+///
+/// Counter and gauge values are aggregated as they are recorded: each value is added to the sum
+/// of its collapsed series, which is then recorded in its place.  Every series is expected to be
+/// recorded once, as when recording a snapshot, so a fresh recorder should be used for each
+/// snapshot.  Gauge deltas are added to the sum too.  Histogram values are passed on under the
+/// collapsed key, which merges their distributions.  Metrics with other names are passed through
+/// unchanged.
+///
+/// Only the sum of each collapsed series is kept, so the state of this recorder grows with the
+/// number of collapsed series rather than with the number of original ones.  Unlike
+/// [`AggregatingRecorder`](crate::AggregatingRecorder), it doesn't hold on to the recorded
+/// metrics either.
+pub struct CollapsingRecorder<R> {
+    inner: R,
+    dropped: HashMap<String, Vec<String>>,
+    counters: HashMap<Key, u64>,
+    gauges: HashMap<Key, i64>,
+}
+
+impl<R> CollapsingRecorder<R> {
+    /// Creates a new [`CollapsingRecorder`] wrapping `inner`, which doesn't drop any labels.
+    ///
+    /// Labels to drop are configured per metric with
+    /// [`CollapsingRecorder::set_dropped_labels_for`].
+    pub fn new(inner: R) -> Self {
+        CollapsingRecorder {
+            inner,
+            dropped: HashMap::new(),
+            counters: HashMap::new(),
+            gauges: HashMap::new(),
+        }
+    }
+
+    /// Sets the labels to drop from the metric with the given name.
+    pub fn set_dropped_labels_for<N: Into<String>>(&mut self, name: N, labels: &[&str]) {
+        let labels = labels.iter().map(|label| (*label).to_owned()).collect();
+        self.dropped.insert(name.into(), labels);
+    }
+
+    /// Consumes this recorder, returning the inner recorder.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    // Gets the key of the collapsed series, if the metric has labels configured to be dropped.
+    fn collapse(&self, key: &Key) -> Option<Key> {
+        let dropped = self.dropped.get(key.name())?;
+        let labels = key
+            .labels()
+            .filter(|label| !dropped.iter().any(|d| d == label.key()))
+            .cloned()
+            .collect::<Vec<Label>>();
+        Some(Key::from_name_and_labels(key.name().to_owned(), labels))
+    }
+}
+
+impl<R: Recorder> Recorder for CollapsingRecorder<R> {
+    fn record_counter<K: Into<Key>>(&mut self, key: K, value: u64) {
+        let key = key.into();
+        let collapsed = match self.collapse(&key) {
+            Some(collapsed) => collapsed,
+            None => return self.inner.record_counter(key, value),
+        };
+
+        let sum = self.counters.entry(collapsed.clone()).or_insert(0);
+        *sum = sum.saturating_add(value);
+        let sum = *sum;
+        self.inner.record_counter(collapsed, sum);
+    }
+
+    fn record_gauge<K: Into<Key>>(&mut self, key: K, value: i64) {
+        let key = key.into();
+        let collapsed = match self.collapse(&key) {
+            Some(collapsed) => collapsed,
+            None => return self.inner.record_gauge(key, value),
+        };
+
+        let sum = self.gauges.entry(collapsed.clone()).or_insert(0);
+        *sum = sum.saturating_add(value);
+        let sum = *sum;
+        self.inner.record_gauge(collapsed, sum);
+    }
+
+    fn record_gauge_add<K: Into<Key>>(&mut self, key: K, delta: i64) {
+        let key = key.into();
+        if self.collapse(&key).is_none() {
+            return self.inner.record_gauge_add(key, delta);
+        }
+
+        // The sum of the collapsed series is tracked, so the delta can be applied to it here.
+        self.record_gauge(key, delta);
+    }
+
+    fn supports_gauge_add(&self) -> bool {
+        true
+    }
+
+    fn record_histogram<K: Into<Key>>(&mut self, key: K, values: &[u64]) {
+        let key = key.into();
+        let key = self.collapse(&key).unwrap_or(key);
+        self.inner.record_histogram(key, values);
+    }
+
+    fn record_histogram_sampled<K: Into<Key>>(&mut self, key: K, values: &[u64], rate: f64) {
+        let key = key.into();
+        let key = self.collapse(&key).unwrap_or(key);
+        self.inner.record_histogram_sampled(key, values, rate);
+    }
+
+    fn record_set<K: Into<Key>>(&mut self, key: K, value: &str) {
+        let key = key.into();
+        let key = self.collapse(&key).unwrap_or(key);
+        self.inner.record_set(key, value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CollapsingRecorder;
+    use crate::test_util::MockRecorder;
+    use metrics_core::{Key, Recorder};
+
+    fn key(name: &'static str, labels: &[(&'static str, &'static str)]) -> Key {
+        Key::from_name_and_labels(name, &labels.to_vec())
+    }
+
+    #[test]
+    fn test_counter_sums() {
+        let mut recorder = CollapsingRecorder::new(MockRecorder::default());
+        recorder.set_dropped_labels_for("http.requests", &["request_id"]);
+
+        recorder.record_counter(key("http.requests", &[("request_id", "1")]), 2);
+        recorder.record_counter(key("http.requests", &[("request_id", "2")]), 3);
+        recorder.record_counter(key("http.requests", &[("request_id", "3")]), 5);
+        let labels = [("method", "get"), ("request_id", "4")];
+        recorder.record_counter(key("http.requests", &labels), 1);
+        recorder.record_counter(key("db.queries", &[("request_id", "1")]), 4);
+
+        // Only the collapsed series are tracked.
+        assert_eq!(recorder.counters.len(), 2);
+        assert_eq!(
+            recorder.into_inner().0,
+            vec![
+                "counter http.requests 2",
+                "counter http.requests 5",
+                "counter http.requests 10",
+                "counter http.requests{method=get} 1",
+                "counter db.queries{request_id=1} 4",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_gauge_sums() {
+        let mut recorder = CollapsingRecorder::new(MockRecorder::default());
+        recorder.set_dropped_labels_for("connections", &["peer"]);
+
+        recorder.record_gauge(key("connections", &[("peer", "a")]), 3);
+        recorder.record_gauge(key("connections", &[("peer", "b")]), 4);
+        recorder.record_gauge_add(key("connections", &[("peer", "c")]), -5);
+
+        assert_eq!(
+            recorder.into_inner().0,
+            vec![
+                "gauge connections 3",
+                "gauge connections 7",
+                "gauge connections 2",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_histogram_merging() {
+        let mut recorder = CollapsingRecorder::new(MockRecorder::default());
+        recorder.set_dropped_labels_for("http.latency", &["request_id", "user"]);
+
+        let labels = [("user", "alice"), ("request_id", "1"), ("route", "/")];
+        recorder.record_histogram(key("http.latency", &labels), &[5, 6]);
+        let labels = [("route", "/"), ("request_id", "2")];
+        recorder.record_histogram(key("http.latency", &labels), &[7]);
+
+        assert_eq!(
+            recorder.into_inner().0,
+            vec![
+                "histogram http.latency{route=/} [5, 6]",
+                "histogram http.latency{route=/} [7]",
+            ]
+        );
+    }
+}
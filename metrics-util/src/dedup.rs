@@ -0,0 +1,147 @@
+use metrics_core::{Key, Recorder};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// A recorder that drops gauge writes which don't change the value.
+///
+/// Only gauge values that differ from the last value forwarded for the same key are passed to the
+/// inner recorder.  To keep downstream systems from marking a gauge as stale, an unchanged value
+/// is still forwarded once `max_suppression` has passed since it was last forwarded.
+///
+/// Counters, histograms, and sets are always passed through, as are gauge deltas, which always
+/// change the value.
+pub struct DedupGaugeRecorder<R> {
+    inner: R,
+    max_suppression: Duration,
+    last: HashMap<Key, (i64, Instant)>,
+}
+
+impl<R> DedupGaugeRecorder<R> {
+    /// Creates a new [`DedupGaugeRecorder`] wrapping `inner`.
+    ///
+    /// Unchanged gauge values are suppressed for at most `max_suppression`.
+    pub fn new(inner: R, max_suppression: Duration) -> Self {
+        DedupGaugeRecorder {
+            inner,
+            max_suppression,
+            last: HashMap::new(),
+        }
+    }
+
+    /// Consumes this recorder, returning the inner recorder.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: Recorder> Recorder for DedupGaugeRecorder<R> {
+    fn record_counter<K: Into<Key>>(&mut self, key: K, value: u64) {
+        self.inner.record_counter(key, value);
+    }
+
+    fn record_gauge<K: Into<Key>>(&mut self, key: K, value: i64) {
+        let key = key.into();
+        let now = Instant::now();
+        if let Some((last_value, last_sent)) = self.last.get(&key) {
+            if *last_value == value && now.duration_since(*last_sent) < self.max_suppression {
+                return;
+            }
+        }
+
+        self.last.insert(key.clone(), (value, now));
+        self.inner.record_gauge(key, value);
+    }
+
+    fn record_gauge_add<K: Into<Key>>(&mut self, key: K, delta: i64) {
+        // The value forwarded last is no longer current, so the next value is always forwarded.
+        let key = key.into();
+        self.last.remove(&key);
+        self.inner.record_gauge_add(key, delta);
+    }
+
+    fn supports_gauge_add(&self) -> bool {
+        self.inner.supports_gauge_add()
+    }
+
+    fn record_histogram<K: Into<Key>>(&mut self, key: K, values: &[u64]) {
+        self.inner.record_histogram(key, values);
+    }
+
+    fn record_histogram_sampled<K: Into<Key>>(&mut self, key: K, values: &[u64], rate: f64) {
+        self.inner.record_histogram_sampled(key, values, rate);
+    }
+
+    fn record_set<K: Into<Key>>(&mut self, key: K, value: &str) {
+        self.inner.record_set(key, value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DedupGaugeRecorder;
+    use crate::test_util::MockRecorder;
+    use metrics_core::Recorder;
+    use std::time::Duration;
+
+    #[test]
+    fn test_suppresses_duplicates() {
+        let mut recorder =
+            DedupGaugeRecorder::new(MockRecorder::default(), Duration::from_secs(3600));
+        recorder.record_gauge("connections", 5);
+        recorder.record_gauge("connections", 5);
+        recorder.record_gauge("queue_depth", 5);
+        recorder.record_gauge("connections", 6);
+        recorder.record_gauge("connections", 6);
+        recorder.record_gauge("connections", 5);
+        recorder.record_counter("requests", 1);
+        recorder.record_counter("requests", 1);
+        recorder.record_histogram("latency", &[1]);
+        recorder.record_histogram("latency", &[1]);
+
+        assert_eq!(
+            recorder.into_inner().0,
+            vec![
+                "gauge connections 5",
+                "gauge queue_depth 5",
+                "gauge connections 6",
+                "gauge connections 5",
+                "counter requests 1",
+                "counter requests 1",
+                "histogram latency [1]",
+                "histogram latency [1]",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_max_suppression() {
+        let mut recorder = DedupGaugeRecorder::new(MockRecorder::default(), Duration::from_secs(0));
+        recorder.record_gauge("connections", 5);
+        recorder.record_gauge("connections", 5);
+
+        assert_eq!(
+            recorder.into_inner().0,
+            vec!["gauge connections 5", "gauge connections 5"]
+        );
+    }
+
+    #[test]
+    fn test_gauge_deltas() {
+        let mut recorder =
+            DedupGaugeRecorder::new(MockRecorder::default(), Duration::from_secs(3600));
+        recorder.record_gauge("connections", 5);
+        recorder.record_gauge_add("connections", 1);
+        recorder.record_gauge_add("connections", 1);
+        recorder.record_gauge("connections", 5);
+
+        assert_eq!(
+            recorder.into_inner().0,
+            vec![
+                "gauge connections 5",
+                "gauge_add connections 1",
+                "gauge_add connections 1",
+                "gauge connections 5",
+            ]
+        );
+    }
+}
@@ -0,0 +1,131 @@
+use metrics_core::{Key, Label, Recorder};
+use std::collections::HashMap;
+
+/// The default name of the counter that dropped recordings are reported under.
+pub const DEFAULT_DROPPED_METRIC: &str = "metrics_internal_dropped_total";
+
+/// Reports recordings dropped by a wrapper recorder to the recorder it wraps.
+///
+/// Wrapper recorders that drop recordings, such as
+/// [`ValidatingRecorder`](crate::ValidatingRecorder) when rejecting values, use this to surface
+/// their drops as a counter, which makes the metrics pipeline itself observable.  The counter is
+/// labeled with the reason for the drop, as in
+/// `metrics_internal_dropped_total{reason="rejected"}`, and holds the total number of recordings
+/// dropped for that reason so far.
+#[derive(Debug, Clone)]
+pub struct DropReporter {
+    name: String,
+    dropped: HashMap<&'static str, u64>,
+}
+
+impl DropReporter {
+    /// Creates a new [`DropReporter`] which reports drops under [`DEFAULT_DROPPED_METRIC`].
+    ///
+    /// If you want to use a different name, you can call [`DropReporter::with_name`].
+    pub fn new() -> Self {
+        Self::with_name(DEFAULT_DROPPED_METRIC)
+    }
+
+    /// Creates a new [`DropReporter`] which reports drops under the given name.
+    pub fn with_name<N: Into<String>>(name: N) -> Self {
+        DropReporter {
+            name: name.into(),
+            dropped: HashMap::new(),
+        }
+    }
+
+    /// Gets the number of recordings dropped for the given reason.
+    pub fn dropped(&self, reason: &str) -> u64 {
+        self.dropped.get(reason).copied().unwrap_or(0)
+    }
+
+    /// Counts a recording dropped for `reason`, and reports the new total to `recorder`.
+    pub fn report<R: Recorder>(&mut self, recorder: &mut R, reason: &'static str) {
+        self.count(reason);
+        self.report_total(recorder, reason);
+    }
+
+    /// Counts a recording dropped for `reason`, without reporting it.
+    ///
+    /// This is for recorders which can't reach the recorder they wrap when they drop a recording,
+    /// such as ones that apply recordings on a background thread, and which report the totals
+    /// later on with [`DropReporter::report_totals`].
+    pub fn count(&mut self, reason: &'static str) {
+        *self.dropped.entry(reason).or_insert(0) += 1;
+    }
+
+    /// Reports the total number of recordings dropped for every reason so far to `recorder`.
+    pub fn report_totals<R: Recorder>(&self, recorder: &mut R) {
+        for reason in self.dropped.keys() {
+            self.report_total(recorder, reason);
+        }
+    }
+
+    // Adds the drops counted by `other` to the ones counted by this reporter.
+    pub(crate) fn merge(&mut self, other: &DropReporter) {
+        for (reason, count) in &other.dropped {
+            *self.dropped.entry(reason).or_insert(0) += count;
+        }
+    }
+
+    fn report_total<R: Recorder>(&self, recorder: &mut R, reason: &'static str) {
+        let key = Key::from_name_and_labels(self.name.clone(), vec![Label::new("reason", reason)]);
+        recorder.record_counter(key, self.dropped(reason));
+    }
+}
+
+impl Default for DropReporter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DropReporter;
+    use crate::test_util::MockRecorder;
+
+    #[test]
+    fn test_report() {
+        let mut recorder = MockRecorder::default();
+        let mut reporter = DropReporter::new();
+        reporter.report(&mut recorder, "rejected");
+        reporter.report(&mut recorder, "sampled");
+        reporter.report(&mut recorder, "rejected");
+
+        let mut renamed = DropReporter::with_name("pipeline_dropped");
+        renamed.report(&mut recorder, "rejected");
+
+        assert_eq!(reporter.dropped("rejected"), 2);
+        assert_eq!(reporter.dropped("overflow"), 0);
+        assert_eq!(
+            recorder.0,
+            vec![
+                "counter metrics_internal_dropped_total{reason=rejected} 1",
+                "counter metrics_internal_dropped_total{reason=sampled} 1",
+                "counter metrics_internal_dropped_total{reason=rejected} 2",
+                "counter pipeline_dropped{reason=rejected} 1",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_report_totals() {
+        let mut reporter = DropReporter::new();
+        reporter.count("full");
+        reporter.count("timeout");
+        reporter.count("full");
+        assert_eq!(reporter.dropped("full"), 2);
+
+        let mut recorder = MockRecorder::default();
+        reporter.report_totals(&mut recorder);
+        recorder.0.sort();
+        assert_eq!(
+            recorder.0,
+            vec![
+                "counter metrics_internal_dropped_total{reason=full} 2",
+                "counter metrics_internal_dropped_total{reason=timeout} 1",
+            ]
+        );
+    }
+}
@@ -0,0 +1,502 @@
+use metrics_core::{Key, Recorder};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime};
+
+/// When a [`FileRecorder`] rotates its file.
+///
+/// Rotated files are renamed to `<name>.YYYYMMDD-HHMMSS`, using the UTC time of the rotation, and
+/// only the newest `max_files` of them are kept.  By default, files are never rotated.
+#[derive(Debug, Clone, Default)]
+pub struct Rotation {
+    max_bytes: Option<u64>,
+    max_age: Option<Duration>,
+    max_files: Option<usize>,
+}
+
+impl Rotation {
+    /// Creates a [`Rotation`] that never rotates.
+    pub fn never() -> Self {
+        Self::default()
+    }
+
+    /// Rotates the file once it has grown to at least `bytes`.
+    pub fn max_bytes(mut self, bytes: u64) -> Self {
+        self.max_bytes = Some(bytes);
+        self
+    }
+
+    /// Rotates the file once it has been written to for at least `age`.
+    pub fn max_age(mut self, age: Duration) -> Self {
+        self.max_age = Some(age);
+        self
+    }
+
+    /// Keeps at most `files` rotated files, deleting the oldest ones.
+    pub fn max_files(mut self, files: usize) -> Self {
+        self.max_files = Some(files);
+        self
+    }
+}
+
+struct RollingFile {
+    path: PathBuf,
+    file: File,
+    written: u64,
+    opened_at: Instant,
+    rotation: Rotation,
+}
+
+impl RollingFile {
+    fn open(path: PathBuf, rotation: Rotation) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let written = file.metadata()?.len();
+
+        Ok(RollingFile {
+            path,
+            file,
+            written,
+            opened_at: Instant::now(),
+            rotation,
+        })
+    }
+
+    fn write(&mut self, output: &str) -> io::Result<()> {
+        self.file.write_all(output.as_bytes())?;
+        self.file.flush()?;
+        self.written += output.len() as u64;
+
+        let too_big = self
+            .rotation
+            .max_bytes
+            .is_some_and(|max| self.written >= max);
+        let too_old = self
+            .rotation
+            .max_age
+            .is_some_and(|max| self.opened_at.elapsed() >= max);
+        if too_big || too_old {
+            self.rotate()?;
+        }
+
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        // Files rotated within the same second get an increasing suffix, so that they still sort
+        // in order even after older ones have been pruned.
+        let timestamp = format_timestamp(SystemTime::now());
+        let next = self
+            .rotated_files()?
+            .into_iter()
+            .filter(|(ts, _, _)| *ts == timestamp)
+            .map(|(_, n, _)| n + 1)
+            .max();
+        let rotated = match next {
+            Some(n) => format!("{}.{}-{}", self.path.display(), timestamp, n),
+            None => format!("{}.{}", self.path.display(), timestamp),
+        };
+
+        fs::rename(&self.path, rotated)?;
+        *self = RollingFile::open(self.path.clone(), self.rotation.clone())?;
+        self.prune()
+    }
+
+    fn prune(&self) -> io::Result<()> {
+        let max_files = match self.rotation.max_files {
+            Some(max) => max,
+            None => return Ok(()),
+        };
+
+        let rotated = self.rotated_files()?;
+        let excess = rotated.len().saturating_sub(max_files);
+        for (_, _, path) in rotated.into_iter().take(excess) {
+            fs::remove_file(path)?;
+        }
+
+        Ok(())
+    }
+
+    // Lists the rotated files as `(timestamp, suffix, path)`, oldest first.
+    fn rotated_files(&self) -> io::Result<Vec<(String, u64, PathBuf)>> {
+        let dir = match self.path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        let prefix = match self.path.file_name() {
+            Some(name) => format!("{}.", name.to_string_lossy()),
+            None => return Ok(Vec::new()),
+        };
+
+        let mut rotated = fs::read_dir(dir)?
+            .filter_map(Result::ok)
+            .filter_map(|entry| {
+                let name = entry.file_name().to_string_lossy().into_owned();
+                let rest = name.strip_prefix(prefix.as_str())?;
+                // Anything else sharing the prefix isn't ours, and must never be pruned.
+                let timestamp = rest.get(..TIMESTAMP_LEN).filter(|ts| is_timestamp(ts))?;
+                let suffix = match &rest[TIMESTAMP_LEN..] {
+                    "" => 0,
+                    suffix => {
+                        let suffix = suffix.strip_prefix('-')?;
+                        if suffix.is_empty() || !suffix.bytes().all(|b| b.is_ascii_digit()) {
+                            return None;
+                        }
+                        suffix.parse().ok()?
+                    }
+                };
+                Some((timestamp.to_owned(), suffix, entry.path()))
+            })
+            .collect::<Vec<_>>();
+        rotated.sort();
+
+        Ok(rotated)
+    }
+}
+
+struct State<R> {
+    template: R,
+    recorder: R,
+    file: RollingFile,
+}
+
+impl<R> State<R>
+where
+    R: Recorder + Clone + Into<String>,
+{
+    fn flush(&mut self) -> io::Result<()> {
+        let recorder = std::mem::replace(&mut self.recorder, self.template.clone());
+        let output: String = recorder.into();
+        self.file.write(output.as_str())
+    }
+}
+
+/// A recorder that periodically appends rendered metrics to a file.
+///
+/// Recordings are accumulated in an inner recorder, and a background thread renders it and
+/// appends the output to the file on every interval, starting again from a clone of the recorder
+/// that was originally given.  The output format is whatever the inner recorder renders.
+///
+/// The file can be rotated based on its size or age, as configured with [`Rotation`].
+pub struct FileRecorder<R> {
+    state: Arc<Mutex<State<R>>>,
+    shutdown: Option<Sender<()>>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl<R> FileRecorder<R>
+where
+    R: Recorder + Clone + Into<String> + Send + 'static,
+{
+    /// Creates a new [`FileRecorder`] which appends to the file at `path` every `interval`.
+    ///
+    /// # Errors
+    /// Returns an error if the file cannot be opened for appending.
+    pub fn new<P: Into<PathBuf>>(
+        recorder: R,
+        path: P,
+        interval: Duration,
+        rotation: Rotation,
+    ) -> io::Result<Self> {
+        let file = RollingFile::open(path.into(), rotation)?;
+        let state = Arc::new(Mutex::new(State {
+            template: recorder.clone(),
+            recorder,
+            file,
+        }));
+
+        let (shutdown, shutdown_rx) = channel();
+        let thread_state = state.clone();
+        let handle = thread::spawn(move || {
+            while let Err(RecvTimeoutError::Timeout) = shutdown_rx.recv_timeout(interval) {
+                // There's nobody to report errors to, so try again on the next interval.
+                let _ = thread_state.lock().unwrap().flush();
+            }
+        });
+
+        Ok(FileRecorder {
+            state,
+            shutdown: Some(shutdown),
+            handle: Some(handle),
+        })
+    }
+
+    /// Immediately appends the metrics recorded so far to the file.
+    pub fn flush(&self) -> io::Result<()> {
+        self.state.lock().unwrap().flush()
+    }
+
+    /// Stops the background thread, and appends any remaining metrics to the file.
+    pub fn shutdown(mut self) -> io::Result<()> {
+        self.stop();
+        self.flush()
+    }
+}
+
+impl<R> FileRecorder<R> {
+    fn stop(&mut self) {
+        // Dropping the sender wakes up the background thread, which then exits.
+        self.shutdown.take();
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+impl<R> Recorder for FileRecorder<R>
+where
+    R: Recorder,
+{
+    fn record_counter<K: Into<Key>>(&mut self, key: K, value: u64) {
+        self.state
+            .lock()
+            .unwrap()
+            .recorder
+            .record_counter(key, value);
+    }
+
+    fn record_gauge<K: Into<Key>>(&mut self, key: K, value: i64) {
+        self.state.lock().unwrap().recorder.record_gauge(key, value);
+    }
+
+    fn record_gauge_add<K: Into<Key>>(&mut self, key: K, delta: i64) {
+        self.state
+            .lock()
+            .unwrap()
+            .recorder
+            .record_gauge_add(key, delta);
+    }
+
+    fn supports_gauge_add(&self) -> bool {
+        self.state.lock().unwrap().recorder.supports_gauge_add()
+    }
+
+    fn record_histogram<K: Into<Key>>(&mut self, key: K, values: &[u64]) {
+        self.state
+            .lock()
+            .unwrap()
+            .recorder
+            .record_histogram(key, values);
+    }
+
+    fn record_histogram_sampled<K: Into<Key>>(&mut self, key: K, values: &[u64], rate: f64) {
+        self.state
+            .lock()
+            .unwrap()
+            .recorder
+            .record_histogram_sampled(key, values, rate);
+    }
+
+    fn record_set<K: Into<Key>>(&mut self, key: K, value: &str) {
+        self.state.lock().unwrap().recorder.record_set(key, value);
+    }
+}
+
+impl<R> Drop for FileRecorder<R> {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+// The length of `YYYYMMDD-HHMMSS`.
+const TIMESTAMP_LEN: usize = 15;
+
+// Checks that `value` is formatted as `YYYYMMDD-HHMMSS`.
+fn is_timestamp(value: &str) -> bool {
+    let bytes = value.as_bytes();
+    bytes.len() == TIMESTAMP_LEN
+        && bytes.iter().enumerate().all(|(i, b)| match i {
+            8 => *b == b'-',
+            _ => b.is_ascii_digit(),
+        })
+}
+
+// Formats a time as `YYYYMMDD-HHMMSS` in UTC.
+fn format_timestamp(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let (days, rem) = (secs / 86_400, secs % 86_400);
+
+    // Converts days since the epoch to a civil date, using Howard Hinnant's algorithm.
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{:04}{:02}{:02}-{:02}{:02}{:02}",
+        year,
+        month,
+        day,
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{format_timestamp, is_timestamp, FileRecorder, Rotation};
+    use metrics_core::{Key, Recorder};
+    use std::fs;
+    use std::path::PathBuf;
+    use std::time::{Duration, SystemTime};
+
+    #[derive(Clone, Default)]
+    struct MockRecorder(String);
+
+    impl Recorder for MockRecorder {
+        fn record_counter<K: Into<Key>>(&mut self, key: K, value: u64) {
+            self.0
+                .push_str(&format!("counter {} {}\n", key.into(), value));
+        }
+
+        fn record_gauge<K: Into<Key>>(&mut self, key: K, value: i64) {
+            self.0
+                .push_str(&format!("gauge {} {}\n", key.into(), value));
+        }
+
+        fn record_histogram<K: Into<Key>>(&mut self, key: K, values: &[u64]) {
+            self.0
+                .push_str(&format!("histogram {} {:?}\n", key.into(), values));
+        }
+    }
+
+    impl From<MockRecorder> for String {
+        fn from(recorder: MockRecorder) -> String {
+            recorder.0
+        }
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let nanos = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap()
+            .as_nanos();
+        let dir = std::env::temp_dir().join(format!(
+            "metrics-util-{}-{}-{}",
+            name,
+            std::process::id(),
+            nanos
+        ));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_format_timestamp() {
+        let time = SystemTime::UNIX_EPOCH + Duration::from_secs(1_556_372_619);
+        assert_eq!(format_timestamp(time), "20190427-134339");
+        assert_eq!(format_timestamp(SystemTime::UNIX_EPOCH), "19700101-000000");
+
+        assert!(is_timestamp("20190427-134339"));
+        assert!(!is_timestamp("backup-original"));
+        assert!(!is_timestamp("20190427_134339"));
+        assert!(!is_timestamp("2019042-1343390"));
+    }
+
+    #[test]
+    fn test_prune_keeps_lookalikes() {
+        let dir = temp_dir("lookalike");
+        let path = dir.join("metrics.log");
+        let lookalikes = [
+            "metrics.log.backup-original",
+            "metrics.log.20190427-134339-old",
+            "metrics.log.20190427-134339-",
+        ];
+        for name in lookalikes.iter() {
+            fs::write(dir.join(name), "keep me").unwrap();
+        }
+
+        let rotation = Rotation::never().max_bytes(1).max_files(1);
+        let mut recorder = FileRecorder::new(
+            MockRecorder::default(),
+            path.clone(),
+            Duration::from_secs(3600),
+            rotation,
+        )
+        .unwrap();
+        for i in 0..3 {
+            recorder.record_counter("requests", i);
+            recorder.flush().unwrap();
+        }
+        recorder.shutdown().unwrap();
+
+        for name in lookalikes.iter() {
+            assert_eq!(fs::read_to_string(dir.join(name)).unwrap(), "keep me");
+        }
+        let rotated = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .filter(|name| name.starts_with("metrics.log.") && !lookalikes.contains(&name.as_str()))
+            .count();
+        assert_eq!(rotated, 1);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_file_recorder() {
+        let dir = temp_dir("append");
+        let path = dir.join("metrics.log");
+        let mut recorder = FileRecorder::new(
+            MockRecorder::default(),
+            path.clone(),
+            Duration::from_secs(3600),
+            Rotation::never(),
+        )
+        .unwrap();
+
+        recorder.record_counter("requests", 1);
+        recorder.flush().unwrap();
+        recorder.record_gauge("connections", 2);
+        recorder.shutdown().unwrap();
+
+        let contents = fs::read_to_string(&path).unwrap();
+        assert_eq!(contents, "counter requests 1\ngauge connections 2\n");
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_file_rotation() {
+        let dir = temp_dir("rotate");
+        let path = dir.join("metrics.log");
+        let rotation = Rotation::never().max_bytes(10).max_files(2);
+        let mut recorder = FileRecorder::new(
+            MockRecorder::default(),
+            path.clone(),
+            Duration::from_secs(3600),
+            rotation,
+        )
+        .unwrap();
+
+        for i in 0..4 {
+            recorder.record_counter("requests", i);
+            recorder.flush().unwrap();
+        }
+        recorder.shutdown().unwrap();
+
+        let mut rotated = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .filter(|name| name.starts_with("metrics.log."))
+            .collect::<Vec<_>>();
+        rotated.sort();
+        assert_eq!(rotated.len(), 2);
+
+        // The newest rotated file holds the last flush, and the active file is empty again.
+        let newest = fs::read_to_string(dir.join(&rotated[1])).unwrap();
+        assert_eq!(newest, "counter requests 3\n");
+        assert_eq!(fs::read_to_string(&path).unwrap(), "");
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
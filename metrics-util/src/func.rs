@@ -0,0 +1,72 @@
+use metrics_core::{Key, Recorder};
+
+/// A recorder that forwards recordings to closures.
+///
+/// This is handy for test doubles and small adapters, where defining a full recorder type would
+/// be overkill.
+///
+/// ```rust
+/// # extern crate metrics_core;
+/// # extern crate metrics_util;
+/// use metrics_core::Recorder;
+/// use metrics_util::FnRecorder;
+///
+/// let mut counters = Vec::new();
+/// let mut gauges = Vec::new();
+/// let mut histograms = Vec::new();
+///
+/// let mut recorder = FnRecorder::new(
+///     |key, value| counters.push((key.to_string(), value)),
+///     |key, value| gauges.push((key.to_string(), value)),
+///     |key, values: &[u64]| histograms.push((key.to_string(), values.to_vec())),
+/// );
+/// recorder.record_counter("requests", 3);
+/// recorder.record_gauge("connections", -1);
+/// recorder.record_histogram("request_time", &[12, 18]);
+/// drop(recorder);
+///
+/// assert_eq!(counters, vec![("requests".to_owned(), 3)]);
+/// assert_eq!(gauges, vec![("connections".to_owned(), -1)]);
+/// assert_eq!(histograms, vec![("request_time".to_owned(), vec![12, 18])]);
+/// ```
+pub struct FnRecorder<C, G, H> {
+    counter: C,
+    gauge: G,
+    histogram: H,
+}
+
+impl<C, G, H> FnRecorder<C, G, H>
+where
+    C: FnMut(Key, u64),
+    G: FnMut(Key, i64),
+    H: FnMut(Key, &[u64]),
+{
+    /// Creates a new [`FnRecorder`] which calls `counter`, `gauge`, and `histogram` for each
+    /// recording of the respective type.
+    pub fn new(counter: C, gauge: G, histogram: H) -> Self {
+        FnRecorder {
+            counter,
+            gauge,
+            histogram,
+        }
+    }
+}
+
+impl<C, G, H> Recorder for FnRecorder<C, G, H>
+where
+    C: FnMut(Key, u64),
+    G: FnMut(Key, i64),
+    H: FnMut(Key, &[u64]),
+{
+    fn record_counter<K: Into<Key>>(&mut self, key: K, value: u64) {
+        (self.counter)(key.into(), value);
+    }
+
+    fn record_gauge<K: Into<Key>>(&mut self, key: K, value: i64) {
+        (self.gauge)(key.into(), value);
+    }
+
+    fn record_histogram<K: Into<Key>>(&mut self, key: K, values: &[u64]) {
+        (self.histogram)(key.into(), values);
+    }
+}
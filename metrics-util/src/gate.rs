@@ -0,0 +1,102 @@
+use metrics_core::{Key, Recorder};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// A recorder that only records metrics while a shared flag is set.
+///
+/// The flag is an [`AtomicBool`] which can be flipped from anywhere holding a clone of it, such
+/// as an admin endpoint, so collection can be turned off and on at runtime without swapping
+/// recorders.  While the flag is unset, every recording is dropped after a single atomic load.
+pub struct GatedRecorder<R> {
+    inner: R,
+    enabled: Arc<AtomicBool>,
+}
+
+impl<R> GatedRecorder<R> {
+    /// Creates a new [`GatedRecorder`] wrapping `inner`, which records while `enabled` is set.
+    pub fn new(inner: R, enabled: Arc<AtomicBool>) -> Self {
+        GatedRecorder { inner, enabled }
+    }
+
+    /// Whether recordings are currently passed on to the inner recorder.
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    /// Consumes this recorder, returning the inner recorder.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: Recorder> Recorder for GatedRecorder<R> {
+    fn record_counter<K: Into<Key>>(&mut self, key: K, value: u64) {
+        if self.is_enabled() {
+            self.inner.record_counter(key, value);
+        }
+    }
+
+    fn record_gauge<K: Into<Key>>(&mut self, key: K, value: i64) {
+        if self.is_enabled() {
+            self.inner.record_gauge(key, value);
+        }
+    }
+
+    fn record_gauge_add<K: Into<Key>>(&mut self, key: K, delta: i64) {
+        if self.is_enabled() {
+            self.inner.record_gauge_add(key, delta);
+        }
+    }
+
+    fn supports_gauge_add(&self) -> bool {
+        self.inner.supports_gauge_add()
+    }
+
+    fn record_histogram<K: Into<Key>>(&mut self, key: K, values: &[u64]) {
+        if self.is_enabled() {
+            self.inner.record_histogram(key, values);
+        }
+    }
+
+    fn record_histogram_sampled<K: Into<Key>>(&mut self, key: K, values: &[u64], rate: f64) {
+        if self.is_enabled() {
+            self.inner.record_histogram_sampled(key, values, rate);
+        }
+    }
+
+    fn record_set<K: Into<Key>>(&mut self, key: K, value: &str) {
+        if self.is_enabled() {
+            self.inner.record_set(key, value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::GatedRecorder;
+    use crate::test_util::MockRecorder;
+    use metrics_core::Recorder;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_gate() {
+        let enabled = Arc::new(AtomicBool::new(true));
+        let mut recorder = GatedRecorder::new(MockRecorder::default(), enabled.clone());
+        recorder.record_counter("http.requests", 1);
+
+        enabled.store(false, Ordering::Relaxed);
+        assert!(!recorder.is_enabled());
+        recorder.record_counter("http.requests", 2);
+        recorder.record_gauge("connections", 3);
+        recorder.record_histogram("http.latency", &[5]);
+
+        enabled.store(true, Ordering::Relaxed);
+        recorder.record_gauge("connections", 4);
+
+        assert_eq!(
+            recorder.into_inner().0,
+            vec!["counter http.requests 1", "gauge connections 4"]
+        );
+    }
+}
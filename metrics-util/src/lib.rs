@@ -1,9 +1,94 @@
 //! Helper types and functions used within the metrics ecosystem.
+mod aggregate;
+pub use aggregate::{AggregatedSnapshot, AggregatingRecorder};
+
 mod bucket;
 pub use bucket::AtomicBucket;
 
+mod bucketize;
+pub use bucketize::bucketize;
+
+mod buffer;
+pub use buffer::{AsyncBufferRecorder, Backpressure};
+
+mod collapse;
+pub use collapse::CollapsingRecorder;
+
+mod dedup;
+pub use dedup::DedupGaugeRecorder;
+
+mod drop;
+pub use drop::{DropReporter, DEFAULT_DROPPED_METRIC};
+
+mod file;
+pub use file::{FileRecorder, Rotation};
+
+mod func;
+pub use func::FnRecorder;
+
+mod gate;
+pub use gate::GatedRecorder;
+
+mod lint;
+pub use lint::{LintRule, LintingRecorder, Violation};
+
+mod promote;
+pub use promote::PromoteLabelRecorder;
+
+#[cfg(feature = "shm")]
+mod shm;
+#[cfg(feature = "shm")]
+pub use shm::{ShmReader, ShmRecorder, ShmValue};
+
+mod suffix;
+pub use suffix::SuffixRecorder;
+
+mod synthetic;
+pub use synthetic::populate_synthetic;
+
+mod tee;
+pub use tee::{NameDiagnostic, TeeRecorder, TeeSink};
+
+mod timeout;
+pub use timeout::{OnFull, TimeoutRecorder};
+
+mod validate;
+pub use validate::{matches_pattern, ValidatingRecorder, Validation};
+
 mod streaming;
 pub use streaming::StreamingIntegers;
 
 mod quantile;
 pub use quantile::{parse_quantiles, Quantile};
+
+#[cfg(test)]
+pub(crate) mod test_util {
+    use metrics_core::{Key, Recorder};
+
+    // A recorder that renders every recording as a line, so tests can check what was passed on.
+    #[derive(Default)]
+    pub(crate) struct MockRecorder(pub(crate) Vec<String>);
+
+    impl Recorder for MockRecorder {
+        fn record_counter<K: Into<Key>>(&mut self, key: K, value: u64) {
+            self.0.push(format!("counter {} {}", key.into(), value));
+        }
+
+        fn record_gauge<K: Into<Key>>(&mut self, key: K, value: i64) {
+            self.0.push(format!("gauge {} {}", key.into(), value));
+        }
+
+        fn record_gauge_add<K: Into<Key>>(&mut self, key: K, delta: i64) {
+            self.0.push(format!("gauge_add {} {}", key.into(), delta));
+        }
+
+        fn record_histogram<K: Into<Key>>(&mut self, key: K, values: &[u64]) {
+            self.0
+                .push(format!("histogram {} {:?}", key.into(), values));
+        }
+
+        fn record_set<K: Into<Key>>(&mut self, key: K, value: &str) {
+            self.0.push(format!("set {} {}", key.into(), value));
+        }
+    }
+}
@@ -0,0 +1,320 @@
+use crate::DropReporter;
+use metrics_core::{Key, Recorder};
+use std::collections::{HashMap, HashSet};
+
+/// A naming rule checked by a [`LintingRecorder`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum LintRule {
+    /// Names are made of lowercase ASCII letters, digits, and underscores, start with a letter,
+    /// and don't end with an underscore.
+    ///
+    /// Dots are allowed as separators between such segments, as they're rendered as underscores.
+    SnakeCase,
+
+    /// Names don't contain consecutive underscores, which Prometheus reserves for internal use.
+    NoDoubleUnderscore,
+
+    /// Counter names end in `_total`.
+    CounterTotal,
+
+    /// Names declared with a unit, via [`LintingRecorder::declare_unit`], end in that unit.
+    ///
+    /// For counters, the unit comes right before the `_total` suffix.
+    UnitSuffix,
+}
+
+const ALL_RULES: &[LintRule] = &[
+    LintRule::SnakeCase,
+    LintRule::NoDoubleUnderscore,
+    LintRule::CounterTotal,
+    LintRule::UnitSuffix,
+];
+
+/// A name that broke a [`LintRule`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Violation {
+    /// Name of the metric.
+    pub name: String,
+
+    /// The rule that the name broke.
+    pub rule: LintRule,
+}
+
+/// A recorder that checks metric names against a naming convention.
+///
+/// Every recorded name is checked against the enabled [`LintRule`]s, which are all of them by
+/// default.  Each distinct violation is collected once, and can be listed with
+/// [`LintingRecorder::violations`], e.g. to fail a test or to print them at shutdown during
+/// development.  In lenient mode, which is the default, metrics breaking a rule are still passed
+/// through to the inner recorder, while in strict mode they're dropped, which can be reported to
+/// the inner recorder with [`LintingRecorder::report_drops`].
+pub struct LintingRecorder<R> {
+    inner: R,
+    rules: Vec<LintRule>,
+    units: HashMap<String, String>,
+    strict: bool,
+    violations: Vec<Violation>,
+    seen: HashSet<Violation>,
+    rejected: u64,
+    reporter: Option<DropReporter>,
+}
+
+impl<R> LintingRecorder<R> {
+    /// Creates a new [`LintingRecorder`] wrapping `inner`, with every rule enabled.
+    pub fn new(inner: R) -> Self {
+        LintingRecorder {
+            inner,
+            rules: ALL_RULES.to_vec(),
+            units: HashMap::new(),
+            strict: false,
+            violations: Vec::new(),
+            seen: HashSet::new(),
+            rejected: 0,
+            reporter: None,
+        }
+    }
+
+    /// Sets the rules that names are checked against.
+    pub fn with_rules(mut self, rules: &[LintRule]) -> Self {
+        self.rules = rules.to_vec();
+        self
+    }
+
+    /// Sets whether metrics breaking a rule are dropped, rather than passed through.
+    ///
+    /// Disabled by default.
+    pub fn with_strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Declares the unit of the metric named `name`, such as `seconds` or `bytes`.
+    ///
+    /// With [`LintRule::UnitSuffix`] enabled, the name is then expected to end in `_<unit>`, or in
+    /// `_<unit>_total` for a counter, so `name` should be given with the suffix included.
+    pub fn declare_unit<N: Into<String>, U: Into<String>>(&mut self, name: N, unit: U) {
+        self.units.insert(name.into(), unit.into());
+    }
+
+    /// Gets every distinct violation seen so far, in the order they were first seen.
+    pub fn violations(&self) -> &[Violation] {
+        &self.violations
+    }
+
+    /// Reports every recording dropped in strict mode to the inner recorder, using `reporter`.
+    ///
+    /// Drops are reported with the reason `lint`.
+    pub fn report_drops(&mut self, reporter: DropReporter) {
+        self.reporter = Some(reporter);
+    }
+
+    /// Gets the number of recordings that were dropped in strict mode.
+    pub fn rejected(&self) -> u64 {
+        self.rejected
+    }
+
+    /// Consumes this recorder, returning the inner recorder.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: Recorder> LintingRecorder<R> {
+    // Checks `name` against every enabled rule, and returns whether the recording should be passed
+    // through.
+    fn check(&mut self, name: &str, counter: bool) -> bool {
+        let mut valid = true;
+        for i in 0..self.rules.len() {
+            let rule = self.rules[i];
+            let passes = match rule {
+                LintRule::SnakeCase => name.split('.').all(is_snake_case),
+                LintRule::NoDoubleUnderscore => !name.contains("__"),
+                LintRule::CounterTotal => !counter || name.ends_with("_total"),
+                LintRule::UnitSuffix => match self.units.get(name) {
+                    Some(unit) => {
+                        let base = if counter {
+                            name.strip_suffix("_total").unwrap_or("")
+                        } else {
+                            name
+                        };
+                        base.strip_suffix(unit.as_str())
+                            .is_some_and(|rest| rest.ends_with('_'))
+                    }
+                    None => true,
+                },
+            };
+            if passes {
+                continue;
+            }
+
+            valid = false;
+            let violation = Violation {
+                name: name.to_owned(),
+                rule,
+            };
+            if self.seen.insert(violation.clone()) {
+                self.violations.push(violation);
+            }
+        }
+
+        if !valid && self.strict {
+            self.rejected += 1;
+            if let Some(reporter) = self.reporter.as_mut() {
+                reporter.report(&mut self.inner, "lint");
+            }
+            return false;
+        }
+        true
+    }
+}
+
+fn is_snake_case(segment: &str) -> bool {
+    segment.starts_with(|c: char| c.is_ascii_lowercase())
+        && !segment.ends_with('_')
+        && segment
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+}
+
+impl<R: Recorder> Recorder for LintingRecorder<R> {
+    fn record_counter<K: Into<Key>>(&mut self, key: K, value: u64) {
+        let key = key.into();
+        if self.check(key.name(), true) {
+            self.inner.record_counter(key, value);
+        }
+    }
+
+    fn record_gauge<K: Into<Key>>(&mut self, key: K, value: i64) {
+        let key = key.into();
+        if self.check(key.name(), false) {
+            self.inner.record_gauge(key, value);
+        }
+    }
+
+    fn record_gauge_add<K: Into<Key>>(&mut self, key: K, delta: i64) {
+        let key = key.into();
+        if self.check(key.name(), false) {
+            self.inner.record_gauge_add(key, delta);
+        }
+    }
+
+    fn supports_gauge_add(&self) -> bool {
+        self.inner.supports_gauge_add()
+    }
+
+    fn record_histogram<K: Into<Key>>(&mut self, key: K, values: &[u64]) {
+        let key = key.into();
+        if self.check(key.name(), false) {
+            self.inner.record_histogram(key, values);
+        }
+    }
+
+    fn record_histogram_sampled<K: Into<Key>>(&mut self, key: K, values: &[u64], rate: f64) {
+        let key = key.into();
+        if self.check(key.name(), false) {
+            self.inner.record_histogram_sampled(key, values, rate);
+        }
+    }
+
+    fn record_set<K: Into<Key>>(&mut self, key: K, value: &str) {
+        let key = key.into();
+        if self.check(key.name(), false) {
+            self.inner.record_set(key, value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LintRule, LintingRecorder, Violation};
+    use crate::test_util::MockRecorder;
+    use crate::DropReporter;
+    use metrics_core::Recorder;
+
+    fn violation(name: &str, rule: LintRule) -> Violation {
+        Violation {
+            name: name.to_owned(),
+            rule,
+        }
+    }
+
+    #[test]
+    fn test_lenient() {
+        let mut recorder = LintingRecorder::new(MockRecorder::default());
+        recorder.declare_unit("http.request_duration_seconds", "seconds");
+        recorder.declare_unit("http.response_size", "bytes");
+        recorder.declare_unit("http.sent_bytes_total", "bytes");
+
+        recorder.record_counter("http.requests_total", 1);
+        recorder.record_counter("http.requests", 2);
+        recorder.record_counter("http.requests", 3);
+        recorder.record_gauge("QueueDepth", 4);
+        recorder.record_gauge("queue__depth", 5);
+        recorder.record_histogram("http.request_duration_seconds", &[6]);
+        recorder.record_histogram("http.response_size", &[7]);
+        recorder.record_counter("http.sent_bytes_total", 8);
+
+        assert_eq!(
+            recorder.violations(),
+            &[
+                violation("http.requests", LintRule::CounterTotal),
+                violation("QueueDepth", LintRule::SnakeCase),
+                violation("queue__depth", LintRule::NoDoubleUnderscore),
+                violation("http.response_size", LintRule::UnitSuffix),
+            ]
+        );
+        assert_eq!(recorder.rejected(), 0);
+        assert_eq!(recorder.into_inner().0.len(), 8);
+    }
+
+    #[test]
+    fn test_strict() {
+        let mut recorder = LintingRecorder::new(MockRecorder::default())
+            .with_rules(&[LintRule::SnakeCase, LintRule::CounterTotal])
+            .with_strict(true);
+
+        recorder.record_counter("jobs_total", 1);
+        recorder.record_counter("jobs", 2);
+        recorder.record_gauge("_workers", 3);
+        recorder.record_gauge("db.pool_", 4);
+        recorder.record_gauge("db.2pool", 5);
+        // Double underscores aren't checked, as that rule isn't enabled.
+        recorder.record_gauge("db__pool", 6);
+
+        assert_eq!(
+            recorder.violations(),
+            &[
+                violation("jobs", LintRule::CounterTotal),
+                violation("_workers", LintRule::SnakeCase),
+                violation("db.pool_", LintRule::SnakeCase),
+                violation("db.2pool", LintRule::SnakeCase),
+            ]
+        );
+        assert_eq!(recorder.rejected(), 4);
+        assert_eq!(
+            recorder.into_inner().0,
+            vec!["counter jobs_total 1", "gauge db__pool 6"]
+        );
+    }
+
+    #[test]
+    fn test_report_drops() {
+        let mut recorder = LintingRecorder::new(MockRecorder::default())
+            .with_rules(&[LintRule::CounterTotal])
+            .with_strict(true);
+        recorder.report_drops(DropReporter::new());
+
+        recorder.record_counter("jobs", 1);
+        recorder.record_counter("jobs_total", 2);
+        recorder.record_counter("jobs", 3);
+
+        assert_eq!(
+            recorder.into_inner().0,
+            vec![
+                "counter metrics_internal_dropped_total{reason=lint} 1",
+                "counter jobs_total 2",
+                "counter metrics_internal_dropped_total{reason=lint} 2",
+            ]
+        );
+    }
+}
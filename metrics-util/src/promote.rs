@@ -0,0 +1,133 @@
+use metrics_core::{Key, Label, Recorder};
+
+/// A recorder that promotes a label value into the metric name.
+///
+/// For every metric with the configured label, the label is removed and its value is embedded
+/// in the name using a template, where `{name}` is replaced with the original name and `{value}`
+/// with the label value.  With a label of `queue` and a template of `queue_{value}_depth`, the
+/// metric `queue_depth{queue="a"}` becomes `queue_a_depth`.
+///
+/// This is useful for backends that don't deal well with high label cardinality.  Metrics without
+/// the label are passed through unchanged.
+pub struct PromoteLabelRecorder<R> {
+    inner: R,
+    label: String,
+    template: String,
+}
+
+impl<R> PromoteLabelRecorder<R> {
+    /// Creates a new [`PromoteLabelRecorder`] wrapping `inner`, that promotes `label`.
+    ///
+    /// The label value is appended to the name, separated with an underscore.  If you want to
+    /// customize the resulting name, you can call [`PromoteLabelRecorder::with_template`].
+    pub fn new<L: Into<String>>(inner: R, label: L) -> Self {
+        Self::with_template(inner, label, "{name}_{value}")
+    }
+
+    /// Creates a new [`PromoteLabelRecorder`] wrapping `inner`, that promotes `label` using the
+    /// given name template.
+    pub fn with_template<L, T>(inner: R, label: L, template: T) -> Self
+    where
+        L: Into<String>,
+        T: Into<String>,
+    {
+        PromoteLabelRecorder {
+            inner,
+            label: label.into(),
+            template: template.into(),
+        }
+    }
+
+    /// Consumes this recorder, returning the inner recorder.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    fn promote(&self, key: Key) -> Key {
+        if !key.labels().any(|label| label.key() == self.label) {
+            return key;
+        }
+
+        let (name, labels) = key.into_parts();
+        let (promoted, labels): (Vec<Label>, Vec<Label>) = labels
+            .into_iter()
+            .partition(|label| label.key() == self.label);
+        let value = promoted[0].value();
+
+        Key::from_name_and_labels(name, labels).map_name(|name| {
+            self.template
+                .replace("{name}", name.as_ref())
+                .replace("{value}", value)
+        })
+    }
+}
+
+impl<R: Recorder> Recorder for PromoteLabelRecorder<R> {
+    fn record_counter<K: Into<Key>>(&mut self, key: K, value: u64) {
+        let key = self.promote(key.into());
+        self.inner.record_counter(key, value);
+    }
+
+    fn record_gauge<K: Into<Key>>(&mut self, key: K, value: i64) {
+        let key = self.promote(key.into());
+        self.inner.record_gauge(key, value);
+    }
+
+    fn record_gauge_add<K: Into<Key>>(&mut self, key: K, delta: i64) {
+        let key = self.promote(key.into());
+        self.inner.record_gauge_add(key, delta);
+    }
+
+    fn supports_gauge_add(&self) -> bool {
+        self.inner.supports_gauge_add()
+    }
+
+    fn record_histogram<K: Into<Key>>(&mut self, key: K, values: &[u64]) {
+        let key = self.promote(key.into());
+        self.inner.record_histogram(key, values);
+    }
+
+    fn record_histogram_sampled<K: Into<Key>>(&mut self, key: K, values: &[u64], rate: f64) {
+        let key = self.promote(key.into());
+        self.inner.record_histogram_sampled(key, values, rate);
+    }
+
+    fn record_set<K: Into<Key>>(&mut self, key: K, value: &str) {
+        let key = self.promote(key.into());
+        self.inner.record_set(key, value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PromoteLabelRecorder;
+    use crate::test_util::MockRecorder;
+    use metrics_core::{Key, Recorder};
+
+    #[test]
+    fn test_promote_label() {
+        let mut recorder = PromoteLabelRecorder::with_template(
+            MockRecorder::default(),
+            "queue",
+            "queue_{value}_depth",
+        );
+        let labels = vec![("queue", "a"), ("host", "h1")];
+        recorder.record_gauge(Key::from_name_and_labels("queue_depth", &labels), 4);
+        recorder.record_gauge("queue_depth", 2);
+
+        let mut recorder = PromoteLabelRecorder::new(recorder.into_inner(), "method");
+        let labels = vec![("method", "get")];
+        recorder.record_counter(Key::from_name_and_labels("http.requests", &labels), 1);
+        recorder.record_histogram(Key::from_name_and_labels("http.latency", &labels), &[5]);
+
+        assert_eq!(
+            recorder.into_inner().0,
+            vec![
+                "gauge queue_a_depth{host=h1} 4",
+                "gauge queue_depth 2",
+                "counter http.requests_get 1",
+                "histogram http.latency_get [5]",
+            ]
+        );
+    }
+}
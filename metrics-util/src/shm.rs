@@ -0,0 +1,415 @@
+use crate::DropReporter;
+use memmap2::{Mmap, MmapMut};
+use metrics_core::{Key, Recorder};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::mem;
+use std::path::Path;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+
+const MAGIC: &[u8; 8] = b"METRSHM\0";
+const VERSION: u32 = 1;
+const HEADER_SIZE: usize = 64;
+const SLOT_SIZE: usize = 256;
+const NAME_OFFSET: usize = 24;
+const MAX_NAME_LEN: usize = SLOT_SIZE - NAME_OFFSET;
+
+const KIND_COUNTER: u32 = 1;
+const KIND_GAUGE: u32 = 2;
+const KIND_HISTOGRAM: u32 = 3;
+
+/// The current value of a metric, as read by a [`ShmReader`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShmValue {
+    /// The total of a counter.
+    Counter(u64),
+
+    /// The current value of a gauge.
+    Gauge(i64),
+
+    /// The number of values recorded for a histogram, and their sum.
+    Histogram {
+        /// Number of values recorded.
+        count: u64,
+
+        /// Sum of the values recorded, wrapping on overflow.
+        sum: u64,
+    },
+}
+
+/// A recorder that writes the current value of every metric into a memory-mapped file.
+///
+/// Each metric gets a fixed slot in the file the first time it's recorded, and its value is then
+/// updated in place with atomic operations, so that a separate process can read the current
+/// values at any time with a [`ShmReader`], without the application serving them over the
+/// network.
+///
+/// Counters and gauges are set to the value recorded, as counters are recorded as their total,
+/// gauge deltas are added to the gauge, and histograms only track the number and sum of the values
+/// recorded.  Metrics are identified by [`Key::as_stable_id`].  Once every slot is taken,
+/// recordings of new metrics are dropped, as are recordings of metrics whose name is longer than
+/// 232 bytes or which were already recorded as a different kind.  Sets are ignored.
+///
+/// Requires the `shm` feature.
+///
+/// # Layout
+///
+/// Integers are in the byte order of the machine, as the file is only shared between processes
+/// on the same machine.  The file starts with a 64-byte header:
+///
+/// | Offset | Size | Field                                              |
+/// |--------|------|----------------------------------------------------|
+/// | 0      | 8    | Magic bytes, `METRSHM\0`                           |
+/// | 8      | 4    | Layout version, currently `1`                      |
+/// | 12     | 4    | Number of slots in the file                        |
+/// | 16     | 4    | Number of slots in use, updated atomically         |
+/// | 20     | 44   | Reserved                                           |
+///
+/// The header is followed by the slots, each 256 bytes long:
+///
+/// | Offset | Size | Field                                              |
+/// |--------|------|----------------------------------------------------|
+/// | 0      | 4    | Kind: `1` for counters, `2` for gauges, `3` for histograms |
+/// | 4      | 4    | Length of the name                                 |
+/// | 8      | 8    | Value, updated atomically                          |
+/// | 16     | 8    | Sum of a histogram's values, updated atomically    |
+/// | 24     | 232  | Name, as UTF-8                                     |
+///
+/// The value is the total of a counter, the current value of a gauge as a two's complement
+/// integer, or the number of values recorded for a histogram.  Slots are only ever appended: a
+/// slot is filled in before the number of slots in use is increased, so every slot a reader sees
+/// is complete, and its kind and name never change afterwards.
+pub struct ShmRecorder {
+    map: MmapMut,
+    capacity: usize,
+    slots: HashMap<Key, usize>,
+    dropped: u64,
+    reporter: Option<DropReporter>,
+}
+
+impl ShmRecorder {
+    /// Creates a [`ShmRecorder`] writing to the file at `path`, with room for `capacity` metrics.
+    ///
+    /// The file is created if it doesn't exist, and any previous contents are discarded.
+    ///
+    /// # Errors
+    /// Returns an error if the file can't be created or mapped.
+    pub fn create<P: AsRef<Path>>(path: P, capacity: usize) -> io::Result<Self> {
+        let slot_count = u32::try_from(capacity)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "too many slots"))?;
+
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)?;
+        file.set_len((HEADER_SIZE + capacity * SLOT_SIZE) as u64)?;
+
+        // SAFETY: the file was just created with the right size, and is only written through
+        // this mapping, either directly while it isn't shared yet, or with atomic operations.
+        let mut map = unsafe { MmapMut::map_mut(&file)? };
+        map[..8].copy_from_slice(MAGIC);
+        map[12..16].copy_from_slice(&slot_count.to_ne_bytes());
+        u32_at(&mut map, 16).store(0, Ordering::Release);
+        u32_at(&mut map, 8).store(VERSION, Ordering::Release);
+
+        Ok(ShmRecorder {
+            map,
+            capacity,
+            slots: HashMap::new(),
+            dropped: 0,
+            reporter: None,
+        })
+    }
+
+    /// Gets the number of recordings dropped because they couldn't be given a slot.
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    /// Reports every dropped recording as a counter in the file itself, using `reporter`.
+    ///
+    /// Drops are reported with the reason `shm`.  The counter needs a slot of its own, so drops
+    /// can only be reported while there is one left for it, or once it has one.
+    pub fn report_drops(&mut self, reporter: DropReporter) {
+        self.reporter = Some(reporter);
+    }
+
+    fn drop_recording(&mut self) {
+        self.dropped += 1;
+        // The reporter is taken while reporting, so drops of the report itself aren't reported.
+        if let Some(mut reporter) = self.reporter.take() {
+            reporter.report(self, "shm");
+            self.reporter = Some(reporter);
+        }
+    }
+
+    // Gets the offset of the slot for `key`, filling in a new slot if it wasn't recorded before.
+    fn slot(&mut self, key: Key, kind: u32) -> Option<usize> {
+        let offset = match self.slots.get(&key) {
+            Some(offset) => *offset,
+            None => {
+                let name = key.as_stable_id();
+                let index = self.slots.len();
+                if index == self.capacity || name.len() > MAX_NAME_LEN {
+                    self.drop_recording();
+                    return None;
+                }
+
+                let offset = HEADER_SIZE + index * SLOT_SIZE;
+                let slot = &mut self.map[offset..offset + SLOT_SIZE];
+                slot[..4].copy_from_slice(&kind.to_ne_bytes());
+                slot[4..8].copy_from_slice(&(name.len() as u32).to_ne_bytes());
+                slot[NAME_OFFSET..NAME_OFFSET + name.len()].copy_from_slice(name.as_bytes());
+                // Publishes the slot, which is complete by now, to readers.
+                u32_at(&mut self.map, 16).store(index as u32 + 1, Ordering::Release);
+
+                self.slots.insert(key, offset);
+                offset
+            }
+        };
+
+        if u32_at(&mut self.map, offset).load(Ordering::Relaxed) == kind {
+            Some(offset)
+        } else {
+            self.drop_recording();
+            None
+        }
+    }
+}
+
+impl Recorder for ShmRecorder {
+    fn record_counter<K: Into<Key>>(&mut self, key: K, value: u64) {
+        if let Some(offset) = self.slot(key.into(), KIND_COUNTER) {
+            u64_at(&mut self.map, offset + 8).store(value, Ordering::Relaxed);
+        }
+    }
+
+    fn record_gauge<K: Into<Key>>(&mut self, key: K, value: i64) {
+        if let Some(offset) = self.slot(key.into(), KIND_GAUGE) {
+            u64_at(&mut self.map, offset + 8).store(value as u64, Ordering::Relaxed);
+        }
+    }
+
+    fn record_gauge_add<K: Into<Key>>(&mut self, key: K, delta: i64) {
+        if let Some(offset) = self.slot(key.into(), KIND_GAUGE) {
+            u64_at(&mut self.map, offset + 8).fetch_add(delta as u64, Ordering::Relaxed);
+        }
+    }
+
+    fn supports_gauge_add(&self) -> bool {
+        true
+    }
+
+    fn record_histogram<K: Into<Key>>(&mut self, key: K, values: &[u64]) {
+        if let Some(offset) = self.slot(key.into(), KIND_HISTOGRAM) {
+            let sum = values.iter().fold(0u64, |sum, v| sum.wrapping_add(*v));
+            u64_at(&mut self.map, offset + 16).fetch_add(sum, Ordering::Relaxed);
+            u64_at(&mut self.map, offset + 8).fetch_add(values.len() as u64, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Reads the metrics written by a [`ShmRecorder`], possibly from another process.
+pub struct ShmReader {
+    map: Mmap,
+}
+
+impl ShmReader {
+    /// Opens the file at `path`, written by a [`ShmRecorder`].
+    ///
+    /// # Errors
+    /// Returns an error if the file can't be opened or mapped, or if it isn't in a layout this
+    /// reader understands.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let file = File::open(path)?;
+        // SAFETY: the recorder only writes to the file with atomic operations once it's been
+        // initialized, and never changes its size, so the mapping stays valid.
+        let map = unsafe { Mmap::map(&file)? };
+
+        let invalid = |message| io::Error::new(io::ErrorKind::InvalidData, message);
+        if map.len() < HEADER_SIZE || &map[..8] != MAGIC {
+            return Err(invalid("not a metrics file"));
+        }
+        if load_u32(&map, 8, Ordering::Acquire) != VERSION {
+            return Err(invalid("unsupported metrics file version"));
+        }
+        let slot_count = load_u32(&map, 12, Ordering::Relaxed) as usize;
+        if map.len() < HEADER_SIZE + slot_count * SLOT_SIZE {
+            return Err(invalid("truncated metrics file"));
+        }
+
+        Ok(ShmReader { map })
+    }
+
+    /// Reads the current value of every metric, in the order they were first recorded.
+    pub fn read(&self) -> Vec<(String, ShmValue)> {
+        let slot_count = load_u32(&self.map, 12, Ordering::Relaxed) as usize;
+        let used = (load_u32(&self.map, 16, Ordering::Acquire) as usize).min(slot_count);
+
+        (0..used)
+            .filter_map(|index| {
+                let offset = HEADER_SIZE + index * SLOT_SIZE;
+                let len =
+                    (load_u32(&self.map, offset + 4, Ordering::Relaxed) as usize).min(MAX_NAME_LEN);
+                let name = &self.map[offset + NAME_OFFSET..offset + NAME_OFFSET + len];
+                let name = String::from_utf8_lossy(name).into_owned();
+
+                let value = load_u64(&self.map, offset + 8, Ordering::Relaxed);
+                let value = match load_u32(&self.map, offset, Ordering::Relaxed) {
+                    KIND_COUNTER => ShmValue::Counter(value),
+                    KIND_GAUGE => ShmValue::Gauge(value as i64),
+                    KIND_HISTOGRAM => ShmValue::Histogram {
+                        count: value,
+                        sum: load_u64(&self.map, offset + 16, Ordering::Relaxed),
+                    },
+                    _ => return None,
+                };
+                Some((name, value))
+            })
+            .collect()
+    }
+}
+
+// Gets a pointer to the `T` at `offset` of the mapping starting at `base`, which is `len` bytes
+// long, panicking unless the whole `T` is in the mapping and the pointer is aligned for it.
+// Mappings are page-aligned, and every field is aligned to its size, so this only fails on a
+// layout bug.
+fn field_at<T>(base: *const u8, len: usize, offset: usize) -> *const T {
+    assert!(offset + mem::size_of::<T>() <= len);
+    let ptr = base.wrapping_add(offset);
+    assert_eq!(ptr as usize % mem::align_of::<T>(), 0);
+    ptr as *const T
+}
+
+// Gets the atomic integer at `offset` of a writable mapping.  The pointer is derived from the
+// mutable pointer of the mapping, rather than from a shared slice of it, as it's written through.
+fn u32_at(map: &mut MmapMut, offset: usize) -> &AtomicU32 {
+    let ptr = field_at::<AtomicU32>(map.as_mut_ptr(), map.len(), offset);
+    // SAFETY: `field_at` checked that the pointer is in bounds of the mapping and aligned for
+    // `AtomicU32`, which has the same layout as the `u32` stored there.  The reference borrows the
+    // mapping mutably, so it can't overlap a slice of it, and every other access to the field,
+    // including from other processes, is atomic too.
+    unsafe { &*ptr }
+}
+
+fn u64_at(map: &mut MmapMut, offset: usize) -> &AtomicU64 {
+    let ptr = field_at::<AtomicU64>(map.as_mut_ptr(), map.len(), offset);
+    // SAFETY: as for `u32_at`, the pointer is in bounds, aligned and derived from the mutable
+    // pointer of a mapping borrowed mutably for as long as the reference lives.
+    unsafe { &*ptr }
+}
+
+// Atomically loads the integer at `offset` of a read-only mapping.
+fn load_u32(map: &Mmap, offset: usize, ordering: Ordering) -> u32 {
+    let ptr = field_at::<AtomicU32>(map.as_ptr(), map.len(), offset);
+    // SAFETY: `field_at` checked that the pointer is in bounds and aligned.  The field is only
+    // loaded, never written, through the mapping, while the recorder writes it atomically.
+    unsafe { (*ptr).load(ordering) }
+}
+
+fn load_u64(map: &Mmap, offset: usize, ordering: Ordering) -> u64 {
+    let ptr = field_at::<AtomicU64>(map.as_ptr(), map.len(), offset);
+    // SAFETY: as for `load_u32`, the pointer is in bounds and aligned, and only loaded from.
+    unsafe { (*ptr).load(ordering) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ShmReader, ShmRecorder, ShmValue};
+    use crate::DropReporter;
+    use metrics_core::{Key, Recorder};
+    use std::fs;
+    use std::path::PathBuf;
+    use std::time::SystemTime;
+
+    fn temp_file(name: &str) -> PathBuf {
+        let nanos = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap()
+            .as_nanos();
+        std::env::temp_dir().join(format!(
+            "metrics-util-{}-{}-{}.shm",
+            name,
+            std::process::id(),
+            nanos
+        ))
+    }
+
+    #[test]
+    fn test_shm_round_trip() {
+        let path = temp_file("round-trip");
+        let mut recorder = ShmRecorder::create(&path, 3).unwrap();
+        let reader = ShmReader::open(&path).unwrap();
+        assert_eq!(reader.read(), vec![]);
+
+        let labels = vec![("method", "get")];
+        recorder.record_counter(Key::from_name_and_labels("requests", &labels), 2);
+        recorder.record_gauge("connections", -4);
+        recorder.record_histogram("latency", &[10, 20, 30]);
+        assert_eq!(
+            reader.read(),
+            vec![
+                ("requests{method=\"get\"}".to_owned(), ShmValue::Counter(2)),
+                ("connections".to_owned(), ShmValue::Gauge(-4)),
+                (
+                    "latency".to_owned(),
+                    ShmValue::Histogram { count: 3, sum: 60 }
+                ),
+            ]
+        );
+
+        // Updates are visible through the existing mapping, and to newly opened readers.
+        recorder.record_counter(Key::from_name_and_labels("requests", &labels), 5);
+        recorder.record_gauge_add("connections", 5);
+        let reopened = ShmReader::open(&path).unwrap();
+        for reader in &[reader, reopened] {
+            let values = reader.read();
+            assert_eq!(values[0].1, ShmValue::Counter(5));
+            assert_eq!(values[1].1, ShmValue::Gauge(1));
+        }
+
+        // Every slot is taken, and a name can't change kind.
+        recorder.record_counter("errors", 1);
+        recorder.record_gauge("latency", 1);
+        assert_eq!(recorder.dropped(), 2);
+        assert_eq!(ShmReader::open(&path).unwrap().read().len(), 3);
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_shm_report_drops() {
+        let path = temp_file("report-drops");
+        let mut recorder = ShmRecorder::create(&path, 2).unwrap();
+        recorder.report_drops(DropReporter::new());
+        recorder.record_gauge("connections", 1);
+        recorder.record_counter("connections", 1);
+        recorder.record_counter("connections", 2);
+        // The file is full, but the drop counter already has a slot.
+        recorder.record_counter("errors", 1);
+
+        let dropped = "metrics_internal_dropped_total{reason=\"shm\"}".to_owned();
+        assert_eq!(recorder.dropped(), 3);
+        assert_eq!(
+            ShmReader::open(&path).unwrap().read(),
+            vec![
+                ("connections".to_owned(), ShmValue::Gauge(1)),
+                (dropped, ShmValue::Counter(3)),
+            ]
+        );
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_shm_invalid_file() {
+        let path = temp_file("invalid");
+        fs::write(&path, vec![0u8; 128]).unwrap();
+        assert!(ShmReader::open(&path).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,3 +1,4 @@
+use std::convert::TryFrom;
 use std::slice;
 
 /// A compressed set of integers.
@@ -17,6 +18,10 @@ use std::slice;
 /// while passing them to a given function, allowing callers to observe the integers without
 /// allocating the entire size of the decompressed set.
 ///
+/// A value repeated many times can be added in one step with
+/// [`compress_repeated`](StreamingIntegers::compress_repeated), which stores the value and its
+/// count rather than every copy of it.
+///
 /// # Performance
 /// As this is a scalar implementation, performance depends heavily on not only the input size, but
 /// also the delta between values, as well as whether or not the decompressed values are being
@@ -51,6 +56,7 @@ pub struct StreamingIntegers {
     inner: Vec<u8>,
     len: usize,
     last: Option<i64>,
+    repeated: Vec<(u64, u64)>,
 }
 
 impl StreamingIntegers {
@@ -90,7 +96,7 @@ impl StreamingIntegers {
 
         let mut buf_idx = self.inner.len();
         let buf_cap = self.inner.capacity();
-        let mut buf = unsafe {
+        let buf = unsafe {
             let buf_ptr = self.inner.as_mut_ptr();
             slice::from_raw_parts_mut(buf_ptr, buf_cap)
         };
@@ -103,7 +109,7 @@ impl StreamingIntegers {
             self.last = Some(first);
 
             let zigzag = zigzag_encode(first);
-            buf_idx = vbyte_encode(zigzag, &mut buf, buf_idx);
+            buf_idx = vbyte_encode(zigzag, buf, buf_idx);
 
             src_idx += 1;
         }
@@ -115,7 +121,7 @@ impl StreamingIntegers {
             let value = src[src_idx] as i64;
             let diff = value - last;
             let zigzag = zigzag_encode(diff);
-            buf_idx = vbyte_encode(zigzag, &mut buf, buf_idx);
+            buf_idx = vbyte_encode(zigzag, buf, buf_idx);
             last = value;
             src_idx += 1;
         }
@@ -127,6 +133,26 @@ impl StreamingIntegers {
         self.last = Some(last);
     }
 
+    /// Adds `value` to the set `count` times.
+    ///
+    /// Only the value and its count are stored, so this takes the same time and space whatever
+    /// the count is.  Repeated values are kept apart from the compressed integers, and are
+    /// decompressed after all of them.
+    pub fn compress_repeated(&mut self, value: u64, count: u64) {
+        if count == 0 {
+            return;
+        }
+
+        let count_len = usize::try_from(count).unwrap_or(usize::MAX);
+        self.len = self.len.saturating_add(count_len);
+        match self.repeated.last_mut() {
+            Some((last, last_count)) if *last == value => {
+                *last_count = last_count.saturating_add(count)
+            }
+            _ => self.repeated.push((value, count)),
+        }
+    }
+
     /// Decompresses all of the integers written to the set.
     ///
     /// Returns a vector with all of the original values.  For larger sets of integers, this can be
@@ -143,7 +169,7 @@ impl StreamingIntegers {
 
         let mut last = 0;
         while buf_idx < buf_len {
-            let (value, new_idx) = vbyte_decode(&buf, buf_idx);
+            let (value, new_idx) = vbyte_decode(buf, buf_idx);
             buf_idx = new_idx;
 
             let delta = zigzag_decode(value);
@@ -153,6 +179,10 @@ impl StreamingIntegers {
             values.push(original as u64);
         }
 
+        for &(value, count) in &self.repeated {
+            values.resize(values.len() + count as usize, value);
+        }
+
         values
     }
 
@@ -173,7 +203,7 @@ impl StreamingIntegers {
 
         let mut last = 0;
         while buf_idx < buf_len {
-            let (value, new_idx) = vbyte_decode(&buf, buf_idx);
+            let (value, new_idx) = vbyte_decode(buf, buf_idx);
             buf_idx = new_idx;
 
             let delta = zigzag_decode(value);
@@ -187,6 +217,20 @@ impl StreamingIntegers {
             }
         }
 
+        for &(value, count) in &self.repeated {
+            let mut remaining = count;
+            while remaining > 0 {
+                let room = (values.capacity() - values.len()) as u64;
+                let take = remaining.min(room);
+                values.resize(values.len() + take as usize, value);
+                remaining -= take;
+                if values.len() == values.capacity() {
+                    f(&values);
+                    values.clear();
+                }
+            }
+        }
+
         if !values.is_empty() {
             f(&values);
         }
@@ -206,7 +250,7 @@ fn zigzag_decode(input: u64) -> i64 {
 #[inline]
 fn vbyte_encode(mut input: u64, buf: &mut [u8], mut buf_idx: usize) -> usize {
     while input >= 128 {
-        buf[buf_idx] = 0x80 as u8 | (input as u8 & 0x7F);
+        buf[buf_idx] = 0x80_u8 | (input as u8 & 0x7F);
         buf_idx += 1;
         input >>= 7;
     }
@@ -292,4 +336,35 @@ mod tests {
         let decompressed = si.decompress();
         assert_eq!(decompressed.len(), 0);
     }
+
+    #[test]
+    fn test_streaming_integers_repeated() {
+        let mut si = StreamingIntegers::new();
+        si.compress_repeated(5, 2);
+        si.compress(&[1, 2]);
+        si.compress_repeated(5, 1);
+        si.compress_repeated(9, 0);
+        si.compress_repeated(7, 3);
+        assert_eq!(si.len(), 8);
+        assert_eq!(si.decompress(), vec![1, 2, 5, 5, 5, 7, 7, 7]);
+
+        // Large counts are stored as a count, and decompressed in batches.
+        let mut si = StreamingIntegers::new();
+        si.compress(&[1]);
+        si.compress_repeated(3, 2500);
+        assert_eq!(si.len(), 2501);
+        let mut batches = Vec::new();
+        let mut sum = 0;
+        si.decompress_with(|values| {
+            batches.push(values.len());
+            sum += values.iter().sum::<u64>();
+        });
+        assert_eq!(batches, vec![1024, 1024, 453]);
+        assert_eq!(sum, 7501);
+
+        let mut si = StreamingIntegers::new();
+        si.compress_repeated(3, 1 << 40);
+        si.compress_repeated(3, 1 << 40);
+        assert_eq!(si.len() as u64, 1 << 41);
+    }
 }
@@ -0,0 +1,92 @@
+use metrics_core::{Key, Recorder};
+
+/// A recorder that appends a fixed suffix to every metric name.
+///
+/// Labels are passed through unchanged.  This is useful for naming schemes which require a
+/// suffix, such as a unit or the name of the environment.
+pub struct SuffixRecorder<R> {
+    inner: R,
+    suffix: String,
+}
+
+impl<R> SuffixRecorder<R> {
+    /// Creates a new [`SuffixRecorder`] wrapping `inner`, that appends `suffix` to every name.
+    pub fn new<S: Into<String>>(inner: R, suffix: S) -> Self {
+        SuffixRecorder {
+            inner,
+            suffix: suffix.into(),
+        }
+    }
+
+    /// Consumes this recorder, returning the inner recorder.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    fn suffixed(&self, key: Key) -> Key {
+        key.map_name(|name| format!("{}{}", name, self.suffix))
+    }
+}
+
+impl<R: Recorder> Recorder for SuffixRecorder<R> {
+    fn record_counter<K: Into<Key>>(&mut self, key: K, value: u64) {
+        let key = self.suffixed(key.into());
+        self.inner.record_counter(key, value);
+    }
+
+    fn record_gauge<K: Into<Key>>(&mut self, key: K, value: i64) {
+        let key = self.suffixed(key.into());
+        self.inner.record_gauge(key, value);
+    }
+
+    fn record_gauge_add<K: Into<Key>>(&mut self, key: K, delta: i64) {
+        let key = self.suffixed(key.into());
+        self.inner.record_gauge_add(key, delta);
+    }
+
+    fn supports_gauge_add(&self) -> bool {
+        self.inner.supports_gauge_add()
+    }
+
+    fn record_histogram<K: Into<Key>>(&mut self, key: K, values: &[u64]) {
+        let key = self.suffixed(key.into());
+        self.inner.record_histogram(key, values);
+    }
+
+    fn record_histogram_sampled<K: Into<Key>>(&mut self, key: K, values: &[u64], rate: f64) {
+        let key = self.suffixed(key.into());
+        self.inner.record_histogram_sampled(key, values, rate);
+    }
+
+    fn record_set<K: Into<Key>>(&mut self, key: K, value: &str) {
+        let key = self.suffixed(key.into());
+        self.inner.record_set(key, value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SuffixRecorder;
+    use crate::test_util::MockRecorder;
+    use metrics_core::{Key, Recorder};
+
+    #[test]
+    fn test_suffix() {
+        let mut recorder = SuffixRecorder::new(MockRecorder::default(), "_staging");
+        let labels = vec![("method", "get")];
+        recorder.record_counter(Key::from_name_and_labels("http.requests", &labels), 1);
+        recorder.record_gauge("connections", 3);
+        recorder.record_histogram("http.latency", &[5, 6]);
+        recorder.record_set("users", "alice");
+
+        assert_eq!(
+            recorder.into_inner().0,
+            vec![
+                "counter http.requests_staging{method=get} 1",
+                "gauge connections_staging 3",
+                "histogram http.latency_staging [5, 6]",
+                "set users_staging alice",
+            ]
+        );
+    }
+}
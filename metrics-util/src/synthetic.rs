@@ -0,0 +1,89 @@
+use metrics_core::{Key, Label, Recorder};
+
+const SHARDS: usize = 10;
+
+/// Populates a recorder with a deterministic, synthetic set of metrics.
+///
+/// Records `n_series` distinct series, split evenly between counters, gauges, and histograms, and
+/// records `n_samples` values for each of them.  Series are spread over several names, each with
+/// a `shard` label, much like the metrics of a real application.  Values are generated from a
+/// fixed seed, so every call records exactly the same metrics in the same order, which makes it
+/// suitable for repeatable benchmarks and tests of recorders.
+///
+/// Counter values are recorded as a running total, gauge values are both positive and
+/// negative, and histogram values are recorded in a single call per series.
+pub fn populate_synthetic<R: Recorder>(recorder: &mut R, n_series: usize, n_samples: usize) {
+    let mut rng = XorShift(0x2545_f491_4f6c_dd1d);
+    let mut values = Vec::with_capacity(n_samples);
+
+    for i in 0..n_series {
+        let (kind, index) = (i % 3, i / 3);
+        let name = match kind {
+            0 => format!("synthetic.counter_{}", index / SHARDS),
+            1 => format!("synthetic.gauge_{}", index / SHARDS),
+            _ => format!("synthetic.histogram_{}", index / SHARDS),
+        };
+        let shard = Label::new("shard", (index % SHARDS).to_string());
+        let key = Key::from_name_and_labels(name, vec![shard]);
+
+        match kind {
+            0 => {
+                let mut total = 0;
+                for _ in 0..n_samples {
+                    total += rng.next() % 100;
+                    recorder.record_counter(key.clone(), total);
+                }
+            }
+            1 => {
+                for _ in 0..n_samples {
+                    let value = (rng.next() % 2_000_000) as i64 - 1_000_000;
+                    recorder.record_gauge(key.clone(), value);
+                }
+            }
+            _ => {
+                values.clear();
+                values.extend((0..n_samples).map(|_| 1 + rng.next() % 1_000_000));
+                recorder.record_histogram(key, &values);
+            }
+        }
+    }
+}
+
+// A small, fast PRNG, so that the generated values don't depend on an external crate.
+struct XorShift(u64);
+
+impl XorShift {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::populate_synthetic;
+    use crate::test_util::MockRecorder;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_populate_synthetic() {
+        let mut first = MockRecorder::default();
+        populate_synthetic(&mut first, 100, 4);
+        let mut second = MockRecorder::default();
+        populate_synthetic(&mut second, 100, 4);
+        assert_eq!(first.0, second.0);
+
+        // Counters and gauges record each sample separately, histograms all at once.
+        assert_eq!(first.0.len(), 34 * 4 + 33 * 4 + 33);
+        let series = first
+            .0
+            .iter()
+            .map(|line| line.splitn(3, ' ').take(2).collect::<Vec<_>>().join(" "))
+            .collect::<HashSet<_>>();
+        assert_eq!(series.len(), 100);
+        assert!(series.contains("counter synthetic.counter_0{shard=0}"));
+        assert!(series.contains("histogram synthetic.histogram_3{shard=2}"));
+    }
+}
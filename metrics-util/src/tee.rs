@@ -0,0 +1,326 @@
+use metrics_core::{Key, Recorder};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// One of the two sinks of a [`TeeRecorder`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum TeeSink {
+    /// The first recorder given to [`TeeRecorder::new`].
+    First,
+
+    /// The second recorder given to [`TeeRecorder::new`].
+    Second,
+}
+
+/// An inconsistency between the names recorded to the sinks of a [`TeeRecorder`].
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum NameDiagnostic {
+    /// A metric was recorded to the other sink, but never to `sink`, as every recording was
+    /// routed away from it.
+    Missing {
+        /// Name of the metric, as it was recorded to the tee.
+        name: String,
+
+        /// The sink the metric is missing from.
+        sink: TeeSink,
+    },
+
+    /// Several metrics were renamed to the same name for `sink`, so they collide there.
+    Collision {
+        /// The sink the metrics collide in.
+        sink: TeeSink,
+
+        /// The name the metrics were all renamed to.
+        renamed: String,
+
+        /// Names of the metrics, as they were recorded to the tee.
+        names: Vec<String>,
+    },
+}
+
+type Rename = Box<dyn Fn(&str) -> Option<String> + Send + Sync>;
+
+/// A recorder that records every metric to two recorders.
+///
+/// Each sink can rename metrics with [`with_rename`](TeeRecorder::with_rename), e.g. to prefix
+/// them differently for different backends, or to route some metrics away from it.  As a
+/// misconfigured rename is easy to miss, names can be tracked with
+/// [`with_name_tracking`](TeeRecorder::with_name_tracking), after which
+/// [`diagnostics`](TeeRecorder::diagnostics) lists the metrics which only reached one of the
+/// sinks, and the metrics which were renamed to the same name within a sink.
+pub struct TeeRecorder<A, B> {
+    first: A,
+    second: B,
+    renames: [Option<Rename>; 2],
+    names: Option<[BTreeMap<String, Option<String>>; 2]>,
+}
+
+impl<A, B> TeeRecorder<A, B> {
+    /// Creates a new [`TeeRecorder`] recording to both `first` and `second`.
+    pub fn new(first: A, second: B) -> Self {
+        TeeRecorder {
+            first,
+            second,
+            renames: [None, None],
+            names: None,
+        }
+    }
+
+    /// Sets how metric names are renamed for `sink`.
+    ///
+    /// `rename` is called with the name of every metric recorded to the tee, and returns the name
+    /// to record it as in `sink`, or `None` to not record it to `sink` at all.  Labels are passed
+    /// through unchanged.  By default, names are passed through unchanged to both sinks.
+    pub fn with_rename<F>(mut self, sink: TeeSink, rename: F) -> Self
+    where
+        F: Fn(&str) -> Option<String> + Send + Sync + 'static,
+    {
+        self.renames[sink as usize] = Some(Box::new(rename));
+        self
+    }
+
+    /// Sets whether the names recorded to each sink are tracked, for [`TeeRecorder::diagnostics`].
+    ///
+    /// Tracking keeps every distinct name in memory, so it's meant for debugging a pipeline, or
+    /// for tests, rather than for production.  Disabled by default.
+    pub fn with_name_tracking(mut self, enabled: bool) -> Self {
+        self.names = if enabled {
+            Some([BTreeMap::new(), BTreeMap::new()])
+        } else {
+            None
+        };
+        self
+    }
+
+    /// Lists the inconsistencies between the names recorded to each sink, sorted.
+    ///
+    /// Always empty when name tracking is disabled.
+    pub fn diagnostics(&self) -> Vec<NameDiagnostic> {
+        let names = match &self.names {
+            Some(names) => names,
+            None => return Vec::new(),
+        };
+
+        let mut diagnostics = Vec::new();
+        for (sink, other) in [
+            (TeeSink::First, TeeSink::Second),
+            (TeeSink::Second, TeeSink::First),
+        ]
+        .iter()
+        .copied()
+        {
+            let reached = |sink: TeeSink, name: &str| {
+                names[sink as usize]
+                    .get(name)
+                    .is_some_and(|renamed| renamed.is_some())
+            };
+            for name in names[other as usize].keys() {
+                if reached(other, name) && !reached(sink, name) {
+                    diagnostics.push(NameDiagnostic::Missing {
+                        name: name.clone(),
+                        sink,
+                    });
+                }
+            }
+
+            let mut originals = BTreeMap::<&str, BTreeSet<&str>>::new();
+            for (name, renamed) in &names[sink as usize] {
+                if let Some(renamed) = renamed {
+                    originals.entry(renamed).or_default().insert(name);
+                }
+            }
+            for (renamed, originals) in originals {
+                if originals.len() > 1 {
+                    diagnostics.push(NameDiagnostic::Collision {
+                        sink,
+                        renamed: renamed.to_owned(),
+                        names: originals.into_iter().map(str::to_owned).collect(),
+                    });
+                }
+            }
+        }
+
+        diagnostics.sort();
+        diagnostics
+    }
+
+    /// Consumes this recorder, returning both recorders.
+    pub fn into_inner(self) -> (A, B) {
+        (self.first, self.second)
+    }
+
+    // Gets the key to record to each sink, if any.
+    fn route(&mut self, key: Key) -> (Option<Key>, Option<Key>) {
+        let renamed = [
+            self.rename(TeeSink::First, &key),
+            self.rename(TeeSink::Second, &key),
+        ];
+        if let Some(names) = &mut self.names {
+            for (names, renamed) in names.iter_mut().zip(renamed.iter()) {
+                let renamed = renamed.as_ref().map(|key| key.name().to_owned());
+                let tracked = names.entry(key.name().to_owned()).or_insert(None);
+                // A name is tracked as reaching the sink if any of its recordings did.
+                if renamed.is_some() {
+                    *tracked = renamed;
+                }
+            }
+        }
+
+        let [first, second] = renamed;
+        (first, second)
+    }
+
+    fn rename(&self, sink: TeeSink, key: &Key) -> Option<Key> {
+        match &self.renames[sink as usize] {
+            Some(rename) => {
+                let name = rename(key.name())?;
+                Some(key.clone().map_name(|_| name))
+            }
+            None => Some(key.clone()),
+        }
+    }
+}
+
+impl<A: Recorder, B: Recorder> Recorder for TeeRecorder<A, B> {
+    fn record_counter<K: Into<Key>>(&mut self, key: K, value: u64) {
+        let (first, second) = self.route(key.into());
+        if let Some(key) = first {
+            self.first.record_counter(key, value);
+        }
+        if let Some(key) = second {
+            self.second.record_counter(key, value);
+        }
+    }
+
+    fn record_gauge<K: Into<Key>>(&mut self, key: K, value: i64) {
+        let (first, second) = self.route(key.into());
+        if let Some(key) = first {
+            self.first.record_gauge(key, value);
+        }
+        if let Some(key) = second {
+            self.second.record_gauge(key, value);
+        }
+    }
+
+    fn record_gauge_add<K: Into<Key>>(&mut self, key: K, delta: i64) {
+        let (first, second) = self.route(key.into());
+        if let Some(key) = first {
+            self.first.record_gauge_add(key, delta);
+        }
+        if let Some(key) = second {
+            self.second.record_gauge_add(key, delta);
+        }
+    }
+
+    fn supports_gauge_add(&self) -> bool {
+        self.first.supports_gauge_add() && self.second.supports_gauge_add()
+    }
+
+    fn record_histogram<K: Into<Key>>(&mut self, key: K, values: &[u64]) {
+        let (first, second) = self.route(key.into());
+        if let Some(key) = first {
+            self.first.record_histogram(key, values);
+        }
+        if let Some(key) = second {
+            self.second.record_histogram(key, values);
+        }
+    }
+
+    fn record_histogram_sampled<K: Into<Key>>(&mut self, key: K, values: &[u64], rate: f64) {
+        let (first, second) = self.route(key.into());
+        if let Some(key) = first {
+            self.first.record_histogram_sampled(key, values, rate);
+        }
+        if let Some(key) = second {
+            self.second.record_histogram_sampled(key, values, rate);
+        }
+    }
+
+    fn record_set<K: Into<Key>>(&mut self, key: K, value: &str) {
+        let (first, second) = self.route(key.into());
+        if let Some(key) = first {
+            self.first.record_set(key, value);
+        }
+        if let Some(key) = second {
+            self.second.record_set(key, value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{NameDiagnostic, TeeRecorder, TeeSink};
+    use crate::test_util::MockRecorder;
+    use metrics_core::{Key, Recorder};
+
+    #[test]
+    fn test_tee() {
+        let mut recorder = TeeRecorder::new(MockRecorder::default(), MockRecorder::default())
+            .with_rename(TeeSink::Second, |name| Some(format!("app.{}", name)));
+        let labels = vec![("method", "get")];
+        recorder.record_counter(Key::from_name_and_labels("requests", &labels), 1);
+        recorder.record_histogram("latency", &[2, 3]);
+        assert!(recorder.diagnostics().is_empty());
+
+        let (first, second) = recorder.into_inner();
+        assert_eq!(
+            first.0,
+            vec!["counter requests{method=get} 1", "histogram latency [2, 3]"]
+        );
+        assert_eq!(
+            second.0,
+            vec![
+                "counter app.requests{method=get} 1",
+                "histogram app.latency [2, 3]"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tee_diagnostics() {
+        // The second sink only gets HTTP metrics, and drops everything after the first dot.
+        let mut recorder = TeeRecorder::new(MockRecorder::default(), MockRecorder::default())
+            .with_rename(TeeSink::First, |name| Some(format!("app_{}", name)))
+            .with_rename(TeeSink::Second, |name| {
+                if name.starts_with("http.") {
+                    name.split('.').take(2).collect::<Vec<_>>().join(".").into()
+                } else {
+                    None
+                }
+            })
+            .with_name_tracking(true);
+
+        recorder.record_counter("http.requests.get", 1);
+        recorder.record_counter("http.requests.post", 1);
+        recorder.record_gauge("http.connections", 4);
+        recorder.record_histogram("db.query_time", &[10]);
+
+        assert_eq!(
+            recorder.diagnostics(),
+            vec![
+                NameDiagnostic::Missing {
+                    name: "db.query_time".to_owned(),
+                    sink: TeeSink::Second,
+                },
+                NameDiagnostic::Collision {
+                    sink: TeeSink::Second,
+                    renamed: "http.requests".to_owned(),
+                    names: vec![
+                        "http.requests.get".to_owned(),
+                        "http.requests.post".to_owned()
+                    ],
+                },
+            ]
+        );
+
+        let (first, second) = recorder.into_inner();
+        assert_eq!(first.0.len(), 4);
+        assert_eq!(
+            second.0,
+            vec![
+                "counter http.requests 1",
+                "counter http.requests 1",
+                "gauge http.connections 4"
+            ]
+        );
+    }
+}
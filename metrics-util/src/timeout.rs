@@ -0,0 +1,382 @@
+use crate::buffer::{Drops, Message};
+use crate::DropReporter;
+use metrics_core::{Key, Recorder};
+use std::collections::VecDeque;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// Behavior of a [`TimeoutRecorder`] when its queue is full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OnFull {
+    /// Drop the recording immediately.
+    Drop,
+
+    /// Wait up to the given duration for room in the queue, and drop the recording if there is
+    /// still none.
+    ///
+    /// If the inner recorder has already been busy with a single recording for longer than the
+    /// duration, it is considered stuck, and recordings are dropped without waiting.
+    Wait(Duration),
+}
+
+struct Queue {
+    messages: VecDeque<Message>,
+    busy_since: Option<Instant>,
+    closed: bool,
+}
+
+struct Shared {
+    queue: Mutex<Queue>,
+    not_empty: Condvar,
+    not_full: Condvar,
+    capacity: usize,
+}
+
+/// A recorder that protects callers from a slow inner recorder.
+///
+/// Recordings are pushed onto a bounded queue, and a background thread applies them to the inner
+/// recorder.  Unlike [`AsyncBufferRecorder`](crate::AsyncBufferRecorder), callers never block
+/// indefinitely: when the queue is full, recordings are dropped, either immediately or after
+/// waiting for a bounded amount of time, as configured with [`OnFull`].  This keeps the latency of
+/// the application independent of the inner recorder, such as one that writes to the network.
+///
+/// The number of dropped recordings can be retrieved with
+/// [`dropped`](TimeoutRecorder::dropped), and reported to the inner recorder with
+/// [`report_drops`](TimeoutRecorder::report_drops).
+pub struct TimeoutRecorder<R> {
+    shared: Arc<Shared>,
+    handle: Option<JoinHandle<R>>,
+    on_full: OnFull,
+    drops: Arc<Drops>,
+    supports_gauge_add: bool,
+}
+
+impl<R> TimeoutRecorder<R>
+where
+    R: Recorder + Send + 'static,
+{
+    /// Creates a new [`TimeoutRecorder`] wrapping `inner`.
+    ///
+    /// Up to `capacity` recordings are queued before `on_full` is applied.  A capacity of zero is
+    /// treated as one, as recordings could never be queued otherwise.
+    pub fn new(inner: R, capacity: usize, on_full: OnFull) -> Self {
+        let capacity = capacity.max(1);
+        let supports_gauge_add = inner.supports_gauge_add();
+        let shared = Arc::new(Shared {
+            queue: Mutex::new(Queue {
+                messages: VecDeque::with_capacity(capacity),
+                busy_since: None,
+                closed: false,
+            }),
+            not_empty: Condvar::new(),
+            not_full: Condvar::new(),
+            capacity,
+        });
+
+        let drops = Arc::new(Drops::new("timeout"));
+        let (worker, worker_drops) = (shared.clone(), drops.clone());
+        let handle = thread::spawn(move || {
+            let mut inner = inner;
+            let mut queue = worker.queue.lock().unwrap();
+            loop {
+                match queue.messages.pop_front() {
+                    Some(message) => {
+                        queue.busy_since = Some(Instant::now());
+                        drop(queue);
+                        worker.not_full.notify_one();
+
+                        message.apply(&mut inner);
+                        worker_drops.report(&mut inner);
+
+                        queue = worker.queue.lock().unwrap();
+                        queue.busy_since = None;
+                    }
+                    // Everything queued before shutdown has been applied.
+                    None if queue.closed => break,
+                    None => queue = worker.not_empty.wait(queue).unwrap(),
+                }
+            }
+            inner
+        });
+
+        TimeoutRecorder {
+            shared,
+            handle: Some(handle),
+            on_full,
+            drops,
+            supports_gauge_add,
+        }
+    }
+
+    /// Gets the number of recordings dropped because the queue was full.
+    pub fn dropped(&self) -> u64 {
+        self.drops.dropped()
+    }
+
+    /// Reports every dropped recording to the inner recorder, using `reporter`.
+    ///
+    /// Drops are reported with the reason `timeout`, by the background thread, once it has
+    /// applied the next queued recording.
+    pub fn report_drops(&mut self, reporter: DropReporter) {
+        self.drops.report_with(reporter);
+    }
+
+    /// Applies all queued recordings and returns the inner recorder.
+    ///
+    /// This waits for the inner recorder, so it blocks for as long as the inner recorder is stuck.
+    ///
+    /// # Panics
+    /// Panics if the background thread panicked while applying a recording.
+    pub fn shutdown(mut self) -> R {
+        self.close();
+        self.handle
+            .take()
+            .expect("background thread already joined")
+            .join()
+            .expect("background thread panicked")
+    }
+
+    fn send(&self, message: Message) {
+        let mut queue = self.shared.queue.lock().unwrap();
+        if queue.messages.len() >= self.shared.capacity {
+            let timeout = match self.on_full {
+                OnFull::Wait(timeout) if !is_stuck(&queue, timeout) => timeout,
+                _ => return self.drop_message(),
+            };
+
+            let (guard, _) = self
+                .shared
+                .not_full
+                .wait_timeout_while(queue, timeout, |queue| {
+                    queue.messages.len() >= self.shared.capacity
+                })
+                .unwrap();
+            queue = guard;
+            if queue.messages.len() >= self.shared.capacity {
+                return self.drop_message();
+            }
+        }
+
+        queue.messages.push_back(message);
+        drop(queue);
+        self.shared.not_empty.notify_one();
+    }
+
+    fn drop_message(&self) {
+        self.drops.count();
+    }
+}
+
+impl<R> TimeoutRecorder<R> {
+    fn close(&self) {
+        self.shared.queue.lock().unwrap().closed = true;
+        self.shared.not_empty.notify_one();
+    }
+}
+
+impl<R> Drop for TimeoutRecorder<R> {
+    fn drop(&mut self) {
+        // Lets the background thread exit once it's done, without waiting for it.
+        self.close();
+    }
+}
+
+fn is_stuck(queue: &Queue, timeout: Duration) -> bool {
+    queue
+        .busy_since
+        .map(|since| since.elapsed() >= timeout)
+        .unwrap_or(false)
+}
+
+impl<R> Recorder for TimeoutRecorder<R>
+where
+    R: Recorder + Send + 'static,
+{
+    fn record_counter<K: Into<Key>>(&mut self, key: K, value: u64) {
+        self.send(Message::Counter(key.into(), value));
+    }
+
+    fn record_gauge<K: Into<Key>>(&mut self, key: K, value: i64) {
+        self.send(Message::Gauge(key.into(), value));
+    }
+
+    fn record_gauge_add<K: Into<Key>>(&mut self, key: K, delta: i64) {
+        self.send(Message::GaugeAdd(key.into(), delta));
+    }
+
+    fn supports_gauge_add(&self) -> bool {
+        self.supports_gauge_add
+    }
+
+    fn record_histogram<K: Into<Key>>(&mut self, key: K, values: &[u64]) {
+        self.send(Message::Histogram(key.into(), values.to_vec()));
+    }
+
+    fn record_histogram_sampled<K: Into<Key>>(&mut self, key: K, values: &[u64], rate: f64) {
+        self.send(Message::HistogramSampled(key.into(), values.to_vec(), rate));
+    }
+
+    fn record_set<K: Into<Key>>(&mut self, key: K, value: &str) {
+        self.send(Message::Set(key.into(), value.to_owned()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{OnFull, TimeoutRecorder};
+    use crate::DropReporter;
+    use metrics_core::{Key, Recorder};
+    use std::sync::mpsc::{channel, Receiver, Sender};
+    use std::thread;
+    use std::time::Duration;
+
+    #[derive(Default)]
+    struct MockRecorder {
+        counters: Vec<(String, u64)>,
+        started: Option<Sender<()>>,
+        gate: Option<Receiver<()>>,
+    }
+
+    impl Recorder for MockRecorder {
+        fn record_counter<K: Into<Key>>(&mut self, key: K, value: u64) {
+            if let Some(started) = self.started.take() {
+                let _ = started.send(());
+            }
+            if let Some(gate) = self.gate.take() {
+                let _ = gate.recv();
+            }
+            self.counters.push((key.into().to_string(), value));
+        }
+
+        fn record_gauge<K: Into<Key>>(&mut self, _key: K, _value: i64) {}
+
+        fn record_histogram<K: Into<Key>>(&mut self, _key: K, _values: &[u64]) {}
+    }
+
+    #[test]
+    fn test_timeout_flushes_on_shutdown() {
+        let mut recorder = TimeoutRecorder::new(
+            MockRecorder::default(),
+            4,
+            OnFull::Wait(Duration::from_secs(5)),
+        );
+        for i in 0..100 {
+            recorder.record_counter("requests", i);
+        }
+
+        let inner = recorder.shutdown();
+        assert_eq!(inner.counters.len(), 100);
+        assert_eq!(inner.counters[99], ("requests".to_owned(), 99));
+    }
+
+    #[test]
+    fn test_timeout_stalled_inner() {
+        // Stall the inner recorder on the first recording, for longer than the timeout.
+        let (started_tx, started) = channel();
+        let (release, gate) = channel();
+        let inner = MockRecorder {
+            started: Some(started_tx),
+            gate: Some(gate),
+            ..Default::default()
+        };
+
+        let timeout = Duration::from_millis(20);
+        let mut recorder = TimeoutRecorder::new(inner, 2, OnFull::Wait(timeout));
+        recorder.record_counter("requests", 0);
+        started.recv().unwrap();
+        thread::sleep(timeout);
+
+        // The inner recorder is stuck, so once the queue is full, recordings are dropped.
+        for i in 1..100 {
+            recorder.record_counter("requests", i);
+        }
+        assert_eq!(recorder.dropped(), 97);
+
+        release.send(()).unwrap();
+        let inner = recorder.shutdown();
+        assert_eq!(inner.counters.len(), 3);
+    }
+
+    #[test]
+    fn test_timeout_waits_for_room() {
+        let (started_tx, started) = channel();
+        let (release, gate) = channel();
+        let inner = MockRecorder {
+            started: Some(started_tx),
+            gate: Some(gate),
+            ..Default::default()
+        };
+
+        // The queue only has room once the inner recorder is released, which happens well within
+        // the timeout, so nothing is dropped.
+        let mut recorder = TimeoutRecorder::new(inner, 1, OnFull::Wait(Duration::from_secs(3600)));
+        recorder.record_counter("requests", 0);
+        started.recv().unwrap();
+        recorder.record_counter("requests", 1);
+        let caller = thread::spawn(move || {
+            recorder.record_counter("requests", 2);
+            recorder
+        });
+        release.send(()).unwrap();
+
+        let recorder = caller.join().unwrap();
+        assert_eq!(recorder.dropped(), 0);
+        assert_eq!(recorder.shutdown().counters.len(), 3);
+    }
+
+    #[test]
+    fn test_timeout_zero_capacity() {
+        let mut recorder = TimeoutRecorder::new(MockRecorder::default(), 0, OnFull::Drop);
+        recorder.record_counter("requests", 1);
+        assert_eq!(recorder.dropped(), 0);
+        assert_eq!(recorder.shutdown().counters.len(), 1);
+    }
+
+    #[test]
+    fn test_timeout_drop_immediately() {
+        let (release, gate) = channel();
+        let inner = MockRecorder {
+            gate: Some(gate),
+            ..Default::default()
+        };
+
+        let mut recorder = TimeoutRecorder::new(inner, 1, OnFull::Drop);
+        for i in 0..10 {
+            recorder.record_counter("requests", i);
+        }
+        let dropped = recorder.dropped();
+        assert!(dropped >= 8, "dropped {}", dropped);
+
+        release.send(()).unwrap();
+        let inner = recorder.shutdown();
+        assert_eq!(inner.counters.len() as u64 + dropped, 10);
+    }
+
+    #[test]
+    fn test_timeout_report_drops() {
+        let (release, gate) = channel();
+        let inner = MockRecorder {
+            gate: Some(gate),
+            ..Default::default()
+        };
+
+        let mut recorder = TimeoutRecorder::new(inner, 1, OnFull::Drop);
+        recorder.report_drops(DropReporter::new());
+        for i in 0..10 {
+            recorder.record_counter("requests", i);
+        }
+        let dropped = recorder.dropped();
+        assert!(dropped >= 8, "dropped {}", dropped);
+
+        release.send(()).unwrap();
+        let inner = recorder.shutdown();
+        let reported = inner
+            .counters
+            .iter()
+            .filter(|(key, _)| key == "metrics_internal_dropped_total{reason=timeout}")
+            .map(|(_, value)| *value)
+            .collect::<Vec<_>>();
+        assert_eq!(reported.last(), Some(&dropped));
+    }
+}
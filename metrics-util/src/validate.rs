@@ -0,0 +1,292 @@
+use crate::DropReporter;
+use metrics_core::{Key, Recorder};
+use std::borrow::Cow;
+
+/// What to do with values that fall outside of the configured bounds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Validation {
+    /// Clamps the value to the nearest bound.
+    Clamp,
+
+    /// Drops the value.
+    Reject,
+}
+
+struct Rule {
+    pattern: String,
+    min: i64,
+    max: i64,
+    validation: Validation,
+}
+
+/// A recorder that validates values against per-metric bounds.
+///
+/// Bounds are configured with [`ValidatingRecorder::add_bounds`] for metric names matching a
+/// pattern, where `*` matches any sequence of characters.  When a name matches multiple patterns,
+/// the bounds that were added first win.  Values outside of the bounds are either clamped or
+/// rejected, depending on the configured [`Validation`], and metrics that match no pattern are
+/// passed through as-is.
+///
+/// For histograms, each value is validated on its own, and the remaining values are passed
+/// through together.  Set values aren't numeric, and gauge deltas can't be checked against bounds
+/// for the absolute value, so both are always passed through.
+///
+/// Rejected values can be reported to the inner recorder with
+/// [`ValidatingRecorder::report_drops`].
+pub struct ValidatingRecorder<R> {
+    inner: R,
+    rules: Vec<Rule>,
+    rejected: u64,
+    reporter: Option<DropReporter>,
+}
+
+impl<R> ValidatingRecorder<R> {
+    /// Creates a new [`ValidatingRecorder`] wrapping `inner`, with no bounds configured.
+    pub fn new(inner: R) -> Self {
+        ValidatingRecorder {
+            inner,
+            rules: Vec::new(),
+            rejected: 0,
+            reporter: None,
+        }
+    }
+
+    /// Adds bounds for all metrics with a name matching `pattern`.
+    ///
+    /// Both `min` and `max` are inclusive.  To reject negative gauge values, for example, use a
+    /// `min` of `0`.
+    pub fn add_bounds<P: Into<String>>(
+        &mut self,
+        pattern: P,
+        min: i64,
+        max: i64,
+        validation: Validation,
+    ) {
+        self.rules.push(Rule {
+            pattern: pattern.into(),
+            min,
+            max,
+            validation,
+        });
+    }
+
+    /// Reports every rejected value to the inner recorder, using `reporter`.
+    ///
+    /// Rejections are reported with the reason `rejected`.
+    pub fn report_drops(&mut self, reporter: DropReporter) {
+        self.reporter = Some(reporter);
+    }
+
+    /// Gets the number of values that were rejected.
+    pub fn rejected(&self) -> u64 {
+        self.rejected
+    }
+
+    /// Consumes this recorder, returning the inner recorder.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: Recorder> ValidatingRecorder<R> {
+    fn validate(&mut self, rule: Option<usize>, value: i128) -> Option<i128> {
+        let rule = match rule {
+            Some(i) => &self.rules[i],
+            None => return Some(value),
+        };
+
+        let (min, max) = (i128::from(rule.min), i128::from(rule.max));
+        if value >= min && value <= max {
+            return Some(value);
+        }
+
+        match rule.validation {
+            Validation::Clamp => Some(value.clamp(min, max)),
+            Validation::Reject => {
+                self.rejected += 1;
+                if let Some(reporter) = self.reporter.as_mut() {
+                    reporter.report(&mut self.inner, "rejected");
+                }
+                None
+            }
+        }
+    }
+
+    // Validates every value of a histogram, returning `None` if none of them are left to record.
+    fn validate_histogram<'a>(&mut self, name: &str, values: &'a [u64]) -> Option<Cow<'a, [u64]>> {
+        let rule = match self.find_rule(name) {
+            Some(rule) => rule,
+            None => return Some(Cow::Borrowed(values)),
+        };
+
+        let values = values
+            .iter()
+            .filter_map(|value| self.validate(Some(rule), i128::from(*value)))
+            .map(|value| value as u64)
+            .collect::<Vec<_>>();
+        if values.is_empty() {
+            None
+        } else {
+            Some(Cow::Owned(values))
+        }
+    }
+
+    fn find_rule(&self, name: &str) -> Option<usize> {
+        self.rules
+            .iter()
+            .position(|rule| matches_pattern(rule.pattern.as_str(), name))
+    }
+}
+
+impl<R: Recorder> Recorder for ValidatingRecorder<R> {
+    fn record_counter<K: Into<Key>>(&mut self, key: K, value: u64) {
+        let key = key.into();
+        let rule = self.find_rule(key.name());
+        if let Some(value) = self.validate(rule, i128::from(value)) {
+            self.inner.record_counter(key, value as u64);
+        }
+    }
+
+    fn record_gauge<K: Into<Key>>(&mut self, key: K, value: i64) {
+        let key = key.into();
+        let rule = self.find_rule(key.name());
+        if let Some(value) = self.validate(rule, i128::from(value)) {
+            self.inner.record_gauge(key, value as i64);
+        }
+    }
+
+    fn record_gauge_add<K: Into<Key>>(&mut self, key: K, delta: i64) {
+        self.inner.record_gauge_add(key, delta);
+    }
+
+    fn supports_gauge_add(&self) -> bool {
+        self.inner.supports_gauge_add()
+    }
+
+    fn record_histogram<K: Into<Key>>(&mut self, key: K, values: &[u64]) {
+        let key = key.into();
+        if let Some(values) = self.validate_histogram(key.name(), values) {
+            self.inner.record_histogram(key, &values);
+        }
+    }
+
+    fn record_histogram_sampled<K: Into<Key>>(&mut self, key: K, values: &[u64], rate: f64) {
+        let key = key.into();
+        if let Some(values) = self.validate_histogram(key.name(), values) {
+            self.inner.record_histogram_sampled(key, &values, rate);
+        }
+    }
+
+    fn record_set<K: Into<Key>>(&mut self, key: K, value: &str) {
+        self.inner.record_set(key, value);
+    }
+}
+
+/// Matches `name` against `pattern`, where `*` matches any sequence of characters.
+///
+/// A pattern without any `*` only matches the exact name, so prefixes are matched with a trailing
+/// `*`, as in `http.*`.  This is the matching used by [`ValidatingRecorder::add_bounds`].
+pub fn matches_pattern(pattern: &str, name: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or("");
+    if !name.starts_with(first) {
+        return false;
+    }
+
+    let mut rest = &name[first.len()..];
+    let mut parts = parts.collect::<Vec<_>>();
+    let last = match parts.pop() {
+        Some(last) => last,
+        // No wildcard, so the whole name has to match.
+        None => return rest.is_empty(),
+    };
+
+    for part in parts {
+        match rest.find(part) {
+            Some(i) => rest = &rest[i + part.len()..],
+            None => return false,
+        }
+    }
+    rest.ends_with(last)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{matches_pattern, ValidatingRecorder, Validation};
+    use crate::test_util::MockRecorder;
+    use crate::DropReporter;
+    use metrics_core::Recorder;
+
+    #[test]
+    fn test_patterns() {
+        assert!(matches_pattern("http.requests", "http.requests"));
+        assert!(!matches_pattern("http.requests", "http.requests_total"));
+        assert!(matches_pattern("http.*", "http.requests"));
+        assert!(matches_pattern("*_time", "http.request_time"));
+        assert!(matches_pattern("db.*.rows", "db.users.rows"));
+        assert!(!matches_pattern("db.*.rows", "db.users.bytes"));
+        assert!(matches_pattern("*", "anything"));
+    }
+
+    #[test]
+    fn test_clamp() {
+        let mut recorder = ValidatingRecorder::new(MockRecorder::default());
+        recorder.add_bounds("*.temperature", -50, 150, Validation::Clamp);
+        recorder.add_bounds("*_time", 0, 1000, Validation::Clamp);
+
+        recorder.record_gauge("cpu.temperature", -300);
+        recorder.record_gauge("cpu.temperature", 70);
+        recorder.record_gauge("fan.speed", -300);
+        recorder.record_histogram("http.request_time", &[5, 5000]);
+
+        assert_eq!(recorder.rejected(), 0);
+        assert_eq!(
+            recorder.into_inner().0,
+            vec![
+                "gauge cpu.temperature -50",
+                "gauge cpu.temperature 70",
+                "gauge fan.speed -300",
+                "histogram http.request_time [5, 1000]",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_reject() {
+        let mut recorder = ValidatingRecorder::new(MockRecorder::default());
+        recorder.add_bounds("queue.depth", 0, i64::MAX, Validation::Reject);
+        recorder.add_bounds("http.*", 0, 100, Validation::Reject);
+
+        recorder.record_gauge("queue.depth", -1);
+        recorder.record_gauge("queue.depth", 3);
+        recorder.record_counter("http.requests", u64::MAX);
+        recorder.record_histogram("http.request_time", &[5, 500, 50]);
+        recorder.record_histogram("http.request_time", &[500]);
+
+        assert_eq!(recorder.rejected(), 4);
+        assert_eq!(
+            recorder.into_inner().0,
+            vec!["gauge queue.depth 3", "histogram http.request_time [5, 50]"]
+        );
+    }
+
+    #[test]
+    fn test_report_drops() {
+        let mut recorder = ValidatingRecorder::new(MockRecorder::default());
+        recorder.add_bounds("queue.depth", 0, i64::MAX, Validation::Reject);
+        recorder.report_drops(DropReporter::new());
+
+        recorder.record_gauge("queue.depth", -1);
+        recorder.record_gauge("queue.depth", 3);
+        recorder.record_gauge("queue.depth", -2);
+
+        assert_eq!(
+            recorder.into_inner().0,
+            vec![
+                "counter metrics_internal_dropped_total{reason=rejected} 1",
+                "gauge queue.depth 3",
+                "counter metrics_internal_dropped_total{reason=rejected} 2",
+            ]
+        );
+    }
+}
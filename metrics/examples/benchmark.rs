@@ -9,7 +9,7 @@ extern crate metrics_core;
 use getopts::Options;
 use hdrhistogram::Histogram;
 use metrics::{Receiver, Sink};
-use metrics_core::{Key, Recorder, Snapshot, SnapshotProvider};
+use metrics_core::SnapshotProvider;
 use quanta::Clock;
 use std::{
     env,
@@ -44,51 +44,13 @@ impl Generator {
             stats,
             t0: None,
             gauge: 0,
-            hist: Histogram::<u64>::new_with_bounds(1, u64::max_value(), 3).unwrap(),
+            hist: Histogram::<u64>::new_with_bounds(1, u64::MAX, 3).unwrap(),
             done,
             rate_counter,
             clock,
         }
     }
 
-    fn run(&mut self) {
-        let mut counter = 0;
-        loop {
-            counter += 1;
-
-            if self.done.load(Ordering::Relaxed) {
-                break;
-            }
-
-            self.gauge += 1;
-
-            let t1 = self.stats.now();
-
-            if let Some(t0) = self.t0 {
-                let start = if counter % 33 == 0 {
-                    self.stats.now()
-                } else {
-                    0
-                };
-
-                let _ = self.stats.record_count("ok", 1);
-                let _ = self.stats.record_timing("ok", t0, t1);
-                let _ = self.stats.record_gauge("total", self.gauge);
-
-                if start != 0 {
-                    let delta = self.stats.now() - start;
-                    self.hist.saturating_record(delta);
-
-                    // We also increment our global counter for the sample rate here.
-                    self.rate_counter
-                        .fetch_add(LOOP_SAMPLE * 3, Ordering::AcqRel);
-                }
-            }
-
-            self.t0 = Some(t1);
-        }
-    }
-
     fn run_cached(&mut self) {
         let mut counter = 0;
 
@@ -114,9 +76,9 @@ impl Generator {
                     0
                 };
 
-                let _ = counter_handle.record(1);
-                let _ = timing_handle.record_timing(t0, t1);
-                let _ = gauge_handle.record(self.gauge);
+                counter_handle.record(1);
+                timing_handle.record_timing(t0, t1);
+                gauge_handle.record(self.gauge);
 
                 if start != 0 {
                     let delta = self.stats.now() - start;
@@ -239,12 +201,12 @@ fn main() {
     let mut total = 0;
     let mut t0 = Instant::now();
 
-    let mut snapshot_hist = Histogram::<u64>::new_with_bounds(1, u64::max_value(), 3).unwrap();
+    let mut snapshot_hist = Histogram::<u64>::new_with_bounds(1, u64::MAX, 3).unwrap();
     for _ in 0..seconds {
         let t1 = Instant::now();
 
         let start = Instant::now();
-        let snapshot = controller.get_snapshot().unwrap();
+        let _snapshot = controller.get_snapshot().unwrap();
         let end = Instant::now();
         snapshot_hist.saturating_record(duration_as_nanos(end - start) as u64);
 
@@ -277,34 +239,6 @@ fn main() {
     }
 }
 
-struct TotalRecorder {
-    total: u64,
-}
-
-impl TotalRecorder {
-    pub fn new() -> Self {
-        Self { total: 0 }
-    }
-
-    pub fn total(&self) -> u64 {
-        self.total
-    }
-}
-
-impl Recorder for TotalRecorder {
-    fn record_counter<K: Into<Key>>(&mut self, _key: K, value: u64) {
-        self.total += value;
-    }
-
-    fn record_gauge<K: Into<Key>>(&mut self, _key: K, value: i64) {
-        self.total += value as u64;
-    }
-
-    fn record_histogram<K: Into<Key>>(&mut self, _key: K, values: &[u64]) {
-        self.total += values.len() as u64;
-    }
-}
-
 fn duration_as_nanos(d: Duration) -> f64 {
     (d.as_secs() as f64 * 1e9) + d.subsec_nanos() as f64
 }
@@ -11,8 +11,8 @@ extern crate metrics_facade;
 
 use getopts::Options;
 use hdrhistogram::Histogram;
-use metrics::{Receiver, Sink};
-use metrics_core::{Key, Recorder, Snapshot, SnapshotProvider};
+use metrics::Receiver;
+use metrics_core::SnapshotProvider;
 use quanta::Clock;
 use std::{
     env,
@@ -40,7 +40,7 @@ impl Generator {
         Generator {
             t0: None,
             gauge: 0,
-            hist: Histogram::<u64>::new_with_bounds(1, u64::max_value(), 3).unwrap(),
+            hist: Histogram::<u64>::new_with_bounds(1, u64::MAX, 3).unwrap(),
             done,
             rate_counter,
             clock,
@@ -187,12 +187,12 @@ fn main() {
     let mut total = 0;
     let mut t0 = Instant::now();
 
-    let mut snapshot_hist = Histogram::<u64>::new_with_bounds(1, u64::max_value(), 3).unwrap();
+    let mut snapshot_hist = Histogram::<u64>::new_with_bounds(1, u64::MAX, 3).unwrap();
     for _ in 0..seconds {
         let t1 = Instant::now();
 
         let start = Instant::now();
-        let snapshot = controller.get_snapshot().unwrap();
+        let _snapshot = controller.get_snapshot().unwrap();
         let end = Instant::now();
         snapshot_hist.saturating_record(duration_as_nanos(end - start) as u64);
 
@@ -225,34 +225,6 @@ fn main() {
     }
 }
 
-struct TotalRecorder {
-    total: u64,
-}
-
-impl TotalRecorder {
-    pub fn new() -> Self {
-        Self { total: 0 }
-    }
-
-    pub fn total(&self) -> u64 {
-        self.total
-    }
-}
-
-impl Recorder for TotalRecorder {
-    fn record_counter<K: Into<Key>>(&mut self, _key: K, value: u64) {
-        self.total += value;
-    }
-
-    fn record_gauge<K: Into<Key>>(&mut self, _key: K, value: i64) {
-        self.total += value as u64;
-    }
-
-    fn record_histogram<K: Into<Key>>(&mut self, _key: K, values: &[u64]) {
-        self.total += values.len() as u64;
-    }
-}
-
 fn duration_as_nanos(d: Duration) -> f64 {
     (d.as_secs() as f64 * 1e9) + d.subsec_nanos() as f64
 }
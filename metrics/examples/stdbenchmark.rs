@@ -29,7 +29,7 @@ impl Generator {
         Generator {
             counter,
             clock: Clock::new(),
-            hist: Histogram::<u64>::new_with_bounds(1, u64::max_value(), 3).unwrap(),
+            hist: Histogram::<u64>::new_with_bounds(1, u64::MAX, 3).unwrap(),
             done,
         }
     }
@@ -147,7 +147,7 @@ fn main() {
     let mut total = 0;
     let mut t0 = Instant::now();
 
-    let mut snapshot_hist = Histogram::<u64>::new_with_bounds(1, u64::max_value(), 3).unwrap();
+    let mut snapshot_hist = Histogram::<u64>::new_with_bounds(1, u64::MAX, 3).unwrap();
     for _ in 0..seconds {
         let t1 = Instant::now();
 
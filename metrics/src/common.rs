@@ -121,6 +121,13 @@ impl MetricValue {
         }
     }
 
+    pub fn update_histogram_n(&self, value: u64, count: u64) {
+        match self.state.deref() {
+            ValueState::Histogram(inner) => inner.record_n(value, count),
+            _ => unreachable!("tried to access as histogram, not a histogram"),
+        }
+    }
+
     pub fn snapshot(&self) -> ValueSnapshot {
         match self.state.deref() {
             ValueState::Counter(inner) => {
@@ -1,5 +1,5 @@
 use crate::data::Snapshot;
-use crate::registry::{MetricRegistry, ScopeRegistry};
+use crate::registry::MetricRegistry;
 use futures::prelude::*;
 use metrics_core::{AsyncSnapshotProvider, SnapshotProvider};
 use std::error::Error;
@@ -36,18 +36,11 @@ impl fmt::Display for SnapshotError {
 #[derive(Clone)]
 pub struct Controller {
     metric_registry: Arc<MetricRegistry>,
-    scope_registry: Arc<ScopeRegistry>,
 }
 
 impl Controller {
-    pub(crate) fn new(
-        metric_registry: Arc<MetricRegistry>,
-        scope_registry: Arc<ScopeRegistry>,
-    ) -> Controller {
-        Controller {
-            metric_registry,
-            scope_registry,
-        }
+    pub(crate) fn new(metric_registry: Arc<MetricRegistry>) -> Controller {
+        Controller { metric_registry }
     }
 }
 
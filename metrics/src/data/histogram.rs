@@ -39,6 +39,8 @@ impl From<MetricValue> for Histogram {
 #[derive(Debug)]
 pub struct AtomicWindowedHistogram {
     buckets: Vec<AtomicBucket<u64>>,
+    // Values recorded with a count, alongside the bucket of the same index.
+    repeated: Vec<AtomicBucket<(u64, u64)>>,
     bucket_count: usize,
     granularity: u64,
     upkeep_index: AtomicUsize,
@@ -68,14 +70,17 @@ impl AtomicWindowedHistogram {
 
         let bucket_count = ((window_ns / granularity_ns) as usize) + 1;
         let mut buckets = Vec::new();
+        let mut repeated = Vec::new();
         for _ in 0..bucket_count {
             buckets.push(AtomicBucket::new());
+            repeated.push(AtomicBucket::new());
         }
 
         let next_upkeep = now + granularity_ns;
 
         AtomicWindowedHistogram {
             buckets,
+            repeated,
             bucket_count,
             granularity: granularity_ns,
             upkeep_index: AtomicUsize::new(0),
@@ -102,6 +107,11 @@ impl AtomicWindowedHistogram {
             let bucket_index = (index + i + 1) % self.bucket_count;
             let bucket = &self.buckets[bucket_index];
             bucket.data_with(|block| streaming.compress(block));
+            self.repeated[bucket_index].data_with(|block| {
+                for &(value, count) in block {
+                    streaming.compress_repeated(value, count);
+                }
+            });
         }
         streaming
     }
@@ -112,6 +122,23 @@ impl AtomicWindowedHistogram {
         self.buckets[index].push(value);
     }
 
+    /// Records a value to the histogram `count` times.
+    ///
+    /// The value is recorded once along with its count, so this takes the same time whatever the
+    /// count is.
+    pub fn record_n(&self, value: u64, count: u64) {
+        if count == 0 {
+            return;
+        }
+        let index = self.upkeep();
+        self.repeated[index].push((value, count));
+    }
+
+    fn clear_bucket(&self, index: usize) {
+        self.buckets[index].clear();
+        self.repeated[index].clear();
+    }
+
     fn upkeep(&self) -> usize {
         let backoff = Backoff::new();
 
@@ -130,7 +157,7 @@ impl AtomicWindowedHistogram {
             // so go ahead and wait until the index is caught up with the upkeep index: the upkeep
             // index will be ahead of index until upkeep is complete.
             let mut upkeep_in_progress = false;
-            let mut index = 0;
+            let mut index;
             loop {
                 index = self.index.load(Ordering::Acquire);
                 let upkeep_index = self.upkeep_index.load(Ordering::Acquire);
@@ -159,14 +186,15 @@ impl AtomicWindowedHistogram {
             let new_index = index + bucket_depth;
             let prev_index = self
                 .index
-                .compare_and_swap(index, new_index, Ordering::SeqCst);
+                .compare_exchange(index, new_index, Ordering::SeqCst, Ordering::SeqCst)
+                .unwrap_or_else(|x| x);
             if prev_index == index {
                 // Clear the target bucket first, and then update the upkeep target time so new
                 // writers can proceed.  We may still have other buckets to clean up if we had
                 // multiple rounds worth of upkeep to do, but this will let new writes proceed as
                 // soon as possible.
                 let clear_index = new_index % self.bucket_count;
-                self.buckets[clear_index].clear();
+                self.clear_bucket(clear_index);
 
                 let now = self.clock.now();
                 let next_upkeep = now + self.granularity;
@@ -180,7 +208,7 @@ impl AtomicWindowedHistogram {
                 while index < last_index {
                     index += 1;
                     let clear_index = index % self.bucket_count;
-                    self.buckets[clear_index].clear();
+                    self.clear_bucket(clear_index);
                 }
 
                 // We've cleared the old buckets, so upkeep is done.  Push our upkeep index forward
@@ -209,7 +237,7 @@ mod tests {
 
         let values = snapshot.decompress();
         assert_eq!(values.len(), 1);
-        assert_eq!(values.get(0).unwrap(), &1245);
+        assert_eq!(values.first().unwrap(), &1245);
     }
 
     #[test]
@@ -227,12 +255,31 @@ mod tests {
 
         let values = snapshot.decompress();
         assert_eq!(values.len(), 4);
-        assert_eq!(values.get(0).unwrap(), &1245);
+        assert_eq!(values.first().unwrap(), &1245);
         assert_eq!(values.get(1).unwrap(), &213);
         assert_eq!(values.get(2).unwrap(), &1022);
         assert_eq!(values.get(3).unwrap(), &1248);
     }
 
+    #[test]
+    fn test_histogram_record_n() {
+        let (clock, ctl) = Clock::mock();
+        let h = AtomicWindowedHistogram::new(Duration::from_secs(5), Duration::from_secs(1), clock);
+
+        h.record(7);
+        h.record_n(42, 3);
+        h.record_n(9, 0);
+
+        let values = h.snapshot().decompress();
+        assert_eq!(values, vec![7, 42, 42, 42]);
+
+        // Large counts are recorded in one step, and roll off with the rest of their bucket.
+        h.record_n(5, 1 << 40);
+        assert_eq!(h.snapshot().len() as u64, (1 << 40) + 4);
+        ctl.increment(Duration::from_secs(6));
+        assert_eq!(h.snapshot().len(), 0);
+    }
+
     #[test]
     fn test_windowed_histogram_rollover() {
         let (clock, ctl) = Clock::mock();
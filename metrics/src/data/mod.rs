@@ -9,4 +9,4 @@ mod histogram;
 pub use histogram::{AtomicWindowedHistogram, Histogram};
 
 mod snapshot;
-pub use snapshot::Snapshot;
+pub use snapshot::{MeasurementDiff, MeasurementKind, Snapshot, SnapshotDiff};
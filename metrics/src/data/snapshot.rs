@@ -1,6 +1,6 @@
 use crate::common::ValueSnapshot;
 use metrics_core::{Key, Recorder, Snapshot as MetricsSnapshot};
-use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
 
 /// A point-in-time view of metric data.
 #[derive(Default, Debug)]
@@ -12,15 +12,143 @@ impl Snapshot {
     pub(crate) fn from(from: Vec<(String, ValueSnapshot)>) -> Self {
         Snapshot { measurements: from }
     }
+
+    /// Computes what changed between a previous snapshot and this one.
+    ///
+    /// Measurements are matched up by their key and type.  Measurements only present in this
+    /// snapshot are reported as added, and ones only present in `previous` as removed.  For
+    /// measurements present in both, any change is reported: the delta for counters, the previous
+    /// and current values for gauges, and the change in the number of samples for histograms.
+    ///
+    /// If a counter has gone down, it is assumed to have been reset, and its delta is its current
+    /// value.
+    pub fn diff(&self, previous: &Snapshot) -> SnapshotDiff {
+        let previous_values = previous
+            .measurements
+            .iter()
+            .map(|(key, value)| ((key.as_str(), kind_of(value)), value))
+            .collect::<HashMap<_, _>>();
+
+        let mut diff = SnapshotDiff::default();
+        for (key, value) in &self.measurements {
+            let kind = kind_of(value);
+            let change = match (previous_values.get(&(key.as_str(), kind)), value) {
+                (None, _) => {
+                    diff.added.push((key.clone(), kind));
+                    continue;
+                }
+                (Some(ValueSnapshot::Counter(prev)), ValueSnapshot::Counter(curr)) => {
+                    let delta = if curr >= prev { curr - prev } else { *curr };
+                    if delta == 0 {
+                        continue;
+                    }
+                    MeasurementDiff::Counter(delta)
+                }
+                (Some(ValueSnapshot::Gauge(prev)), ValueSnapshot::Gauge(curr)) => {
+                    if prev == curr {
+                        continue;
+                    }
+                    MeasurementDiff::Gauge(*prev, *curr)
+                }
+                (Some(ValueSnapshot::Histogram(prev)), ValueSnapshot::Histogram(curr)) => {
+                    let delta = curr.len() as i64 - prev.len() as i64;
+                    if delta == 0 {
+                        continue;
+                    }
+                    MeasurementDiff::Histogram(delta)
+                }
+                _ => unreachable!("measurements are matched by type"),
+            };
+            diff.changed.push((key.clone(), change));
+        }
+
+        let current = self
+            .measurements
+            .iter()
+            .map(|(key, value)| (key.as_str(), kind_of(value)))
+            .collect::<HashSet<_>>();
+        for (key, value) in &previous.measurements {
+            let kind = kind_of(value);
+            if !current.contains(&(key.as_str(), kind)) {
+                diff.removed.push((key.clone(), kind));
+            }
+        }
+
+        diff
+    }
+}
+
+/// The type of a measurement.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MeasurementKind {
+    /// A counter.
+    Counter,
+
+    /// A gauge.
+    Gauge,
+
+    /// A histogram.
+    Histogram,
+}
+
+/// How a measurement changed between two snapshots.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MeasurementDiff {
+    /// The amount a counter increased by.
+    Counter(u64),
+
+    /// The previous and current value of a gauge.
+    Gauge(i64, i64),
+
+    /// The change in the number of samples in a histogram.
+    Histogram(i64),
+}
+
+/// The differences between two snapshots.
+///
+/// Created by [`Snapshot::diff`].
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct SnapshotDiff {
+    added: Vec<(String, MeasurementKind)>,
+    removed: Vec<(String, MeasurementKind)>,
+    changed: Vec<(String, MeasurementDiff)>,
+}
+
+impl SnapshotDiff {
+    /// Measurements that are only present in the newer snapshot.
+    pub fn added(&self) -> &[(String, MeasurementKind)] {
+        &self.added
+    }
+
+    /// Measurements that are only present in the older snapshot.
+    pub fn removed(&self) -> &[(String, MeasurementKind)] {
+        &self.removed
+    }
+
+    /// Measurements that are present in both snapshots, but changed.
+    pub fn changed(&self) -> &[(String, MeasurementDiff)] {
+        &self.changed
+    }
+
+    /// Returns `true` if nothing changed between the snapshots.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+fn kind_of(value: &ValueSnapshot) -> MeasurementKind {
+    match value {
+        ValueSnapshot::Counter(_) => MeasurementKind::Counter,
+        ValueSnapshot::Gauge(_) => MeasurementKind::Gauge,
+        ValueSnapshot::Histogram(_) => MeasurementKind::Histogram,
+    }
 }
 
 impl MetricsSnapshot for Snapshot {
     /// Records the snapshot to the given recorder.
     fn record<R: Recorder>(&self, recorder: &mut R) {
         for (key, snapshot) in &self.measurements {
-            // TODO: switch this to Key::Owned once type_alias_enum_variants lands
-            // in 1.37.0 (#61682)
-            let owned_key: Key = Cow::Owned(key.clone());
+            let owned_key = Key::from_name(key.clone());
             match snapshot {
                 ValueSnapshot::Counter(value) => recorder.record_counter(owned_key.clone(), *value),
                 ValueSnapshot::Gauge(value) => recorder.record_gauge(owned_key.clone(), *value),
@@ -34,7 +162,9 @@ impl MetricsSnapshot for Snapshot {
 
 #[cfg(test)]
 mod tests {
-    use super::{MetricsSnapshot, Recorder, Snapshot, ValueSnapshot};
+    use super::{
+        MeasurementDiff, MeasurementKind, MetricsSnapshot, Recorder, Snapshot, ValueSnapshot,
+    };
     use metrics_core::Key;
     use metrics_util::StreamingIntegers;
     use std::collections::HashMap;
@@ -99,4 +229,66 @@ mod tests {
         let hsum = recorder.get_histogram_values(&key).map(|x| x.iter().sum());
         assert_eq!(hsum, Some(174));
     }
+
+    fn histogram(values: &[u64]) -> ValueSnapshot {
+        let mut stream = StreamingIntegers::new();
+        stream.compress(values);
+        ValueSnapshot::Histogram(stream)
+    }
+
+    #[test]
+    fn test_snapshot_diff() {
+        let previous = Snapshot::from(vec![
+            ("requests".to_owned(), ValueSnapshot::Counter(10)),
+            ("restarts".to_owned(), ValueSnapshot::Counter(3)),
+            ("connections".to_owned(), ValueSnapshot::Gauge(5)),
+            ("queue_depth".to_owned(), ValueSnapshot::Gauge(1)),
+            ("latency".to_owned(), histogram(&[1, 2, 3])),
+            ("evictions".to_owned(), ValueSnapshot::Counter(1)),
+        ]);
+        let current = Snapshot::from(vec![
+            ("requests".to_owned(), ValueSnapshot::Counter(15)),
+            ("restarts".to_owned(), ValueSnapshot::Counter(3)),
+            ("connections".to_owned(), ValueSnapshot::Gauge(2)),
+            ("queue_depth".to_owned(), ValueSnapshot::Gauge(1)),
+            ("latency".to_owned(), histogram(&[1, 2, 3, 4, 5])),
+            ("errors".to_owned(), ValueSnapshot::Counter(1)),
+            ("evictions".to_owned(), ValueSnapshot::Gauge(1)),
+        ]);
+
+        let diff = current.diff(&previous);
+        assert_eq!(
+            diff.changed(),
+            &[
+                ("requests".to_owned(), MeasurementDiff::Counter(5)),
+                ("connections".to_owned(), MeasurementDiff::Gauge(5, 2)),
+                ("latency".to_owned(), MeasurementDiff::Histogram(2)),
+            ]
+        );
+        assert_eq!(
+            diff.added(),
+            &[
+                ("errors".to_owned(), MeasurementKind::Counter),
+                ("evictions".to_owned(), MeasurementKind::Gauge),
+            ]
+        );
+        assert_eq!(
+            diff.removed(),
+            &[("evictions".to_owned(), MeasurementKind::Counter)]
+        );
+
+        assert!(current.diff(&current).is_empty());
+    }
+
+    #[test]
+    fn test_snapshot_diff_counter_reset() {
+        let previous = Snapshot::from(vec![("requests".to_owned(), ValueSnapshot::Counter(10))]);
+        let current = Snapshot::from(vec![("requests".to_owned(), ValueSnapshot::Counter(4))]);
+
+        let diff = current.diff(&previous);
+        assert_eq!(
+            diff.changed(),
+            &[("requests".to_owned(), MeasurementDiff::Counter(4))]
+        );
+    }
 }
@@ -13,7 +13,7 @@ use std::cell::RefCell;
 use std::sync::Arc;
 
 thread_local! {
-    static SINK: RefCell<Option<Sink>> = RefCell::new(None);
+    static SINK: RefCell<Option<Sink>> = const { RefCell::new(None) };
 }
 
 /// Central store for metrics.
@@ -76,10 +76,12 @@ impl Receiver {
 
     /// Creates a [`Controller`] bound to this receiver.
     pub fn get_controller(&self) -> Controller {
-        Controller::new(self.metric_registry.clone(), self.scope_registry.clone())
+        Controller::new(self.metric_registry.clone())
     }
 }
 
+// Sinks do not yet support labels, so only the name of the key is used when recording through the
+// facade.
 impl Recorder for Receiver {
     fn record_counter(&self, key: Key, value: u64) {
         SINK.with(move |sink| {
@@ -89,7 +91,8 @@ impl Recorder for Receiver {
                 *sink = Some(new_sink);
             }
 
-            sink.as_mut().unwrap().record_count(key, value);
+            let (name, _) = key.into_parts();
+            sink.as_mut().unwrap().record_count(name, value);
         });
     }
 
@@ -101,7 +104,8 @@ impl Recorder for Receiver {
                 *sink = Some(new_sink);
             }
 
-            sink.as_mut().unwrap().record_gauge(key, value);
+            let (name, _) = key.into_parts();
+            sink.as_mut().unwrap().record_gauge(name, value);
         });
     }
 
@@ -113,7 +117,21 @@ impl Recorder for Receiver {
                 *sink = Some(new_sink);
             }
 
-            sink.as_mut().unwrap().record_value(key, value);
+            let (name, _) = key.into_parts();
+            sink.as_mut().unwrap().record_value(name, value);
+        });
+    }
+
+    fn record_histogram_n(&self, key: Key, value: u64, count: u64) {
+        SINK.with(move |sink| {
+            let mut sink = sink.borrow_mut();
+            if sink.is_none() {
+                let new_sink = self.get_sink();
+                *sink = Some(new_sink);
+            }
+
+            let (name, _) = key.into_parts();
+            sink.as_mut().unwrap().record_value_n(name, value, count);
         });
     }
 }
@@ -140,6 +140,15 @@ impl Sink {
         value_handle.update_histogram(value);
     }
 
+    /// Records the value for a value histogram identified by the given name, as observed `count`
+    /// times.
+    pub fn record_value_n<N: Into<MetricName>>(&mut self, name: N, value: u64, count: u64) {
+        let identifier =
+            MetricIdentifier::Unlabeled(name.into(), self.scope_handle, MetricKind::Histogram);
+        let value_handle = self.get_cached_value_handle(identifier);
+        value_handle.update_histogram_n(value, count);
+    }
+
     /// Creates a handle to the given counter.
     ///
     /// This handle can be embedded into an existing type and used to directly update the